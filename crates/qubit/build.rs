@@ -2,24 +2,27 @@
 
 use std::fs::File;
 use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use proc_macro2::TokenStream;
 use qubit_config::cargo::BuildCfgs;
 use qubit_config::general::Device;
 use qubit_config::linker::output_linker_script;
-use quote::quote;
+use qubit_config::mcu::Mcu;
+use quote::{format_ident, quote};
 
 // TODO: RA doesn't seem to work with `target-applies-to-host` option in config.toml
 // even though it builds just fine with cargo.
 // #[cfg(device = "import")]
-qubit_macros::import_device!(QUBIT_AUTHOR, QUBIT_MODEL);
+qubit_macros::import_device!(QUBIT_AUTHOR, QUBIT_MODEL, QUBIT_DEVICE);
 
 fn keyboard_tokens() -> TokenStream {
 	let mcu = device::MCU.as_str();
 
 	let keymap = device::LAYER0.0.iter().map(|row| {
-		let row_tokens = row.iter().map(|&n| proc_macro2::Literal::u8_unsuffixed(n));
+		let row_tokens = row
+			.iter()
+			.map(|action| proc_macro2::Literal::u16_unsuffixed(action.to_bits()));
 
 		quote! { [#(#row_tokens),*] }
 	});
@@ -46,8 +49,53 @@ fn keyboard_tokens() -> TokenStream {
 	}
 }
 
+/// Generates the `setup_led` macro, which takes the LED pin out of the MCU pins.
+fn led_tokens() -> TokenStream {
+	let Some(pin) = device::LED_PIN else {
+		return quote! {};
+	};
+
+	let pin_field = match device::MCU {
+		Mcu::RP2040 => {
+			let field = format_ident!("gpio{pin}");
+
+			quote! { $pins.#field }
+		}
+		Mcu::STM32F411 => {
+			let pin = pin.to_lowercase();
+
+			let (bank_letter, num) = pin.split_at(1);
+
+			let bank = format_ident!("gpio_{bank_letter}");
+			let field = format_ident!("p{bank_letter}{num}");
+
+			quote! { $pins.#bank.#field }
+		}
+	};
+
+	quote! {
+		macro_rules! setup_led {
+			($pins:expr) => {
+				#pin_field
+			};
+		}
+	}
+}
+
 fn codegen(file: &mut BufWriter<File>) {
-	let device_path = {
+	// A device module generated from a TOML file takes the place of the `qubit_device` model.
+	let device_path = if let Some(path) = option_env!("QUBIT_DEVICE").filter(|path| !path.is_empty()) {
+		quote! {
+			#[allow(dead_code, reason = "The module has all the items of a model.")]
+			mod device {
+				include!(#path);
+			}
+
+			pub use device::*;
+
+			use ::qubit_device as _;
+		}
+	} else {
 		let author_val = env!("QUBIT_AUTHOR");
 		let model_val = env!("QUBIT_MODEL");
 
@@ -58,6 +106,7 @@ fn codegen(file: &mut BufWriter<File>) {
 	};
 
 	let keyboard_tokens = keyboard_tokens();
+	let led_tokens = led_tokens();
 
 	let tokens = quote! {
 		#[comment = " This file is automatically generated and not intended for manual editing."]
@@ -65,6 +114,8 @@ fn codegen(file: &mut BufWriter<File>) {
 		#device_path
 
 		#keyboard_tokens
+
+		#led_tokens
 	};
 
 	let parsed: syn::File = syn::parse2(tokens).unwrap();
@@ -73,6 +124,23 @@ fn codegen(file: &mut BufWriter<File>) {
 	file.write_all(formatted.as_bytes()).unwrap();
 }
 
+/// Writes the VIA definition of the keyboard next to the firmware, to be loaded in the configurator.
+fn output_via_definition(out: &Path) {
+	let definition =
+		qubit_config::via::definition(device::NAME, &device::USB, &device::LAYER0, device::LED_PIN.is_some());
+
+	std::fs::write(out.join("via.json"), &definition).unwrap();
+
+	// The out directory is `target/<triple>/<profile>/build/<package>/out`.
+	if let Some(profile_dir) = out.ancestors().nth(3) {
+		std::fs::write(
+			profile_dir.join(format!("{}.via.json", env!("QUBIT_MODEL"))),
+			definition,
+		)
+		.unwrap();
+	}
+}
+
 fn main() {
 	let out = std::env::var_os("OUT_DIR").unwrap();
 	let out = PathBuf::from(out);
@@ -83,19 +151,21 @@ fn main() {
 	let mcu = device::MCU;
 	let device_type = device::DEVICE;
 
-	let memory_x = {
-		const KEYMAP_SIZE: usize = device::LAYER0.get_packed_size();
+	let keyboard_len = qubit_config::section::keyboard_len(
+		device::ROW_PINS.len(),
+		device::COL_PINS.len(),
+		device::LAYER0.get_packed_size(),
+	);
 
-		output_linker_script::<qubit_config::keyboard::KeyboardConfiguration<KEYMAP_SIZE>>(
-			mcu,
-			device::FLASH,
-			device_type,
-		)
-	};
+	let memory_x = output_linker_script(mcu, device::FLASH, device_type, keyboard_len);
 
 	let mut mem_x_file = File::create(out.join("memory.x")).unwrap();
 	mem_x_file.write_all(memory_x.as_bytes()).unwrap();
 
+	if std::env::var("CARGO_FEATURE_VIA").is_ok() {
+		output_via_definition(&out);
+	}
+
 	println!("cargo:rustc-link-search={}", out.display());
 
 	println!("cargo:rustc-link-arg-bins=--nmagic");
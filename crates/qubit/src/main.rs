@@ -13,8 +13,11 @@ use defmt_rtt as _;
 use panic_probe as _;
 
 use qubit_config::general::Configuration;
+use qubit_config::keyboard::layer::LAYER_COUNT;
+use qubit_config::section::CONFIGURATION_LEN;
 
 mod setup;
+mod storage;
 #[cfg(mcu = "rp2040")]
 mod time;
 mod usb;
@@ -27,17 +30,31 @@ mod codegen {
 		reason = "The pub export is required to access this macro from other modules."
 	)]
 	pub(crate) use setup_keyboard;
+	#[cfg(has_led)]
+	#[allow(
+		clippy::single_component_path_imports,
+		reason = "The pub export is required to access this macro from other modules."
+	)]
+	pub(crate) use setup_led;
 }
 
-#[used]
-#[unsafe(link_section = ".configuration")]
-static DEVICE_CONFIG: Configuration = Configuration {
+const DEVICE_CONFIG: Configuration<'static> = Configuration {
 	name: codegen::NAME,
 	author: codegen::AUTHOR,
 	version: codegen::VERSION.as_bitmap(),
 	device: codegen::DEVICE,
+	mcu: codegen::MCU,
+	usb: codegen::USB,
+	rows: codegen::ROW_PINS.len(),
+	cols: codegen::COL_PINS.len(),
+	layer_count: LAYER_COUNT,
 };
 
+/// The configuration in the format host tools read from the firmware image.
+#[used]
+#[unsafe(link_section = ".configuration")]
+static CONFIGURATION_SECTION: [u8; CONFIGURATION_LEN] = DEVICE_CONFIG.encode();
+
 /// The main entry point for the program.
 #[setup::entry]
 fn main() -> ! {
@@ -54,9 +71,17 @@ fn main() -> ! {
 	setup::start_countdown(&mut countdown);
 
 	loop {
-		if countdown.wait().is_ok() {
-			#[cfg(keyboard)]
-			qubit_usb_device.keyboard.send_pressed_keys();
+		#[cfg(keyboard)]
+		{
+			if countdown.wait().is_ok() {
+				qubit_usb_device.keyboard.scan();
+			}
+
+			qubit_usb_device.keyboard.process_events();
+			qubit_usb_device.keyboard.send_reports();
+
+			#[cfg(has_led)]
+			qubit_usb_device.keyboard.update_indicators();
 		}
 	}
 }
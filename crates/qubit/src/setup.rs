@@ -9,3 +9,6 @@ mod stm32f411;
 use stm32f411 as mcu;
 
 pub use mcu::*;
+
+/// The time between two scans of the keyboard matrix, in milliseconds.
+pub const SCAN_PERIOD_MS: u32 = 1;
@@ -14,6 +14,9 @@ pub static BOOT2: [u8; 256] = ::rp2040_boot2::BOOT_LOADER_GENERIC_03H;
 
 pub type CountDuration = hal::fugit::MicrosDurationU64;
 
+#[cfg(has_led)]
+pub type LedPin = hal::gpio::Pin<hal::gpio::DynPinId, hal::gpio::FunctionSioOutput, hal::gpio::PullDown>;
+
 /// Initialize all the peripherals and components the device needs.
 ///
 /// # Safety
@@ -45,9 +48,8 @@ pub unsafe fn initialize_mcu() -> (QubitDevice, Countdown) {
 		hal::gpio::Pins::new(dp.IO_BANK0, dp.PADS_BANK0, sio.gpio_bank0, &mut dp.RESETS)
 	};
 
-	// // Use this LED to check for errors during setup.
-	// #[cfg(has_led)]
-	// let mut led_pin = pins.gpio25.into_push_pull_output_in_state(PinState::High);
+	#[cfg(has_led)]
+	let led_pin = crate::codegen::setup_led!(pins).into_push_pull_output().into_dyn_pin();
 
 	let countdown = {
 		let timer = hal::timer::Timer::new(dp.TIMER, &mut dp.RESETS, &clocks);
@@ -71,10 +73,19 @@ pub unsafe fn initialize_mcu() -> (QubitDevice, Countdown) {
 	let kb_matrix = crate::codegen::setup_keyboard!(pins);
 
 	// SAFETY: The caller guarantees this is called once, before interrupts are enabled.
-	let qubit_usb_device = unsafe { crate::usb::QubitDevice::new(usb_alloc, kb_matrix) };
+	unsafe {
+		crate::storage::init(crate::storage::McuFlash::new());
+	}
 
-	// #[cfg(has_led)]
-	// led_pin.set_low().unwrap();
+	// SAFETY: The caller guarantees this is called once, before interrupts are enabled.
+	let qubit_usb_device = unsafe {
+		crate::usb::QubitDevice::new(
+			usb_alloc,
+			kb_matrix,
+			#[cfg(has_led)]
+			led_pin,
+		)
+	};
 
 	(qubit_usb_device, countdown)
 }
@@ -93,8 +104,33 @@ pub unsafe fn enable_interrupt() {
 	}
 }
 
+/// Restarts the MCU, running the firmware from the start.
+pub fn reset() -> ! {
+	cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Restarts the MCU into the USB bootloader of its ROM, the one holding BOOTSEL at power on starts.
+pub fn reset_to_bootloader() -> ! {
+	// Both the mass storage and the PICOBOOT interfaces, without an activity LED.
+	hal::rom_data::reset_to_usb_boot(0, 0);
+
+	// The ROM resets the MCU with the watchdog and never returns.
+	loop {
+		cortex_m::asm::nop();
+	}
+}
+
+/// Turns the LED on or off.
+#[cfg(has_led)]
+pub fn set_led(led: &mut LedPin, on: bool) {
+	use embedded_hal::digital::{OutputPin as _, PinState};
+
+	// The pin can't fail to be set.
+	_ = led.set_state(PinState::from(on));
+}
+
 pub fn start_countdown(countdown: &mut Countdown) {
-	countdown.start(CountDuration::millis(10));
+	countdown.start(CountDuration::millis(u64::from(super::SCAN_PERIOD_MS)));
 }
 
 /// Poll the USB for new events.
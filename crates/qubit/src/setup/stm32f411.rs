@@ -1,3 +1,5 @@
+use core::mem::MaybeUninit;
+
 pub use cortex_m_rt::entry;
 
 use hal::pac::interrupt;
@@ -12,6 +14,20 @@ pub type UsbBusAllocator = usb_device::bus::UsbBusAllocator<UsbBus>;
 
 pub type CountDuration = fugit::TimerDurationU32<1_000_000>;
 
+/// The start of the system memory, which holds the DFU bootloader of the MCU.
+const SYSTEM_MEMORY: usize = 0x1FFF_0000;
+
+/// The value of [`BOOTLOADER_FLAG`] that asks for the bootloader.
+const BOOTLOADER_REQUEST: u32 = 0xB007_10AD;
+
+/// Kept through a reset, since the startup code doesn't initialize `.uninit`.
+#[unsafe(link_section = ".uninit.BOOTLOADER_FLAG")]
+static mut BOOTLOADER_FLAG: MaybeUninit<u32> = MaybeUninit::uninit();
+
+#[cfg(has_led)]
+pub type LedPin = hal::gpio::ErasedPin<hal::gpio::Output<hal::gpio::PushPull>>;
+
+#[allow(clippy::struct_field_names, reason = "The fields are named after the GPIO banks.")]
 struct Pins {
 	pub gpio_a: hal::gpio::gpioa::Parts,
 	#[cfg(stm32f411_bank_b)]
@@ -32,6 +48,11 @@ struct Pins {
 ///
 /// The function needs to be called only once, before enabling interrupts.
 pub unsafe fn initialize_mcu() -> (QubitDevice, Countdown) {
+	// SAFETY: The caller guarantees this is called before anything is set up.
+	unsafe {
+		enter_requested_bootloader();
+	}
+
 	let dp = hal::pac::Peripherals::take().unwrap();
 
 	let clocks = dp
@@ -58,6 +79,9 @@ pub unsafe fn initialize_mcu() -> (QubitDevice, Countdown) {
 		gpio_h: dp.GPIOH.split(),
 	};
 
+	#[cfg(has_led)]
+	let led_pin = crate::codegen::setup_led!(pins).into_push_pull_output().erase();
+
 	let countdown = dp.TIM2.counter_us(&clocks);
 
 	let usb_alloc = {
@@ -83,8 +107,20 @@ pub unsafe fn initialize_mcu() -> (QubitDevice, Countdown) {
 	#[cfg(keyboard)]
 	let kb_matrix = crate::codegen::setup_keyboard!(pins);
 
+	// SAFETY: The caller guarantees this is called once, before interrupts are enabled.
+	unsafe {
+		crate::storage::init(crate::storage::McuFlash::new(dp.FLASH));
+	}
+
 	// SAFETY: The caller guarantees this is called once, before interrupts are enabled..
-	let qubit_usb_device = unsafe { crate::usb::QubitDevice::new(usb_alloc, kb_matrix) };
+	let qubit_usb_device = unsafe {
+		crate::usb::QubitDevice::new(
+			usb_alloc,
+			kb_matrix,
+			#[cfg(has_led)]
+			led_pin,
+		)
+	};
 
 	(qubit_usb_device, countdown)
 }
@@ -103,8 +139,61 @@ pub unsafe fn enable_interrupt() {
 	}
 }
 
+/// Restarts the MCU, running the firmware from the start.
+pub fn reset() -> ! {
+	cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Restarts the MCU into the DFU bootloader of its system memory.
+///
+/// The bootloader expects the peripherals the way a reset leaves them, so the MCU is reset first
+/// and jumps to the bootloader at the start of [`initialize_mcu`].
+pub fn reset_to_bootloader() -> ! {
+	cortex_m::interrupt::disable();
+
+	// SAFETY: The interrupts are disabled and the flag is otherwise only used before they are enabled.
+	unsafe {
+		(&raw mut BOOTLOADER_FLAG)
+			.cast::<u32>()
+			.write_volatile(BOOTLOADER_REQUEST);
+	}
+
+	cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Jumps to the bootloader if [`reset_to_bootloader`] asked for it before the last reset.
+///
+/// # Safety
+///
+/// The function needs to be called before any peripheral or interrupt is set up.
+unsafe fn enter_requested_bootloader() {
+	let flag = (&raw mut BOOTLOADER_FLAG).cast::<u32>();
+
+	// SAFETY: The RAM holds a value after any reset, and nothing else uses the flag yet.
+	if unsafe { flag.read_volatile() } != BOOTLOADER_REQUEST {
+		return;
+	}
+
+	// SAFETY: As above. The flag is cleared so the next reset runs the firmware again.
+	unsafe {
+		flag.write_volatile(0);
+	}
+
+	// SAFETY: The system memory starts with the vector table of the bootloader, and the MCU is still
+	// in the state the reset left it in.
+	unsafe {
+		cortex_m::asm::bootload(SYSTEM_MEMORY as *const u32);
+	}
+}
+
+/// Turns the LED on or off.
+#[cfg(has_led)]
+pub fn set_led(led: &mut LedPin, on: bool) {
+	led.set_state(hal::gpio::PinState::from(on));
+}
+
 pub fn start_countdown(countdown: &mut Countdown) {
-	countdown.start(CountDuration::millis(10)).unwrap();
+	countdown.start(CountDuration::millis(super::SCAN_PERIOD_MS)).unwrap();
 }
 
 #[interrupt]
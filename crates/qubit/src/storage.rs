@@ -0,0 +1,113 @@
+//! The persistent storage of the device, kept in the last sectors of the flash.
+//!
+//! The sectors are reserved by the linker script and managed by the
+//! [`Store`](qubit_config::storage::Store), which only needs a backend to access the flash of the MCU.
+
+#[cfg(any(feature = "silverplate", feature = "via"))]
+use core::sync::atomic::{AtomicBool, Ordering, compiler_fence};
+
+use qubit_config::storage::{StorageLayout, Store};
+
+use crate::codegen;
+
+#[cfg(mcu = "rp2040")]
+mod rp2040;
+#[cfg(mcu = "rp2040")]
+use rp2040 as mcu;
+
+#[cfg(mcu = "stm32f411")]
+mod stm32f411;
+#[cfg(mcu = "stm32f411")]
+use stm32f411 as mcu;
+
+pub use mcu::McuFlash;
+
+/// The part of the flash the linker script reserves for the storage.
+pub const LAYOUT: StorageLayout = StorageLayout::new(codegen::MCU, codegen::FLASH);
+
+/// The key of the keymaps set by the user, stored after the layout they were packed with.
+pub const KEYMAPS_KEY: u16 = 0x0001;
+/// The key of the macro buffer set through VIA.
+#[cfg(feature = "via")]
+pub const MACROS_KEY: u16 = 0x0002;
+/// The key of the backlight brightness set through VIA.
+#[cfg(all(feature = "via", has_led))]
+pub const BACKLIGHT_KEY: u16 = 0x0003;
+
+static mut STORE: Option<Store<McuFlash>> = None;
+
+/// Set while the main loop uses the store, which keeps the USB interrupt away from it.
+#[cfg(any(feature = "silverplate", feature = "via"))]
+static HELD_BY_MAIN_LOOP: AtomicBool = AtomicBool::new(false);
+
+/// Mounts the store. If the flash can't be used, the device keeps working without the storage.
+///
+/// # Safety
+///
+/// This function must be called **only once** for the lifetime of the program and before enabling
+/// the interrupts.
+pub unsafe fn init(flash: McuFlash) {
+	let store = Store::mount(flash).ok();
+
+	#[cfg(feature = "defmt")]
+	if store.is_none() {
+		defmt::error!("The storage couldn't be mounted.");
+	}
+
+	let ptr = &raw mut STORE;
+
+	// SAFETY: `ptr` was obtained from a static value and so is guaranteed to be non-null and properly
+	// aligned. The caller guarantees nothing else is accessing it.
+	unsafe {
+		*ptr = store;
+	}
+}
+
+/// Returns a mutable reference to the store, if it was mounted.
+///
+/// # Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+///
+/// * [`init`] must have been called before this function.
+/// * No other reference to the static value exists.
+///
+/// Returns `None` while the main loop uses the store with [`with_store`].
+pub unsafe fn get_mut<'a>() -> Option<&'a mut Store<McuFlash>> {
+	#[cfg(any(feature = "silverplate", feature = "via"))]
+	if HELD_BY_MAIN_LOOP.load(Ordering::Relaxed) {
+		return None;
+	}
+
+	let ptr = &raw mut STORE;
+
+	// SAFETY: The caller guarantees no other reference exists.
+	unsafe { (*ptr).as_mut() }
+}
+
+/// Runs the closure with the store, if it was mounted, from the main loop.
+///
+/// Flash writes are slow, so the interrupts aren't disabled meanwhile. The USB interrupt can still
+/// run, but [`get_mut`] doesn't give it the store until the closure returns.
+///
+/// # Safety
+///
+/// This function must only be called from the main loop, after [`init`].
+#[cfg(any(feature = "silverplate", feature = "via"))]
+pub unsafe fn with_store<R>(f: impl FnOnce(Option<&mut Store<McuFlash>>) -> R) -> R {
+	// The USB interrupt always runs to completion before the main loop goes on, so it's done with
+	// the store by now and won't take it again until the flag is cleared.
+	HELD_BY_MAIN_LOOP.store(true, Ordering::Relaxed);
+	compiler_fence(Ordering::SeqCst);
+
+	let ptr = &raw mut STORE;
+
+	// SAFETY: `ptr` was obtained from a static value and the flag keeps the USB interrupt from
+	// taking another reference. The caller guarantees the store was initialized.
+	let result = f(unsafe { (*ptr).as_mut() });
+
+	compiler_fence(Ordering::SeqCst);
+	HELD_BY_MAIN_LOOP.store(false, Ordering::Relaxed);
+
+	result
+}
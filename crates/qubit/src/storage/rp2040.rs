@@ -0,0 +1,167 @@
+//! The RP2040 runs its code straight from an external flash mapped into the address space (XIP).
+//! The flash can't be read while it's erased or programmed, so those operations run from RAM
+//! through the functions of the boot ROM, with the interrupts disabled.
+
+use qubit_config::storage::Flash;
+
+use super::LAYOUT;
+use crate::setup::hal::rom_data;
+
+/// The address the flash is mapped at.
+const XIP_BASE: u32 = 0x1000_0000;
+/// The flash can only be programmed a whole page at a time.
+const PAGE_SIZE: usize = 256;
+/// The command that erases a 4 KiB sector.
+const SECTOR_ERASE_CMD: u8 = 0x20;
+
+/// The boot ROM functions used to erase and program the flash.
+///
+/// They are looked up before leaving XIP mode, since the lookup runs from the flash.
+struct RomFunctions {
+	connect_internal_flash: unsafe extern "C" fn(),
+	flash_exit_xip: unsafe extern "C" fn(),
+	flash_range_erase: unsafe extern "C" fn(u32, usize, u32, u8),
+	flash_range_program: unsafe extern "C" fn(u32, *const u8, usize),
+	flash_flush_cache: unsafe extern "C" fn(),
+	flash_enter_cmd_xip: unsafe extern "C" fn(),
+}
+
+impl RomFunctions {
+	fn lookup() -> Self {
+		Self {
+			connect_internal_flash: rom_data::connect_internal_flash::ptr(),
+			flash_exit_xip: rom_data::flash_exit_xip::ptr(),
+			flash_range_erase: rom_data::flash_range_erase::ptr(),
+			flash_range_program: rom_data::flash_range_program::ptr(),
+			flash_flush_cache: rom_data::flash_flush_cache::ptr(),
+			flash_enter_cmd_xip: rom_data::flash_enter_cmd_xip::ptr(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+	/// Erases the sector at the address.
+	Erase(u32),
+	/// Programs a page at the address.
+	Program(u32, *const u8),
+}
+
+/// Runs `operation` on the flash, from RAM.
+///
+/// The flash goes back to the same slow XIP mode the second stage bootloader sets up.
+///
+/// # Safety
+///
+/// The interrupts need to be disabled, since the handlers run from the flash. The address needs to
+/// be aligned to the operation and the data of a program needs to be a page long.
+#[unsafe(link_section = ".data.ram_func")]
+#[inline(never)]
+unsafe fn run_from_ram(rom: &RomFunctions, operation: Operation) {
+	// SAFETY: The caller guarantees nothing runs from the flash while it's out of XIP mode, and the
+	// ROM functions are called in the order the datasheet requires.
+	unsafe {
+		(rom.connect_internal_flash)();
+		(rom.flash_exit_xip)();
+
+		match operation {
+			Operation::Erase(address) => {
+				(rom.flash_range_erase)(
+					address,
+					LAYOUT.sector_size as usize,
+					LAYOUT.sector_size,
+					SECTOR_ERASE_CMD,
+				);
+			}
+			Operation::Program(address, data) => (rom.flash_range_program)(address, data, PAGE_SIZE),
+		}
+
+		(rom.flash_flush_cache)();
+		(rom.flash_enter_cmd_xip)();
+	}
+}
+
+fn run(operation: Operation) {
+	let rom = RomFunctions::lookup();
+
+	cortex_m::interrupt::free(|_| {
+		// SAFETY: The interrupts are disabled and the callers align the operations.
+		unsafe {
+			run_from_ram(&rom, operation);
+		}
+	});
+}
+
+#[derive(Debug)]
+pub struct McuFlash;
+
+impl McuFlash {
+	#[must_use]
+	pub const fn new() -> Self {
+		Self
+	}
+
+	/// The offset of the store from the start of the flash, used by the ROM functions.
+	const fn flash_offset(offset: u32) -> u32 {
+		LAYOUT.origin - XIP_BASE + offset
+	}
+}
+
+impl Flash for McuFlash {
+	type Error = core::convert::Infallible;
+
+	fn sector_size(&self) -> u32 {
+		LAYOUT.sector_size
+	}
+
+	fn sector_count(&self) -> u32 {
+		LAYOUT.sector_count
+	}
+
+	fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+		let address = (LAYOUT.origin + offset) as *const u8;
+
+		// SAFETY: The store only reads inside the storage region, which is mapped to the address
+		// space through XIP.
+		unsafe {
+			core::ptr::copy_nonoverlapping(address, bytes.as_mut_ptr(), bytes.len());
+		}
+
+		Ok(())
+	}
+
+	fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+		#[allow(clippy::cast_possible_truncation, reason = "`PAGE_SIZE` fits in a u32.")]
+		const PAGE: u32 = PAGE_SIZE as u32;
+
+		let mut offset = Self::flash_offset(offset);
+		let mut bytes = bytes;
+
+		while !bytes.is_empty() {
+			let page_start = offset - offset % PAGE;
+			let start = (offset - page_start) as usize;
+			let len = bytes.len().min(PAGE_SIZE - start);
+
+			// Programming can only clear bits, so the rest of the page is left as it is.
+			let mut page = [0xFF; PAGE_SIZE];
+			page[start..start + len].copy_from_slice(&bytes[..len]);
+
+			run(Operation::Program(page_start, page.as_ptr()));
+
+			#[allow(clippy::cast_possible_truncation, reason = "`len` is at most `PAGE_SIZE`.")]
+			{
+				offset += len as u32;
+			}
+
+			bytes = &bytes[len..];
+		}
+
+		Ok(())
+	}
+
+	fn erase(&mut self, sector: u32) -> Result<(), Self::Error> {
+		run(Operation::Erase(Self::flash_offset(sector * LAYOUT.sector_size)));
+
+		Ok(())
+	}
+}
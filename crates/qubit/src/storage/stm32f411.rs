@@ -0,0 +1,63 @@
+//! The STM32F411 erases its internal flash a sector at a time through the flash interface. The
+//! CPU stalls while the flash is busy, so nothing else needs to be done to keep running from it.
+
+use qubit_config::storage::Flash;
+
+use super::LAYOUT;
+use crate::setup::hal;
+
+use hal::flash::FlashExt;
+
+#[derive(Debug)]
+pub struct McuFlash {
+	flash: hal::pac::FLASH,
+}
+
+impl McuFlash {
+	#[must_use]
+	pub const fn new(flash: hal::pac::FLASH) -> Self {
+		Self { flash }
+	}
+
+	/// The offset of the store from the start of the flash, used by the flash interface.
+	fn flash_offset(&self, offset: u32) -> usize {
+		(LAYOUT.origin + offset) as usize - self.flash.address()
+	}
+}
+
+impl Flash for McuFlash {
+	type Error = hal::flash::Error;
+
+	fn sector_size(&self) -> u32 {
+		LAYOUT.sector_size
+	}
+
+	fn sector_count(&self) -> u32 {
+		LAYOUT.sector_count
+	}
+
+	fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+		let start = self.flash_offset(offset);
+
+		bytes.copy_from_slice(&self.flash.read()[start..start + bytes.len()]);
+
+		Ok(())
+	}
+
+	fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+		let start = self.flash_offset(offset);
+
+		self.flash.unlocked().program(start, bytes.iter())
+	}
+
+	fn erase(&mut self, sector: u32) -> Result<(), Self::Error> {
+		let start = self.flash_offset(sector * LAYOUT.sector_size);
+
+		let flash_sector = self
+			.flash
+			.sector(start)
+			.expect("The storage layout only covers sectors of the flash.");
+
+		self.flash.unlocked().erase(flash_sector.number)
+	}
+}
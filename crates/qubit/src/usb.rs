@@ -1,6 +1,6 @@
 use core::mem::MaybeUninit;
 
-use usb_device::device::{StringDescriptors, UsbDevice, UsbDeviceBuilder, UsbVidPid};
+use usb_device::device::{StringDescriptors, UsbDevice, UsbDeviceBuilder, UsbDeviceState, UsbVidPid};
 
 use crate::DEVICE_CONFIG;
 use crate::codegen::{KeyboardMatrix, USB};
@@ -27,7 +27,11 @@ impl QubitDevice {
 	/// This method will initialize all the static variables the firmware needs. This must be called
 	/// **only once** for the lifetime of the program AND **before** enabling the
 	/// interrupts.
-	pub unsafe fn new(bus_alloc: UsbBusAllocator, matrix: KeyboardMatrix) -> Self {
+	pub unsafe fn new(
+		bus_alloc: UsbBusAllocator,
+		matrix: KeyboardMatrix,
+		#[cfg(has_led)] led: crate::setup::LedPin,
+	) -> Self {
 		let usb_bus_alloc = {
 			let ptr = &raw mut USB_BUS_ALLOC;
 
@@ -47,7 +51,14 @@ impl QubitDevice {
 
 		// SAFETY: Serial was initialized above and the caller guarantees this will be called only once.
 		#[cfg(keyboard)]
-		let keyboard = unsafe { keyboard::KeyboardInstance::new(usb_bus_alloc, matrix) };
+		let keyboard = unsafe {
+			keyboard::KeyboardInstance::new(
+				usb_bus_alloc,
+				matrix,
+				#[cfg(has_led)]
+				led,
+			)
+		};
 
 		let usb_device = {
 			let builder_res = UsbDeviceBuilder::new(usb_bus_alloc, vid_pid).strings(&[descriptors]);
@@ -101,15 +112,38 @@ pub unsafe fn poll_device() {
 	#[cfg(keyboard)]
 	let keyboard_hid = unsafe { keyboard::get_mut() };
 
+	// SAFETY: The function is called inside an interrupt. The VIA class was initialized together with
+	// the keyboard.
+	#[cfg(all(keyboard, feature = "via"))]
+	let via_hid = unsafe { keyboard::via::get_mut() };
+
 	// The classes are passed in the same order they were configured in.
 	let may_have_data = device.poll(&mut [
 		#[cfg(keyboard)]
 		keyboard_hid,
+		#[cfg(all(keyboard, feature = "via"))]
+		via_hid,
 	]);
 
+	// A bus reset brings the keyboard back to the report protocol.
+	#[cfg(keyboard)]
+	if device.state() == UsbDeviceState::Default {
+		keyboard::reset_protocol(keyboard_hid);
+	}
+
+	// A response VIA didn't get yet goes before the next one.
+	// SAFETY: The function is called inside the USB interrupt.
+	#[cfg(all(keyboard, feature = "via"))]
+	unsafe {
+		keyboard::via::send_pending_response(via_hid);
+	}
+
 	if may_have_data {
 		// Check for an incoming keyboard report.
 		#[cfg(keyboard)]
 		keyboard::process_incoming_report(keyboard_hid);
+
+		#[cfg(all(keyboard, feature = "via"))]
+		keyboard::via::process_report(via_hid);
 	}
 }
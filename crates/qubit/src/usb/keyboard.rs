@@ -1,52 +1,160 @@
 use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
-use qubit_config::keyboard::keycodes::{
-	KM_LALT, KM_LCTRL, KM_LMETA, KM_LSHIFT, KM_RALT, KM_RCTRL, KM_RMETA, KM_RSHIFT,
-};
+#[cfg(feature = "defmt")]
+use qubit_config::keyboard::LockIndicator;
+use qubit_config::keyboard::{Debouncer, TapHoldEngine, event, key_bit};
+use qubit_config::silverplate::RebootMode;
+use usb_device::UsbError;
 use usb_device::bus::UsbBusAllocator;
-use usbd_hid::hid_class::{HIDClass, HidClassSettings, HidCountryCode, HidProtocol, HidSubClass, ProtocolModeConfig};
+use usbd_hid::hid_class::{
+	HIDClass, HidClassSettings, HidCountryCode, HidProtocol, HidProtocolMode, HidSubClass, ProtocolModeConfig,
+};
 
 use crate::codegen::KeyboardMatrix;
 use crate::setup::UsbBus;
 
+mod bootmagic;
 mod descriptor;
 mod keymaps;
+mod layers;
+mod lock;
+mod pipeline;
 mod report;
 #[cfg(feature = "silverplate")]
 mod silverplate;
+mod state;
+#[cfg(feature = "via")]
+pub mod via;
 
 //
-use qubit_config::keyboard::Keymaps;
+use qubit_config::section;
 
 use crate::codegen;
 
 pub const PACKED_SIZE: usize = codegen::LAYER0.get_packed_size();
 pub const PRESSED_KEYS_BITMAPS_LEN: usize = PACKED_SIZE.div_ceil(usize::BITS as usize);
 
-pub type KeyboardConfiguration = qubit_config::keyboard::KeyboardConfiguration<PACKED_SIZE>;
+pub const KEYBOARD_SECTION_LEN: usize =
+	section::keyboard_len(codegen::ROW_PINS.len(), codegen::COL_PINS.len(), PACKED_SIZE);
 //
 
+const _: () = {
+	assert!(
+		codegen::LAYER1.has_layout_of(&codegen::LAYER0)
+			&& codegen::LAYER2.has_layout_of(&codegen::LAYER0)
+			&& codegen::LAYER3.has_layout_of(&codegen::LAYER0)
+			&& codegen::LAYER4.has_layout_of(&codegen::LAYER0),
+		"All layers must have their empty spaces in the same positions as LAYER0."
+	);
+};
+
+/// The tapping term of every key, with the per-key overrides applied.
+static TAPPING_TERMS: [u16; PACKED_SIZE] = {
+	let mut terms = [codegen::TAP_HOLD.tapping_term; PACKED_SIZE];
+
+	let per_key = codegen::TAP_HOLD.per_key;
+
+	let mut i = 0;
+
+	while i < per_key.len() {
+		let key = per_key[i];
+
+		match codegen::LAYER0.packed_index(key.row, key.col) {
+			Some(index) => terms[index] = key.tapping_term,
+			None => panic!("Tapping terms can only be set for positions that have a key."),
+		}
+
+		i += 1;
+	}
+
+	terms
+};
+
+/// The keymaps in the format host tools read from, and patch into, the firmware image.
 #[used]
 #[unsafe(link_section = ".keyboard")]
-static CONFIG: KeyboardConfiguration = KeyboardConfiguration {
-	keymaps: Keymaps {
-		keymap_0: codegen::LAYER0.get_packed(),
-		keymap_1: codegen::LAYER1.get_packed(),
-		keymap_2: codegen::LAYER2.get_packed(),
-		keymap_3: codegen::LAYER3.get_packed(),
-		keymap_4: codegen::LAYER4.get_packed(),
-	},
-};
+static KEYBOARD_SECTION: [u8; KEYBOARD_SECTION_LEN] = section::encode_keyboard([
+	&codegen::LAYER0,
+	&codegen::LAYER1,
+	&codegen::LAYER2,
+	&codegen::LAYER3,
+	&codegen::LAYER4,
+]);
 
 /// HID class for a keyboard device.
 static mut HID_CLASS: MaybeUninit<HIDClass<'static, UsbBus>> = MaybeUninit::uninit();
 
+/// Whether the NKRO report was chosen over the 6KRO one, by a key or a vendor command.
+///
+/// The boot report is still used while the host asks for the boot protocol.
+static NKRO_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// The reboot a key or the host asked for, as a [`RebootMode`], or [`NO_REBOOT`].
+static REBOOT_REQUEST: AtomicU8 = AtomicU8::new(NO_REBOOT);
+
+const NO_REBOOT: u8 = u8::MAX;
+
+/// The time between a reboot request and the reboot, in milliseconds, so the host can read the
+/// last reports and responses.
+const REBOOT_DELAY_MS: u32 = 50;
+
+/// Asks for a reboot, which happens [`REBOOT_DELAY_MS`] later.
+fn request_reboot(mode: RebootMode) {
+	REBOOT_REQUEST.store(mode as u8, Ordering::Relaxed);
+}
+
+/// Takes the reboot request, if there is one.
+fn take_reboot_request() -> Option<RebootMode> {
+	let request = RebootMode::from_u8(REBOOT_REQUEST.load(Ordering::Relaxed));
+
+	if request.is_some() {
+		REBOOT_REQUEST.store(NO_REBOOT, Ordering::Relaxed);
+	}
+
+	request
+}
+
+fn is_nkro_enabled() -> bool {
+	NKRO_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Chooses between the NKRO and the 6KRO report.
+fn set_nkro(enabled: bool) {
+	NKRO_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// The format of the reports that should be sent, taking the protocol the host asked for into account.
+fn report_format(hid_class: &HIDClass<UsbBus>) -> report::ReportFormat {
+	if matches!(hid_class.get_protocol_mode(), Ok(HidProtocolMode::Boot)) {
+		report::ReportFormat::Boot
+	} else if is_nkro_enabled() {
+		report::ReportFormat::Nkro
+	} else {
+		report::ReportFormat::SixKro
+	}
+}
+
 #[derive(Debug)]
 pub struct KeyboardInstance {
-	is_nkro: bool,
-	prev_nkro_report: report::KeyboardNkroReport,
-	prev_6kro_report: report::Keyboard6kroReport,
 	matrix: KeyboardMatrix,
+	debouncer: Debouncer<PACKED_SIZE, PRESSED_KEYS_BITMAPS_LEN>,
+	prev_pressed_keys: [usize; PRESSED_KEYS_BITMAPS_LEN],
+	events: pipeline::EventQueue,
+	tap_hold: TapHoldEngine,
+	reporter: pipeline::Reporter,
+	/// The time of the current scan in milliseconds.
+	now: u32,
+	#[cfg(has_led)]
+	led: crate::setup::LedPin,
+	/// The report format changed and the host was not told yet.
+	#[cfg(feature = "silverplate")]
+	report_format_changed: bool,
+	/// The reboot that was asked for, and the time it was asked at.
+	reboot_request: Option<(RebootMode, u32)>,
+	/// The changes made through VIA that weren't stored yet.
+	#[cfg(feature = "via")]
+	via_stores: via::PendingStores,
 }
 
 impl KeyboardInstance {
@@ -58,26 +166,20 @@ impl KeyboardInstance {
 	///
 	/// If the `serial` feature is enabled, the caller must ensure the static for the port was
 	/// already initialized using [`init_class`](super::serial::init_class) before calling this method.
-	pub unsafe fn new(usb_bus_alloc: &'static UsbBusAllocator<UsbBus>, matrix: KeyboardMatrix) -> Self {
+	pub unsafe fn new(
+		usb_bus_alloc: &'static UsbBusAllocator<UsbBus>,
+		mut matrix: KeyboardMatrix,
+		#[cfg(has_led)] led: crate::setup::LedPin,
+	) -> Self {
 		// Set the value of the HID static.
 		let hid_settings = HidClassSettings {
-			subclass: HidSubClass::NoSubClass,
+			subclass: HidSubClass::Boot,
 			protocol: HidProtocol::Keyboard,
-			config: ProtocolModeConfig::ForceReport,
+			config: ProtocolModeConfig::DefaultBehavior,
 			locale: HidCountryCode::US,
 		};
 
-		// TODO: Find a way to switch between boot and report mode.
-
-		let is_nkro = true;
-
-		let report_descriptor = if is_nkro {
-			descriptor::DESCRIPTOR_NKRO
-		} else {
-			descriptor::DESCRIPTOR_6KRO
-		};
-
-		let hid_class = HIDClass::new_with_settings(usb_bus_alloc, report_descriptor, 1, hid_settings);
+		let hid_class = HIDClass::new_with_settings(usb_bus_alloc, descriptor::DESCRIPTOR, 1, hid_settings);
 
 		let ptr = &raw mut HID_CLASS;
 
@@ -87,78 +189,219 @@ impl KeyboardInstance {
 			(*ptr).write(hid_class);
 		}
 
+		// The keys held while plugging in can clear the settings and keymaps before they are read.
+		// SAFETY: The caller guarantees this will be called only once, after the storage was initialized.
+		unsafe {
+			bootmagic::check(&mut matrix);
+		}
+
+		// The VIA interface follows the one of the keyboard.
+		// SAFETY: The caller guarantees this will be called only once, after the storage was initialized.
+		#[cfg(feature = "via")]
+		unsafe {
+			via::init(usb_bus_alloc);
+		}
+
 		// SAFETY: The caller guarantees this will be called only once.
 		unsafe {
 			keymaps::init_active_keymaps();
 		}
 
 		Self {
-			is_nkro,
-			prev_nkro_report: [0; 34],
-			prev_6kro_report: [0; 9],
 			matrix,
+			debouncer: Debouncer::new(codegen::DEBOUNCE),
+			prev_pressed_keys: [0; PRESSED_KEYS_BITMAPS_LEN],
+			events: pipeline::EventQueue::new(),
+			tap_hold: TapHoldEngine::new(codegen::TAP_HOLD.flavor, &TAPPING_TERMS),
+			reporter: pipeline::Reporter::new(if is_nkro_enabled() {
+				report::ReportFormat::Nkro
+			} else {
+				report::ReportFormat::SixKro
+			}),
+			now: 0,
+			#[cfg(has_led)]
+			led,
+			#[cfg(feature = "silverplate")]
+			report_format_changed: false,
+			reboot_request: None,
+			#[cfg(feature = "via")]
+			via_stores: via::PendingStores::new(),
+		}
+	}
+
+	/// Scans the keyboard matrix and queues an event for every key that changed since the last scan.
+	///
+	/// Needs to be called once every [`SCAN_PERIOD_MS`](crate::setup::SCAN_PERIOD_MS).
+	pub fn scan(&mut self) {
+		self.now = self.now.wrapping_add(crate::setup::SCAN_PERIOD_MS);
+
+		let pressed_keys = self.debouncer.debounce(self.matrix.get_pressed_keys(), self.now);
+
+		event::diff_scans(&mut self.prev_pressed_keys, pressed_keys, self.now, &mut self.events);
+	}
+
+	/// Passes the queued events through the key features, queueing the reports they produce.
+	pub fn process_events(&mut self) {
+		#[cfg(any(feature = "silverplate", feature = "via"))]
+		self.process_host_requests();
+
+		while let Some(event) = self.events.pop_front() {
+			self.tap_hold.process(event, &mut self.reporter);
+		}
+
+		self.tap_hold.tick(self.now, &mut self.reporter);
+
+		self.process_reboot_request();
+
+		let format = with_hid_class(|hid_class| report_format(hid_class));
+
+		#[cfg_attr(not(feature = "silverplate"), allow(unused_variables))]
+		let changed = self.reporter.set_format(format);
+
+		#[cfg(feature = "silverplate")]
+		{
+			self.report_format_changed |= changed;
 		}
 	}
 
-	/// Scans the keyboard matrix, constructs a HID report, and sends it over USB (if changed).
-	/// A critical section is used to ensure safe, exclusive access to global mutable state.
-	pub fn send_pressed_keys(&mut self) {
-		let pressed_keys = self.matrix.get_pressed_keys();
+	/// Applies the requests of the host that can't be handled inside the USB interrupt.
+	#[cfg(any(feature = "silverplate", feature = "via"))]
+	fn process_host_requests(&mut self) {
+		#[cfg(feature = "silverplate")]
+		self.reporter.apply_layer_request();
 
-		if self.is_nkro {
-			// SAFETY: The active keymap was initialized before this call.
-			let report = unsafe { report::construct_nkro_report(pressed_keys) };
+		// SAFETY: This runs from the main loop and the keymaps are initialized together with the
+		// keyboard.
+		#[cfg(feature = "silverplate")]
+		unsafe {
+			silverplate::process_commit_request();
+		}
 
-			if report != self.prev_nkro_report {
-				cortex_m::interrupt::free(|_| {
-					let hid_class = {
-						let ptr = &raw const HID_CLASS;
+		// SAFETY: This runs from the main loop, after the keyboard was initialized.
+		#[cfg(feature = "via")]
+		unsafe {
+			self.via_stores.process(self.now);
+		}
+	}
 
-						// SAFETY: This is safe because:
-						//
-						// * The content was fully initialized when this struct was created.
-						// * We access this inside the critical section which prevents two mutable references
-						// to the value from being created.
-						unsafe { (*ptr).assume_init_ref() }
-					};
+	/// Reboots once the delay after a reboot request has passed.
+	fn process_reboot_request(&mut self) {
+		if let Some(mode) = take_reboot_request() {
+			self.reboot_request = Some((mode, self.now));
+		}
 
-					_ = hid_class.push_raw_input(report.as_ref());
-				});
+		if let Some((mode, time)) = self.reboot_request
+			&& self.now.wrapping_sub(time) >= REBOOT_DELAY_MS
+		{
+			// The changes VIA made just before the reboot would be lost otherwise.
+			// SAFETY: This runs from the main loop, after the keyboard was initialized.
+			#[cfg(feature = "via")]
+			unsafe {
+				self.via_stores.flush();
+			}
 
-				self.prev_nkro_report = report;
+			match mode {
+				RebootMode::Firmware => crate::setup::reset(),
+				RebootMode::Bootloader => crate::setup::reset_to_bootloader(),
+			}
+		}
+	}
 
-				#[cfg(feature = "defmt")]
-				report::log_nkro_report(report);
+	/// Shows the lock state reported by the host on the LED.
+	#[cfg(has_led)]
+	pub fn update_indicators(&mut self) {
+		let is_on = lock::LockState::current().is_on(codegen::LED_INDICATOR);
+
+		// VIA can turn the LED off.
+		#[cfg(feature = "via")]
+		let is_on = is_on && via::is_led_enabled();
+
+		crate::setup::set_led(&mut self.led, is_on);
+	}
+
+	/// Sends the queued reports over USB, for as long as the endpoint accepts them.
+	pub fn send_reports(&mut self) {
+		// The response to a vendor command goes before anything else.
+		#[cfg(feature = "silverplate")]
+		{
+			// SAFETY: `with_hid_class` runs the closure inside a critical section.
+			let result = with_hid_class(|hid_class| unsafe { silverplate::send_pending_response(hid_class) });
+
+			if result.is_err() {
+				return;
+			}
+		}
+
+		#[cfg(feature = "silverplate")]
+		if self.report_format_changed {
+			let response = silverplate::report_format_response(self.reporter.format());
+
+			match with_hid_class(|hid_class| push_input(hid_class, &response)) {
+				Err(UsbError::WouldBlock) => return,
+				_ => self.report_format_changed = false,
 			}
-		} else {
-			// SAFETY: The active keymap was initialized before this call.
-			let report = unsafe { report::construct_6kro_report(pressed_keys) };
-
-			if report != self.prev_6kro_report {
-				cortex_m::interrupt::free(|_| {
-					let hid_class = {
-						let ptr = &raw const HID_CLASS;
-
-						// SAFETY: This is safe because:
-						//
-						// * The content was fully initialized when this struct was created.
-						// * We access this inside the critical section which prevents two mutable references
-						// to the value from being created.
-						unsafe { (*ptr).assume_init_ref() }
-					};
-
-					_ = hid_class.push_raw_input(report.as_ref());
-				});
-
-				self.prev_6kro_report = report;
-
-				#[cfg(feature = "defmt")]
-				report::log_6kro_report(report);
+		}
+
+		while let Some(report) = self.reporter.next_report() {
+			match with_hid_class(|hid_class| push_input(hid_class, report.as_bytes())) {
+				// The previous report wasn't read yet, try again later.
+				Err(UsbError::WouldBlock) => return,
+				Ok(_) => {
+					#[cfg(feature = "defmt")]
+					report.log();
+				}
+				// A report that can't be sent is dropped instead of blocking the queue.
+				Err(_) => {}
 			}
+
+			self.reporter.report_sent();
 		}
 	}
 }
 
+/// Runs the closure with the HID class of the keyboard.
+/// A critical section is used to ensure safe, exclusive access to global mutable state.
+fn with_hid_class<R>(f: impl FnOnce(&mut HIDClass<'static, UsbBus>) -> R) -> R {
+	cortex_m::interrupt::free(|_| {
+		let hid_class = {
+			let ptr = &raw mut HID_CLASS;
+
+			// SAFETY: This is safe because:
+			//
+			// * The content was fully initialized when the keyboard instance was created, which is
+			// needed to call any of its methods.
+			// * We access this inside the critical section which prevents two mutable references
+			// to the value from being created.
+			unsafe { (*ptr).assume_init_mut() }
+		};
+
+		f(hid_class)
+	})
+}
+
+/// Pushes an input report, whatever protocol the host selected.
+///
+/// `usbd-hid` only accepts input reports while the boot protocol is selected on an interface with
+/// the boot subclass, so the boot protocol is selected just for the push.
+fn push_input(hid_class: &mut HIDClass<UsbBus>, data: &[u8]) -> Result<usize, UsbError> {
+	if let Ok(HidProtocolMode::Report) = hid_class.get_protocol_mode() {
+		_ = hid_class.set_protocol_mode(HidProtocolMode::Boot, ProtocolModeConfig::DefaultBehavior);
+
+		let result = hid_class.push_raw_input(data);
+
+		_ = hid_class.set_protocol_mode(HidProtocolMode::Report, ProtocolModeConfig::DefaultBehavior);
+
+		result
+	} else {
+		hid_class.push_raw_input(data)
+	}
+}
+
+/// Selects the report protocol again, as the host expects after a bus reset.
+pub fn reset_protocol(keyboard_hid: &mut HIDClass<UsbBus>) {
+	_ = keyboard_hid.set_protocol_mode(HidProtocolMode::Report, ProtocolModeConfig::DefaultBehavior);
+}
+
 /// Returns a mutable reference to the HID class instance for the keyboard.
 ///
 /// # Safety
@@ -182,6 +425,15 @@ pub fn process_incoming_report(keyboard_hid: &mut HIDClass<UsbBus>) {
 		return;
 	};
 
+	// In boot protocol the report is only the LED byte, without a report ID.
+	if let Ok(HidProtocolMode::Boot) = keyboard_hid.get_protocol_mode() {
+		if rep_size == 1 {
+			process_led_report(buf[0]);
+		}
+
+		return;
+	}
+
 	cfg_select! {
 		feature = "silverplate" => {
 			if rep_size < 2 {
@@ -195,9 +447,7 @@ pub fn process_incoming_report(keyboard_hid: &mut HIDClass<UsbBus>) {
 					process_led_report(buf[1]);
 				}
 				silverplate::VEND_REP_ID_OUT => {
-					let vendor_byte = buf[1];
-
-					silverplate::process_vendor_report(keyboard_hid, vendor_byte);
+					silverplate::process_vendor_report(keyboard_hid, &buf[1..rep_size]);
 				}
 				_ => {}
 			}
@@ -214,61 +464,27 @@ pub fn process_incoming_report(keyboard_hid: &mut HIDClass<UsbBus>) {
 }
 
 fn process_led_report(led_byte: u8) {
-	let mut left_ctrl = false;
-	let mut left_shift = false;
-	let mut left_alt = false;
-	let mut left_meta = false;
-	let mut right_ctrl = false;
-	let mut right_shift = false;
-	let mut right_alt = false;
-	let mut right_meta = false;
-
-	let is_left_ctrl = (led_byte & KM_LCTRL.get()) != 0;
-	let is_left_alt = (led_byte & KM_LALT.get()) != 0;
-	let is_left_shift = (led_byte & KM_LSHIFT.get()) != 0;
-	let is_left_meta = (led_byte & KM_LMETA.get()) != 0;
-	let is_right_ctrl = (led_byte & KM_RCTRL.get()) != 0;
-	let is_right_shift = (led_byte & KM_RSHIFT.get()) != 0;
-	let is_right_alt = (led_byte & KM_RALT.get()) != 0;
-	let is_right_meta = (led_byte & KM_RMETA.get()) != 0;
+	let lock_state = lock::LockState::from_led_report(led_byte);
+
+	#[cfg_attr(not(feature = "defmt"), allow(unused_variables))]
+	let previous = lock_state.store();
 
 	#[cfg(feature = "defmt")]
 	{
-		if is_left_ctrl == left_ctrl {
-			defmt::info!("Received left CTRL LED report!");
-		}
-		if is_left_shift == left_shift {
-			defmt::info!("Received left SHIFT LED report!");
-		}
-		if is_left_alt == left_alt {
-			defmt::info!("Received left ALT LED report!");
-		}
-		if is_left_meta == left_meta {
-			defmt::info!("Received left META LED report!");
-		}
-		if is_right_ctrl == right_ctrl {
-			defmt::info!("Received right CTRL LED report!");
-		}
-		if is_right_shift == right_shift {
-			defmt::info!("Received right SHIFT LED report!");
-		}
-		if is_right_alt == right_alt {
-			defmt::info!("Received right ALT LED report!");
-		}
-		if is_right_meta == right_meta {
-			defmt::info!("Received right META LED report!");
+		const LOCKS: [(LockIndicator, &str); 5] = [
+			(LockIndicator::NumLock, "Num Lock"),
+			(LockIndicator::CapsLock, "Caps Lock"),
+			(LockIndicator::ScrollLock, "Scroll Lock"),
+			(LockIndicator::Compose, "Compose"),
+			(LockIndicator::Kana, "Kana"),
+		];
+
+		for (indicator, name) in LOCKS {
+			let is_on = lock_state.is_on(indicator);
+
+			if is_on != previous.is_on(indicator) {
+				defmt::info!("{} turned {}", name, if is_on { "on" } else { "off" });
+			}
 		}
 	}
-
-	#[allow(unused_assignments)]
-	{
-		left_ctrl = is_left_ctrl;
-		left_shift = is_left_shift;
-		left_alt = is_left_alt;
-		left_meta = is_left_meta;
-		right_ctrl = is_right_ctrl;
-		right_shift = is_right_shift;
-		right_alt = is_right_alt;
-		right_meta = is_right_meta;
-	}
 }
@@ -0,0 +1,64 @@
+use qubit_config::keyboard::{BootmagicAction, KeyPosition};
+
+use super::key_bit;
+use crate::codegen::{self, KeyboardMatrix};
+use crate::storage;
+
+const _: () = {
+	let bootmagic = codegen::BOOTMAGIC;
+
+	assert!(
+		codegen::LAYER0
+			.packed_index(bootmagic.clear.row, bootmagic.clear.col)
+			.is_some(),
+		"The bootmagic keys must have a key in LAYER0."
+	);
+
+	if let Some(bootloader) = bootmagic.bootloader {
+		assert!(
+			codegen::LAYER0.packed_index(bootloader.row, bootloader.col).is_some(),
+			"The bootmagic keys must have a key in LAYER0."
+		);
+	}
+};
+
+/// Scans the matrix once and acts on the bootmagic keys held while the board was plugged in.
+///
+/// Holding the clear key erases the storage, so the keymaps and settings the firmware was built
+/// with are used again. Holding it together with the bootloader key restarts into the bootloader
+/// instead.
+///
+/// # Safety
+///
+/// This function must be called before anything is read from the storage, while nothing else is
+/// using it.
+pub unsafe fn check(matrix: &mut KeyboardMatrix) {
+	let pressed_keys = matrix.get_pressed_keys();
+
+	let is_held = |key: KeyPosition| {
+		codegen::LAYER0.packed_index(key.row, key.col).is_some_and(|index| {
+			let (word, mask) = key_bit(index);
+
+			pressed_keys[word] & mask != 0
+		})
+	};
+
+	match codegen::BOOTMAGIC.action(is_held) {
+		Some(BootmagicAction::Bootloader) => crate::setup::reset_to_bootloader(),
+		Some(BootmagicAction::ClearStorage) => {
+			// SAFETY: The caller guarantees nothing else is using the storage.
+			let Some(store) = (unsafe { storage::get_mut() }) else {
+				return;
+			};
+
+			#[cfg_attr(not(feature = "defmt"), allow(unused_variables))]
+			let result = store.clear();
+
+			#[cfg(feature = "defmt")]
+			if result.is_err() {
+				defmt::error!("The storage couldn't be cleared.");
+			}
+		}
+		None => {}
+	}
+}
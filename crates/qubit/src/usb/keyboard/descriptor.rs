@@ -1,5 +1,8 @@
 pub const KB_REP_ID_IN: u8 = 0x01;
 pub const KB_REP_ID_OUT: u8 = 0x02;
+pub const KB_NKRO_REP_ID_IN: u8 = 0x05;
+pub const CONSUMER_REP_ID_IN: u8 = 0x06;
+pub const SYSTEM_REP_ID_IN: u8 = 0x07;
 
 // https://usb.org/document-library/hid-usage-tables-16
 // https://learn.microsoft.com/en-us/windows-hardware/drivers/hid/hid-usages
@@ -15,8 +18,45 @@ const VENDOR_REPORT: &[u8] = cfg_select! {
 	_ => { &[] }
 };
 
+/// Covers the 12 bits of usage a Consumer action holds, so none of them are discarded by the host.
 #[rustfmt::skip]
-pub const DESCRIPTOR_6KRO: &[u8] = constcat::concat_slices!([u8]:
+const CONSUMER_REPORT: &[u8] = &[
+	0x05, 0x0C,                  // UsagePage(Consumer)
+	0x09, 0x01,                  // UsageId(Consumer Control)
+	0xA1, 0x01,                  // Collection(Application)
+	0x85, CONSUMER_REP_ID_IN,    // ReportId()
+	0x19, 0x00,                  // UsageMinimum(0x0000)
+	0x2A, 0xFF, 0x0F,            // UsageMaximum(0x0FFF)
+	0x15, 0x00,                  // LogicalMinimum(0)
+	0x26, 0xFF, 0x0F,            // LogicalMaximum(4095)
+	0x75, 0x10,                  // ReportSize(16)
+	0x95, 0x01,                  // ReportCount(1)
+	0x81, 0x00,                  // Input(Data, Array, Absolute)
+	0xC0,                        // EndCollection()
+];
+
+#[rustfmt::skip]
+const SYSTEM_REPORT: &[u8] = &[
+	0x05, 0x01,                  // UsagePage(Generic Desktop)
+	0x09, 0x80,                  // UsageId(System Control)
+	0xA1, 0x01,                  // Collection(Application)
+	0x85, SYSTEM_REP_ID_IN,      // ReportId()
+	0x19, 0x01,                  // UsageMinimum(0x01)
+	0x29, 0xB7,                  // UsageMaximum(0xB7)
+	0x15, 0x01,                  // LogicalMinimum(1)
+	0x26, 0xB7, 0x00,            // LogicalMaximum(183)
+	0x75, 0x08,                  // ReportSize(8)
+	0x95, 0x01,                  // ReportCount(1)
+	0x81, 0x00,                  // Input(Data, Array, Absolute)
+	0xC0,                        // EndCollection()
+];
+
+/// The keyboard exposes both the 6KRO and the NKRO report, so the firmware can switch between
+/// them at runtime. Only one of them is sent at a time.
+///
+/// The Consumer and System Control reports follow in their own collections.
+#[rustfmt::skip]
+pub const DESCRIPTOR: &[u8] = constcat::concat_slices!([u8]:
 	REPORT_HEADER,
 
 	// Basic keyboard report
@@ -61,18 +101,9 @@ pub const DESCRIPTOR_6KRO: &[u8] = constcat::concat_slices!([u8]:
 		0x91, 0x01,                  // Output(Constant)
 	],
 
-	VENDOR_REPORT,
-
-	&[0xC0] // EndCollection()
-);
-
-#[rustfmt::skip]
-pub const DESCRIPTOR_NKRO: &[u8] = constcat::concat_slices!([u8]:
-	REPORT_HEADER,
-
-	// Basic keyboard report
+	// NKRO keyboard report
 	&[
-		0x85, KB_REP_ID_IN,          // ReportId()
+		0x85, KB_NKRO_REP_ID_IN,     // ReportId()
 		// --- Modifier Keys (1 byte) ---
 		0x05, 0x07,                  // UsagePage(Keyboard/Keypad)
 		0x19, 0xE0,                  // UsageMinimum(Keyboard LeftControl)
@@ -91,24 +122,12 @@ pub const DESCRIPTOR_NKRO: &[u8] = constcat::concat_slices!([u8]:
 		0x75, 0x01,                  // ReportSize(1)
 		0x96, 0x00, 0x01,            // ReportCount(256)
 		0x81, 0x02,                  // Input(Data, Variable, Absolute)
-		// --- Output Report for LEDs ---
-		0x85, KB_REP_ID_OUT,         // ReportId(2)
-		// --- LEDs Byte (1 byte) ---
-		0x05, 0x08,                  // UsagePage(LEDs)
-		0x19, 0x01,                  // UsageMinimum(Num Lock)
-		0x29, 0x05,                  // UsageMaximum(Kana)
-		0x15, 0x00,                  // LogicalMinimum(0)
-		0x25, 0x01,                  // LogicalMaximum(1)
-		0x75, 0x01,                  // ReportSize(1)
-		0x95, 0x05,                  // ReportCount(5)
-		0x91, 0x02,                  // Output(Data, Variable, Absolute)
-		// --- Remaining bits for padding ---
-		0x75, 0x03,                  // ReportSize(3)
-		0x95, 0x01,                  // ReportCount(1)
-		0x91, 0x01,                  // Output(Constant)
 	],
 
 	VENDOR_REPORT,
 
-	&[0xC0] // EndCollection()
+	&[0xC0], // EndCollection()
+
+	CONSUMER_REPORT,
+	SYSTEM_REPORT,
 );
@@ -1,38 +1,104 @@
 use core::mem::MaybeUninit;
 
-use qubit_config::keyboard::Keymaps;
+use qubit_config::keyboard::{Action, Keymaps};
+use qubit_config::section::KeyboardSection;
+#[cfg(any(feature = "silverplate", feature = "via"))]
+use qubit_config::storage::Store;
 
-use super::{CONFIG, PACKED_SIZE};
+use super::layers::LayerState;
+use super::{KEYBOARD_SECTION, PACKED_SIZE};
+#[cfg(any(feature = "silverplate", feature = "via"))]
+use crate::storage::McuFlash;
+use crate::{codegen, storage};
 
+/// The keymaps the keyboard uses.
+///
+/// With the `silverplate` or `via` feature, they are replaced from the USB interrupt when the host
+/// changes them, so they are only read inside critical sections.
 static mut ACTIVE_KEYMAPS: MaybeUninit<Keymaps<PACKED_SIZE>> = MaybeUninit::uninit();
 
-/// Get the keymap from the storage.
+/// The keymaps changed by silverplate, which only replace the active ones once they are committed.
 ///
-/// This does nothing for now and it's just a reminder to implement EEPROM support.
+/// They are only accessed from the USB interrupt, other than in critical sections of the main loop
+/// to be committed.
+#[cfg(feature = "silverplate")]
+static mut PENDING_KEYMAPS: MaybeUninit<Keymaps<PACKED_SIZE>> = MaybeUninit::uninit();
+
+const LAYOUT_LEN: usize = codegen::LAYER0.layout_len();
+
+/// Where the keys of the packed keymaps are, stored in front of the keymaps set by the user.
+const LAYOUT: [u8; LAYOUT_LEN] = {
+	let mut layout = [0; LAYOUT_LEN];
+	codegen::LAYER0.write_layout(&mut layout);
+
+	layout
+};
+
+/// The size of the keymaps set by the user in the storage, together with their layout.
+const STORED_LEN: usize = LAYOUT_LEN + Keymaps::<PACKED_SIZE>::BYTES_LEN;
+
+/// Reads the keymaps set by the user from the storage.
 ///
-/// TODO: Keymaps should be stored in an EEPROM or something similar.
+/// Keymaps stored for a different layout are ignored, so the default ones are used instead. Two
+/// layouts with the same number of keys in different positions are told apart too.
 fn fetch_stored_keymap() -> Option<Keymaps<PACKED_SIZE>> {
-	// Here I need to read from the EEPROM chip.
-	let stored_keymap: Option<Keymaps<PACKED_SIZE>> = None;
+	// SAFETY: The storage is initialized before the keyboard and only used during initialization.
+	let store = unsafe { storage::get_mut() }?;
 
-	stored_keymap
+	let mut bytes = [0; STORED_LEN];
+	let len = store.read(storage::KEYMAPS_KEY, &mut bytes).ok()??;
+
+	let (layout, keymaps) = bytes[..len].split_at_checked(LAYOUT_LEN)?;
+
+	if layout != LAYOUT {
+		return None;
+	}
+
+	Keymaps::from_bytes(keymaps)
+}
+
+/// The keymaps the firmware was built with.
+const BUILT_IN_KEYMAPS: Keymaps<PACKED_SIZE> = Keymaps {
+	keymap_0: codegen::LAYER0.get_packed(),
+	keymap_1: codegen::LAYER1.get_packed(),
+	keymap_2: codegen::LAYER2.get_packed(),
+	keymap_3: codegen::LAYER3.get_packed(),
+	keymap_4: codegen::LAYER4.get_packed(),
+};
+
+/// The keymaps of the `.keyboard` section.
+///
+/// The section can be patched in the firmware image after the build, so it's read back instead of
+/// using the built-in keymaps. Those are only used if the patched section is corrupted or has a
+/// different layout.
+fn default_keymaps() -> Keymaps<PACKED_SIZE> {
+	// SAFETY: The pointer was obtained from a static value that is never written to. The read is
+	// volatile so that it isn't replaced with the bytes the section was built with.
+	let bytes = unsafe { core::ptr::read_volatile(&raw const KEYBOARD_SECTION) };
+
+	KeyboardSection::decode(&bytes)
+		.ok()
+		.filter(|section| section.has_layout_of(&codegen::LAYER0))
+		.and_then(|section| Keymaps::from_bytes(section.keymap_bytes()))
+		.unwrap_or(BUILT_IN_KEYMAPS)
 }
 
 /// # Safety
 ///
 /// This function must be called **only once** for the lifetime of the program.
 pub unsafe fn init_active_keymaps() {
-	let keymap = if let Some(keymap) = fetch_stored_keymap() {
-		keymap
-	} else {
-		Keymaps {
-			keymap_0: CONFIG.keymaps.keymap_0,
-			keymap_1: CONFIG.keymaps.keymap_1,
-			keymap_2: CONFIG.keymaps.keymap_2,
-			keymap_3: CONFIG.keymaps.keymap_3,
-			keymap_4: CONFIG.keymaps.keymap_4,
+	let keymap = fetch_stored_keymap().unwrap_or_else(default_keymaps);
+
+	#[cfg(feature = "silverplate")]
+	{
+		let ptr = &raw mut PENDING_KEYMAPS;
+
+		// SAFETY: `ptr` was obtained from a static value and so is guaranteed to be non-null and
+		// properly aligned. This sets the value of the MaybeUninit.
+		unsafe {
+			(*ptr).write(keymap.clone());
 		}
-	};
+	}
 
 	let ptr = &raw mut ACTIVE_KEYMAPS;
 
@@ -43,16 +109,209 @@ pub unsafe fn init_active_keymaps() {
 	}
 }
 
+/// Returns the keymaps currently used by the keyboard.
+///
 /// # Safety
 ///
-/// Calling this function before initializing the active keymap is **undefined behavior**.
-pub const unsafe fn get_keymap_keycode(index: usize) -> u8 {
-	let active_keymap = {
+/// Calling this function before initializing the active keymap is **undefined behavior**. The
+/// keymaps are replaced from the USB interrupt, so the reference can't be kept outside of it.
+#[cfg(any(feature = "silverplate", feature = "via"))]
+pub unsafe fn active_keymaps<'a>() -> &'a Keymaps<PACKED_SIZE> {
+	let ptr = &raw const ACTIVE_KEYMAPS;
+
+	// SAFETY: The caller gurantees the keymap was initialized.
+	unsafe { (*ptr).assume_init_ref() }
+}
+
+/// Returns the keymaps changed by the host, which aren't used until they are committed.
+///
+/// # Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+///
+/// * The keymaps must have been initialized before this function.
+/// * No other reference to the pending keymaps exists, which holds when only the USB interrupt
+///   calls this, or a critical section that doesn't keep the reference.
+#[cfg(feature = "silverplate")]
+pub unsafe fn pending_keymaps<'a>() -> &'a mut Keymaps<PACKED_SIZE> {
+	let ptr = &raw mut PENDING_KEYMAPS;
+
+	// SAFETY: The caller guarantees the keymaps were initialized and no other reference exists.
+	unsafe { (*ptr).assume_init_mut() }
+}
+
+/// The keymaps couldn't be stored.
+#[cfg(any(feature = "silverplate", feature = "via"))]
+#[derive(Debug, Clone, Copy)]
+pub enum CommitError {
+	/// The storage couldn't be mounted at boot.
+	NoStorage,
+	/// The storage failed to write the keymaps.
+	Storage,
+}
+
+/// Writes the keymaps to the storage, after the layout they were packed with.
+#[cfg(any(feature = "silverplate", feature = "via"))]
+fn write_keymaps(store: Option<&mut Store<McuFlash>>, keymaps: &Keymaps<PACKED_SIZE>) -> Result<(), CommitError> {
+	let store = store.ok_or(CommitError::NoStorage)?;
+
+	let mut bytes = [0; STORED_LEN];
+	let (layout, keymap_bytes) = bytes.split_at_mut(LAYOUT_LEN);
+
+	layout.copy_from_slice(&LAYOUT);
+	keymaps.write_bytes(keymap_bytes);
+
+	store
+		.write(storage::KEYMAPS_KEY, &bytes)
+		.map_err(|_| CommitError::Storage)
+}
+
+/// Persists the pending keymaps and makes them the active ones.
+///
+/// Writing to the flash takes too long for the USB interrupt, so this runs from the main loop. The
+/// pending keymaps are copied first, so the host can't change them halfway. They are only used
+/// once they are in the storage, and they are replaced in a critical section, so a key is never
+/// looked up in a half-written keymap.
+///
+/// # Safety
+///
+/// This function must only be called from the main loop, after the keymaps were initialized.
+#[cfg(feature = "silverplate")]
+pub unsafe fn commit_keymaps() -> Result<(), CommitError> {
+	// SAFETY: The keymaps were initialized and the critical section keeps the USB interrupt from
+	// changing them while they are copied.
+	let keymaps = cortex_m::interrupt::free(|_| unsafe { pending_keymaps() }.clone());
+
+	// SAFETY: The caller guarantees this runs from the main loop.
+	unsafe { storage::with_store(|store| write_keymaps(store, &keymaps)) }?;
+
+	// VIA can change keys while the keymaps are written, and it changes the pending keymaps too,
+	// so they are used instead of the copy.
+	cortex_m::interrupt::free(|_| {
+		// SAFETY: The keymaps were initialized and the critical section keeps the USB interrupt from
+		// accessing them meanwhile.
+		activate(unsafe { pending_keymaps() });
+	});
+
+	Ok(())
+}
+
+/// Makes the keymaps the active ones, without storing them.
+#[cfg(any(feature = "silverplate", feature = "via"))]
+fn activate(keymaps: &Keymaps<PACKED_SIZE>) {
+	cortex_m::interrupt::free(|_| {
 		let ptr = &raw mut ACTIVE_KEYMAPS;
 
-		// SAFETY: The caller gurantees the keymap was initialized.
-		unsafe { (*ptr).assume_init_ref() }
-	};
+		// SAFETY: The keymaps were initialized and the critical section keeps the main loop from
+		// reading them while they are replaced.
+		unsafe {
+			(*ptr).assume_init_mut().clone_from(keymaps);
+		}
+	});
+}
+
+/// Changes the action of a key in the active keymaps, which are the ones VIA works on.
+///
+/// With silverplate, the pending keymaps get the same change so a commit doesn't undo it, while
+/// their own changes stay pending until they are committed or reverted.
+///
+/// # Safety
+///
+/// The keymaps must have been initialized, and this must only be called from the USB interrupt.
+#[cfg(feature = "via")]
+pub unsafe fn set_active_action(layer: usize, index: usize, action: Action) {
+	let ptr = &raw mut ACTIVE_KEYMAPS;
+
+	// SAFETY: The keymaps were initialized. The main loop only reads them inside critical sections,
+	// which the USB interrupt can't run in the middle of.
+	unsafe {
+		(*ptr).assume_init_mut().layer_mut(layer)[index] = action;
+	}
+
+	// SAFETY: The keymaps were initialized and this runs in the USB interrupt.
+	#[cfg(feature = "silverplate")]
+	unsafe {
+		pending_keymaps().layer_mut(layer)[index] = action;
+	}
+}
+
+/// Persists the active keymaps, after VIA changed them.
+///
+/// Like a commit, this runs from the main loop. The keymaps are copied first, so VIA can keep
+/// changing them while they are written.
+///
+/// # Safety
+///
+/// This function must only be called from the main loop, after the keymaps were initialized.
+#[cfg(feature = "via")]
+pub unsafe fn store_active_keymaps() -> Result<(), CommitError> {
+	// SAFETY: The keymaps were initialized and the critical section keeps the USB interrupt from
+	// changing them while they are copied.
+	let keymaps = cortex_m::interrupt::free(|_| unsafe { active_keymaps() }.clone());
+
+	// SAFETY: The caller guarantees this runs from the main loop.
+	unsafe { storage::with_store(|store| write_keymaps(store, &keymaps)) }
+}
+
+/// Goes back to the keymaps the firmware was built with. The ones set by the user are removed from
+/// the storage separately, from the main loop.
+///
+/// The changes silverplate didn't commit yet are dropped too.
+///
+/// # Safety
+///
+/// The keymaps must have been initialized, and this must only be called from the USB interrupt.
+#[cfg(feature = "via")]
+pub unsafe fn reset_keymaps() {
+	let keymaps = default_keymaps();
+
+	// SAFETY: The keymaps were initialized and this runs in the USB interrupt.
+	#[cfg(feature = "silverplate")]
+	unsafe {
+		pending_keymaps().clone_from(&keymaps);
+	}
+
+	activate(&keymaps);
+}
+
+/// Drops the changes of the host, going back to the active keymaps.
+///
+/// # Safety
+///
+/// The same conditions as [`pending_keymaps`] apply.
+#[cfg(feature = "silverplate")]
+pub unsafe fn revert_keymaps() {
+	// SAFETY: The caller upholds the conditions.
+	let pending = unsafe { pending_keymaps() };
+
+	// SAFETY: The keymaps were initialized. The main loop only touches them for a commit request
+	// the USB interrupt left in the silverplate module: it copies the pending keymaps in a critical
+	// section, writes the copy while the storage is held by it, then replaces the active keymaps
+	// in another critical section. This runs in the USB interrupt, so it never sees a half-replaced
+	// copy, and VIA only changes them from the USB interrupt too.
+	pending.clone_from(unsafe { active_keymaps() });
+}
+
+/// Looks up the action for a key, starting from the highest active layer and falling through
+/// transparent keys until one is found.
+///
+/// # Safety
+///
+/// Calling this function before initializing the active keymap is **undefined behavior**.
+pub unsafe fn get_keymap_action(layers: LayerState, index: usize) -> Action {
+	// The host can replace the keymaps from the USB interrupt.
+	cortex_m::interrupt::free(|_| {
+		let active_keymap = {
+			let ptr = &raw const ACTIVE_KEYMAPS;
+
+			// SAFETY: The caller gurantees the keymap was initialized.
+			unsafe { (*ptr).assume_init_ref() }
+		};
 
-	active_keymap.keymap_0[index]
+		layers
+			.iter_active()
+			.map(|layer| active_keymap.layer(layer as usize)[index])
+			.find(|action| !action.is_transparent())
+			.unwrap_or(Action::NO)
+	})
 }
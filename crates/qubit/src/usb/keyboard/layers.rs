@@ -0,0 +1,124 @@
+#[cfg(feature = "silverplate")]
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use qubit_config::keyboard::layer::LAYER_COUNT;
+
+/// The layer state of the keys, published for the vendor commands.
+#[cfg(feature = "silverplate")]
+static CURRENT: AtomicU16 = AtomicU16::new(0);
+
+/// A layer state set by the host, waiting to replace the one of the keys.
+#[cfg(feature = "silverplate")]
+static REQUESTED: AtomicU16 = AtomicU16::new(NO_REQUEST);
+
+#[cfg(feature = "silverplate")]
+const NO_REQUEST: u16 = u16::MAX;
+
+/// The layer stack of the keyboard.
+///
+/// Every layer is represented by a bit. The default layer is always active and is the last
+/// one checked when looking up a key.
+#[derive(Debug, Clone, Copy)]
+pub struct LayerState {
+	active: u8,
+	default: u8,
+}
+
+impl LayerState {
+	#[must_use]
+	pub const fn new() -> Self {
+		Self { active: 0, default: 0 }
+	}
+
+	const fn mask(layer: u8) -> u8 {
+		if (layer as usize) < LAYER_COUNT { 1 << layer } else { 0 }
+	}
+
+	/// Checks if the layer is part of the stack.
+	#[must_use]
+	pub const fn is_active(self, layer: u8) -> bool {
+		layer == self.default || (self.active & Self::mask(layer)) != 0
+	}
+
+	/// Returns the active layers, from the highest to the lowest one.
+	pub fn iter_active(self) -> impl Iterator<Item = u8> {
+		#[allow(clippy::cast_possible_truncation, reason = "LAYER_COUNT fits inside a u8.")]
+		(0..LAYER_COUNT as u8).rev().filter(move |&layer| self.is_active(layer))
+	}
+
+	/// Adds the layer to the stack.
+	pub const fn activate(&mut self, layer: u8) {
+		self.active |= Self::mask(layer);
+	}
+
+	/// Removes the layer from the stack.
+	pub const fn deactivate(&mut self, layer: u8) {
+		self.active &= !Self::mask(layer);
+	}
+
+	/// Flips the state of the layer.
+	pub const fn toggle(&mut self, layer: u8) {
+		self.active ^= Self::mask(layer);
+	}
+
+	/// Leaves the layer as the only one active, on top of the default layer.
+	pub const fn switch_to(&mut self, layer: u8) {
+		self.active = Self::mask(layer);
+	}
+
+	/// Creates a layer state from the bitmap of the active layers and the default layer.
+	#[cfg(feature = "silverplate")]
+	#[must_use]
+	pub const fn from_parts(active: u8, default: u8) -> Self {
+		Self { active, default }
+	}
+
+	/// The bitmap of the active layers, without the default one, and the default layer.
+	#[cfg(feature = "silverplate")]
+	#[must_use]
+	pub const fn parts(self) -> (u8, u8) {
+		(self.active, self.default)
+	}
+
+	/// Makes the state visible to [`current`](Self::current).
+	#[cfg(feature = "silverplate")]
+	pub fn publish(self) {
+		CURRENT.store(u16::from_le_bytes([self.active, self.default]), Ordering::Relaxed);
+	}
+
+	/// The last published state.
+	#[cfg(feature = "silverplate")]
+	#[must_use]
+	pub fn current() -> Self {
+		let [active, default] = CURRENT.load(Ordering::Relaxed).to_le_bytes();
+
+		Self { active, default }
+	}
+
+	/// Asks for the state to replace the one of the keys.
+	#[cfg(feature = "silverplate")]
+	pub fn request(self) {
+		REQUESTED.store(u16::from_le_bytes([self.active, self.default]), Ordering::Relaxed);
+	}
+
+	/// Takes the state asked for with [`request`](Self::request), if there is one.
+	#[cfg(feature = "silverplate")]
+	#[must_use]
+	pub fn take_request() -> Option<Self> {
+		if REQUESTED.load(Ordering::Relaxed) == NO_REQUEST {
+			return None;
+		}
+
+		// The request is taken inside a critical section so a newer one isn't lost.
+		let bits = cortex_m::interrupt::free(|_| {
+			let bits = REQUESTED.load(Ordering::Relaxed);
+			REQUESTED.store(NO_REQUEST, Ordering::Relaxed);
+
+			bits
+		});
+
+		let [active, default] = bits.to_le_bytes();
+
+		Some(Self { active, default })
+	}
+}
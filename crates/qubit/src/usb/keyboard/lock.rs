@@ -0,0 +1,41 @@
+//! The lock state the host reports through the LED output report.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use qubit_config::keyboard::LockIndicator;
+
+/// The last LED output report received from the host.
+static LOCK_STATE: AtomicU8 = AtomicU8::new(0);
+
+/// The state of the Num, Caps and Scroll Lock, Compose and Kana on the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockState(u8);
+
+impl LockState {
+	/// Decodes the byte of a LED output report.
+	#[must_use]
+	pub const fn from_led_report(led_byte: u8) -> Self {
+		Self(led_byte & 0x1F)
+	}
+
+	#[must_use]
+	#[cfg_attr(not(has_led), allow(dead_code, reason = "Only the LED follows the lock state."))]
+	pub const fn is_on(self, indicator: LockIndicator) -> bool {
+		self.0 & indicator.mask() != 0
+	}
+
+	/// The lock state last reported by the host.
+	#[cfg_attr(not(has_led), allow(dead_code, reason = "Only the LED follows the lock state."))]
+	pub fn current() -> Self {
+		Self(LOCK_STATE.load(Ordering::Relaxed))
+	}
+
+	/// Stores the state reported by the host, returning the previous one.
+	pub fn store(self) -> Self {
+		let previous = Self(LOCK_STATE.load(Ordering::Relaxed));
+
+		LOCK_STATE.store(self.0, Ordering::Relaxed);
+
+		previous
+	}
+}
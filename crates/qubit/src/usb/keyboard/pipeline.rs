@@ -0,0 +1,220 @@
+//! The stages a key change goes through before it reaches the host.
+//!
+//! 1. Two consecutive scans are diffed into timestamped [`KeyEvent`]s.
+//! 2. The events wait in an [`EventQueue`] until they are processed.
+//! 3. Every event passes through the feature processors (tap-hold) and ends up as a
+//!    [`ResolvedEvent`] applied to the key state by the [`Reporter`].
+//! 4. The [`Reporter`] builds a report after every resolved event and queues it.
+//! 5. The queued reports are sent one by one, as fast as the host reads them.
+//!
+//! Since every event gets its own report, a press and release that happen between two reads of
+//! the host are both sent, in the order they happened.
+
+use heapless::Deque;
+use qubit_config::keyboard::event::{EventSink, KeyEvent, ReportQueue, ResolvedEvent};
+use qubit_config::keyboard::{Action, ActionKind, FirmwareAction};
+use qubit_config::silverplate::RebootMode;
+
+use super::report::{
+	self, ConsumerReport, Keyboard6kroReport, KeyboardBootReport, KeyboardNkroReport, ReportFormat, SystemReport,
+};
+use super::state::KeyState;
+
+/// The maximum number of key events waiting to be processed.
+const EVENT_QUEUE_LEN: usize = 32;
+/// The maximum number of reports waiting to be sent.
+const REPORT_QUEUE_LEN: usize = 16;
+
+pub type EventQueue = Deque<KeyEvent, EVENT_QUEUE_LEN>;
+
+/// A report queued to be sent to the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Report {
+	Boot(KeyboardBootReport),
+	SixKro(Keyboard6kroReport),
+	Nkro(KeyboardNkroReport),
+	Consumer(ConsumerReport),
+	System(SystemReport),
+}
+
+impl Report {
+	#[must_use]
+	pub fn as_bytes(&self) -> &[u8] {
+		match self {
+			Self::Boot(report) => report,
+			Self::SixKro(report) => report,
+			Self::Nkro(report) => report,
+			Self::Consumer(report) => report,
+			Self::System(report) => report,
+		}
+	}
+
+	#[cfg(feature = "defmt")]
+	pub fn log(self) {
+		match self {
+			Self::Boot(report) => report::log_boot_report(report),
+			Self::SixKro(report) => report::log_6kro_report(report),
+			Self::Nkro(report) => report::log_nkro_report(report),
+			Self::Consumer(report) => defmt::info!("Consumer report sent: {}", report),
+			Self::System(report) => defmt::info!("System report sent: {}", report),
+		}
+	}
+}
+
+/// Applies the resolved events to the key state and queues the reports they produce.
+#[derive(Debug)]
+pub struct Reporter {
+	state: KeyState,
+	format: ReportFormat,
+	/// The last keyboard report that was queued.
+	last_report: Report,
+	last_consumer_report: ConsumerReport,
+	last_system_report: SystemReport,
+	reports: ReportQueue<Report, REPORT_QUEUE_LEN>,
+}
+
+impl Reporter {
+	#[must_use]
+	pub fn new(format: ReportFormat) -> Self {
+		let state = KeyState::new();
+
+		Self {
+			last_report: Self::build_report(&state, format),
+			last_consumer_report: report::construct_consumer_report(state.active_actions()),
+			last_system_report: report::construct_system_report(state.active_actions()),
+			state,
+			format,
+			reports: ReportQueue::new(),
+		}
+	}
+
+	fn build_report(state: &KeyState, format: ReportFormat) -> Report {
+		match format {
+			ReportFormat::Boot => Report::Boot(report::construct_boot_report(state.active_actions())),
+			ReportFormat::SixKro => Report::SixKro(report::construct_6kro_report(state.active_actions())),
+			ReportFormat::Nkro => Report::Nkro(report::construct_nkro_report(state.active_actions())),
+		}
+	}
+
+	/// Queues the reports of the current state that changed since the last ones.
+	fn queue_report(&mut self) {
+		let report = Self::build_report(&self.state, self.format);
+
+		if report != self.last_report {
+			self.last_report = report;
+
+			self.reports.push(report);
+		}
+
+		// The host doesn't know about the other reports while in boot protocol.
+		if self.format != ReportFormat::Boot {
+			let consumer_report = report::construct_consumer_report(self.state.active_actions());
+			let system_report = report::construct_system_report(self.state.active_actions());
+
+			self.queue_media_reports(consumer_report, system_report);
+		}
+	}
+
+	fn queue_media_reports(&mut self, consumer_report: ConsumerReport, system_report: SystemReport) {
+		if consumer_report != self.last_consumer_report {
+			self.last_consumer_report = consumer_report;
+
+			self.reports.push(Report::Consumer(consumer_report));
+		}
+
+		if system_report != self.last_system_report {
+			self.last_system_report = system_report;
+
+			self.reports.push(Report::System(system_report));
+		}
+	}
+
+	/// Switches the format of the reports. Returns `true` if the format changed.
+	///
+	/// The keys are released in the old format before the current state is sent in the new one,
+	/// so the host doesn't keep any key stuck.
+	pub fn set_format(&mut self, format: ReportFormat) -> bool {
+		if format == self.format {
+			return false;
+		}
+
+		let released = KeyState::new();
+
+		let released_report = Self::build_report(&released, self.format);
+
+		if released_report != self.last_report {
+			self.reports.push(released_report);
+		}
+
+		if format == ReportFormat::Boot {
+			self.queue_media_reports(
+				report::construct_consumer_report(released.active_actions()),
+				report::construct_system_report(released.active_actions()),
+			);
+		}
+
+		self.format = format;
+
+		self.last_report = Self::build_report(&self.state, format);
+
+		self.reports.push(self.last_report);
+
+		if format != ReportFormat::Boot {
+			self.queue_report();
+		}
+
+		true
+	}
+
+	#[cfg(feature = "silverplate")]
+	#[must_use]
+	pub const fn format(&self) -> ReportFormat {
+		self.format
+	}
+
+	/// Switches to the layer state the host asked for, if there is one.
+	///
+	/// Keys that are already held keep the action they were pressed with.
+	#[cfg(feature = "silverplate")]
+	pub fn apply_layer_request(&mut self) {
+		self.state.apply_layer_request();
+	}
+
+	/// The next report waiting to be sent.
+	#[must_use]
+	pub fn next_report(&self) -> Option<&Report> {
+		self.reports.front()
+	}
+
+	/// Removes the next report from the queue, once it was sent.
+	pub fn report_sent(&mut self) {
+		self.reports.pop_front();
+	}
+}
+
+impl EventSink for Reporter {
+	fn action(&self, index: usize) -> Action {
+		self.state.action(index)
+	}
+
+	fn emit(&mut self, event: ResolvedEvent) {
+		match event {
+			ResolvedEvent::Press { index, action } => {
+				if let ActionKind::Firmware(firmware_action) = action.kind() {
+					match firmware_action {
+						FirmwareAction::NkroOn => super::set_nkro(true),
+						FirmwareAction::NkroOff => super::set_nkro(false),
+						FirmwareAction::NkroToggle => super::set_nkro(!super::is_nkro_enabled()),
+						FirmwareAction::Bootloader => super::request_reboot(RebootMode::Bootloader),
+						FirmwareAction::Reboot => super::request_reboot(RebootMode::Firmware),
+					}
+				}
+
+				self.state.press(index, action);
+			}
+			ResolvedEvent::Release { index } => self.state.release(index),
+		}
+
+		self.queue_report();
+	}
+}
@@ -1,15 +1,74 @@
 use core::num::NonZeroU8;
 
-use qubit_config::keyboard::keycodes::{KC_A, KC_LEFTCTRL, KC_RIGHTMETA, RESERVED};
-
-use super::PRESSED_KEYS_BITMAPS_LEN;
-use super::descriptor::KB_REP_ID_IN;
-use super::keymaps::get_keymap_keycode;
-
+use qubit_config::keyboard::keycodes::{
+	KC_A, KC_LEFTCTRL, KC_M_BACK, KC_M_CALC, KC_M_COFFEE, KC_M_EDIT, KC_M_EJECTCD, KC_M_FIND, KC_M_FORWARD, KC_M_MUTE,
+	KC_M_NEXTSONG, KC_M_PLAYPAUSE, KC_M_PREVIOUSSONG, KC_M_REFRESH, KC_M_SCROLLDOWN, KC_M_SCROLLUP, KC_M_SLEEP,
+	KC_M_STOP, KC_M_STOPCD, KC_M_VOLUMEDOWN, KC_M_VOLUMEUP, KC_M_WWW, KC_MUTE, KC_RIGHTMETA, KC_VOLUMEDOWN,
+	KC_VOLUMEUP, RESERVED,
+};
+use qubit_config::keyboard::{Action, ActionKind};
+
+use super::descriptor::{CONSUMER_REP_ID_IN, KB_NKRO_REP_ID_IN, KB_REP_ID_IN, SYSTEM_REP_ID_IN};
+
+// modifier + reserved + 6 keys
+pub type KeyboardBootReport = [u8; 8];
 // id + modifier + reserved + 6 keys
 pub type Keyboard6kroReport = [u8; 9];
 // id + modifier + 32 bytes bitmap
 pub type KeyboardNkroReport = [u8; 34];
+// id + 16 bit usage
+pub type ConsumerReport = [u8; 3];
+// id + usage
+pub type SystemReport = [u8; 2];
+
+/// The format of the keyboard reports sent to the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+	/// The boot protocol report, without a report ID.
+	Boot,
+	SixKro,
+	Nkro,
+}
+
+/// A usage sent outside of the keyboard report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MediaUsage {
+	/// A Consumer page usage.
+	Consumer(u16),
+	/// A System Control usage from the Generic Desktop page.
+	System(u8),
+}
+
+/// Maps the keycodes that hosts only understand as Consumer or System Control usages.
+///
+/// The keyboard page volume keys are mapped as well, since Windows and macOS ignore them.
+fn media_usage(key_code: NonZeroU8) -> Option<MediaUsage> {
+	let usage = match key_code {
+		KC_MUTE | KC_M_MUTE => MediaUsage::Consumer(0x00E2),
+		KC_VOLUMEUP | KC_M_VOLUMEUP => MediaUsage::Consumer(0x00E9),
+		KC_VOLUMEDOWN | KC_M_VOLUMEDOWN => MediaUsage::Consumer(0x00EA),
+		KC_M_PLAYPAUSE => MediaUsage::Consumer(0x00CD),
+		KC_M_STOPCD => MediaUsage::Consumer(0x00B7),
+		KC_M_PREVIOUSSONG => MediaUsage::Consumer(0x00B6),
+		KC_M_NEXTSONG => MediaUsage::Consumer(0x00B5),
+		KC_M_EJECTCD => MediaUsage::Consumer(0x00B8),
+		KC_M_WWW => MediaUsage::Consumer(0x0196),
+		KC_M_BACK => MediaUsage::Consumer(0x0224),
+		KC_M_FORWARD => MediaUsage::Consumer(0x0225),
+		KC_M_STOP => MediaUsage::Consumer(0x0226),
+		KC_M_FIND => MediaUsage::Consumer(0x0221),
+		KC_M_SCROLLUP => MediaUsage::Consumer(0x0233),
+		KC_M_SCROLLDOWN => MediaUsage::Consumer(0x0234),
+		KC_M_EDIT => MediaUsage::Consumer(0x0185),
+		KC_M_COFFEE => MediaUsage::Consumer(0x019E),
+		KC_M_REFRESH => MediaUsage::Consumer(0x0227),
+		KC_M_CALC => MediaUsage::Consumer(0x0192),
+		KC_M_SLEEP => MediaUsage::System(0x82),
+		_ => return None,
+	};
+
+	Some(usage)
+}
 
 /// Checks the keycode is within the range of "normal" codes.
 fn is_normal_key(key_code: NonZeroU8) -> bool {
@@ -35,103 +94,146 @@ fn is_modifier_key(key_code: NonZeroU8) -> Option<NonZeroU8> {
 	}
 }
 
-/// # Safety
-///
-/// Calling this function before the active keymap was initiated is **undefined behavior**.
-pub unsafe fn construct_6kro_report(pressed_keys: [usize; PRESSED_KEYS_BITMAPS_LEN]) -> Keyboard6kroReport {
-	const USIZE_BITS: usize = usize::BITS as usize;
+/// Splits an action into the keycode and modifier mask it adds to a keyboard report.
+fn report_keys(action: Action) -> Option<(Option<NonZeroU8>, u8)> {
+	match action.kind() {
+		ActionKind::Key(keycode) if media_usage(keycode).is_some() => None,
+		ActionKind::Key(keycode) => Some((Some(keycode), 0)),
+		ActionKind::ModifiedKey { modifiers, keycode } => Some((Some(keycode), modifiers.hid_mask())),
+		ActionKind::Modifiers(modifiers) => Some((None, modifiers.hid_mask())),
+		_ => None,
+	}
+}
+
+/// Builds a 6KRO report from the actions of the keys that are held down.
+pub fn construct_6kro_report(actions: impl Iterator<Item = Action>) -> Keyboard6kroReport {
 	const REPORT_LEN: usize = core::mem::size_of::<Keyboard6kroReport>();
 
 	let mut report: Keyboard6kroReport = [KB_REP_ID_IN, 0, RESERVED, 0, 0, 0, 0, 0, 0];
 
 	let mut i = 3;
 
-	for (index, mut bitmap) in pressed_keys.into_iter().enumerate() {
-		let offset = index * USIZE_BITS;
-
-		while bitmap != 0 {
-			let pressed_bit = bitmap.trailing_zeros() as usize;
-
-			let flat_index = offset + pressed_bit;
-
-			// SAFETY: The caller gurantees the keymap was initiated.
-			let code = unsafe { get_keymap_keycode(flat_index) };
+	for (code, modifiers) in actions.filter_map(report_keys) {
+		report[1] |= modifiers;
 
-			if let Some(code) = NonZeroU8::new(code) {
-				if i < REPORT_LEN && is_normal_key(code) {
-					report[i] = code.get();
+		let Some(code) = code else {
+			continue;
+		};
 
-					i += 1;
-				} else if let Some(mod_code) = is_modifier_key(code) {
-					report[1] |= mod_code.get();
-				}
-			}
+		if i < REPORT_LEN && is_normal_key(code) {
+			report[i] = code.get();
 
-			// Clear the bit
-			bitmap &= !(1 << pressed_bit);
+			i += 1;
+		} else if let Some(mod_code) = is_modifier_key(code) {
+			report[1] |= mod_code.get();
 		}
 	}
 
 	report
 }
 
-/// # Safety
+/// Builds a boot protocol report from the actions of the keys that are held down.
 ///
-/// Calling this function before the active keymap was initiated is **undefined behavior**.
-pub unsafe fn construct_nkro_report(pressed_keys: [usize; PRESSED_KEYS_BITMAPS_LEN]) -> KeyboardNkroReport {
-	const USIZE_BITS: usize = usize::BITS as usize;
+/// It has the same layout as the 6KRO report, without the report ID.
+pub fn construct_boot_report(actions: impl Iterator<Item = Action>) -> KeyboardBootReport {
+	let report = construct_6kro_report(actions);
+
+	let mut boot_report: KeyboardBootReport = [0; 8];
+
+	boot_report.copy_from_slice(&report[1..]);
+
+	boot_report
+}
+
+/// Builds a NKRO report from the actions of the keys that are held down.
+pub fn construct_nkro_report(actions: impl Iterator<Item = Action>) -> KeyboardNkroReport {
 	const NKRO_REP_LEN: usize = 34;
 
 	// [report_id, modifier, keys...]
 	let mut report = [0_u8; NKRO_REP_LEN];
 
-	report[0] = KB_REP_ID_IN;
-
-	for (index, mut bitmap) in pressed_keys.into_iter().enumerate() {
-		let offset = index * USIZE_BITS;
+	report[0] = KB_NKRO_REP_ID_IN;
 
-		while bitmap != 0 {
-			let pressed_bit = bitmap.trailing_zeros() as usize;
+	for (code, modifiers) in actions.filter_map(report_keys) {
+		report[1] |= modifiers;
 
-			let flat_index = offset + pressed_bit;
-
-			// SAFETY: The caller gurantees the keymap was initiated.
-			let code = unsafe { get_keymap_keycode(flat_index) };
+		let Some(code) = code else {
+			continue;
+		};
 
-			if let Some(code) = NonZeroU8::new(code) {
-				if is_normal_key(code) {
-					let key_code = code.get();
+		if is_normal_key(code) {
+			let key_code = code.get();
 
-					let byte_index = (key_code / 8) as usize + 2;
-					let bit_index = (key_code % 8) as usize;
+			let byte_index = (key_code / 8) as usize + 2;
+			let bit_index = (key_code % 8) as usize;
 
-					if byte_index < NKRO_REP_LEN {
-						report[byte_index] |= 1 << bit_index;
-					}
-				} else if let Some(mod_code) = is_modifier_key(code) {
-					report[1] |= mod_code.get();
-				}
+			if byte_index < NKRO_REP_LEN {
+				report[byte_index] |= 1 << bit_index;
 			}
-
-			// Clear the bit
-			bitmap &= !(1 << pressed_bit);
+		} else if let Some(mod_code) = is_modifier_key(code) {
+			report[1] |= mod_code.get();
 		}
 	}
 
 	report
 }
 
+/// Builds a Consumer report from the actions of the keys that are held down.
+///
+/// Only one usage fits in the report, the first key pressed wins.
+pub fn construct_consumer_report(mut actions: impl Iterator<Item = Action>) -> ConsumerReport {
+	let usage = actions
+		.find_map(|action| match action.kind() {
+			ActionKind::Consumer(usage) => Some(usage),
+			ActionKind::Key(keycode) => match media_usage(keycode) {
+				Some(MediaUsage::Consumer(usage)) => Some(usage),
+				_ => None,
+			},
+			_ => None,
+		})
+		.unwrap_or_default();
+
+	let [low, high] = usage.to_le_bytes();
+
+	[CONSUMER_REP_ID_IN, low, high]
+}
+
+/// Builds a System Control report from the actions of the keys that are held down.
+pub fn construct_system_report(mut actions: impl Iterator<Item = Action>) -> SystemReport {
+	let usage = actions
+		.find_map(|action| match action.kind() {
+			ActionKind::System(usage) => Some(usage),
+			ActionKind::Key(keycode) => match media_usage(keycode) {
+				Some(MediaUsage::System(usage)) => Some(usage),
+				_ => None,
+			},
+			_ => None,
+		})
+		.unwrap_or_default();
+
+	[SYSTEM_REP_ID_IN, usage]
+}
+
+#[cfg(feature = "defmt")]
+pub fn log_boot_report(report: KeyboardBootReport) {
+	log_key_array_report("Boot", &report);
+}
+
 #[cfg(feature = "defmt")]
 pub fn log_6kro_report(report: Keyboard6kroReport) {
+	// remove report id
+	log_key_array_report("6KRO", &report[1..]);
+}
+
+/// Logs a report made of the modifiers, the reserved byte and an array of keys.
+#[cfg(feature = "defmt")]
+fn log_key_array_report(name: &str, report: &[u8]) {
 	use core::fmt::Write;
 
 	let mut msg = heapless::String::<500>::new();
 
 	let mut keys = report.iter();
 
-	// remove report id
-	keys.next();
-
 	let modifier_msg = if keys.next().is_some_and(|&v| v == 0) {
 		"none"
 	} else {
@@ -155,7 +257,7 @@ pub fn log_6kro_report(report: Keyboard6kroReport) {
 		write!(pressed_keys, "0x{key:02}").unwrap();
 	}
 
-	writeln!(msg, "6KRO report sent:").ok();
+	writeln!(msg, "{name} report sent:").ok();
 	writeln!(msg, "Modifiers: {modifier_msg}").ok();
 	writeln!(msg, "Keys: [{pressed_keys}]").ok();
 	write!(msg, "---").ok();
@@ -1,123 +1,306 @@
-use core::mem::size_of_val;
+use core::sync::atomic::{AtomicU16, Ordering};
 
+use qubit_config::keyboard::Action;
+use qubit_config::silverplate::{
+	self, Capabilities, Command, Device, FirmwareInfo, Frame, LayerState, REPORT_LEN, RebootMode, Status,
+};
+use usb_device::UsbError;
 use usbd_hid::hid_class::HIDClass;
 
-use super::CONFIG;
-use crate::DEVICE_CONFIG;
+use super::keymaps::{self, CommitError};
+use super::layers;
+use super::report::ReportFormat;
 use crate::setup::UsbBus;
+use crate::{DEVICE_CONFIG, codegen, storage};
 
 const BUILD_DATE: u16 = qubit_macros::build_date_bitmap!();
 
-pub const VEND_REP_ID_OUT: u8 = 0x03;
-pub const VEND_REP_ID_IN: u8 = 0x04;
-
-// Vendor reports coming from the host
-const REQ_GET_FIRMWARE_INFO: u8 = 0x01;
+pub const VEND_REP_ID_OUT: u8 = silverplate::REPORT_ID_OUT;
+pub const VEND_REP_ID_IN: u8 = silverplate::REPORT_ID_IN;
 
 // Input reports must begin with the report ID, followed by the payload.
 // Although the descriptor defines the report size as the payload size, the actual data passed
 // to the push function must include the report ID, making the total size payload_size + 1.
 
-// The firmware report contains:
-// * the build date, packed into a bitmap
-// * the firmware version, packed into a bitmap
-const FW_REP_LEN: u8 = {
-	let size = size_of_val(&BUILD_DATE) + size_of_val(&DEVICE_CONFIG.version);
+/// The payload size of the vendor reports, filling a 64 byte packet together with the report ID.
+#[allow(clippy::cast_possible_truncation, reason = "A report is shorter than a packet.")]
+const VEND_REP_LEN: u8 = REPORT_LEN as u8;
 
-	assert!(size < 64);
+type Response = [u8; REPORT_LEN + 1];
 
-	#[allow(
-		clippy::cast_possible_truncation,
-		reason = "Clippy suggestion does not work in const contexts.
-		The assertion above also guarantees the value will not be truncated."
-	)]
-	{
-		size as u8
-	}
+/// The response the endpoint wasn't ready for, sent again by [`send_pending_response`].
+///
+/// It's only accessed from the USB interrupt or an interrupt-free context.
+static mut PENDING_RESPONSE: Option<Response> = None;
+
+/// The sequence number of the commit the host asked for, which the main loop does and answers.
+static COMMIT_REQUEST: AtomicU16 = AtomicU16::new(NO_COMMIT);
+
+const NO_COMMIT: u16 = u16::MAX;
+
+const ROWS: usize = codegen::LAYER0.0.len();
+const COLS: usize = codegen::LAYER0.0[0].len();
+
+const _: () = {
+	assert!(
+		ROWS <= u8::MAX as usize,
+		"Too many rows to be sent over the vendor reports."
+	);
+	assert!(
+		COLS <= u8::MAX as usize,
+		"Too many columns to be sent over the vendor reports."
+	);
+	assert!(
+		(ROWS * COLS).div_ceil(silverplate::ACTIONS_PER_CHUNK) <= u8::MAX as usize,
+		"The keymap is too big to be sent over the vendor reports."
+	);
 };
 
 #[rustfmt::skip]
 pub const REPORT: &[u8] = &[
-	0x85, VEND_REP_ID_OUT,     // ReportId()
-	0x06, 0x00, 0xFF,          // UsagePage(VendorDefined)
-	0x09, 0x01,                // UsageId(VendorDefined 1)
-	0x15, 0x00,                // LogicalMinimum(0)
-	0x26, 0x64, 0x00,          // LogicalMaximum(100)
-	0x75, 0x08,                // ReportSize(8)
-	0x95, 0x01,                // ReportCount(1)
-	0x91, 0x02,                // Output(Data, Variable, Absolute)
-
-	0x85, VEND_REP_ID_IN,      // ReportId()
-	0x06, 0x00, 0xFF,          // UsagePage(VendorDefined)
-	0x09, 0x02,                // UsageId(VendorDefined 2)
-	0x15, 0x00,                // LogicalMinimum(0)
-	0x26, 0x64, 0x00,          // LogicalMaximum(100)
-	0x75, 0x08,                // ReportSize(8)
-	0x95, FW_REP_LEN,          // ReportCount()
-	0x81, 0x00,                // Input(Data, Array, Absolute)
+	0x85, VEND_REP_ID_OUT,         // ReportId()
+	0x06, 0x00, 0xFF,              // UsagePage(VendorDefined)
+	0x09, 0x01,                    // UsageId(VendorDefined 1)
+	0x15, 0x00,                    // LogicalMinimum(0)
+	0x26, 0xFF, 0x00,              // LogicalMaximum(255)
+	0x75, 0x08,                    // ReportSize(8)
+	0x95, VEND_REP_LEN,            // ReportCount()
+	0x91, 0x02,                    // Output(Data, Variable, Absolute)
+
+	0x85, VEND_REP_ID_IN,          // ReportId()
+	0x06, 0x00, 0xFF,              // UsagePage(VendorDefined)
+	0x09, 0x02,                    // UsageId(VendorDefined 2)
+	0x15, 0x00,                    // LogicalMinimum(0)
+	0x26, 0xFF, 0x00,              // LogicalMaximum(255)
+	0x75, 0x08,                    // ReportSize(8)
+	0x95, VEND_REP_LEN,            // ReportCount()
+	0x81, 0x02,                    // Input(Data, Variable, Absolute)
 ];
 
-pub fn process_vendor_report(hid_class: &HIDClass<UsbBus>, req_byte: u8) {
-	// check the "command byte"
-	match req_byte {
-		REQ_GET_FIRMWARE_INFO => {
-			const BUILD_DATE_BYTES: [u8; 2] = BUILD_DATE.to_le_bytes();
-			const VERSION_BYTES: [u8; 4] = DEVICE_CONFIG.version.to_le_bytes();
-
-			const RESPONSE: [u8; (FW_REP_LEN + 1) as usize] = [
-				VEND_REP_ID_IN,
-				BUILD_DATE_BYTES[0],
-				BUILD_DATE_BYTES[1],
-				VERSION_BYTES[0],
-				VERSION_BYTES[1],
-				VERSION_BYTES[2],
-				VERSION_BYTES[3],
-			];
-
-			_ = hid_class.push_raw_input(&RESPONSE).is_ok();
+const fn protocol_format(format: ReportFormat) -> silverplate::ReportFormat {
+	match format {
+		ReportFormat::SixKro => silverplate::ReportFormat::SixKro,
+		ReportFormat::Nkro => silverplate::ReportFormat::Nkro,
+		ReportFormat::Boot => silverplate::ReportFormat::Boot,
+	}
+}
+
+fn response(frame: &[u8; REPORT_LEN]) -> Response {
+	let mut response = [0; REPORT_LEN + 1];
+
+	response[0] = VEND_REP_ID_IN;
+	response[1..].copy_from_slice(frame);
+
+	response
+}
+
+/// A notification sent every time the report format changes.
+pub fn report_format_response(format: ReportFormat) -> Response {
+	response(&silverplate::notification(
+		Command::GetReportFormat,
+		&[protocol_format(format) as u8],
+	))
+}
+
+/// The keyboard, as seen by the silverplate commands.
+///
+/// Keymaps are read from the active keymaps and changed in the pending ones.
+struct Keyboard<'a, 'b> {
+	hid_class: &'a HIDClass<'b, UsbBus>,
+	/// Whether the command was a commit, which is answered by the main loop.
+	commit_requested: bool,
+}
+
+impl Device for Keyboard<'_, '_> {
+	fn capabilities(&self) -> Capabilities {
+		let capabilities = Capabilities::KEYMAP_READ
+			.union(Capabilities::KEYMAP_WRITE)
+			.union(Capabilities::REPORT_FORMAT)
+			.union(Capabilities::LAYER_STATE)
+			.union(Capabilities::REBOOT)
+			.union(Capabilities::BOOTLOADER);
+
+		// SAFETY: The storage is initialized before the USB interrupt is enabled and only used from it.
+		if unsafe { storage::get_mut() }.is_some() {
+			capabilities.union(Capabilities::KEYMAP_PERSIST)
+		} else {
+			capabilities
+		}
+	}
+
+	fn firmware_info(&self) -> FirmwareInfo<'_> {
+		FirmwareInfo {
+			build_date: BUILD_DATE,
+			version: DEVICE_CONFIG.version,
+			name: DEVICE_CONFIG.name,
+			author: DEVICE_CONFIG.author,
+		}
+	}
+
+	fn matrix_size(&self) -> (usize, usize) {
+		(ROWS, COLS)
+	}
+
+	fn action(&self, layer: usize, row: usize, col: usize) -> Option<Action> {
+		let index = codegen::LAYER0.packed_index(row, col)?;
+
+		// SAFETY: The active keymaps are initialized together with the HID class.
+		Some(unsafe { keymaps::active_keymaps() }.layer(layer)[index])
+	}
+
+	fn set_action(&mut self, layer: usize, row: usize, col: usize, action: Action) -> bool {
+		let Some(index) = codegen::LAYER0.packed_index(row, col) else {
+			return false;
+		};
+
+		// SAFETY: The keymaps are initialized together with the HID class and the vendor reports are
+		// only processed in the USB interrupt.
+		unsafe { keymaps::pending_keymaps() }.layer_mut(layer)[index] = action;
+
+		true
+	}
+
+	fn commit(&mut self) -> Result<(), Status> {
+		self.commit_requested = true;
+
+		Ok(())
+	}
+
+	fn revert(&mut self) {
+		// SAFETY: The keymaps are initialized together with the HID class and the vendor reports are
+		// only processed in the USB interrupt.
+		unsafe {
+			keymaps::revert_keymaps();
 		}
-		0x02 => {
-			// req info
+	}
 
-			{
-				let keymap = &CONFIG.keymaps.keymap_0;
+	fn report_format(&self) -> silverplate::ReportFormat {
+		protocol_format(super::report_format(self.hid_class))
+	}
 
-				let size = keymap.len() as u64;
-				let size: [u8; 8] = size.to_le_bytes();
+	fn set_nkro(&mut self, enabled: bool) {
+		super::set_nkro(enabled);
+	}
 
-				let response: [u8; 10 + 1] = [
-					0x05, // report id
-					0x00, // number of rows
-					0x00, // number of cols
-					size[0], size[1], size[2], size[3], size[4], size[5], size[6], size[7],
-				];
+	fn layer_state(&self) -> LayerState {
+		let (active, default) = layers::LayerState::current().parts();
 
-				_ = hid_class.push_raw_input(&response).is_ok();
-			}
+		LayerState { active, default }
+	}
 
-			// {
-			// 	let keymap = unsafe { temp_get_active_keymap_ref() };
+	fn set_layer_state(&mut self, state: LayerState) {
+		layers::LayerState::from_parts(state.active, state.default).request();
+	}
+
+	fn reboot(&mut self, mode: RebootMode) -> Result<(), Status> {
+		super::request_reboot(mode);
+
+		Ok(())
+	}
+}
+
+/// Handles a vendor report, which holds a single silverplate frame, and answers it, unless it's a
+/// commit left to [`process_commit_request`].
+///
+/// If the endpoint is still busy, the response is kept until [`send_pending_response`] sends it.
+/// The host waits for every response before the next command, so a response still pending is
+/// one the host gave up on and is replaced.
+pub fn process_vendor_report(hid_class: &mut HIDClass<UsbBus>, payload: &[u8]) {
+	let mut keyboard = Keyboard {
+		hid_class,
+		commit_requested: false,
+	};
 
-			// 	let chunk_size = 59;
+	let frame = silverplate::handle(&mut keyboard, payload);
 
-			// 	let chunk_count = keymap.len().div_ceil(chunk_size);
+	// Writing the keymaps to the flash takes too long for the interrupt, so the main loop commits
+	// them and answers instead.
+	if keyboard.commit_requested {
+		if let Ok(request) = Frame::decode(payload) {
+			COMMIT_REQUEST.store(u16::from(request.sequence), Ordering::Relaxed);
+		}
 
-			// 	for index in 0..chunk_count {
-			// 		let start = index * chunk_size;
-			// 		let end = (start + chunk_size).min(keymap.len());
+		return;
+	}
 
-			// 		let mut response: [u8; 61] = [0; 61];
-			// 		response[0] = 0x06; // reportID
-			// 		response[1] = index as u8 + 1; // chunk index
+	let response = response(&frame);
 
-			// 		let data_slice = &keymap[start..end];
+	let pending = match super::push_input(hid_class, &response) {
+		Err(UsbError::WouldBlock) => Some(response),
+		// A response that can't be sent is dropped, like the reports.
+		_ => None,
+	};
 
-			// 		response[2..(2 + data_slice.len())].copy_from_slice(data_slice);
+	let ptr = &raw mut PENDING_RESPONSE;
 
-			// 		_ = hid_class.push_raw_input(&response).is_ok();
-			// 	}
-			// }
+	// SAFETY: `ptr` was obtained from a static value and the vendor reports are only processed in
+	// the USB interrupt.
+	unsafe {
+		*ptr = pending;
+	}
+}
+
+/// Sends the response the endpoint wasn't ready for, if there is one.
+///
+/// Returns [`UsbError::WouldBlock`] while the endpoint is still busy.
+///
+/// # Safety
+///
+/// The function needs to be called inside an **interrupt** or **interrupt-free** context.
+pub unsafe fn send_pending_response(hid_class: &mut HIDClass<UsbBus>) -> Result<(), UsbError> {
+	let ptr = &raw mut PENDING_RESPONSE;
+
+	// SAFETY: `ptr` was obtained from a static value and the caller guarantees the USB interrupt
+	// can't access it meanwhile.
+	let pending = unsafe { &mut *ptr };
+
+	if let Some(response) = pending {
+		if let Err(UsbError::WouldBlock) = super::push_input(hid_class, response) {
+			return Err(UsbError::WouldBlock);
 		}
-		_ => {}
+
+		*pending = None;
 	}
+
+	Ok(())
+}
+
+/// Commits the keymaps if the host asked for it, and leaves the answer to
+/// [`send_pending_response`].
+///
+/// # Safety
+///
+/// This function must only be called from the main loop, after the keymaps were initialized.
+pub unsafe fn process_commit_request() {
+	// The USB interrupt can't ask for another commit between the load and the store.
+	let request = cortex_m::interrupt::free(|_| {
+		let request = COMMIT_REQUEST.load(Ordering::Relaxed);
+		COMMIT_REQUEST.store(NO_COMMIT, Ordering::Relaxed);
+
+		request
+	});
+
+	let Ok(sequence) = u8::try_from(request) else {
+		return;
+	};
+
+	// SAFETY: The caller guarantees this runs from the main loop, after the keymaps were initialized.
+	let status = match unsafe { keymaps::commit_keymaps() } {
+		Ok(()) => Status::Ok,
+		Err(CommitError::NoStorage) => Status::NoStorage,
+		Err(CommitError::Storage) => Status::StorageError,
+	};
+
+	let frame =
+		Frame::new(Command::CommitKeymap as u8, sequence, status, &[]).map_or([0; REPORT_LEN], |frame| frame.encode());
+
+	cortex_m::interrupt::free(|_| {
+		let ptr = &raw mut PENDING_RESPONSE;
+
+		// SAFETY: `ptr` was obtained from a static value and the critical section keeps the USB
+		// interrupt from accessing it meanwhile.
+		unsafe {
+			*ptr = Some(response(&frame));
+		}
+	});
 }
@@ -0,0 +1,71 @@
+use qubit_config::keyboard::layer::LayerAction;
+use qubit_config::keyboard::{Action, ActionKind};
+
+use super::layers::LayerState;
+use super::{PACKED_SIZE, keymaps};
+
+/// The state of the keys after all the key features were applied.
+#[derive(Debug)]
+pub struct KeyState {
+	layers: LayerState,
+	/// The action each held key resolved to when it was pressed, so releasing it after a
+	/// layer change releases the same action.
+	held_actions: [Action; PACKED_SIZE],
+}
+
+impl KeyState {
+	#[must_use]
+	pub const fn new() -> Self {
+		Self {
+			layers: LayerState::new(),
+			held_actions: [Action::NO; PACKED_SIZE],
+		}
+	}
+
+	/// Looks up the action of the key using the current layer state.
+	pub fn action(&self, index: usize) -> Action {
+		// SAFETY: The active keymap is initialized before any key is scanned.
+		unsafe { keymaps::get_keymap_action(self.layers, index) }
+	}
+
+	/// The actions of the keys that are held down.
+	pub fn active_actions(&self) -> impl Iterator<Item = Action> {
+		self.held_actions.into_iter().filter(|action| !action.is_no())
+	}
+
+	pub fn press(&mut self, index: usize, action: Action) {
+		if let ActionKind::Layer(layer_action) = action.kind() {
+			match layer_action {
+				LayerAction::Momentary(layer) => self.layers.activate(layer),
+				LayerAction::Toggle(layer) => self.layers.toggle(layer),
+				LayerAction::To(layer) => self.layers.switch_to(layer),
+				LayerAction::Tap { .. } => {}
+			}
+		}
+
+		self.held_actions[index] = action;
+
+		#[cfg(feature = "silverplate")]
+		self.layers.publish();
+	}
+
+	pub fn release(&mut self, index: usize) {
+		let action = core::mem::replace(&mut self.held_actions[index], Action::NO);
+
+		if let ActionKind::Layer(LayerAction::Momentary(layer)) = action.kind() {
+			self.layers.deactivate(layer);
+
+			#[cfg(feature = "silverplate")]
+			self.layers.publish();
+		}
+	}
+
+	/// Switches to the layer state the host asked for, if there is one.
+	#[cfg(feature = "silverplate")]
+	pub fn apply_layer_request(&mut self) {
+		if let Some(layers) = LayerState::take_request() {
+			self.layers = layers;
+			self.layers.publish();
+		}
+	}
+}
@@ -0,0 +1,456 @@
+//! The raw HID interface used by the VIA and Vial configurators.
+//!
+//! It has its own HID class, next to the one of the keyboard, since VIA looks for an interface with
+//! only its usage page. Changes are used right away, and the main loop stores them in the flash
+//! once VIA stops making them for a while.
+//!
+//! VIA works on the active keymaps. The changes silverplate didn't commit yet stay pending, and
+//! only VIA's reset drops them.
+
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use qubit_config::keyboard::Action;
+use qubit_config::silverplate::RebootMode;
+use qubit_config::via::{self, Device, REPORT_LEN};
+use usb_device::UsbError;
+use usb_device::bus::UsbBusAllocator;
+use usbd_hid::hid_class::HIDClass;
+
+use super::keymaps;
+use crate::setup::UsbBus;
+use crate::{DEVICE_CONFIG, codegen, storage};
+
+/// The size of the macro buffer, shared by all the macros.
+const MACRO_BUFFER_SIZE: usize = 512;
+
+#[allow(clippy::cast_possible_truncation, reason = "A report is shorter than a packet.")]
+const REP_LEN: u8 = REPORT_LEN as u8;
+
+#[rustfmt::skip]
+const DESCRIPTOR: &[u8] = &[
+	0x06, 0x60, 0xFF,              // UsagePage(VIA)
+	0x09, via::USAGE,              // UsageId(VIA)
+	0xA1, 0x01,                    // Collection(Application)
+	0x09, 0x62,                    // UsageId(Data In)
+	0x15, 0x00,                    // LogicalMinimum(0)
+	0x26, 0xFF, 0x00,              // LogicalMaximum(255)
+	0x75, 0x08,                    // ReportSize(8)
+	0x95, REP_LEN,                 // ReportCount()
+	0x81, 0x02,                    // Input(Data, Variable, Absolute)
+	0x09, 0x63,                    // UsageId(Data Out)
+	0x15, 0x00,                    // LogicalMinimum(0)
+	0x26, 0xFF, 0x00,              // LogicalMaximum(255)
+	0x75, 0x08,                    // ReportSize(8)
+	0x95, REP_LEN,                 // ReportCount()
+	0x91, 0x02,                    // Output(Data, Variable, Absolute)
+	0xC0,                          // EndCollection()
+];
+
+const _: () = {
+	assert!(
+		via::USAGE_PAGE.to_le_bytes()[0] == DESCRIPTOR[1] && via::USAGE_PAGE.to_le_bytes()[1] == DESCRIPTOR[2],
+		"The descriptor must use the usage page of VIA."
+	);
+};
+
+/// HID class of the VIA interface.
+static mut HID_CLASS: MaybeUninit<HIDClass<'static, UsbBus>> = MaybeUninit::uninit();
+
+/// The macros set through VIA, which the firmware keeps but doesn't play yet.
+///
+/// They are only accessed from the USB interrupt after initialization.
+static mut MACROS: [u8; MACRO_BUFFER_SIZE] = [0; MACRO_BUFFER_SIZE];
+
+/// The response the endpoint wasn't ready for, sent again by [`send_pending_response`].
+///
+/// It's only accessed from the USB interrupt.
+static mut PENDING_RESPONSE: Option<[u8; REPORT_LEN]> = None;
+
+/// The changes that weren't stored yet, as `STORE_*` and `RESET_*` bits.
+///
+/// The USB interrupt sets them and the main loop takes them in a critical section.
+static STORE_REQUESTS: AtomicU8 = AtomicU8::new(0);
+
+/// The keymaps changed.
+const STORE_KEYMAPS: u8 = 1 << 0;
+/// The keymaps were reset, so the stored ones are removed.
+const RESET_KEYMAPS: u8 = 1 << 1;
+/// The macros changed.
+const STORE_MACROS: u8 = 1 << 2;
+/// The brightness was saved.
+#[cfg(has_led)]
+const STORE_BACKLIGHT: u8 = 1 << 3;
+/// The brightness was reset, so the stored one is removed.
+#[cfg(has_led)]
+const RESET_BACKLIGHT: u8 = 1 << 4;
+
+/// How long VIA has to stop making changes before they are stored, in milliseconds.
+///
+/// VIA sends a request for every key and every chunk of the keymaps, so the flash is written once
+/// for all of them instead of after each one.
+const STORE_DELAY_MS: u32 = 500;
+
+/// The brightness of the LED, which is off at 0 and on otherwise.
+#[cfg(has_led)]
+static BRIGHTNESS: AtomicU8 = AtomicU8::new(u8::MAX);
+
+/// Creates the HID class and reads the macros and the brightness from the storage.
+///
+/// # Safety
+///
+/// This function must be called **only once** for the lifetime of the program, after the storage
+/// was initialized and before enabling the interrupts.
+pub unsafe fn init(usb_bus_alloc: &'static UsbBusAllocator<UsbBus>) {
+	let ptr = &raw mut HID_CLASS;
+
+	// SAFETY: `ptr` was obtained from a static value and so is guaranteed to be non-null and properly
+	// aligned. This sets the value of the MaybeUninit.
+	unsafe {
+		(*ptr).write(HIDClass::new(usb_bus_alloc, DESCRIPTOR, 1));
+	}
+
+	// SAFETY: The caller guarantees nothing else is using the storage.
+	let Some(store) = (unsafe { storage::get_mut() }) else {
+		return;
+	};
+
+	let ptr = &raw mut MACROS;
+
+	// SAFETY: The interrupts aren't enabled yet, so nothing else is accessing the macros.
+	let macros = unsafe { &mut *ptr };
+
+	_ = store.read(storage::MACROS_KEY, macros);
+
+	#[cfg(has_led)]
+	{
+		let mut brightness = [0];
+
+		if let Ok(Some(1)) = store.read(storage::BACKLIGHT_KEY, &mut brightness) {
+			BRIGHTNESS.store(brightness[0], Ordering::Relaxed);
+		}
+	}
+}
+
+/// Checks if the LED should show the lock state, or stay off.
+#[cfg(has_led)]
+pub fn is_led_enabled() -> bool {
+	BRIGHTNESS.load(Ordering::Relaxed) > 0
+}
+
+/// Returns a mutable reference to the HID class of the VIA interface.
+///
+/// # Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+///
+/// * [`init`] must have been called before this function.
+/// * No other reference to the static value exists.
+/// * The function needs to be called inside an **interrupt** or **interrupt-free** context
+pub unsafe fn get_mut<'a>() -> &'a mut HIDClass<'static, UsbBus> {
+	let ptr = &raw mut HID_CLASS;
+
+	// SAFETY: The caller guarantees the content was initialized.
+	unsafe { (*ptr).assume_init_mut() }
+}
+
+/// Adds a request to the earlier ones. Storing something and resetting it replace each other.
+fn merge_requests(mut requests: u8, request: u8) -> u8 {
+	if request & (STORE_KEYMAPS | RESET_KEYMAPS) != 0 {
+		requests &= !(STORE_KEYMAPS | RESET_KEYMAPS);
+	}
+
+	#[cfg(has_led)]
+	if request & (STORE_BACKLIGHT | RESET_BACKLIGHT) != 0 {
+		requests &= !(STORE_BACKLIGHT | RESET_BACKLIGHT);
+	}
+
+	requests | request
+}
+
+/// Asks the main loop to store a change. Only called from the USB interrupt.
+fn request_store(request: u8) {
+	// The main loop only takes the requests in a critical section, so it can't run in between.
+	let requests = STORE_REQUESTS.load(Ordering::Relaxed);
+
+	STORE_REQUESTS.store(merge_requests(requests, request), Ordering::Relaxed);
+}
+
+/// Takes the requests made by the USB interrupt since the last call.
+fn take_store_requests() -> u8 {
+	cortex_m::interrupt::free(|_| {
+		let requests = STORE_REQUESTS.load(Ordering::Relaxed);
+
+		STORE_REQUESTS.store(0, Ordering::Relaxed);
+
+		requests
+	})
+}
+
+/// Writes a value to the storage, or removes it if there is none.
+///
+/// # Safety
+///
+/// This function must only be called from the main loop.
+unsafe fn write_value(key: u16, value: Option<&[u8]>) -> bool {
+	// SAFETY: The caller guarantees this runs from the main loop.
+	unsafe {
+		storage::with_store(|store| {
+			store.is_some_and(|store| match value {
+				Some(bytes) => store.write(key, bytes).is_ok(),
+				None => store.remove(key).is_ok(),
+			})
+		})
+	}
+}
+
+/// Stores the changes made through VIA from the main loop, where writing the flash doesn't hold up
+/// the USB interrupt.
+#[derive(Debug)]
+pub struct PendingStores {
+	/// The requests taken from the USB interrupt that weren't stored yet.
+	requests: u8,
+	/// When the last request was taken, in milliseconds.
+	time: u32,
+}
+
+impl PendingStores {
+	pub const fn new() -> Self {
+		Self { requests: 0, time: 0 }
+	}
+
+	/// Takes the new requests and stores the changes once VIA stopped making them for
+	/// [`STORE_DELAY_MS`].
+	///
+	/// # Safety
+	///
+	/// This function must only be called from the main loop, after the keyboard was initialized.
+	pub unsafe fn process(&mut self, now: u32) {
+		let requests = take_store_requests();
+
+		if requests != 0 {
+			self.requests = merge_requests(self.requests, requests);
+			self.time = now;
+		}
+
+		if self.requests != 0 && now.wrapping_sub(self.time) >= STORE_DELAY_MS {
+			// SAFETY: The caller upholds the conditions.
+			unsafe {
+				self.flush();
+			}
+		}
+	}
+
+	/// Stores the changes right away, like before a reboot.
+	///
+	/// # Safety
+	///
+	/// This function must only be called from the main loop, after the keyboard was initialized.
+	#[cfg_attr(
+		not(feature = "defmt"),
+		allow(
+			unused_variables,
+			unused_assignments,
+			reason = "The result is only logged with defmt."
+		)
+	)]
+	pub unsafe fn flush(&mut self) {
+		let requests = merge_requests(self.requests, take_store_requests());
+		self.requests = 0;
+
+		let mut stored = true;
+
+		if requests & STORE_KEYMAPS != 0 {
+			// SAFETY: The caller guarantees this runs from the main loop, after the keymaps were
+			// initialized.
+			stored &= unsafe { keymaps::store_active_keymaps() }.is_ok();
+		}
+
+		if requests & RESET_KEYMAPS != 0 {
+			// SAFETY: The caller guarantees this runs from the main loop.
+			stored &= unsafe { write_value(storage::KEYMAPS_KEY, None) };
+		}
+
+		if requests & STORE_MACROS != 0 {
+			let ptr = &raw const MACROS;
+
+			// SAFETY: `ptr` was obtained from a static value, and the critical section keeps the USB
+			// interrupt from changing the macros while they are copied.
+			let macros = cortex_m::interrupt::free(|_| unsafe { *ptr });
+
+			// SAFETY: The caller guarantees this runs from the main loop.
+			stored &= unsafe { write_value(storage::MACROS_KEY, Some(&macros)) };
+		}
+
+		#[cfg(has_led)]
+		if requests & STORE_BACKLIGHT != 0 {
+			let brightness = BRIGHTNESS.load(Ordering::Relaxed);
+
+			// SAFETY: The caller guarantees this runs from the main loop.
+			stored &= unsafe { write_value(storage::BACKLIGHT_KEY, Some(&[brightness])) };
+		}
+
+		#[cfg(has_led)]
+		if requests & RESET_BACKLIGHT != 0 {
+			// SAFETY: The caller guarantees this runs from the main loop.
+			stored &= unsafe { write_value(storage::BACKLIGHT_KEY, None) };
+		}
+
+		#[cfg(feature = "defmt")]
+		if !stored {
+			defmt::error!("The VIA changes couldn't be stored.");
+		}
+	}
+}
+
+/// The keyboard, as seen by the VIA commands.
+///
+/// Keymaps are changed in the active keymaps. The store methods only ask the main loop to store
+/// the changes, since writing the flash takes too long for the USB interrupt.
+struct Keyboard {
+	macros: &'static mut [u8; MACRO_BUFFER_SIZE],
+}
+
+impl Device for Keyboard {
+	fn matrix_size(&self) -> (usize, usize) {
+		(codegen::LAYER0.0.len(), codegen::LAYER0.0[0].len())
+	}
+
+	fn firmware_version(&self) -> u32 {
+		DEVICE_CONFIG.version
+	}
+
+	fn action(&self, layer: usize, row: usize, col: usize) -> Option<Action> {
+		let index = codegen::LAYER0.packed_index(row, col)?;
+
+		// SAFETY: The active keymaps are initialized together with the HID class and only replaced
+		// from the USB interrupt or inside critical sections.
+		Some(unsafe { keymaps::active_keymaps() }.layer(layer)[index])
+	}
+
+	fn set_action(&mut self, layer: usize, row: usize, col: usize, action: Action) -> bool {
+		let Some(index) = codegen::LAYER0.packed_index(row, col) else {
+			return false;
+		};
+
+		// SAFETY: The keymaps are initialized together with the HID class and the VIA reports are
+		// only processed in the USB interrupt.
+		unsafe {
+			keymaps::set_active_action(layer, index, action);
+		}
+
+		true
+	}
+
+	fn store_keymaps(&mut self) {
+		request_store(STORE_KEYMAPS);
+	}
+
+	fn reset_keymaps(&mut self) {
+		// SAFETY: The keymaps are initialized together with the HID class and the VIA reports are
+		// only processed in the USB interrupt.
+		unsafe {
+			keymaps::reset_keymaps();
+		}
+
+		request_store(RESET_KEYMAPS);
+	}
+
+	fn macro_buffer(&self) -> &[u8] {
+		self.macros
+	}
+
+	fn macro_buffer_mut(&mut self) -> &mut [u8] {
+		self.macros
+	}
+
+	fn store_macros(&mut self) {
+		request_store(STORE_MACROS);
+	}
+
+	#[cfg(has_led)]
+	fn backlight(&self) -> Option<u8> {
+		Some(BRIGHTNESS.load(Ordering::Relaxed))
+	}
+
+	#[cfg(has_led)]
+	fn set_backlight(&mut self, brightness: u8) -> bool {
+		BRIGHTNESS.store(brightness, Ordering::Relaxed);
+
+		true
+	}
+
+	#[cfg(has_led)]
+	fn store_backlight(&mut self) -> bool {
+		request_store(STORE_BACKLIGHT);
+
+		true
+	}
+
+	#[cfg(has_led)]
+	fn reset_backlight(&mut self) {
+		BRIGHTNESS.store(u8::MAX, Ordering::Relaxed);
+
+		request_store(RESET_BACKLIGHT);
+	}
+
+	fn jump_to_bootloader(&mut self) -> bool {
+		super::request_reboot(RebootMode::Bootloader);
+
+		true
+	}
+}
+
+/// Handles a VIA report and sends the response back.
+///
+/// If the endpoint is still busy, the response is kept until [`send_pending_response`] sends it.
+/// VIA waits for every response before the next request, so a response still pending is one it
+/// gave up on and is replaced.
+pub fn process_report(hid_class: &mut HIDClass<UsbBus>) {
+	let mut report = [0; REPORT_LEN];
+
+	let Ok(REPORT_LEN) = hid_class.pull_raw_output(&mut report) else {
+		return;
+	};
+
+	let ptr = &raw mut MACROS;
+
+	// SAFETY: The macros are only accessed from the USB interrupt after initialization, which is
+	// where the reports are processed.
+	let macros = unsafe { &mut *ptr };
+
+	via::handle(&mut Keyboard { macros }, &mut report);
+
+	let pending = match hid_class.push_raw_input(&report) {
+		Err(UsbError::WouldBlock) => Some(report),
+		// A response that can't be sent is dropped, like the reports.
+		_ => None,
+	};
+
+	let ptr = &raw mut PENDING_RESPONSE;
+
+	// SAFETY: `ptr` was obtained from a static value and the reports are only processed in the USB
+	// interrupt.
+	unsafe {
+		*ptr = pending;
+	}
+}
+
+/// Sends the response the endpoint wasn't ready for, if there is one.
+///
+/// # Safety
+///
+/// The function needs to be called inside the USB **interrupt**.
+pub unsafe fn send_pending_response(hid_class: &mut HIDClass<UsbBus>) {
+	let ptr = &raw mut PENDING_RESPONSE;
+
+	// SAFETY: `ptr` was obtained from a static value and the caller guarantees this runs in the USB
+	// interrupt, the only place that accesses it.
+	let pending = unsafe { &mut *ptr };
+
+	if let Some(response) = pending
+		&& !matches!(hid_class.push_raw_input(response), Err(UsbError::WouldBlock))
+	{
+		*pending = None;
+	}
+}
@@ -1,6 +1,7 @@
 use std::collections::HashSet;
+use std::fmt;
 
-use crate::mcu::Mcu;
+use crate::mcu::{Mcu, PinError, Reserved};
 
 #[derive(Debug, Default)]
 pub struct BuildCfgs {
@@ -58,7 +59,7 @@ impl BuildCfgs {
 
 /// # Panics
 ///
-/// Panics if a pin is used more than once.
+/// Panics if a pin isn't a pin of the MCU, is reserved or is used more than once.
 pub fn output_cargo_instructions<const R: usize, const C: usize>(
 	mcu: Mcu,
 	row: &[&str; R],
@@ -66,11 +67,11 @@ pub fn output_cargo_instructions<const R: usize, const C: usize>(
 	led: Option<&str>,
 	build_cfgs: &mut BuildCfgs,
 ) {
+	let pins = collect_pins(mcu, row, col, led).unwrap_or_else(|err| panic!("{err}"));
+
 	match mcu {
 		Mcu::RP2040 => {}
 		Mcu::STM32F411 => {
-			let pins = collect_pins(row, col, led).unwrap();
-
 			let bank_enabled = pins.iter().any(|pin| pin.starts_with('B'));
 			build_cfgs.if_enable_cfg("stm32f411_bank_b", bank_enabled);
 
@@ -102,66 +103,83 @@ pub fn output_cargo_instructions<const R: usize, const C: usize>(
 #[derive(Debug)]
 pub enum ErrReason {
 	Duplicate,
-	Reserved,
+	Reserved(Reserved),
+	/// The MCU has no pin with that name.
+	Unknown,
 }
 
 #[derive(Debug)]
 pub struct PinCollectError<'a> {
 	pub reason: ErrReason,
 	pub pin: &'a str,
+	pub mcu: Mcu,
 }
 
 impl<'a> PinCollectError<'a> {
 	#[must_use]
-	pub fn reserved(pin: &'a str) -> Self {
+	pub fn reserved(mcu: Mcu, pin: &'a str, reserved: Reserved) -> Self {
 		Self {
-			reason: ErrReason::Reserved,
+			reason: ErrReason::Reserved(reserved),
 			pin,
+			mcu,
 		}
 	}
 
 	#[must_use]
-	pub fn duplicate(pin: &'a str) -> Self {
+	pub fn duplicate(mcu: Mcu, pin: &'a str) -> Self {
 		Self {
 			reason: ErrReason::Duplicate,
 			pin,
+			mcu,
+		}
+	}
+
+	#[must_use]
+	pub fn unknown(mcu: Mcu, pin: &'a str) -> Self {
+		Self {
+			reason: ErrReason::Unknown,
+			pin,
+			mcu,
+		}
+	}
+}
+
+impl fmt::Display for PinCollectError<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let (pin, mcu) = (self.pin, self.mcu.as_str());
+
+		match self.reason {
+			ErrReason::Duplicate => write!(f, "Pin {pin} is used more than once."),
+			ErrReason::Reserved(reserved) => write!(f, "Pin {pin} is reserved for {} on the {mcu}.", reserved.as_str()),
+			ErrReason::Unknown => write!(f, "{pin} isn't a pin of the {mcu}."),
 		}
 	}
 }
 
+impl std::error::Error for PinCollectError<'_> {}
+
 /// Collect all pins used.
 ///
 /// # Errors
 ///
-/// Returns an error if a pin is used more than once.
+/// Returns an error if a pin isn't a pin of the MCU, is reserved or is used more than once.
 pub fn collect_pins<'a, const R: usize, const C: usize>(
+	mcu: Mcu,
 	row: &[&'a str; R],
 	col: &[&'a str; C],
 	led: Option<&'a str>,
 ) -> Result<HashSet<&'a str>, PinCollectError<'a>> {
 	let mut pins = HashSet::new();
 
-	for p in row {
-		let is_new = pins.insert(*p);
-
-		if !is_new {
-			return Err(PinCollectError::duplicate(p));
+	for &pin in row.iter().chain(col).chain(&led) {
+		match mcu.usable_pin(pin) {
+			Ok(_) => {}
+			Err(PinError::Unknown) => return Err(PinCollectError::unknown(mcu, pin)),
+			Err(PinError::Reserved(reserved)) => return Err(PinCollectError::reserved(mcu, pin, reserved)),
 		}
-	}
-
-	for p in col {
-		let is_new = pins.insert(*p);
-
-		if !is_new {
-			return Err(PinCollectError::duplicate(p));
-		}
-	}
-
-	if let Some(p) = led {
-		let is_new = pins.insert(p);
 
-		if !is_new {
-			return Err(PinCollectError::duplicate(p));
+		if !pins.insert(pin) {
+			return Err(PinCollectError::duplicate(mcu, pin));
 		}
 	}
 
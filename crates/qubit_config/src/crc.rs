@@ -0,0 +1,73 @@
+//! Checksums shared by the firmware and the host tools.
+
+/// The reflected CRC-32 (IEEE 802.3) polynomial.
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+const TABLE: [u32; 256] = {
+	let mut table = [0; 256];
+
+	let mut i = 0;
+	while i < 256 {
+		#[allow(clippy::cast_possible_truncation, reason = "`i` is always below 256.")]
+		let mut crc = i as u32;
+
+		let mut bit = 0;
+		while bit < 8 {
+			crc = if crc & 1 == 0 {
+				crc >> 1
+			} else {
+				(crc >> 1) ^ POLYNOMIAL
+			};
+
+			bit += 1;
+		}
+
+		table[i] = crc;
+
+		i += 1;
+	}
+
+	table
+};
+
+/// A CRC-32 (IEEE 802.3) computed over data that doesn't come in a single slice.
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+	#[must_use]
+	pub const fn new() -> Self {
+		Self(0xFFFF_FFFF)
+	}
+
+	#[must_use]
+	pub const fn update(self, bytes: &[u8]) -> Self {
+		let mut crc = self.0;
+
+		let mut i = 0;
+		while i < bytes.len() {
+			crc = TABLE[((crc ^ bytes[i] as u32) & 0xFF) as usize] ^ (crc >> 8);
+
+			i += 1;
+		}
+
+		Self(crc)
+	}
+
+	#[must_use]
+	pub const fn finish(self) -> u32 {
+		!self.0
+	}
+}
+
+impl Default for Crc32 {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// Computes the CRC-32 (IEEE 802.3) of `bytes`.
+#[must_use]
+pub const fn crc32(bytes: &[u8]) -> u32 {
+	Crc32::new().update(bytes).finish()
+}
@@ -1,4 +1,7 @@
-#[derive(Debug, Clone, Copy)]
+use crate::mcu::Mcu;
+use crate::usb::Usb;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(serde::Deserialize, serde::Serialize))]
 #[cfg_attr(feature = "std", serde(rename_all = "lowercase"))]
 pub enum Device {
@@ -21,14 +24,24 @@ impl Device {
 	}
 }
 
-#[derive(Debug)]
-pub struct Configuration {
+/// Describes the board to host tools, encoded in the `.configuration` section of the firmware.
+///
+/// See [`section`](crate::section) for the format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Configuration<'a> {
 	/// Tha name of the board.
-	pub name: &'static str,
+	pub name: &'a str,
 	/// The board author or manufacturer.
-	pub author: &'static str,
-	/// A [`Version`] represented as a bitmap.
+	pub author: &'a str,
+	/// A [`Version`](crate::version::Version) represented as a bitmap.
 	pub version: u32,
 
 	pub device: Device,
+	pub mcu: Mcu,
+	pub usb: Usb,
+	/// The rows of the key matrix.
+	pub rows: usize,
+	/// The columns of the key matrix.
+	pub cols: usize,
+	pub layer_count: usize,
 }
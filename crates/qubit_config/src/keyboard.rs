@@ -1,15 +1,90 @@
+mod action;
+mod bootmagic;
+mod debounce;
+pub mod event;
+mod indicator;
 pub mod keycodes;
+pub mod layer;
+mod tap_hold;
 
-pub type PackedKeymap<const S: usize> = [u8; S];
+pub use action::{Action, ActionKind, FirmwareAction, Modifiers};
+pub use bootmagic::{Bootmagic, BootmagicAction, KeyPosition};
+pub use debounce::{Debounce, Debouncer};
+pub use indicator::LockIndicator;
+pub use tap_hold::{KeyTappingTerm, TapHold, TapHoldEngine, TapHoldFlavor};
 
-pub struct Keymap<const R: usize, const C: usize>(pub [[u8; C]; R]);
+pub type PackedKeymap<const S: usize> = [Action; S];
+
+/// Returns the word and the mask of a key inside the bitmaps of pressed keys, which are indexed the
+/// same way as the packed keymap.
+#[must_use]
+pub const fn key_bit(index: usize) -> (usize, usize) {
+	const USIZE_BITS: usize = usize::BITS as usize;
+
+	(index / USIZE_BITS, 1 << (index % USIZE_BITS))
+}
+
+pub struct Keymap<const R: usize, const C: usize>(pub [[Action; C]; R]);
 
 impl<const R: usize, const C: usize> Keymap<R, C> {
 	#[must_use]
-	pub const fn new(keymap: [[u8; C]; R]) -> Self {
+	pub const fn new(keymap: [[Action; C]; R]) -> Self {
 		Self(keymap)
 	}
 
+	/// Checks that both keymaps have their empty spaces in the same positions.
+	///
+	/// Every layer is packed the same way, so a packed index needs to point to the same
+	/// physical key on all of them.
+	#[must_use]
+	pub const fn has_layout_of(&self, other: &Self) -> bool {
+		let mut i = 0;
+		while i < R {
+			let mut j = 0;
+			while j < C {
+				if self.0[i][j].is_no() != other.0[i][j].is_no() {
+					return false;
+				}
+
+				j += 1;
+			}
+
+			i += 1;
+		}
+
+		true
+	}
+
+	/// Returns the index of the key inside the packed keymap, if there is a key at that position.
+	#[must_use]
+	pub const fn packed_index(&self, row: usize, col: usize) -> Option<usize> {
+		if row >= R || col >= C || self.0[row][col].is_no() {
+			return None;
+		}
+
+		let mut index = 0;
+
+		let mut i = 0;
+		while i < R {
+			let mut j = 0;
+			while j < C {
+				if i == row && j == col {
+					return Some(index);
+				}
+
+				if !self.0[i][j].is_no() {
+					index += 1;
+				}
+
+				j += 1;
+			}
+
+			i += 1;
+		}
+
+		None
+	}
+
 	#[must_use]
 	pub const fn get_packed_size(&self) -> usize {
 		let keymap = &self.0;
@@ -24,7 +99,7 @@ impl<const R: usize, const C: usize> Keymap<R, C> {
 			while j < row.len() {
 				let key = row[j];
 
-				if key != 0 {
+				if !key.is_no() {
 					size += 1;
 				}
 
@@ -37,6 +112,56 @@ impl<const R: usize, const C: usize> Keymap<R, C> {
 		size
 	}
 
+	/// The size of the layout written by [`write_layout`](Self::write_layout).
+	#[must_use]
+	pub const fn layout_len(&self) -> usize {
+		2 + (R * C).div_ceil(8)
+	}
+
+	/// Writes where the keys are, as the `.keyboard` section has it: the rows, the columns and a bit
+	/// for every position of the matrix, row by row, set where there is a key.
+	///
+	/// Packed keymaps only make sense for the layout they were packed with, so it's stored with them.
+	///
+	/// # Panics
+	///
+	/// Panics if `bytes` isn't [`layout_len`](Self::layout_len) long or if the matrix doesn't fit in
+	/// a byte.
+	#[allow(clippy::cast_possible_truncation, reason = "The matrix is checked to fit first.")]
+	pub const fn write_layout(&self, bytes: &mut [u8]) {
+		assert!(bytes.len() == self.layout_len(), "Invalid length!");
+		assert!(
+			R <= u8::MAX as usize && C <= u8::MAX as usize,
+			"The matrix doesn't fit in a byte."
+		);
+
+		bytes[0] = R as u8;
+		bytes[1] = C as u8;
+
+		let mut k = 2;
+		while k < bytes.len() {
+			bytes[k] = 0;
+
+			k += 1;
+		}
+
+		let mut i = 0;
+		while i < R {
+			let mut j = 0;
+			while j < C {
+				if !self.0[i][j].is_no() {
+					let position = i * C + j;
+
+					bytes[2 + position / 8] |= 1 << (position % 8);
+				}
+
+				j += 1;
+			}
+
+			i += 1;
+		}
+	}
+
 	/// # Panics
 	///
 	/// Panics if there is a logical error and the length assertion fails.
@@ -44,7 +169,7 @@ impl<const R: usize, const C: usize> Keymap<R, C> {
 	pub const fn get_packed<const S: usize>(&self) -> PackedKeymap<S> {
 		let keymap = &self.0;
 
-		let mut packed_keymap = [0; S];
+		let mut packed_keymap = [Action::NO; S];
 
 		let mut base = 0;
 		let mut i = 0;
@@ -56,7 +181,7 @@ impl<const R: usize, const C: usize> Keymap<R, C> {
 			while j < row.len() {
 				let key = row[j];
 
-				if key != 0 {
+				if !key.is_no() {
 					packed_keymap[base] = key;
 
 					base += 1;
@@ -74,7 +199,7 @@ impl<const R: usize, const C: usize> Keymap<R, C> {
 	}
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Keymaps<const S: usize> {
 	pub keymap_0: PackedKeymap<S>,
 	pub keymap_1: PackedKeymap<S>,
@@ -83,26 +208,148 @@ pub struct Keymaps<const S: usize> {
 	pub keymap_4: PackedKeymap<S>,
 }
 
-#[derive(Debug)]
-pub struct KeyboardConfiguration<const S: usize> {
-	pub keymaps: Keymaps<S>,
+impl<const S: usize> Keymaps<S> {
+	/// Returns the keymap for the given layer.
+	///
+	/// # Panics
+	///
+	/// Panics if the layer is outside the [`LAYER_COUNT`](layer::LAYER_COUNT) bounds.
+	#[must_use]
+	pub const fn layer(&self, layer: usize) -> &PackedKeymap<S> {
+		match layer {
+			0 => &self.keymap_0,
+			1 => &self.keymap_1,
+			2 => &self.keymap_2,
+			3 => &self.keymap_3,
+			4 => &self.keymap_4,
+			_ => panic!("Layer index out of bounds."),
+		}
+	}
+
+	/// Returns the keymap for the given layer, to change it.
+	///
+	/// # Panics
+	///
+	/// Panics if the layer is outside the [`LAYER_COUNT`](layer::LAYER_COUNT) bounds.
+	#[must_use]
+	pub const fn layer_mut(&mut self, layer: usize) -> &mut PackedKeymap<S> {
+		match layer {
+			0 => &mut self.keymap_0,
+			1 => &mut self.keymap_1,
+			2 => &mut self.keymap_2,
+			3 => &mut self.keymap_3,
+			4 => &mut self.keymap_4,
+			_ => panic!("Layer index out of bounds."),
+		}
+	}
+
+	/// The size of the keymaps once encoded with [`write_bytes`](Self::write_bytes).
+	pub const BYTES_LEN: usize = S * 2 * layer::LAYER_COUNT;
+
+	/// Encodes the actions of every layer, one after the other, as little endian.
+	///
+	/// # Panics
+	///
+	/// Panics if `bytes` isn't [`BYTES_LEN`](Self::BYTES_LEN) long.
+	pub fn write_bytes(&self, bytes: &mut [u8]) {
+		assert!(bytes.len() == Self::BYTES_LEN, "Invalid length!");
+
+		for (layer, layer_bytes) in bytes.chunks_exact_mut(S * 2).enumerate() {
+			for (action, action_bytes) in self.layer(layer).iter().zip(layer_bytes.chunks_exact_mut(2)) {
+				action_bytes.copy_from_slice(&action.to_bits().to_le_bytes());
+			}
+		}
+	}
+
+	/// Decodes keymaps encoded with [`write_bytes`](Self::write_bytes).
+	///
+	/// Returns `None` if the length doesn't match, which happens when they were encoded for a
+	/// different layout.
+	#[must_use]
+	pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+		if bytes.len() != Self::BYTES_LEN {
+			return None;
+		}
+
+		let mut layers = bytes.chunks_exact(S * 2).map(|layer_bytes| {
+			let mut keymap = [Action::NO; S];
+
+			for (action, action_bytes) in keymap.iter_mut().zip(layer_bytes.chunks_exact(2)) {
+				*action = Action::from_bits(u16::from_le_bytes([action_bytes[0], action_bytes[1]]));
+			}
+
+			keymap
+		});
+
+		Some(Self {
+			keymap_0: layers.next()?,
+			keymap_1: layers.next()?,
+			keymap_2: layers.next()?,
+			keymap_3: layers.next()?,
+			keymap_4: layers.next()?,
+		})
+	}
 }
 
 /// Generate a keymap using the predefined keycodes.
-/// The literal '-' can be passed to represent an empty space.
+/// The literal '-' can be passed to represent an empty space and '_' for a transparent key.
+///
+/// Besides plain keycodes, the following actions are supported:
+///
+/// * `MO(layer)`, `TG(layer)`, `TO(layer)` and `LT(layer, keycode)` for layer keys.
+/// * `LCTL(..)`, `LSFT(..)`, `LALT(..)`, `LGUI(..)` and their `R` counterparts to wrap a keycode
+///   in modifiers, eg. `LCTL(KC_C)` or `LCTL(LSFT(KC_ESC))`.
+/// * `MT(modifier, keycode)` for a mod-tap key, eg. `MT(LCTRL, KC_A)`.
+/// * `CC(usage)` for a Consumer page usage and `SYS(usage)` for a System Control usage.
+/// * `MACRO(index)` and `CUSTOM(id)` for macros and custom actions.
+/// * `NK_ON`, `NK_OFF` and `NK_TOGG` to switch between the NKRO and 6KRO reports.
+/// * `QK_BOOT` to restart into the bootloader and `QK_RBT` to restart the firmware.
 #[macro_export]
 macro_rules! keymap {
-	($( [ $($key:tt),* $(,)? ] ),* $(,)?) => {
+	($( [ $($key:tt $(($($arg:tt)*))?),* $(,)? ] ),* $(,)?) => {
 		$crate::keyboard::Keymap::new([
 			$(
 				[
-					$( $crate::keymap!(@internal $key) ),*
+					$( $crate::keymap!(@internal $key $(($($arg)*))?) ),*
 				]
 			),*
 		])
 	};
-	(@internal -) => { 0 };
-	(@internal $key:ident) => {{
-		::core::num::NonZeroU8::get($crate::keyboard::keycodes::$key)
-	}};
+	(@internal -) => { $crate::keyboard::Action::NO };
+	(@internal _) => { $crate::keyboard::Action::TRANSPARENT };
+	(@internal MO($layer:literal)) => { $crate::keyboard::Action::momentary($layer) };
+	(@internal TG($layer:literal)) => { $crate::keyboard::Action::toggle($layer) };
+	(@internal TO($layer:literal)) => { $crate::keyboard::Action::to($layer) };
+	(@internal LT($layer:literal, $key:ident)) => {
+		$crate::keyboard::Action::layer_tap($layer, $crate::keyboard::keycodes::$key)
+	};
+	(@internal MT($mods:ident, $key:ident)) => {
+		$crate::keyboard::Action::mod_tap($crate::keyboard::Modifiers::$mods, $crate::keyboard::keycodes::$key)
+	};
+	(@internal CC($usage:literal)) => { $crate::keyboard::Action::consumer($usage) };
+	(@internal SYS($usage:literal)) => { $crate::keyboard::Action::system($usage) };
+	(@internal MACRO($index:literal)) => { $crate::keyboard::Action::macro_action($index) };
+	(@internal CUSTOM($id:literal)) => { $crate::keyboard::Action::custom($id) };
+	(@internal NK_ON) => { $crate::keyboard::Action::firmware($crate::keyboard::FirmwareAction::NkroOn) };
+	(@internal NK_OFF) => { $crate::keyboard::Action::firmware($crate::keyboard::FirmwareAction::NkroOff) };
+	(@internal NK_TOGG) => { $crate::keyboard::Action::firmware($crate::keyboard::FirmwareAction::NkroToggle) };
+	(@internal QK_BOOT) => { $crate::keyboard::Action::firmware($crate::keyboard::FirmwareAction::Bootloader) };
+	(@internal QK_RBT) => { $crate::keyboard::Action::firmware($crate::keyboard::FirmwareAction::Reboot) };
+	(@internal LCTL($($inner:tt)+)) => { $crate::keymap!(@modifiers LCTRL, $($inner)+) };
+	(@internal LSFT($($inner:tt)+)) => { $crate::keymap!(@modifiers LSHIFT, $($inner)+) };
+	(@internal LALT($($inner:tt)+)) => { $crate::keymap!(@modifiers LALT, $($inner)+) };
+	(@internal LGUI($($inner:tt)+)) => { $crate::keymap!(@modifiers LGUI, $($inner)+) };
+	(@internal RCTL($($inner:tt)+)) => { $crate::keymap!(@modifiers RCTRL, $($inner)+) };
+	(@internal RSFT($($inner:tt)+)) => { $crate::keymap!(@modifiers RSHIFT, $($inner)+) };
+	(@internal RALT($($inner:tt)+)) => { $crate::keymap!(@modifiers RALT, $($inner)+) };
+	(@internal RGUI($($inner:tt)+)) => { $crate::keymap!(@modifiers RGUI, $($inner)+) };
+	(@internal $key:ident) => {
+		$crate::keyboard::Action::key($crate::keyboard::keycodes::$key)
+	};
+	(@modifiers $mods:ident, $key:tt $(($($arg:tt)*))?) => {
+		$crate::keyboard::Action::with_modifiers(
+			$crate::keymap!(@internal $key $(($($arg)*))?),
+			$crate::keyboard::Modifiers::$mods,
+		)
+	};
 }
@@ -0,0 +1,390 @@
+//! The action a key performs and its encoding inside a keymap.
+//!
+//! Actions are stored as a single 16 bit value so a keymap takes the same amount of flash
+//! no matter what the keys do.
+//!
+//! # Layout
+//!
+//! * `0x0000` - No action
+//! * `0x0001` - Transparent, the lookup falls through to the next active layer
+//! * `0x0002..=0x0003` - Reserved, like the HID error codes they would hold, and decoded as no
+//!   action
+//! * `0x0004..=0x00FF` - Plain HID keycode
+//! * `0x0100..=0x1FFF` - Keycode wrapped in modifiers, bits 8-12 hold the [`Modifiers`]. A keycode
+//!   of `0x00` means only the modifiers are pressed
+//! * `0x2000..=0x3FFF` - Mod-tap, bits 8-12 hold the [`Modifiers`] and bits 0-7 the tapped keycode
+//! * `0x4000..=0x4FFF` - Layer-tap, bits 8-11 hold the layer and bits 0-7 the tapped keycode
+//! * `0x5000..=0x500F` - Momentary layer
+//! * `0x5010..=0x501F` - Toggle layer
+//! * `0x5020..=0x502F` - Switch to layer
+//! * `0x6000..=0x6FFF` - Consumer page usage
+//! * `0x7000..=0x70FF` - Macro
+//! * `0x7100..=0x71FF` - Custom action
+//! * `0x7200..=0x72FF` - Generic Desktop page System Control usage
+//! * `0x7C00..=0x7CFF` - Firmware action, see [`FirmwareAction`]
+
+use core::num::NonZeroU8;
+
+use super::layer::{LAYER_COUNT, LayerAction};
+
+const KIND_MASK: u16 = 0xF000;
+const LAYER_MASK: u16 = 0x000F;
+
+const MODIFIED_KEY_END: u16 = 0x1FFF;
+const MOD_TAP: u16 = 0x2000;
+const MOD_TAP_END: u16 = 0x3FFF;
+const LAYER_TAP: u16 = 0x4000;
+const MOMENTARY: u16 = 0x5000;
+const TOGGLE: u16 = 0x5010;
+const TO: u16 = 0x5020;
+const CONSUMER: u16 = 0x6000;
+const MACRO: u16 = 0x7000;
+const CUSTOM: u16 = 0x7100;
+const SYSTEM: u16 = 0x7200;
+const FIRMWARE: u16 = 0x7C00;
+
+/// A compact set of modifiers that can wrap a keycode.
+///
+/// Only one side can be used at a time. The lower 4 bits select Control, Shift, Alt and GUI
+/// and the 5th bit switches them to the right hand side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+	pub const CTRL: Self = Self(0x01);
+	pub const SHIFT: Self = Self(0x02);
+	pub const ALT: Self = Self(0x04);
+	pub const GUI: Self = Self(0x08);
+	pub const RIGHT: Self = Self(0x10);
+
+	pub const LCTRL: Self = Self::CTRL;
+	pub const LSHIFT: Self = Self::SHIFT;
+	pub const LALT: Self = Self::ALT;
+	pub const LGUI: Self = Self::GUI;
+	pub const RCTRL: Self = Self::CTRL.union(Self::RIGHT);
+	pub const RSHIFT: Self = Self::SHIFT.union(Self::RIGHT);
+	pub const RALT: Self = Self::ALT.union(Self::RIGHT);
+	pub const RGUI: Self = Self::GUI.union(Self::RIGHT);
+
+	#[must_use]
+	pub const fn from_bits(bits: u8) -> Self {
+		Self(bits & 0x1F)
+	}
+
+	#[must_use]
+	pub const fn bits(self) -> u8 {
+		self.0
+	}
+
+	#[must_use]
+	pub const fn union(self, other: Self) -> Self {
+		Self(self.0 | other.0)
+	}
+
+	/// Checks if the modifiers are the right hand side ones.
+	#[must_use]
+	pub const fn is_right(self) -> bool {
+		self.0 & Self::RIGHT.0 != 0
+	}
+
+	/// The modifiers as the mask used in the first byte of a HID report.
+	#[must_use]
+	pub const fn hid_mask(self) -> u8 {
+		let mods = self.0 & 0x0F;
+
+		if self.is_right() { mods << 4 } else { mods }
+	}
+}
+
+/// Actions that change how the firmware itself behaves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FirmwareAction {
+	/// Switches to the NKRO report.
+	NkroOn = 0x00,
+	/// Switches to the 6KRO report.
+	NkroOff = 0x01,
+	/// Switches between the NKRO and the 6KRO report.
+	NkroToggle = 0x02,
+	/// Restarts the MCU into its bootloader, to flash a new firmware.
+	Bootloader = 0x03,
+	/// Restarts the firmware.
+	Reboot = 0x04,
+}
+
+impl FirmwareAction {
+	#[must_use]
+	pub const fn from_bits(bits: u8) -> Option<Self> {
+		match bits {
+			0x00 => Some(Self::NkroOn),
+			0x01 => Some(Self::NkroOff),
+			0x02 => Some(Self::NkroToggle),
+			0x03 => Some(Self::Bootloader),
+			0x04 => Some(Self::Reboot),
+			_ => None,
+		}
+	}
+}
+
+/// The decoded form of an [`Action`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionKind {
+	/// The key does nothing.
+	No,
+	/// The key falls through to the next active layer.
+	Transparent,
+	/// A plain HID keycode.
+	Key(NonZeroU8),
+	/// A HID keycode sent together with the modifiers.
+	ModifiedKey { modifiers: Modifiers, keycode: NonZeroU8 },
+	/// Only modifiers, without any keycode.
+	Modifiers(Modifiers),
+	/// Holds the modifiers while the key is held, sends the keycode when tapped.
+	ModTap { modifiers: Modifiers, keycode: NonZeroU8 },
+	/// An action performed on the layer stack.
+	Layer(LayerAction),
+	/// A usage from the Consumer page.
+	Consumer(u16),
+	/// A macro, referenced by its index.
+	Macro(u8),
+	/// A custom action, handled by the firmware.
+	Custom(u8),
+	/// A System Control usage from the Generic Desktop page.
+	System(u8),
+	/// An action that changes how the firmware behaves.
+	Firmware(FirmwareAction),
+}
+
+/// The action performed by a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Action(u16);
+
+impl Action {
+	/// The key does nothing.
+	pub const NO: Self = Self(0x0000);
+	/// The key falls through to the next active layer.
+	pub const TRANSPARENT: Self = Self(0x0001);
+
+	/// Creates an action from its raw representation.
+	#[must_use]
+	pub const fn from_bits(bits: u16) -> Self {
+		Self(bits)
+	}
+
+	/// The raw representation of the action, as stored in a keymap.
+	#[must_use]
+	pub const fn to_bits(self) -> u16 {
+		self.0
+	}
+
+	#[must_use]
+	pub const fn is_no(self) -> bool {
+		self.0 == Self::NO.0
+	}
+
+	#[must_use]
+	pub const fn is_transparent(self) -> bool {
+		self.0 == Self::TRANSPARENT.0
+	}
+
+	const fn checked_layer(layer: u8) -> u16 {
+		assert!((layer as usize) < LAYER_COUNT, "Layer index out of bounds.");
+
+		layer as u16
+	}
+
+	/// A plain keycode.
+	#[must_use]
+	pub const fn key(keycode: NonZeroU8) -> Self {
+		Self(keycode.get() as u16)
+	}
+
+	/// Wraps a keycode in modifiers. Wrapping an already modified keycode adds the modifiers.
+	///
+	/// # Panics
+	///
+	/// Panics if the action is not a keycode, or if it already has modifiers of the other side, as
+	/// only one side can be stored.
+	#[must_use]
+	pub const fn with_modifiers(self, modifiers: Modifiers) -> Self {
+		assert!(
+			self.0 > 0x0003 && self.0 <= MODIFIED_KEY_END,
+			"Only keycodes can be wrapped in modifiers."
+		);
+
+		#[allow(clippy::cast_possible_truncation, reason = "The keycode is shifted out.")]
+		let current = Modifiers::from_bits((self.0 >> 8) as u8);
+
+		assert!(
+			current.bits() == 0 || current.is_right() == modifiers.is_right(),
+			"Left and right modifiers can't be mixed."
+		);
+
+		Self(self.0 | ((modifiers.bits() as u16) << 8))
+	}
+
+	/// Presses only the modifiers.
+	#[must_use]
+	pub const fn modifiers(modifiers: Modifiers) -> Self {
+		Self((modifiers.bits() as u16) << 8)
+	}
+
+	/// Holds the modifiers while held, sends the keycode when tapped.
+	#[must_use]
+	pub const fn mod_tap(modifiers: Modifiers, keycode: NonZeroU8) -> Self {
+		Self(MOD_TAP | ((modifiers.bits() as u16) << 8) | keycode.get() as u16)
+	}
+
+	/// Checks that the layer of a layer action is inside the [`LAYER_COUNT`] bounds.
+	///
+	/// The encoding has room for 16 layers, so actions decoded from raw values can point to layers
+	/// that don't exist.
+	#[must_use]
+	pub const fn has_valid_layer(self) -> bool {
+		match self.kind() {
+			ActionKind::Layer(action) => (action.layer() as usize) < LAYER_COUNT,
+			_ => true,
+		}
+	}
+
+	/// Checks if the key acts differently when tapped and when held.
+	#[must_use]
+	pub const fn is_tap_hold(self) -> bool {
+		matches!(
+			self.kind(),
+			ActionKind::ModTap { .. } | ActionKind::Layer(LayerAction::Tap { .. })
+		)
+	}
+
+	/// Activates the layer while the key is held.
+	///
+	/// # Panics
+	///
+	/// Panics if the layer is outside the [`LAYER_COUNT`] bounds.
+	#[must_use]
+	pub const fn momentary(layer: u8) -> Self {
+		Self(MOMENTARY | Self::checked_layer(layer))
+	}
+
+	/// Toggles the layer when the key is pressed.
+	///
+	/// # Panics
+	///
+	/// Panics if the layer is outside the [`LAYER_COUNT`] bounds.
+	#[must_use]
+	pub const fn toggle(layer: u8) -> Self {
+		Self(TOGGLE | Self::checked_layer(layer))
+	}
+
+	/// Leaves the layer as the only active one, on top of the default layer.
+	///
+	/// # Panics
+	///
+	/// Panics if the layer is outside the [`LAYER_COUNT`] bounds.
+	#[must_use]
+	pub const fn to(layer: u8) -> Self {
+		Self(TO | Self::checked_layer(layer))
+	}
+
+	/// Activates the layer while held, sends the keycode when tapped.
+	///
+	/// # Panics
+	///
+	/// Panics if the layer is outside the [`LAYER_COUNT`] bounds.
+	#[must_use]
+	pub const fn layer_tap(layer: u8, keycode: NonZeroU8) -> Self {
+		Self(LAYER_TAP | (Self::checked_layer(layer) << 8) | keycode.get() as u16)
+	}
+
+	/// A usage from the Consumer page.
+	///
+	/// # Panics
+	///
+	/// Panics if the usage does not fit in 12 bits.
+	#[must_use]
+	pub const fn consumer(usage: u16) -> Self {
+		assert!(usage <= 0x0FFF, "Consumer usage out of bounds.");
+
+		Self(CONSUMER | usage)
+	}
+
+	/// Plays the macro with the given index.
+	#[must_use]
+	pub const fn macro_action(index: u8) -> Self {
+		Self(MACRO | index as u16)
+	}
+
+	/// A custom action handled by the firmware.
+	#[must_use]
+	pub const fn custom(id: u8) -> Self {
+		Self(CUSTOM | id as u16)
+	}
+
+	/// A System Control usage from the Generic Desktop page, eg. `0x82` for System Sleep.
+	#[must_use]
+	pub const fn system(usage: u8) -> Self {
+		Self(SYSTEM | usage as u16)
+	}
+
+	/// An action that changes how the firmware behaves.
+	#[must_use]
+	pub const fn firmware(action: FirmwareAction) -> Self {
+		Self(FIRMWARE | action as u16)
+	}
+
+	/// Decodes the action.
+	///
+	/// Values that don't match any encoding are treated as [`ActionKind::No`].
+	#[must_use]
+	#[allow(clippy::cast_possible_truncation, reason = "All values are masked before the casts.")]
+	pub const fn kind(self) -> ActionKind {
+		let bits = self.0;
+
+		let low_byte = (bits & 0xFF) as u8;
+		let layer = (bits & LAYER_MASK) as u8;
+
+		match bits {
+			0x0000 => ActionKind::No,
+			0x0001 => ActionKind::Transparent,
+			0x0004..=0x00FF => match NonZeroU8::new(low_byte) {
+				Some(keycode) => ActionKind::Key(keycode),
+				None => ActionKind::No,
+			},
+			0x0100..=MODIFIED_KEY_END => {
+				let modifiers = Modifiers::from_bits((bits >> 8) as u8);
+
+				match NonZeroU8::new(low_byte) {
+					Some(keycode) => ActionKind::ModifiedKey { modifiers, keycode },
+					None => ActionKind::Modifiers(modifiers),
+				}
+			}
+			MOD_TAP..=MOD_TAP_END => match NonZeroU8::new(low_byte) {
+				Some(keycode) => ActionKind::ModTap {
+					modifiers: Modifiers::from_bits((bits >> 8) as u8),
+					keycode,
+				},
+				None => ActionKind::No,
+			},
+			_ if bits & KIND_MASK == LAYER_TAP => match NonZeroU8::new(low_byte) {
+				Some(keycode) => ActionKind::Layer(LayerAction::Tap {
+					layer: ((bits >> 8) & LAYER_MASK) as u8,
+					keycode,
+				}),
+				None => ActionKind::No,
+			},
+			_ if bits & 0xFFF0 == MOMENTARY => ActionKind::Layer(LayerAction::Momentary(layer)),
+			_ if bits & 0xFFF0 == TOGGLE => ActionKind::Layer(LayerAction::Toggle(layer)),
+			_ if bits & 0xFFF0 == TO => ActionKind::Layer(LayerAction::To(layer)),
+			_ if bits & KIND_MASK == CONSUMER => ActionKind::Consumer(bits & 0x0FFF),
+			_ if bits & 0xFF00 == MACRO => ActionKind::Macro(low_byte),
+			_ if bits & 0xFF00 == CUSTOM => ActionKind::Custom(low_byte),
+			_ if bits & 0xFF00 == SYSTEM => ActionKind::System(low_byte),
+			_ if bits & 0xFF00 == FIRMWARE => match FirmwareAction::from_bits(low_byte) {
+				Some(action) => ActionKind::Firmware(action),
+				None => ActionKind::No,
+			},
+			_ => ActionKind::No,
+		}
+	}
+}
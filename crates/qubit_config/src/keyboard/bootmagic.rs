@@ -0,0 +1,98 @@
+use super::Keymap;
+
+/// A key of the matrix, by its row and column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "std", serde(deny_unknown_fields))]
+pub struct KeyPosition {
+	pub row: usize,
+	pub col: usize,
+}
+
+impl KeyPosition {
+	#[must_use]
+	pub const fn new(row: usize, col: usize) -> Self {
+		Self { row, col }
+	}
+}
+
+/// What the keys held at power on ask for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootmagicAction {
+	/// Clears the stored settings and keymaps, going back to the ones the firmware was built with.
+	ClearStorage,
+	/// Restarts into the bootloader, to flash a new firmware.
+	Bootloader,
+}
+
+/// The keys checked once at power on, before USB starts, to recover a board whose keymaps or
+/// settings lock the user out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bootmagic {
+	/// Held alone, clears the stored settings and keymaps.
+	pub clear: KeyPosition,
+	/// Held together with the `clear` key, restarts into the bootloader instead.
+	pub bootloader: Option<KeyPosition>,
+}
+
+impl Bootmagic {
+	#[must_use]
+	pub const fn new(clear: KeyPosition) -> Self {
+		Self {
+			clear,
+			bootloader: None,
+		}
+	}
+
+	#[must_use]
+	pub const fn with_bootloader(self, bootloader: KeyPosition) -> Self {
+		Self {
+			bootloader: Some(bootloader),
+			..self
+		}
+	}
+
+	/// Uses the top left key of the keymap, and the key after it for the bootloader.
+	///
+	/// # Panics
+	///
+	/// Panics if the keymap has no keys.
+	#[must_use]
+	pub const fn top_left<const R: usize, const C: usize>(keymap: &Keymap<R, C>) -> Self {
+		let mut keys = [None; 2];
+		let mut found = 0;
+
+		let mut i = 0;
+		while i < R && found < keys.len() {
+			let mut j = 0;
+			while j < C && found < keys.len() {
+				if !keymap.0[i][j].is_no() {
+					keys[found] = Some(KeyPosition::new(i, j));
+					found += 1;
+				}
+
+				j += 1;
+			}
+
+			i += 1;
+		}
+
+		match keys {
+			[Some(clear), bootloader] => Self { clear, bootloader },
+			[None, _] => panic!("Bootmagic needs a keymap with at least one key."),
+		}
+	}
+
+	/// The action asked for by the held keys.
+	pub fn action(&self, is_held: impl Fn(KeyPosition) -> bool) -> Option<BootmagicAction> {
+		if !is_held(self.clear) {
+			return None;
+		}
+
+		if self.bootloader.is_some_and(is_held) {
+			Some(BootmagicAction::Bootloader)
+		} else {
+			Some(BootmagicAction::ClearStorage)
+		}
+	}
+}
@@ -0,0 +1,138 @@
+//! Filters the switch chatter out of the raw matrix scans.
+//!
+//! Like the tap-hold resolution, the [`Debouncer`] doesn't read any clock by itself and gets the
+//! time of every scan instead.
+
+use super::key_bit;
+
+/// The algorithm used to filter the switch chatter out of the matrix scans.
+///
+/// The durations are in milliseconds. The matrix is only scanned once every scan period, so the
+/// effective duration is rounded up to a multiple of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(
+	feature = "std",
+	serde(tag = "algorithm", rename_all = "snake_case", deny_unknown_fields)
+)]
+pub enum Debounce {
+	/// Waits until the whole matrix stops changing for `ms` before reporting any change.
+	SymmetricDefer { ms: u16 },
+	/// Reports every change of a key right away, then ignores that key for `ms`.
+	EagerPerKey { ms: u16 },
+	/// Reports presses right away and ignores the key for `press_ms` after them. Releases are only
+	/// reported once the key stays released for `release_ms`.
+	Asymmetric { press_ms: u16, release_ms: u16 },
+}
+
+/// Filters the switch chatter out of the raw matrix scans, with the algorithm of a [`Debounce`].
+///
+/// The scans are bitmaps of `K` keys, indexed like the packed keymap, in `W` words.
+#[derive(Debug)]
+pub struct Debouncer<const K: usize, const W: usize> {
+	algorithm: Debounce,
+	/// The debounced state of the keys.
+	state: [usize; W],
+	/// The last raw scan, used by the symmetric algorithm.
+	last_raw: [usize; W],
+	/// When the raw scan last changed, used by the symmetric algorithm.
+	changed_at: u32,
+	/// Keys that ignore any change until their lockout runs out.
+	locked: [usize; W],
+	/// Keys waiting for their release to settle.
+	deferred: [usize; W],
+	/// When the lockout or the deferral of each key started.
+	timers: [u32; K],
+}
+
+impl<const K: usize, const W: usize> Debouncer<K, W> {
+	/// # Panics
+	///
+	/// Panics if `W` isn't the number of words that hold a bit for each of the `K` keys.
+	#[must_use]
+	pub const fn new(algorithm: Debounce) -> Self {
+		assert!(
+			W == K.div_ceil(usize::BITS as usize),
+			"The bitmaps need a bit for every key."
+		);
+
+		Self {
+			algorithm,
+			state: [0; W],
+			last_raw: [0; W],
+			changed_at: 0,
+			locked: [0; W],
+			deferred: [0; W],
+			timers: [0; K],
+		}
+	}
+
+	/// Takes a raw scan of the matrix made at `now` and returns the debounced pressed keys.
+	pub fn debounce(&mut self, raw: [usize; W], now: u32) -> [usize; W] {
+		match self.algorithm {
+			Debounce::SymmetricDefer { ms } => self.symmetric_defer(raw, now, ms),
+			Debounce::EagerPerKey { ms } => self.per_key(raw, now, ms, None),
+			Debounce::Asymmetric { press_ms, release_ms } => self.per_key(raw, now, press_ms, Some(release_ms)),
+		}
+
+		self.state
+	}
+
+	fn symmetric_defer(&mut self, raw: [usize; W], now: u32, ms: u16) {
+		if raw != self.last_raw {
+			self.last_raw = raw;
+			self.changed_at = now;
+		}
+
+		if now.wrapping_sub(self.changed_at) >= u32::from(ms) {
+			self.state = raw;
+		}
+	}
+
+	/// Debounces every key on its own. Changes are applied right away and lock the key for
+	/// `lockout`, unless `release_defer` is set, in which case releases wait for it instead.
+	fn per_key(&mut self, raw: [usize; W], now: u32, lockout: u16, release_defer: Option<u16>) {
+		for index in 0..K {
+			let (word, mask) = key_bit(index);
+
+			if self.locked[word] & mask != 0 {
+				if now.wrapping_sub(self.timers[index]) < u32::from(lockout) {
+					continue;
+				}
+
+				self.locked[word] &= !mask;
+			}
+
+			let is_pressed = self.state[word] & mask != 0;
+
+			if (raw[word] & mask != 0) == is_pressed {
+				// The key bounced back before its release settled.
+				self.deferred[word] &= !mask;
+
+				continue;
+			}
+
+			if let Some(defer) = release_defer
+				&& is_pressed
+			{
+				if self.deferred[word] & mask == 0 {
+					self.deferred[word] |= mask;
+					self.timers[index] = now;
+				}
+
+				if now.wrapping_sub(self.timers[index]) < u32::from(defer) {
+					continue;
+				}
+
+				self.deferred[word] &= !mask;
+				self.state[word] &= !mask;
+
+				continue;
+			}
+
+			self.state[word] ^= mask;
+			self.locked[word] |= mask;
+			self.timers[index] = now;
+		}
+	}
+}
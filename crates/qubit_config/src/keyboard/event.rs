@@ -0,0 +1,124 @@
+//! The events a key change goes through, from the matrix scan to the reports sent to the host.
+
+use core::mem;
+
+use heapless::Deque;
+
+use super::{Action, key_bit};
+
+/// A key changing state, as seen by the matrix scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+	/// The index of the key inside the packed keymap.
+	pub index: usize,
+	pub pressed: bool,
+	/// The time of the event in milliseconds.
+	pub time: u32,
+}
+
+/// A key event after the tap-hold keys are resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolvedEvent {
+	Press { index: usize, action: Action },
+	Release { index: usize },
+}
+
+/// Where the resolved events end up.
+pub trait EventSink {
+	/// Looks up the action of the key using the current layer state.
+	fn action(&self, index: usize) -> Action;
+
+	/// Handles a resolved event.
+	fn emit(&mut self, event: ResolvedEvent);
+}
+
+/// Queues an event for every key that changed between the two scans.
+///
+/// Keys that don't fit in the queue keep their previous state in `prev`, so they are picked up
+/// again by the next diff.
+pub fn diff_scans<const W: usize, const Q: usize>(
+	prev: &mut [usize; W],
+	current: [usize; W],
+	time: u32,
+	events: &mut Deque<KeyEvent, Q>,
+) {
+	const USIZE_BITS: usize = usize::BITS as usize;
+
+	for (word, bitmap) in current.into_iter().enumerate() {
+		let mut changed = bitmap ^ prev[word];
+
+		while changed != 0 {
+			let index = word * USIZE_BITS + changed.trailing_zeros() as usize;
+			let (_, mask) = key_bit(index);
+
+			let event = KeyEvent {
+				index,
+				pressed: bitmap & mask != 0,
+				time,
+			};
+
+			if events.push_back(event).is_err() {
+				return;
+			}
+
+			prev[word] ^= mask;
+
+			// Clear the bit
+			changed &= !mask;
+		}
+	}
+}
+
+/// The reports waiting to be sent to the host, oldest first.
+///
+/// `R` is an enum with a variant for every kind of report.
+#[derive(Debug)]
+pub struct ReportQueue<R, const N: usize> {
+	reports: Deque<R, N>,
+}
+
+impl<R, const N: usize> ReportQueue<R, N> {
+	#[must_use]
+	pub const fn new() -> Self {
+		Self { reports: Deque::new() }
+	}
+
+	/// Queues a report.
+	///
+	/// When the host falls behind, the newest report replaces the last queued one of the same kind
+	/// so the final state is never lost. If there is none, the oldest report is dropped instead.
+	pub fn push(&mut self, report: R) {
+		if let Err(report) = self.reports.push_back(report) {
+			let same_kind = self
+				.reports
+				.iter_mut()
+				.rev()
+				.find(|queued| mem::discriminant(*queued) == mem::discriminant(&report));
+
+			if let Some(queued) = same_kind {
+				*queued = report;
+			} else {
+				self.reports.pop_front();
+
+				_ = self.reports.push_back(report);
+			}
+		}
+	}
+
+	/// The next report waiting to be sent.
+	#[must_use]
+	pub fn front(&self) -> Option<&R> {
+		self.reports.front()
+	}
+
+	/// Removes the next report from the queue, once it was sent.
+	pub fn pop_front(&mut self) -> Option<R> {
+		self.reports.pop_front()
+	}
+}
+
+impl<R, const N: usize> Default for ReportQueue<R, N> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
@@ -0,0 +1,20 @@
+/// A lock the host reports through the LED output report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "snake_case"))]
+#[repr(u8)]
+pub enum LockIndicator {
+	NumLock = 0,
+	CapsLock = 1,
+	ScrollLock = 2,
+	Compose = 3,
+	Kana = 4,
+}
+
+impl LockIndicator {
+	/// The bit of the lock inside the LED output report.
+	#[must_use]
+	pub const fn mask(self) -> u8 {
+		1 << self as u8
+	}
+}
@@ -436,3 +436,182 @@ pub const KC_M_SLEEP: NonZeroU8 = NonZeroU8::new(0xF8).unwrap();
 pub const KC_M_COFFEE: NonZeroU8 = NonZeroU8::new(0xF9).unwrap();
 pub const KC_M_REFRESH: NonZeroU8 = NonZeroU8::new(0xFA).unwrap();
 pub const KC_M_CALC: NonZeroU8 = NonZeroU8::new(0xFB).unwrap();
+
+/// The scan codes by the names of their constants, as used in the keymaps.
+pub const NAMES: &[(&str, NonZeroU8)] = &[
+	("KC_ERR_OVF", KC_ERR_OVF),
+	("KC_A", KC_A),
+	("KC_B", KC_B),
+	("KC_C", KC_C),
+	("KC_D", KC_D),
+	("KC_E", KC_E),
+	("KC_F", KC_F),
+	("KC_G", KC_G),
+	("KC_H", KC_H),
+	("KC_I", KC_I),
+	("KC_J", KC_J),
+	("KC_K", KC_K),
+	("KC_L", KC_L),
+	("KC_M", KC_M),
+	("KC_N", KC_N),
+	("KC_O", KC_O),
+	("KC_P", KC_P),
+	("KC_Q", KC_Q),
+	("KC_R", KC_R),
+	("KC_S", KC_S),
+	("KC_T", KC_T),
+	("KC_U", KC_U),
+	("KC_V", KC_V),
+	("KC_W", KC_W),
+	("KC_X", KC_X),
+	("KC_Y", KC_Y),
+	("KC_Z", KC_Z),
+	("KC_1", KC_1),
+	("KC_2", KC_2),
+	("KC_3", KC_3),
+	("KC_4", KC_4),
+	("KC_5", KC_5),
+	("KC_6", KC_6),
+	("KC_7", KC_7),
+	("KC_8", KC_8),
+	("KC_9", KC_9),
+	("KC_0", KC_0),
+	("KC_ENTER", KC_ENTER),
+	("KC_ESC", KC_ESC),
+	("KC_BACKSPACE", KC_BACKSPACE),
+	("KC_TAB", KC_TAB),
+	("KC_SPACE", KC_SPACE),
+	("KC_MINUS", KC_MINUS),
+	("KC_EQUAL", KC_EQUAL),
+	("KC_LEFTBRACE", KC_LEFTBRACE),
+	("KC_RIGHTBRACE", KC_RIGHTBRACE),
+	("KC_BACKSLASH", KC_BACKSLASH),
+	("KC_HASHTILDE", KC_HASHTILDE),
+	("KC_SEMICOLON", KC_SEMICOLON),
+	("KC_APOSTROPHE", KC_APOSTROPHE),
+	("KC_GRAVE", KC_GRAVE),
+	("KC_COMMA", KC_COMMA),
+	("KC_DOT", KC_DOT),
+	("KC_SLASH", KC_SLASH),
+	("KC_CAPSLOCK", KC_CAPSLOCK),
+	("KC_F1", KC_F1),
+	("KC_F2", KC_F2),
+	("KC_F3", KC_F3),
+	("KC_F4", KC_F4),
+	("KC_F5", KC_F5),
+	("KC_F6", KC_F6),
+	("KC_F7", KC_F7),
+	("KC_F8", KC_F8),
+	("KC_F9", KC_F9),
+	("KC_F10", KC_F10),
+	("KC_F11", KC_F11),
+	("KC_F12", KC_F12),
+	("KC_SYSRQ", KC_SYSRQ),
+	("KC_SCROLLLOCK", KC_SCROLLLOCK),
+	("KC_PAUSE", KC_PAUSE),
+	("KC_INSERT", KC_INSERT),
+	("KC_HOME", KC_HOME),
+	("KC_PAGEUP", KC_PAGEUP),
+	("KC_DELETE", KC_DELETE),
+	("KC_END", KC_END),
+	("KC_PAGEDOWN", KC_PAGEDOWN),
+	("KC_RIGHT", KC_RIGHT),
+	("KC_LEFT", KC_LEFT),
+	("KC_DOWN", KC_DOWN),
+	("KC_UP", KC_UP),
+	("KC_NUMLOCK", KC_NUMLOCK),
+	("KC_KP_SLASH", KC_KP_SLASH),
+	("KC_KP_ASTERISK", KC_KP_ASTERISK),
+	("KC_KP_MINUS", KC_KP_MINUS),
+	("KC_KP_PLUS", KC_KP_PLUS),
+	("KC_KP_ENTER", KC_KP_ENTER),
+	("KC_KP_1", KC_KP_1),
+	("KC_KP_2", KC_KP_2),
+	("KC_KP_3", KC_KP_3),
+	("KC_KP_4", KC_KP_4),
+	("KC_KP_5", KC_KP_5),
+	("KC_KP_6", KC_KP_6),
+	("KC_KP_7", KC_KP_7),
+	("KC_KP_8", KC_KP_8),
+	("KC_KP_9", KC_KP_9),
+	("KC_KP_0", KC_KP_0),
+	("KC_KP_DOT", KC_KP_DOT),
+	("KC_102ND", KC_102ND),
+	("KC_COMPOSE", KC_COMPOSE),
+	("KC_POWER", KC_POWER),
+	("KC_KP_EQUAL", KC_KP_EQUAL),
+	("KC_F13", KC_F13),
+	("KC_F14", KC_F14),
+	("KC_F15", KC_F15),
+	("KC_F16", KC_F16),
+	("KC_F17", KC_F17),
+	("KC_F18", KC_F18),
+	("KC_F19", KC_F19),
+	("KC_F20", KC_F20),
+	("KC_F21", KC_F21),
+	("KC_F22", KC_F22),
+	("KC_F23", KC_F23),
+	("KC_F24", KC_F24),
+	("KC_OPEN", KC_OPEN),
+	("KC_HELP", KC_HELP),
+	("KC_PROPS", KC_PROPS),
+	("KC_FRONT", KC_FRONT),
+	("KC_STOP", KC_STOP),
+	("KC_AGAIN", KC_AGAIN),
+	("KC_UNDO", KC_UNDO),
+	("KC_CUT", KC_CUT),
+	("KC_COPY", KC_COPY),
+	("KC_PASTE", KC_PASTE),
+	("KC_FIND", KC_FIND),
+	("KC_MUTE", KC_MUTE),
+	("KC_VOLUMEUP", KC_VOLUMEUP),
+	("KC_VOLUMEDOWN", KC_VOLUMEDOWN),
+	("KC_KP_COMMA", KC_KP_COMMA),
+	("KC_RO", KC_RO),
+	("KC_KATAKANAHIRAGANA", KC_KATAKANAHIRAGANA),
+	("KC_YEN", KC_YEN),
+	("KC_HENKAN", KC_HENKAN),
+	("KC_MUHENKAN", KC_MUHENKAN),
+	("KC_KPJPCOMMA", KC_KPJPCOMMA),
+	("KC_HANGEUL", KC_HANGEUL),
+	("KC_HANJA", KC_HANJA),
+	("KC_KATAKANA", KC_KATAKANA),
+	("KC_HIRAGANA", KC_HIRAGANA),
+	("KC_ZENKAKUHANKAKU", KC_ZENKAKUHANKAKU),
+	("KC_KP_LEFTPAREN", KC_KP_LEFTPAREN),
+	("KC_KP_RIGHTPAREN", KC_KP_RIGHTPAREN),
+	("KC_LEFTCTRL", KC_LEFTCTRL),
+	("KC_LEFTSHIFT", KC_LEFTSHIFT),
+	("KC_LEFTALT", KC_LEFTALT),
+	("KC_LEFTMETA", KC_LEFTMETA),
+	("KC_RIGHTCTRL", KC_RIGHTCTRL),
+	("KC_RIGHTSHIFT", KC_RIGHTSHIFT),
+	("KC_RIGHTALT", KC_RIGHTALT),
+	("KC_RIGHTMETA", KC_RIGHTMETA),
+	("KC_M_PLAYPAUSE", KC_M_PLAYPAUSE),
+	("KC_M_STOPCD", KC_M_STOPCD),
+	("KC_M_PREVIOUSSONG", KC_M_PREVIOUSSONG),
+	("KC_M_NEXTSONG", KC_M_NEXTSONG),
+	("KC_M_EJECTCD", KC_M_EJECTCD),
+	("KC_M_VOLUMEUP", KC_M_VOLUMEUP),
+	("KC_M_VOLUMEDOWN", KC_M_VOLUMEDOWN),
+	("KC_M_MUTE", KC_M_MUTE),
+	("KC_M_WWW", KC_M_WWW),
+	("KC_M_BACK", KC_M_BACK),
+	("KC_M_FORWARD", KC_M_FORWARD),
+	("KC_M_STOP", KC_M_STOP),
+	("KC_M_FIND", KC_M_FIND),
+	("KC_M_SCROLLUP", KC_M_SCROLLUP),
+	("KC_M_SCROLLDOWN", KC_M_SCROLLDOWN),
+	("KC_M_EDIT", KC_M_EDIT),
+	("KC_M_SLEEP", KC_M_SLEEP),
+	("KC_M_COFFEE", KC_M_COFFEE),
+	("KC_M_REFRESH", KC_M_REFRESH),
+	("KC_M_CALC", KC_M_CALC),
+];
+
+/// Looks up a scan code by the name of its constant, e.g. `KC_ESC`.
+#[must_use]
+pub fn from_name(name: &str) -> Option<NonZeroU8> {
+	NAMES.iter().find(|(key, _)| *key == name).map(|&(_, keycode)| keycode)
+}
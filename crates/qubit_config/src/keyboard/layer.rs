@@ -0,0 +1,31 @@
+//! Layer related definitions.
+//!
+//! See [`Action`](super::Action) for how the layer keys are encoded inside a keymap.
+
+use core::num::NonZeroU8;
+
+/// The number of layers stored for each keyboard.
+pub const LAYER_COUNT: usize = 5;
+
+/// Actions performed on the layer stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerAction {
+	/// Activates the layer while the key is held.
+	Momentary(u8),
+	/// Toggles the layer on or off when the key is pressed.
+	Toggle(u8),
+	/// Activates the layer and deactivates every other layer, except the default one.
+	To(u8),
+	/// Activates the layer while the key is held, sends the keycode when tapped.
+	Tap { layer: u8, keycode: NonZeroU8 },
+}
+
+impl LayerAction {
+	/// The layer the action acts on.
+	#[must_use]
+	pub const fn layer(self) -> u8 {
+		match self {
+			Self::Momentary(layer) | Self::Toggle(layer) | Self::To(layer) | Self::Tap { layer, .. } => layer,
+		}
+	}
+}
@@ -0,0 +1,252 @@
+//! Tap-hold keys (mod-tap and layer-tap), which act differently when tapped and when held.
+//!
+//! The [`TapHoldEngine`] doesn't read any clock by itself, every event carries the time it happened
+//! at and [`TapHoldEngine::tick`] is called with the current time. This keeps the resolution logic
+//! independent of the hardware timers.
+
+use heapless::Deque;
+
+use super::event::{EventSink, KeyEvent, ResolvedEvent};
+use super::layer::{LAYER_COUNT, LayerAction};
+use super::{Action, ActionKind};
+
+/// Decides when a tap-hold key is resolved as held before the tapping term runs out.
+///
+/// A key is always resolved as tapped when released before the tapping term and no other rule
+/// applies, and as held once the tapping term is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "snake_case"))]
+pub enum TapHoldFlavor {
+	/// Only the tapping term decides between a tap and a hold.
+	Default,
+	/// Resolved as held when another key is pressed and released while the key is held.
+	PermissiveHold,
+	/// Resolved as held as soon as another key is pressed.
+	HoldOnOtherKeyPress,
+}
+
+/// A tapping term that only applies to the key at the given position.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "std", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "std", serde(deny_unknown_fields))]
+pub struct KeyTappingTerm {
+	pub row: usize,
+	pub col: usize,
+	/// The tapping term in milliseconds.
+	pub tapping_term: u16,
+}
+
+impl KeyTappingTerm {
+	#[must_use]
+	pub const fn new(row: usize, col: usize, tapping_term: u16) -> Self {
+		Self { row, col, tapping_term }
+	}
+}
+
+/// The tap-hold settings of a keyboard.
+#[derive(Debug, Clone, Copy)]
+pub struct TapHold {
+	/// The time in milliseconds a key needs to be held before it's resolved as held.
+	pub tapping_term: u16,
+	pub flavor: TapHoldFlavor,
+	/// Keys that use a different tapping term than the global one.
+	pub per_key: &'static [KeyTappingTerm],
+}
+
+impl TapHold {
+	#[must_use]
+	pub const fn new(tapping_term: u16, flavor: TapHoldFlavor) -> Self {
+		Self {
+			tapping_term,
+			flavor,
+			per_key: &[],
+		}
+	}
+
+	#[must_use]
+	pub const fn with_per_key(self, per_key: &'static [KeyTappingTerm]) -> Self {
+		Self { per_key, ..self }
+	}
+}
+
+/// The maximum number of key events held back while a tap-hold key is undecided.
+const BUFFER_LEN: usize = 16;
+
+/// The action sent when the key is tapped.
+fn tap_action(action: Action) -> Action {
+	match action.kind() {
+		ActionKind::ModTap { keycode, .. } | ActionKind::Layer(LayerAction::Tap { keycode, .. }) => {
+			Action::key(keycode)
+		}
+		_ => action,
+	}
+}
+
+/// The action sent when the key is held.
+///
+/// The host can store layer-taps of layers that don't exist, which do nothing when held.
+fn hold_action(action: Action) -> Action {
+	match action.kind() {
+		ActionKind::ModTap { modifiers, .. } => Action::modifiers(modifiers),
+		ActionKind::Layer(LayerAction::Tap { layer, .. }) if usize::from(layer) < LAYER_COUNT => {
+			Action::momentary(layer)
+		}
+		ActionKind::Layer(LayerAction::Tap { .. }) => Action::NO,
+		_ => action,
+	}
+}
+
+/// A tap-hold key that was pressed but not yet resolved.
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+	index: usize,
+	action: Action,
+	pressed_at: u32,
+	tapping_term: u32,
+}
+
+/// Resolves tap-hold keys into their tap or hold action, holding back the events of the other keys
+/// while one is undecided.
+#[derive(Debug)]
+pub struct TapHoldEngine {
+	flavor: TapHoldFlavor,
+	/// The tapping term of every key, indexed the same way as the packed keymap.
+	tapping_terms: &'static [u16],
+	pending: Option<Pending>,
+	/// Events that happened while a key is pending, replayed once it's resolved.
+	buffer: Deque<KeyEvent, BUFFER_LEN>,
+}
+
+impl TapHoldEngine {
+	#[must_use]
+	pub const fn new(flavor: TapHoldFlavor, tapping_terms: &'static [u16]) -> Self {
+		Self {
+			flavor,
+			tapping_terms,
+			pending: None,
+			buffer: Deque::new(),
+		}
+	}
+
+	/// Resolves the pending key as held if its tapping term ran out.
+	pub fn tick(&mut self, now: u32, sink: &mut impl EventSink) {
+		if let Some(pending) = self.pending
+			&& now.wrapping_sub(pending.pressed_at) >= pending.tapping_term
+		{
+			self.resolve_hold(sink);
+		}
+	}
+
+	/// Processes a key event coming from the matrix.
+	pub fn process(&mut self, event: KeyEvent, sink: &mut impl EventSink) {
+		let Some(pending) = self.pending else {
+			self.handle(event, sink);
+
+			return;
+		};
+
+		if event.index == pending.index {
+			if event.pressed {
+				// The key can't be pressed twice, ignore it.
+				return;
+			}
+
+			if event.time.wrapping_sub(pending.pressed_at) < pending.tapping_term {
+				self.resolve_tap(sink);
+			} else {
+				self.resolve_hold(sink);
+			}
+
+			sink.emit(ResolvedEvent::Release { index: event.index });
+
+			return;
+		}
+
+		if event.pressed {
+			if self.flavor == TapHoldFlavor::HoldOnOtherKeyPress {
+				self.resolve_hold(sink);
+				self.handle(event, sink);
+			} else if let Err(event) = self.buffer.push_back(event) {
+				// There is no room left to wait, so settle for a hold.
+				self.resolve_hold(sink);
+				self.handle(event, sink);
+			}
+
+			return;
+		}
+
+		let pressed_after = self.buffer.iter().any(|e| e.index == event.index && e.pressed);
+
+		if !pressed_after {
+			// The key was held before the tap-hold key, its release doesn't depend on it.
+			self.handle(event, sink);
+		} else if self.flavor == TapHoldFlavor::PermissiveHold {
+			self.resolve_hold(sink);
+			self.handle(event, sink);
+		} else if let Err(event) = self.buffer.push_back(event) {
+			self.resolve_hold(sink);
+			self.handle(event, sink);
+		}
+	}
+
+	/// Handles an event while no key is pending.
+	fn handle(&mut self, event: KeyEvent, sink: &mut impl EventSink) {
+		if !event.pressed {
+			sink.emit(ResolvedEvent::Release { index: event.index });
+
+			return;
+		}
+
+		let action = sink.action(event.index);
+
+		if action.is_tap_hold() {
+			let tapping_term = self.tapping_terms.get(event.index).copied().unwrap_or_default();
+
+			self.pending = Some(Pending {
+				index: event.index,
+				action,
+				pressed_at: event.time,
+				tapping_term: u32::from(tapping_term),
+			});
+		} else {
+			sink.emit(ResolvedEvent::Press {
+				index: event.index,
+				action,
+			});
+		}
+	}
+
+	fn resolve_tap(&mut self, sink: &mut impl EventSink) {
+		if let Some(pending) = self.pending.take() {
+			sink.emit(ResolvedEvent::Press {
+				index: pending.index,
+				action: tap_action(pending.action),
+			});
+
+			self.replay(sink);
+		}
+	}
+
+	fn resolve_hold(&mut self, sink: &mut impl EventSink) {
+		if let Some(pending) = self.pending.take() {
+			sink.emit(ResolvedEvent::Press {
+				index: pending.index,
+				action: hold_action(pending.action),
+			});
+
+			self.replay(sink);
+		}
+	}
+
+	/// Processes the buffered events again, now that the pending key is resolved.
+	fn replay(&mut self, sink: &mut impl EventSink) {
+		for _ in 0..self.buffer.len() {
+			let Some(event) = self.buffer.pop_front() else {
+				break;
+			};
+
+			self.process(event, sink);
+		}
+	}
+}
@@ -3,6 +3,7 @@
 
 #[cfg(feature = "build")]
 pub mod cargo;
+pub mod crc;
 pub mod general;
 pub mod keyboard;
 #[cfg(feature = "build")]
@@ -10,5 +11,11 @@ pub mod linker;
 pub mod mcu;
 #[cfg(feature = "std")]
 pub mod parse;
+pub mod section;
+pub mod silverplate;
+pub mod storage;
+#[cfg(feature = "std")]
+pub mod uf2;
 pub mod usb;
 pub mod version;
+pub mod via;
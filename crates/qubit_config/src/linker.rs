@@ -1,23 +1,26 @@
-use crate::general::{Configuration, Device};
+use crate::general::Device;
 use crate::mcu::Mcu;
+use crate::section::CONFIGURATION_LEN;
+use crate::storage::StorageLayout;
 
 mod family;
 mod mcu;
 
+/// Outputs the linker script, with `device_config_size` bytes for the section of the device, e.g.
+/// the [`keyboard_len`](crate::section::keyboard_len) of a keyboard.
+///
 /// # Panics
 ///
-/// Will panic if the [`Configuration`](qubit_config::general::Configuration) size is outside
-/// the range of u32. This should never happen though.
+/// Will panic if a section size is outside the range of u32. This should never happen though.
 #[must_use]
-pub fn output_linker_script<T>(mcu: Mcu, flash: u32, device: Device) -> String {
-	let config_size = std::mem::size_of::<Configuration>();
-	let config_size = u32::try_from(config_size).unwrap();
-
-	let device_config_size = std::mem::size_of::<T>();
+pub fn output_linker_script(mcu: Mcu, flash: u32, device: Device, device_config_size: usize) -> String {
+	let config_size = u32::try_from(CONFIGURATION_LEN).unwrap();
 	let device_config_size = u32::try_from(device_config_size).unwrap();
 
+	let storage = StorageLayout::new(mcu, flash);
+
 	match mcu {
-		Mcu::RP2040 => mcu::rp2040::linker_layout(flash, device, config_size, device_config_size),
-		Mcu::STM32F411 => mcu::stm32f411::linker_layout(flash, device, config_size, device_config_size),
+		Mcu::RP2040 => mcu::rp2040::linker_layout(storage, device, config_size, device_config_size),
+		Mcu::STM32F411 => mcu::stm32f411::linker_layout(storage, device, config_size, device_config_size),
 	}
 }
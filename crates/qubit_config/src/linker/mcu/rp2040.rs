@@ -1,5 +1,6 @@
 use crate::general::Device;
 use crate::linker;
+use crate::storage::StorageLayout;
 use linker::family::cortex_m::{MemoryRegion, MemorySpace, Permissions, Section};
 
 const BOOT2_ORIGIN: u32 = 0x1000_0000;
@@ -10,9 +11,10 @@ const RAM_LENGTH: u32 = 0x42000;
 
 const BOO2_EXTERN_DEF: &str = "EXTERN(BOOT2_FIRMWARE)";
 
-pub fn linker_layout(flash_size: u32, device: Device, config_size: u32, dev_config_size: u32) -> String {
+pub fn linker_layout(storage: StorageLayout, device: Device, config_size: u32, dev_config_size: u32) -> String {
 	let remaining_flash_size = {
-		let size = flash_size;
+		// Everything up to the storage, which takes the last sectors of the flash.
+		let size = storage.origin.strict_sub(BOOT2_ORIGIN);
 		let size = size.strict_sub(BOOT2_LENGTH);
 		let size = size.strict_sub(config_size);
 
@@ -41,6 +43,8 @@ pub fn linker_layout(flash_size: u32, device: Device, config_size: u32, dev_conf
 		dev_config_size,
 	);
 
+	let storage = MemoryRegion::new("STORAGE", Permissions::read_only(), storage.origin, storage.length());
+
 	let ram = MemoryRegion::new_ram(Permissions::read_write(), RAM_ORIGIN, RAM_LENGTH);
 
 	let mut mem_space = MemorySpace::new(flash, ram);
@@ -48,7 +52,7 @@ pub fn linker_layout(flash_size: u32, device: Device, config_size: u32, dev_conf
 	mem_space.before_flash_regions.extend_from_slice(&[boot2]);
 	mem_space
 		.after_flash_regions
-		.extend_from_slice(&[config, device_config]);
+		.extend_from_slice(&[config, device_config, storage]);
 
 	crate::linker::family::cortex_m::mem_x(
 		&mem_space,
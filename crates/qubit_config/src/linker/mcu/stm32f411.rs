@@ -1,5 +1,6 @@
 use crate::general::Device;
 use crate::linker::family::cortex_m;
+use crate::storage::StorageLayout;
 
 use cortex_m::{MemoryRegion, MemorySpace, Permissions, Section};
 
@@ -8,9 +9,10 @@ const FLASH_ORIGIN: u32 = 0x0800_0000;
 const RAM_ORIGIN: u32 = 0x2000_0000;
 const RAM_LENGTH: u32 = 0x20000;
 
-pub fn linker_layout(flash_size: u32, device: Device, config_size: u32, dev_config_size: u32) -> String {
+pub fn linker_layout(storage: StorageLayout, device: Device, config_size: u32, dev_config_size: u32) -> String {
 	let remaining_flash_size = {
-		let size = flash_size;
+		// Everything up to the storage, which takes the last sectors of the flash.
+		let size = storage.origin.strict_sub(FLASH_ORIGIN);
 		let size = size.strict_sub(config_size);
 
 		size.strict_sub(dev_config_size)
@@ -32,13 +34,15 @@ pub fn linker_layout(flash_size: u32, device: Device, config_size: u32, dev_conf
 		dev_config_size,
 	);
 
+	let storage = MemoryRegion::new("STORAGE", Permissions::read_only(), storage.origin, storage.length());
+
 	let ram = MemoryRegion::new_ram(Permissions::read_write(), RAM_ORIGIN, RAM_LENGTH);
 
 	let mut mem_space = MemorySpace::new(flash, ram);
 
 	mem_space
 		.after_flash_regions
-		.extend_from_slice(&[config, device_config]);
+		.extend_from_slice(&[config, device_config, storage]);
 
 	let mut mem_x = String::new();
 
@@ -3,7 +3,11 @@ use core::str::FromStr;
 #[cfg(feature = "std")]
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
+pub mod pins;
+
+pub use pins::{Pin, Reserved};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(Deserialize, Serialize))]
 pub enum Mcu {
 	RP2040,
@@ -27,6 +31,50 @@ impl Mcu {
 		}
 	}
 
+	/// The address the flash memory is mapped at.
+	#[must_use]
+	pub const fn flash_origin(&self) -> u32 {
+		match self {
+			Self::RP2040 => 0x1000_0000,
+			Self::STM32F411 => 0x0800_0000,
+		}
+	}
+
+	/// Every GPIO pin of the MCU.
+	#[must_use]
+	pub const fn pins(&self) -> &'static [Pin] {
+		match self {
+			Self::RP2040 => pins::RP2040,
+			Self::STM32F411 => pins::STM32F411,
+		}
+	}
+
+	/// Finds a GPIO pin of the MCU by name, e.g. `"25"` on the RP2040 or `"B12"` on the STM32F411.
+	#[must_use]
+	pub fn pin(&self, name: &str) -> Option<&'static Pin> {
+		self.pins().iter().find(|pin| pin.name == name)
+	}
+
+	/// Checks if the name is a GPIO pin of the MCU.
+	#[must_use]
+	pub fn has_pin(&self, name: &str) -> bool {
+		self.pin(name).is_some()
+	}
+
+	/// Finds a pin a device can use.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the MCU has no such pin or it's reserved.
+	pub fn usable_pin(&self, name: &str) -> Result<&'static Pin, PinError> {
+		let pin = self.pin(name).ok_or(PinError::Unknown)?;
+
+		match pin.reserved {
+			Some(reserved) => Err(PinError::Reserved(reserved)),
+			None => Ok(pin),
+		}
+	}
+
 	#[must_use]
 	pub const fn target_triple(&self) -> &'static str {
 		match self {
@@ -36,6 +84,13 @@ impl Mcu {
 	}
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinError {
+	/// The MCU has no pin with that name.
+	Unknown,
+	Reserved(Reserved),
+}
+
 #[derive(Debug)]
 pub enum ParseMcuError {
 	InvalidMcu,
@@ -0,0 +1,188 @@
+//! The GPIO pins of every supported MCU.
+//!
+//! The pins are named the way the models write them: by number on the RP2040 and by bank and number
+//! on the STM32F411, e.g. `"25"` and `"B12"`.
+
+/// What a pin is taken by, which keeps it from being used by a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reserved {
+	Usb,
+	/// The debug probe interface.
+	Swd,
+	/// The external flash chip.
+	Flash,
+	/// The external oscillator.
+	Crystal,
+}
+
+impl Reserved {
+	#[must_use]
+	pub const fn as_str(self) -> &'static str {
+		match self {
+			Self::Usb => "USB",
+			Self::Swd => "SWD",
+			Self::Flash => "the flash",
+			Self::Crystal => "the crystal",
+		}
+	}
+}
+
+/// A GPIO pin and what it can be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pin {
+	pub name: &'static str,
+	pub reserved: Option<Reserved>,
+	/// The ADC channel of the pin.
+	pub adc: Option<u8>,
+	/// Whether a timer can output PWM on the pin.
+	pub pwm: bool,
+}
+
+impl Pin {
+	const fn gpio(name: &'static str) -> Self {
+		Self {
+			name,
+			reserved: None,
+			adc: None,
+			pwm: false,
+		}
+	}
+
+	const fn reserved(self, reserved: Reserved) -> Self {
+		Self {
+			reserved: Some(reserved),
+			..self
+		}
+	}
+
+	const fn adc(self, channel: u8) -> Self {
+		Self {
+			adc: Some(channel),
+			..self
+		}
+	}
+
+	const fn pwm(self) -> Self {
+		Self { pwm: true, ..self }
+	}
+}
+
+/// The USB, SWD, crystal and QSPI flash pins of the RP2040 aren't GPIO pins, so none of the GPIO pins
+/// are reserved. Every pin is connected to a PWM slice.
+pub const RP2040: &[Pin] = &[
+	Pin::gpio("0").pwm(),
+	Pin::gpio("1").pwm(),
+	Pin::gpio("2").pwm(),
+	Pin::gpio("3").pwm(),
+	Pin::gpio("4").pwm(),
+	Pin::gpio("5").pwm(),
+	Pin::gpio("6").pwm(),
+	Pin::gpio("7").pwm(),
+	Pin::gpio("8").pwm(),
+	Pin::gpio("9").pwm(),
+	Pin::gpio("10").pwm(),
+	Pin::gpio("11").pwm(),
+	Pin::gpio("12").pwm(),
+	Pin::gpio("13").pwm(),
+	Pin::gpio("14").pwm(),
+	Pin::gpio("15").pwm(),
+	Pin::gpio("16").pwm(),
+	Pin::gpio("17").pwm(),
+	Pin::gpio("18").pwm(),
+	Pin::gpio("19").pwm(),
+	Pin::gpio("20").pwm(),
+	Pin::gpio("21").pwm(),
+	Pin::gpio("22").pwm(),
+	Pin::gpio("23").pwm(),
+	Pin::gpio("24").pwm(),
+	Pin::gpio("25").pwm(),
+	Pin::gpio("26").adc(0).pwm(),
+	Pin::gpio("27").adc(1).pwm(),
+	Pin::gpio("28").adc(2).pwm(),
+	Pin::gpio("29").adc(3).pwm(),
+];
+
+/// The pins of the largest package. PB11 isn't bonded out on any package of the STM32F411.
+pub const STM32F411: &[Pin] = &[
+	Pin::gpio("A0").adc(0).pwm(),
+	Pin::gpio("A1").adc(1).pwm(),
+	Pin::gpio("A2").adc(2).pwm(),
+	Pin::gpio("A3").adc(3).pwm(),
+	Pin::gpio("A4").adc(4),
+	Pin::gpio("A5").adc(5).pwm(),
+	Pin::gpio("A6").adc(6).pwm(),
+	Pin::gpio("A7").adc(7).pwm(),
+	Pin::gpio("A8").pwm(),
+	Pin::gpio("A9").pwm(),
+	Pin::gpio("A10").pwm(),
+	Pin::gpio("A11").reserved(Reserved::Usb).pwm(),
+	Pin::gpio("A12").reserved(Reserved::Usb),
+	Pin::gpio("A13").reserved(Reserved::Swd),
+	Pin::gpio("A14").reserved(Reserved::Swd),
+	Pin::gpio("A15").pwm(),
+	Pin::gpio("B0").adc(8).pwm(),
+	Pin::gpio("B1").adc(9).pwm(),
+	Pin::gpio("B2"),
+	Pin::gpio("B3").pwm(),
+	Pin::gpio("B4").pwm(),
+	Pin::gpio("B5").pwm(),
+	Pin::gpio("B6").pwm(),
+	Pin::gpio("B7").pwm(),
+	Pin::gpio("B8").pwm(),
+	Pin::gpio("B9").pwm(),
+	Pin::gpio("B10").pwm(),
+	Pin::gpio("B12"),
+	Pin::gpio("B13"),
+	Pin::gpio("B14"),
+	Pin::gpio("B15"),
+	Pin::gpio("C0").adc(10),
+	Pin::gpio("C1").adc(11),
+	Pin::gpio("C2").adc(12),
+	Pin::gpio("C3").adc(13),
+	Pin::gpio("C4").adc(14),
+	Pin::gpio("C5").adc(15),
+	Pin::gpio("C6").pwm(),
+	Pin::gpio("C7").pwm(),
+	Pin::gpio("C8").pwm(),
+	Pin::gpio("C9").pwm(),
+	Pin::gpio("C10"),
+	Pin::gpio("C11"),
+	Pin::gpio("C12"),
+	Pin::gpio("C13"),
+	Pin::gpio("C14"),
+	Pin::gpio("C15"),
+	Pin::gpio("D0"),
+	Pin::gpio("D1"),
+	Pin::gpio("D2"),
+	Pin::gpio("D3"),
+	Pin::gpio("D4"),
+	Pin::gpio("D5"),
+	Pin::gpio("D6"),
+	Pin::gpio("D7"),
+	Pin::gpio("D8"),
+	Pin::gpio("D9"),
+	Pin::gpio("D10"),
+	Pin::gpio("D11"),
+	Pin::gpio("D12").pwm(),
+	Pin::gpio("D13").pwm(),
+	Pin::gpio("D14").pwm(),
+	Pin::gpio("D15").pwm(),
+	Pin::gpio("E0"),
+	Pin::gpio("E1"),
+	Pin::gpio("E2"),
+	Pin::gpio("E3"),
+	Pin::gpio("E4"),
+	Pin::gpio("E5").pwm(),
+	Pin::gpio("E6").pwm(),
+	Pin::gpio("E7"),
+	Pin::gpio("E8"),
+	Pin::gpio("E9").pwm(),
+	Pin::gpio("E10"),
+	Pin::gpio("E11").pwm(),
+	Pin::gpio("E12"),
+	Pin::gpio("E13").pwm(),
+	Pin::gpio("E14").pwm(),
+	Pin::gpio("E15"),
+	Pin::gpio("H0").reserved(Reserved::Crystal),
+	Pin::gpio("H1").reserved(Reserved::Crystal),
+];
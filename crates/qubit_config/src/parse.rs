@@ -1,51 +1,139 @@
-use serde::{Deserialize, Deserializer, de};
+use std::path::Path;
+
+use toml::Spanned;
 
 use crate::general::Device;
+use crate::keyboard::layer::LAYER_COUNT;
+use crate::keyboard::{Action, Bootmagic, KeyPosition, LockIndicator};
+use crate::mcu::{Mcu, PinError};
+use crate::usb::Usb;
+
+mod action;
+mod error;
+mod module;
+
+pub use action::{action_name, parse_action};
+pub use error::{Diagnostic, Location, ParseError, ParseErrorKind, Span};
 
 mod raw {
 	use semver::Version;
 	use serde::{Deserialize, Serialize};
+	use toml::Spanned;
 
 	use crate::general::Device;
+	use crate::keyboard::{Debounce, KeyPosition, KeyTappingTerm, LockIndicator, TapHoldFlavor};
 	use crate::mcu::Mcu;
+	use crate::usb::Usb;
 
 	#[derive(Debug, Deserialize, Serialize)]
+	#[serde(deny_unknown_fields)]
 	pub struct Firmware {
 		pub author: String,
 		pub id: String,
-		pub version: Version,
+		pub version: Spanned<Version>,
 		pub device: Device,
 	}
 
 	#[derive(Debug, Deserialize, Serialize)]
+	#[serde(deny_unknown_fields)]
+	pub struct Led {
+		pub pin: Spanned<String>,
+		#[serde(default = "default_indicator")]
+		pub indicator: LockIndicator,
+	}
+
+	#[derive(Debug, Deserialize, Serialize)]
+	#[serde(deny_unknown_fields)]
 	pub struct Keymap {
 		pub rows: usize,
 		pub cols: usize,
-		pub row_pins: Vec<u8>,
-		pub col_pins: Vec<u8>,
-		pub layer0: Vec<Vec<u8>>,
-		pub layer1: Vec<Vec<u8>>,
-		pub layer2: Option<Vec<Vec<u8>>>,
-		pub layer3: Option<Vec<Vec<u8>>>,
-		pub layer4: Option<Vec<Vec<u8>>>,
+		pub row_pins: Spanned<Vec<Spanned<String>>>,
+		pub col_pins: Spanned<Vec<Spanned<String>>>,
+		pub layer0: Layer,
+		pub layer1: Option<Layer>,
+		pub layer2: Option<Layer>,
+		pub layer3: Option<Layer>,
+		pub layer4: Option<Layer>,
 	}
 
+	/// The rows of a layer, with the position of every action in the file.
+	pub type Layer = Spanned<Vec<Spanned<Vec<Spanned<String>>>>>;
+
 	#[derive(Debug, Deserialize, Serialize)]
+	#[serde(deny_unknown_fields)]
+	pub struct TapHold {
+		#[serde(default = "default_tapping_term")]
+		pub tapping_term: u16,
+		#[serde(default = "default_flavor")]
+		pub flavor: TapHoldFlavor,
+		#[serde(default)]
+		pub per_key: Vec<Spanned<KeyTappingTerm>>,
+	}
+
+	impl Default for TapHold {
+		fn default() -> Self {
+			Self {
+				tapping_term: default_tapping_term(),
+				flavor: default_flavor(),
+				per_key: Vec::new(),
+			}
+		}
+	}
+
+	/// The keys missing from the file default to the first keys of `layer0`.
+	#[derive(Debug, Default, Deserialize, Serialize)]
+	#[serde(deny_unknown_fields)]
+	pub struct Bootmagic {
+		pub clear: Option<Spanned<KeyPosition>>,
+		pub bootloader: Option<Spanned<KeyPosition>>,
+	}
+
+	#[derive(Debug, Deserialize, Serialize)]
+	#[serde(deny_unknown_fields)]
 	pub struct Keyboard {
 		pub mcu: Mcu,
+		/// The size of the flash chip, in bytes.
+		pub flash: u32,
+		#[serde(default = "default_debounce")]
+		pub debounce: Debounce,
+		#[serde(default)]
+		pub tap_hold: TapHold,
+		#[serde(default)]
+		pub bootmagic: Bootmagic,
 		pub keymap: Keymap,
 	}
 
 	#[derive(Debug, Deserialize, Serialize)]
+	#[serde(deny_unknown_fields)]
 	pub struct RawConfig {
 		pub name: String,
 		pub firmware: Firmware,
+		pub led: Option<Led>,
+		pub usb: Usb,
 		pub keyboard: Keyboard,
-		pub pins: Vec<usize>,
+	}
+
+	// The defaults match the official models.
+
+	const fn default_indicator() -> LockIndicator {
+		LockIndicator::CapsLock
+	}
+
+	const fn default_tapping_term() -> u16 {
+		200
+	}
+
+	const fn default_flavor() -> TapHoldFlavor {
+		TapHoldFlavor::Default
+	}
+
+	const fn default_debounce() -> Debounce {
+		Debounce::EagerPerKey { ms: 5 }
 	}
 }
 
-mod keyboard {
+pub mod keyboard {
+	use crate::keyboard::{Action, Bootmagic, Debounce, KeyTappingTerm, TapHoldFlavor};
 	use crate::mcu::Mcu;
 
 	#[derive(Debug)]
@@ -54,19 +142,41 @@ mod keyboard {
 		pub rows: usize,
 		/// The number of columns for each row.
 		pub cols: usize,
-		pub row_pins: Vec<u8>,
-		pub col_pins: Vec<u8>,
-		pub layer0: Vec<Vec<u8>>,
-		pub layer1: Vec<Vec<u8>>,
+		pub row_pins: Vec<String>,
+		pub col_pins: Vec<String>,
+		/// Every layer of the keyboard, as rows of actions. The layers missing from the file are
+		/// transparent.
+		pub layers: Vec<Vec<Vec<Action>>>,
+	}
+
+	#[derive(Debug)]
+	pub struct TapHold {
+		/// The time in milliseconds a key needs to be held before it's resolved as held.
+		pub tapping_term: u16,
+		pub flavor: TapHoldFlavor,
+		/// Keys that use a different tapping term than the global one.
+		pub per_key: Vec<KeyTappingTerm>,
 	}
 
 	#[derive(Debug)]
 	pub struct KeyboardConfig {
 		pub mcu: Mcu,
+		/// The size of the flash chip, in bytes.
+		pub flash: u32,
+		pub debounce: Debounce,
+		pub tap_hold: TapHold,
+		pub bootmagic: Bootmagic,
 		pub keymap: Keymap,
 	}
 }
 
+/// The LED of the board and the lock it shows.
+#[derive(Debug)]
+pub struct Led {
+	pub pin: String,
+	pub indicator: LockIndicator,
+}
+
 #[derive(Debug)]
 pub struct TomlConfiguration {
 	/// Tha name of the board.
@@ -79,129 +189,428 @@ pub struct TomlConfiguration {
 	pub version: u32,
 
 	pub device: Device,
+	pub led: Option<Led>,
+	/// The USB vendor and product IDs.
+	pub usb: Usb,
 
 	pub keyboard: keyboard::KeyboardConfig,
 }
 
-impl<'de> Deserialize<'de> for TomlConfiguration {
-	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-		// First deserialize into a raw version before parsing for errors.
-		let raw_config = raw::RawConfig::deserialize(deserializer)?;
+impl TomlConfiguration {
+	/// Parses and checks a configuration.
+	///
+	/// # Errors
+	///
+	/// Returns the error if the text isn't valid TOML or doesn't match the schema. Otherwise returns
+	/// every problem found in the values, like pin counts that don't match the matrix, unknown pins or
+	/// actions.
+	pub fn from_toml(source: &str) -> Result<Self, Vec<Diagnostic>> {
+		let raw_config: raw::RawConfig =
+			toml::from_str(source).map_err(|error| vec![Diagnostic::from_toml(source, &error)])?;
+
+		let mut checker = Checker {
+			source,
+			diagnostics: Vec::new(),
+		};
 
-		// Parse the version, checking if the values are within the supported bounds.
-		if raw_config.firmware.version.pre != semver::Prerelease::EMPTY {
-			return Err(de::Error::custom("Pre-release format not supported."));
+		let config = checker.check(raw_config);
+
+		match config {
+			Some(config) if checker.diagnostics.is_empty() => Ok(config),
+			_ => Err(checker.diagnostics),
 		}
+	}
+}
 
-		if raw_config.firmware.version.build != semver::BuildMetadata::EMPTY {
-			return Err(de::Error::custom("Build metadata format not supported."));
+/// Checks the values of a configuration, collecting every problem before giving up.
+struct Checker<'a> {
+	source: &'a str,
+	diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+	fn error(&mut self, message: impl Into<String>, key: impl Into<String>, span: core::ops::Range<usize>) {
+		self.diagnostics.push(Diagnostic::new(self.source, message, key, span));
+	}
+
+	fn check(&mut self, raw_config: raw::RawConfig) -> Option<TomlConfiguration> {
+		let version = self.check_version(&raw_config.firmware.version);
+
+		let raw_keymap = raw_config.keyboard.keymap;
+		let mcu = raw_config.keyboard.mcu;
+
+		// Check if the rows and cols size match
+		let rows_count = raw_keymap.rows;
+		let cols_count = raw_keymap.cols;
+
+		for (name, pins, count) in [
+			("row_pins", &raw_keymap.row_pins, rows_count),
+			("col_pins", &raw_keymap.col_pins, cols_count),
+		] {
+			if pins.get_ref().len() != count {
+				self.error(
+					format!("Expected {count} pins, got {}", pins.get_ref().len()),
+					format!("keyboard.keymap.{name}"),
+					pins.span(),
+				);
+			}
+		}
+
+		let row_pins = raw_keymap.row_pins.get_ref().iter().enumerate();
+		let col_pins = raw_keymap.col_pins.get_ref().iter().enumerate();
+
+		let pins: Vec<(String, &Spanned<String>)> = row_pins
+			.map(|(index, pin)| (format!("keyboard.keymap.row_pins[{index}]"), pin))
+			.chain(col_pins.map(|(index, pin)| (format!("keyboard.keymap.col_pins[{index}]"), pin)))
+			.chain(raw_config.led.as_ref().map(|led| ("led.pin".to_string(), &led.pin)))
+			.collect();
+
+		self.check_pins(mcu, &pins);
+
+		let layer0_span = raw_keymap.layer0.span();
+
+		let layers = [
+			Some(raw_keymap.layer0),
+			raw_keymap.layer1,
+			raw_keymap.layer2,
+			raw_keymap.layer3,
+			raw_keymap.layer4,
+		];
+
+		let layers = self.check_layers(layers, rows_count, cols_count);
+
+		for (index, key) in raw_config.keyboard.tap_hold.per_key.iter().enumerate() {
+			if key.get_ref().row >= rows_count || key.get_ref().col >= cols_count {
+				self.error(
+					format!(
+						"The tapping term of row {} col {} is outside the {rows_count}x{cols_count} matrix",
+						key.get_ref().row,
+						key.get_ref().col
+					),
+					format!("keyboard.tap_hold.per_key[{index}]"),
+					key.span(),
+				);
+			}
 		}
 
-		let Ok(major) = u16::try_from(raw_config.firmware.version.major) else {
-			return Err(de::Error::custom("Major version out of supported bounds."));
+		let bootmagic = layers
+			.as_ref()
+			.and_then(|layers| self.check_bootmagic(&raw_config.keyboard.bootmagic, &layers[0], layer0_span));
+
+		let keymap = keyboard::Keymap {
+			rows: rows_count,
+			cols: cols_count,
+			row_pins: raw_keymap
+				.row_pins
+				.into_inner()
+				.into_iter()
+				.map(Spanned::into_inner)
+				.collect(),
+			col_pins: raw_keymap
+				.col_pins
+				.into_inner()
+				.into_iter()
+				.map(Spanned::into_inner)
+				.collect(),
+			layers: layers?,
 		};
 
-		let Ok(minor) = u16::try_from(raw_config.firmware.version.minor) else {
-			return Err(de::Error::custom("Minor version out of supported bounds."));
+		let tap_hold = keyboard::TapHold {
+			tapping_term: raw_config.keyboard.tap_hold.tapping_term,
+			flavor: raw_config.keyboard.tap_hold.flavor,
+			per_key: raw_config
+				.keyboard
+				.tap_hold
+				.per_key
+				.into_iter()
+				.map(Spanned::into_inner)
+				.collect(),
 		};
 
-		let Ok(patch) = u16::try_from(raw_config.firmware.version.patch) else {
-			return Err(de::Error::custom("Patch version out of supported bounds."));
+		let keyboard_config = keyboard::KeyboardConfig {
+			mcu,
+			flash: raw_config.keyboard.flash,
+			debounce: raw_config.keyboard.debounce,
+			tap_hold,
+			bootmagic: bootmagic?,
+			keymap,
 		};
 
-		let version = crate::version::Version::new(crate::version::Api::V0, major, minor, patch);
+		Some(TomlConfiguration {
+			name: raw_config.name,
+			author: raw_config.firmware.author,
+			id: raw_config.firmware.id,
+			version: version?.as_bitmap(),
+			device: raw_config.firmware.device,
+			led: raw_config.led.map(|led| Led {
+				pin: led.pin.into_inner(),
+				indicator: led.indicator,
+			}),
+			usb: raw_config.usb,
+			keyboard: keyboard_config,
+		})
+	}
 
-		// Check if the rows and cols size match
-		let rows_count = raw_config.keyboard.keymap.rows;
-		let cols_count = raw_config.keyboard.keymap.cols;
+	/// Checks if the version is within the supported bounds.
+	fn check_version(&mut self, version: &Spanned<semver::Version>) -> Option<crate::version::Version> {
+		const KEY: &str = "firmware.version";
 
-		let row_pins_count = raw_config.keyboard.keymap.row_pins.len();
-		let col_pins_count = raw_config.keyboard.keymap.col_pins.len();
+		let span = version.span();
+		let version = version.get_ref();
 
-		if row_pins_count != rows_count {
-			return Err(de::Error::custom(format!(
-				"Expected {rows_count} row pins, got {row_pins_count}",
-			)));
+		if version.pre != semver::Prerelease::EMPTY {
+			self.error("Pre-release format not supported", KEY, span.clone());
 		}
 
-		if col_pins_count != cols_count {
-			return Err(de::Error::custom(format!(
-				"Expected {cols_count} col pins, got {col_pins_count}",
-			)));
+		if version.build != semver::BuildMetadata::EMPTY {
+			self.error("Build metadata format not supported", KEY, span.clone());
 		}
 
-		let layer0 = &raw_config.keyboard.keymap.layer0;
+		let mut part = |name: &str, value: u64| {
+			let part = u16::try_from(value).ok();
 
-		if layer0.len() != rows_count {
-			return Err(de::Error::custom(format!(
-				"Expected {rows_count} rows for layer0, got {}",
-				layer0.len()
-			)));
-		}
+			if part.is_none() {
+				self.error(format!("{name} version out of supported bounds"), KEY, span.clone());
+			}
+
+			part
+		};
+
+		let major = part("Major", version.major);
+		let minor = part("Minor", version.minor);
+		let patch = part("Patch", version.patch);
 
-		for (i, row) in layer0.iter().enumerate() {
-			if row.len() != cols_count {
-				return Err(de::Error::custom(format!(
-					"Expected {} cols for layer0's row {i}, got {}",
-					cols_count,
-					row.len()
-				)));
+		Some(crate::version::Version::new(
+			crate::version::Api::V0,
+			major?,
+			minor?,
+			patch?,
+		))
+	}
+
+	/// Checks that every pin belongs to the MCU, isn't reserved and is only used once.
+	fn check_pins(&mut self, mcu: Mcu, pins: &[(String, &Spanned<String>)]) {
+		for (index, (key, pin)) in pins.iter().enumerate() {
+			match mcu.usable_pin(pin.get_ref()) {
+				Ok(_) => {}
+				Err(PinError::Unknown) => {
+					self.error(
+						format!("{} isn't a pin of the {}", pin.get_ref(), mcu.as_str()),
+						key,
+						pin.span(),
+					);
+				}
+				Err(PinError::Reserved(reserved)) => {
+					self.error(
+						format!(
+							"{} is reserved for {} on the {}",
+							pin.get_ref(),
+							reserved.as_str(),
+							mcu.as_str()
+						),
+						key,
+						pin.span(),
+					);
+				}
+			}
+
+			if let Some((other, _)) = pins[..index].iter().find(|(_, other)| other.get_ref() == pin.get_ref()) {
+				self.error(
+					format!("Pin {} is already used by {other}", pin.get_ref()),
+					key,
+					pin.span(),
+				);
 			}
 		}
+	}
 
-		let layer1 = &raw_config.keyboard.keymap.layer1;
+	/// Parses the layers in the file, filling the missing ones with transparent actions.
+	///
+	/// The missing layers fall through to the ones below, with the same positions without a key as
+	/// the first layer.
+	fn check_layers(
+		&mut self,
+		layers: [Option<raw::Layer>; LAYER_COUNT],
+		rows: usize,
+		cols: usize,
+	) -> Option<Vec<Vec<Vec<Action>>>> {
+		let parsed: Vec<Option<Vec<Vec<Action>>>> = layers
+			.into_iter()
+			.enumerate()
+			.map(|(index, layer)| layer.and_then(|layer| self.check_layer(index, &layer, rows, cols)))
+			.collect();
+
+		let base = parsed.first()?.clone()?;
+
+		let transparent: Vec<Vec<Action>> = base
+			.iter()
+			.map(|row| {
+				row.iter()
+					.map(|action| {
+						if action.is_no() {
+							Action::NO
+						} else {
+							Action::TRANSPARENT
+						}
+					})
+					.collect()
+			})
+			.collect();
+
+		Some(
+			parsed
+				.into_iter()
+				.map(|layer| layer.unwrap_or_else(|| transparent.clone()))
+				.collect(),
+		)
+	}
 
-		if layer1.len() != rows_count {
-			return Err(de::Error::custom(format!(
-				"Expected {} rows for layer1, got {}",
-				rows_count,
-				layer1.len()
-			)));
-		}
+	/// Checks that the bootmagic keys are keys of the first layer and different from each other.
+	///
+	/// The clear key defaults to the first key of the layer, and the bootloader key to the first one
+	/// after it that isn't the clear key.
+	fn check_bootmagic(
+		&mut self,
+		bootmagic: &raw::Bootmagic,
+		layer0: &[Vec<Action>],
+		layer0_span: core::ops::Range<usize>,
+	) -> Option<Bootmagic> {
+		let has_key = |key: KeyPosition| {
+			layer0
+				.get(key.row)
+				.and_then(|row| row.get(key.col))
+				.is_some_and(|action| !action.is_no())
+		};
 
-		for (i, row) in layer1.iter().enumerate() {
-			if row.len() != cols_count {
-				return Err(de::Error::custom(format!(
-					"Expected {} cols for layer1's row {i}, got {}",
-					cols_count,
-					row.len()
-				)));
+		let mut keys = layer0.iter().enumerate().flat_map(|(row, actions)| {
+			actions
+				.iter()
+				.enumerate()
+				.filter(|(_, action)| !action.is_no())
+				.map(move |(col, _)| KeyPosition::new(row, col))
+		});
+
+		let mut is_valid = true;
+
+		for (name, key) in [("clear", &bootmagic.clear), ("bootloader", &bootmagic.bootloader)] {
+			let Some(key) = key else {
+				continue;
+			};
+
+			if !has_key(*key.get_ref()) {
+				self.error(
+					format!(
+						"There is no key at row {} col {} of layer0",
+						key.get_ref().row,
+						key.get_ref().col
+					),
+					format!("keyboard.bootmagic.{name}"),
+					key.span(),
+				);
+
+				is_valid = false;
 			}
 		}
 
-		let keymap = keyboard::Keymap {
-			rows: raw_config.keyboard.keymap.rows,
-			cols: raw_config.keyboard.keymap.cols,
-			row_pins: raw_config.keyboard.keymap.row_pins,
-			col_pins: raw_config.keyboard.keymap.col_pins,
-			layer0: raw_config.keyboard.keymap.layer0,
-			layer1: raw_config.keyboard.keymap.layer1,
+		let Some(clear) = bootmagic
+			.clear
+			.as_ref()
+			.map(|key| *key.get_ref())
+			.or_else(|| keys.next())
+		else {
+			self.error(
+				"Bootmagic needs at least one key in layer0",
+				"keyboard.keymap.layer0",
+				layer0_span,
+			);
+
+			return None;
 		};
 
-		let keyboard_config = keyboard::KeyboardConfig {
-			mcu: raw_config.keyboard.mcu,
-			keymap,
+		let bootloader = match &bootmagic.bootloader {
+			Some(key) if *key.get_ref() == clear => {
+				self.error(
+					"The bootloader key has to be different from the clear key",
+					"keyboard.bootmagic.bootloader",
+					key.span(),
+				);
+
+				is_valid = false;
+
+				None
+			}
+			Some(key) => Some(*key.get_ref()),
+			None => keys.find(|&key| key != clear),
 		};
 
-		Ok(Self {
-			name: raw_config.name,
-			author: raw_config.firmware.author,
-			id: raw_config.firmware.id,
-			version: version.as_bitmap(),
-			device: raw_config.firmware.device,
-			keyboard: keyboard_config,
+		let bootmagic = Bootmagic::new(clear);
+
+		is_valid.then_some(match bootloader {
+			Some(bootloader) => bootmagic.with_bootloader(bootloader),
+			None => bootmagic,
 		})
 	}
+
+	/// Checks the size of a layer and parses its actions.
+	fn check_layer(&mut self, index: usize, layer: &raw::Layer, rows: usize, cols: usize) -> Option<Vec<Vec<Action>>> {
+		let name = format!("keyboard.keymap.layer{index}");
+		let mut is_valid = true;
+
+		if layer.get_ref().len() != rows {
+			self.error(
+				format!("Expected {rows} rows, got {}", layer.get_ref().len()),
+				&name,
+				layer.span(),
+			);
+
+			is_valid = false;
+		}
+
+		let mut parsed = Vec::with_capacity(rows);
+
+		for (row_index, row) in layer.get_ref().iter().enumerate() {
+			if row.get_ref().len() != cols {
+				self.error(
+					format!("Expected {cols} cols, got {}", row.get_ref().len()),
+					format!("{name}[{row_index}]"),
+					row.span(),
+				);
+
+				is_valid = false;
+			}
+
+			let mut actions = Vec::with_capacity(cols);
+
+			for (col_index, action) in row.get_ref().iter().enumerate() {
+				match parse_action(action.get_ref()) {
+					Ok(action) => actions.push(action),
+					Err(message) => {
+						self.error(message, format!("{name}[{row_index}][{col_index}]"), action.span());
+
+						is_valid = false;
+					}
+				}
+			}
+
+			parsed.push(actions);
+		}
+
+		is_valid.then_some(parsed)
+	}
 }
 
+/// Reads and checks a configuration file.
+///
 /// # Errors
 ///
-/// Returns an error if the path does not exist. Other errors might also be returned, check
-/// the error docs for [`read_to_string`](std::fs::read_to_string).
-pub fn parse_file(path: &str) -> Result<Option<TomlConfiguration>, std::io::Error> {
-	let path = std::path::Path::new(path);
+/// Returns an error if the file can't be read, isn't valid TOML or any of its values are invalid.
+/// The error lists every problem found, and its [`Display`](std::fmt::Display) shows them with the
+/// lines of the file they point at.
+pub fn parse_file(path: impl AsRef<Path>) -> Result<TomlConfiguration, ParseError> {
+	let path = path.as_ref();
 
-	let contents = std::fs::read_to_string(path)?;
+	let contents = std::fs::read_to_string(path).map_err(|error| ParseError::io(path.to_path_buf(), error))?;
 
-	Ok(toml::from_str(&contents).ok())
+	TomlConfiguration::from_toml(&contents)
+		.map_err(|diagnostics| ParseError::invalid(path.to_path_buf(), contents, diagnostics))
 }
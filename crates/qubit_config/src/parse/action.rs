@@ -0,0 +1,241 @@
+use core::fmt::Write as _;
+use core::num::NonZeroU8;
+
+use crate::keyboard::layer::{LAYER_COUNT, LayerAction};
+use crate::keyboard::{Action, ActionKind, FirmwareAction, Modifiers, keycodes};
+
+/// The last bits of a keycode, with or without modifiers.
+const MODIFIED_KEY_END: u16 = 0x1FFF;
+
+/// Parses an action written the way the `keymap!` macro takes it, e.g. `KC_ESC`, `MO(1)`,
+/// `LT(2, KC_SPACE)` or `LCTL(KC_C)`. `-` is a position without a key and `_` is transparent.
+///
+/// # Errors
+///
+/// Returns a message for the user if the text isn't a known action or its arguments are out of
+/// bounds.
+pub fn parse_action(text: &str) -> Result<Action, String> {
+	let text = text.trim();
+
+	match text {
+		"-" => return Ok(Action::NO),
+		"_" => return Ok(Action::TRANSPARENT),
+		"NK_ON" => return Ok(Action::firmware(FirmwareAction::NkroOn)),
+		"NK_OFF" => return Ok(Action::firmware(FirmwareAction::NkroOff)),
+		"NK_TOGG" => return Ok(Action::firmware(FirmwareAction::NkroToggle)),
+		"QK_BOOT" => return Ok(Action::firmware(FirmwareAction::Bootloader)),
+		"QK_RBT" => return Ok(Action::firmware(FirmwareAction::Reboot)),
+		_ => {}
+	}
+
+	let Some((name, args)) = text.strip_suffix(')').and_then(|text| text.split_once('(')) else {
+		return keycode(text).map(Action::key);
+	};
+
+	let args = split_args(args);
+
+	match (name.trim(), args.as_slice()) {
+		("MO", [layer]) => Ok(Action::momentary(self::layer(layer)?)),
+		("TG", [layer]) => Ok(Action::toggle(self::layer(layer)?)),
+		("TO", [layer]) => Ok(Action::to(self::layer(layer)?)),
+		("LT", [layer, key]) => Ok(Action::layer_tap(self::layer(layer)?, keycode(key)?)),
+		("MT", [modifiers, key]) => Ok(Action::mod_tap(self::modifiers(modifiers)?, keycode(key)?)),
+		("CC", [usage]) => {
+			let usage = number::<u16>(usage)?;
+
+			if usage > 0x0FFF {
+				return Err(format!("The consumer usage {usage:#06X} doesn't fit in 12 bits"));
+			}
+
+			Ok(Action::consumer(usage))
+		}
+		("SYS", [usage]) => Ok(Action::system(number(usage)?)),
+		("MACRO", [index]) => Ok(Action::macro_action(number(index)?)),
+		("CUSTOM", [id]) => Ok(Action::custom(number(id)?)),
+		("LCTL", [inner]) => with_modifiers(inner, Modifiers::LCTRL),
+		("LSFT", [inner]) => with_modifiers(inner, Modifiers::LSHIFT),
+		("LALT", [inner]) => with_modifiers(inner, Modifiers::LALT),
+		("LGUI", [inner]) => with_modifiers(inner, Modifiers::LGUI),
+		("RCTL", [inner]) => with_modifiers(inner, Modifiers::RCTRL),
+		("RSFT", [inner]) => with_modifiers(inner, Modifiers::RSHIFT),
+		("RALT", [inner]) => with_modifiers(inner, Modifiers::RALT),
+		("RGUI", [inner]) => with_modifiers(inner, Modifiers::RGUI),
+		_ => Err(format!("`{text}` isn't a known action")),
+	}
+}
+
+/// Writes an action the way [`parse_action`] reads it, e.g. `LT(2, KC_SPACE)`.
+///
+/// Actions that can't be written by name, like keycodes without a `KC_` constant, are written as
+/// their hexadecimal encoding.
+#[must_use]
+pub fn action_name(action: Action) -> String {
+	let name = match action.kind() {
+		ActionKind::No => Some(String::from("-")),
+		ActionKind::Transparent => Some(String::from("_")),
+		ActionKind::Key(keycode) => keycode_name(keycode).map(String::from),
+		ActionKind::ModifiedKey { modifiers, keycode } => keycode_name(keycode).map(|name| wrap(name, modifiers)),
+		ActionKind::ModTap { modifiers, keycode } => modifier_name(modifiers)
+			.zip(keycode_name(keycode))
+			.map(|(modifier, name)| format!("MT({modifier}, {name})")),
+		ActionKind::Layer(LayerAction::Momentary(layer)) => Some(format!("MO({layer})")),
+		ActionKind::Layer(LayerAction::Toggle(layer)) => Some(format!("TG({layer})")),
+		ActionKind::Layer(LayerAction::To(layer)) => Some(format!("TO({layer})")),
+		ActionKind::Layer(LayerAction::Tap { layer, keycode }) => {
+			keycode_name(keycode).map(|name| format!("LT({layer}, {name})"))
+		}
+		ActionKind::Consumer(usage) => Some(format!("CC({usage:#06X})")),
+		ActionKind::System(usage) => Some(format!("SYS({usage:#04X})")),
+		ActionKind::Macro(index) => Some(format!("MACRO({index})")),
+		ActionKind::Custom(id) => Some(format!("CUSTOM({id})")),
+		ActionKind::Firmware(FirmwareAction::NkroOn) => Some(String::from("NK_ON")),
+		ActionKind::Firmware(FirmwareAction::NkroOff) => Some(String::from("NK_OFF")),
+		ActionKind::Firmware(FirmwareAction::NkroToggle) => Some(String::from("NK_TOGG")),
+		ActionKind::Firmware(FirmwareAction::Bootloader) => Some(String::from("QK_BOOT")),
+		ActionKind::Firmware(FirmwareAction::Reboot) => Some(String::from("QK_RBT")),
+		ActionKind::Modifiers(_) => None,
+	};
+
+	name.unwrap_or_else(|| format!("{:#06X}", action.to_bits()))
+}
+
+fn keycode_name(keycode: NonZeroU8) -> Option<&'static str> {
+	keycodes::NAMES
+		.iter()
+		.find(|(_, value)| *value == keycode)
+		.map(|(name, _)| *name)
+}
+
+/// The name `MT` takes for a single modifier.
+fn modifier_name(modifiers: Modifiers) -> Option<&'static str> {
+	[
+		(Modifiers::LCTRL, "LCTRL"),
+		(Modifiers::LSHIFT, "LSHIFT"),
+		(Modifiers::LALT, "LALT"),
+		(Modifiers::LGUI, "LGUI"),
+		(Modifiers::RCTRL, "RCTRL"),
+		(Modifiers::RSHIFT, "RSHIFT"),
+		(Modifiers::RALT, "RALT"),
+		(Modifiers::RGUI, "RGUI"),
+	]
+	.into_iter()
+	.find(|(modifier, _)| *modifier == modifiers)
+	.map(|(_, name)| name)
+}
+
+/// Wraps the keycode in one function per modifier, e.g. `LCTL(LSFT(KC_ESC))`.
+fn wrap(name: &str, modifiers: Modifiers) -> String {
+	let side = if modifiers.bits() & Modifiers::RIGHT.bits() == 0 {
+		'L'
+	} else {
+		'R'
+	};
+
+	let wrappers = [
+		(Modifiers::CTRL, "CTL"),
+		(Modifiers::SHIFT, "SFT"),
+		(Modifiers::ALT, "ALT"),
+		(Modifiers::GUI, "GUI"),
+	];
+
+	let mut open = String::new();
+	let mut close = String::new();
+
+	for (modifier, wrapper) in wrappers {
+		if modifiers.bits() & modifier.bits() != 0 {
+			_ = write!(open, "{side}{wrapper}(");
+			close.push(')');
+		}
+	}
+
+	format!("{open}{name}{close}")
+}
+
+/// Splits the arguments of an action on the commas that aren't inside another action.
+fn split_args(args: &str) -> Vec<&str> {
+	let mut parts = Vec::new();
+	let mut depth = 0_usize;
+	let mut start = 0;
+
+	for (index, char) in args.char_indices() {
+		match char {
+			'(' => depth += 1,
+			')' => depth = depth.saturating_sub(1),
+			',' if depth == 0 => {
+				parts.push(args[start..index].trim());
+				start = index + 1;
+			}
+			_ => {}
+		}
+	}
+
+	parts.push(args[start..].trim());
+
+	parts
+}
+
+fn keycode(name: &str) -> Result<NonZeroU8, String> {
+	keycodes::from_name(name.trim()).ok_or_else(|| format!("`{}` isn't a known keycode", name.trim()))
+}
+
+fn layer(text: &str) -> Result<u8, String> {
+	let layer = number::<u8>(text)?;
+
+	if usize::from(layer) >= LAYER_COUNT {
+		return Err(format!(
+			"Layer {layer} doesn't exist, there are only {LAYER_COUNT} layers"
+		));
+	}
+
+	Ok(layer)
+}
+
+fn modifiers(name: &str) -> Result<Modifiers, String> {
+	match name {
+		"LCTRL" => Ok(Modifiers::LCTRL),
+		"LSHIFT" => Ok(Modifiers::LSHIFT),
+		"LALT" => Ok(Modifiers::LALT),
+		"LGUI" => Ok(Modifiers::LGUI),
+		"RCTRL" => Ok(Modifiers::RCTRL),
+		"RSHIFT" => Ok(Modifiers::RSHIFT),
+		"RALT" => Ok(Modifiers::RALT),
+		"RGUI" => Ok(Modifiers::RGUI),
+		_ => Err(format!("`{name}` isn't a known modifier")),
+	}
+}
+
+fn with_modifiers(inner: &str, modifiers: Modifiers) -> Result<Action, String> {
+	let action = parse_action(inner)?;
+
+	// Only keycodes, with or without modifiers already, can be wrapped.
+	if !(0x0004..=MODIFIED_KEY_END).contains(&action.to_bits()) {
+		return Err(format!("Only keycodes can be wrapped in modifiers, not `{inner}`"));
+	}
+
+	// A keycode stores a single side for all of its modifiers.
+	let current = match action.kind() {
+		ActionKind::ModifiedKey { modifiers, .. } | ActionKind::Modifiers(modifiers) => Some(modifiers),
+		_ => None,
+	};
+
+	if current.is_some_and(|current| current.is_right() != modifiers.is_right()) {
+		return Err(format!(
+			"Left and right modifiers can't be mixed, `{inner}` has modifiers of the other side"
+		));
+	}
+
+	Ok(action.with_modifiers(modifiers))
+}
+
+/// Parses a decimal or a `0x` prefixed hexadecimal number.
+fn number<T: TryFrom<u32>>(text: &str) -> Result<T, String> {
+	let value = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+		Some(hex) => u32::from_str_radix(hex, 16),
+		None => text.parse(),
+	};
+
+	value
+		.ok()
+		.and_then(|value| T::try_from(value).ok())
+		.ok_or_else(|| format!("`{text}` isn't a valid number here"))
+}
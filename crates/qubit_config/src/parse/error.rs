@@ -0,0 +1,226 @@
+use std::fmt;
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// A position in a file, both starting at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+	pub line: usize,
+	pub column: usize,
+}
+
+impl Location {
+	/// Finds the line and column of a byte offset in the source.
+	#[must_use]
+	pub fn of(source: &str, offset: usize) -> Self {
+		let offset = offset.min(source.len());
+		let before = source.get(..offset).unwrap_or(source);
+
+		let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+
+		Self {
+			line: before.matches('\n').count() + 1,
+			column: before[line_start..].chars().count() + 1,
+		}
+	}
+}
+
+/// The part of a file a diagnostic points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+	/// The byte range in the file.
+	pub bytes: Range<usize>,
+	pub start: Location,
+	pub end: Location,
+}
+
+impl Span {
+	#[must_use]
+	pub fn new(source: &str, bytes: Range<usize>) -> Self {
+		Self {
+			start: Location::of(source, bytes.start),
+			end: Location::of(source, bytes.end),
+			bytes,
+		}
+	}
+}
+
+/// A single problem found in a configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+	pub message: String,
+	/// The dotted path of the offending key, e.g. `keyboard.keymap.row_pins`.
+	pub key: Option<String>,
+	pub span: Option<Span>,
+}
+
+impl Diagnostic {
+	/// A problem with a key, found after the file was read.
+	pub(super) fn new(source: &str, message: impl Into<String>, key: impl Into<String>, bytes: Range<usize>) -> Self {
+		Self {
+			message: message.into(),
+			key: Some(key.into()),
+			span: Some(Span::new(source, bytes)),
+		}
+	}
+
+	/// A problem found while reading the file, like a syntax error or a value of the wrong type.
+	pub(super) fn from_toml(source: &str, error: &toml::de::Error) -> Self {
+		let span = error.span().map(|bytes| Span::new(source, bytes));
+		let key = span.as_ref().and_then(|span| key_at(source, span.bytes.start));
+
+		Self {
+			message: error.message().trim().to_string(),
+			key,
+			span,
+		}
+	}
+
+	/// Writes the diagnostic the way the compiler shows its errors, with the line it points at.
+	fn render(&self, f: &mut fmt::Formatter<'_>, path: &str, source: &str) -> fmt::Result {
+		writeln!(f, "error: {}", self.message)?;
+
+		let Some(span) = &self.span else {
+			return writeln!(f, "  --> {path}");
+		};
+
+		let line_num = span.start.line.to_string();
+		let gutter = " ".repeat(line_num.len());
+
+		writeln!(f, "{gutter}--> {path}:{}:{}", span.start.line, span.start.column)?;
+
+		let line = source.lines().nth(span.start.line - 1).unwrap_or_default();
+
+		// Spans over several lines are only underlined up to the end of the first one.
+		let width = if span.end.line == span.start.line {
+			span.end.column.saturating_sub(span.start.column).max(1)
+		} else {
+			line.chars().count().saturating_sub(span.start.column - 1).max(1)
+		};
+
+		let padding = " ".repeat(span.start.column - 1);
+		let marker = "^".repeat(width);
+		let label = self.key.as_deref().unwrap_or_default();
+
+		writeln!(f, "{gutter} |")?;
+		writeln!(f, "{line_num} | {line}")?;
+		writeln!(f, "{gutter} | {padding}{marker} {label}")
+	}
+}
+
+#[derive(Debug)]
+pub enum ParseErrorKind {
+	Io(std::io::Error),
+	/// The file was read, but isn't a valid configuration.
+	Invalid(Vec<Diagnostic>),
+}
+
+/// The error of [`parse_file`](super::parse_file).
+#[derive(Debug)]
+pub struct ParseError {
+	pub path: PathBuf,
+	pub kind: ParseErrorKind,
+	/// The contents of the file, used to show the lines the diagnostics point at.
+	source: String,
+}
+
+impl ParseError {
+	pub(super) fn io(path: PathBuf, error: std::io::Error) -> Self {
+		Self {
+			path,
+			kind: ParseErrorKind::Io(error),
+			source: String::new(),
+		}
+	}
+
+	pub(super) fn invalid(path: PathBuf, source: String, diagnostics: Vec<Diagnostic>) -> Self {
+		Self {
+			path,
+			kind: ParseErrorKind::Invalid(diagnostics),
+			source,
+		}
+	}
+
+	/// Every problem found in the file, empty if it couldn't be read.
+	#[must_use]
+	pub fn diagnostics(&self) -> &[Diagnostic] {
+		match &self.kind {
+			ParseErrorKind::Io(_) => &[],
+			ParseErrorKind::Invalid(diagnostics) => diagnostics,
+		}
+	}
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let path = self.path.display().to_string();
+
+		match &self.kind {
+			ParseErrorKind::Io(error) => write!(f, "error: failed to read {path}: {error}"),
+			ParseErrorKind::Invalid(diagnostics) => {
+				for diagnostic in diagnostics {
+					diagnostic.render(f, &path, &self.source)?;
+					writeln!(f)?;
+				}
+
+				match diagnostics.len() {
+					1 => write!(f, "error: {path} has 1 problem"),
+					count => write!(f, "error: {path} has {count} problems"),
+				}
+			}
+		}
+	}
+}
+
+impl std::error::Error for ParseError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match &self.kind {
+			ParseErrorKind::Io(error) => Some(error),
+			ParseErrorKind::Invalid(_) => None,
+		}
+	}
+}
+
+/// Guesses the dotted key of the value at the offset, from the table header and the assignment
+/// before it.
+fn key_at(source: &str, offset: usize) -> Option<String> {
+	let offset = offset.min(source.len());
+	let line_end = source[offset..].find('\n').map_or(source.len(), |index| offset + index);
+
+	// The line of the offset is included, since the key is on it.
+	let lines = source[..line_end].lines().rev();
+
+	let mut key = None;
+
+	for line in lines {
+		let line = line.trim();
+
+		// Rows of arrays also start with a bracket, but aren't bare keys.
+		if let Some(header) = line.strip_prefix('[')
+			&& let table = header.trim_start_matches('[').trim_end_matches(']').trim()
+			&& is_bare_key(table)
+		{
+			return Some(match key {
+				Some(key) => format!("{table}.{key}"),
+				None => table.to_string(),
+			});
+		}
+
+		if key.is_none()
+			&& let Some((name, _)) = line.split_once('=')
+			&& is_bare_key(name.trim())
+		{
+			key = Some(name.trim().to_string());
+		}
+	}
+
+	// Keys before the first table are at the root.
+	key
+}
+
+fn is_bare_key(text: &str) -> bool {
+	!text.is_empty()
+		&& text
+			.chars()
+			.all(|char| char.is_ascii_alphanumeric() || matches!(char, '_' | '-' | '.'))
+}
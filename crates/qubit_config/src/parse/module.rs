@@ -0,0 +1,139 @@
+use std::fmt::Write as _;
+
+use super::TomlConfiguration;
+use crate::keyboard::Bootmagic;
+use crate::version::Version;
+
+impl TomlConfiguration {
+	/// Generates the source of a device module, with the same items as the models of `qubit_device`.
+	///
+	/// The firmware includes it in place of a model when it's built from a TOML file.
+	#[must_use]
+	pub fn device_module(&self) -> String {
+		let keyboard = &self.keyboard;
+		let keymap = &keyboard.keymap;
+		let version = Version::from_bitmap(self.version);
+
+		let mut module = String::from(
+			"// This file is automatically generated and not intended for manual editing.
+
+use ::qubit_config::general::Device;
+use ::qubit_config::keyboard::{Action, Bootmagic, Debounce, KeyPosition, Keymap, LockIndicator, TapHold, TapHoldFlavor};
+use ::qubit_config::mcu::Mcu;
+use ::qubit_config::usb::Usb;
+use ::qubit_config::version::Version;
+
+",
+		);
+
+		// Writing to a `String` can't fail.
+		_ = writeln!(module, "pub const NAME: &str = {:?};", self.name);
+		_ = writeln!(module, "pub const AUTHOR: &str = {:?};", self.author);
+		_ = writeln!(
+			module,
+			"pub const VERSION: Version = Version::new_zero({}, {}, {});",
+			version.major, version.minor, version.patch
+		);
+		_ = writeln!(module, "pub const DEVICE: Device = Device::{:?};", self.device);
+		_ = writeln!(
+			module,
+			"pub const LED_PIN: Option<&str> = {:?};",
+			self.led.as_ref().map(|led| &led.pin)
+		);
+		_ = writeln!(
+			module,
+			"pub const LED_INDICATOR: LockIndicator = LockIndicator::{:?};",
+			self.led
+				.as_ref()
+				.map_or(crate::keyboard::LockIndicator::CapsLock, |led| led.indicator)
+		);
+		_ = writeln!(module);
+		_ = writeln!(module, "pub const MCU: Mcu = Mcu::{:?};", keyboard.mcu);
+		_ = writeln!(
+			module,
+			"pub const FLASH: u32 = 0x{:04X}_{:04X};",
+			keyboard.flash >> 16,
+			keyboard.flash & 0xFFFF
+		);
+		_ = writeln!(
+			module,
+			"pub const USB: Usb = Usb::new({:#06X}, {:#06X});",
+			self.usb.vid, self.usb.pid
+		);
+		_ = writeln!(module);
+		_ = writeln!(module, "pub const ROW_NUM: usize = {};", keymap.rows);
+		_ = writeln!(module, "pub const COL_NUM: usize = {};", keymap.cols);
+		_ = writeln!(module, "pub const ROW_PINS: [&str; ROW_NUM] = {:?};", keymap.row_pins);
+		_ = writeln!(module, "pub const COL_PINS: [&str; COL_NUM] = {:?};", keymap.col_pins);
+
+		for (index, layer) in keymap.layers.iter().enumerate() {
+			_ = writeln!(module);
+			_ = writeln!(
+				module,
+				"pub const LAYER{index}: Keymap<ROW_NUM, COL_NUM> = Keymap::new(["
+			);
+
+			for row in layer {
+				let actions: Vec<String> = row
+					.iter()
+					.map(|action| format!("Action::from_bits({:#06X})", action.to_bits()))
+					.collect();
+
+				_ = writeln!(module, "\t[{}],", actions.join(", "));
+			}
+
+			_ = writeln!(module, "]);");
+		}
+
+		let tap_hold = &keyboard.tap_hold;
+
+		let per_key: Vec<String> = tap_hold
+			.per_key
+			.iter()
+			.map(|key| {
+				format!(
+					"::qubit_config::keyboard::KeyTappingTerm::new({}, {}, {})",
+					key.row, key.col, key.tapping_term
+				)
+			})
+			.collect();
+
+		_ = writeln!(module);
+		_ = writeln!(
+			module,
+			"pub const DEBOUNCE: Debounce = Debounce::{:?};",
+			keyboard.debounce
+		);
+		_ = writeln!(
+			module,
+			"pub const TAP_HOLD: TapHold = TapHold::new({}, TapHoldFlavor::{:?}).with_per_key(&[{}]);",
+			tap_hold.tapping_term,
+			tap_hold.flavor,
+			per_key.join(", ")
+		);
+		_ = writeln!(
+			module,
+			"pub const BOOTMAGIC: Bootmagic = {};",
+			bootmagic(&keyboard.bootmagic)
+		);
+
+		module
+	}
+}
+
+/// The expression that builds the bootmagic keys.
+fn bootmagic(bootmagic: &Bootmagic) -> String {
+	let clear = bootmagic.clear;
+
+	let mut expression = format!("Bootmagic::new(KeyPosition::new({}, {}))", clear.row, clear.col);
+
+	if let Some(bootloader) = bootmagic.bootloader {
+		_ = write!(
+			expression,
+			".with_bootloader(KeyPosition::new({}, {}))",
+			bootloader.row, bootloader.col
+		);
+	}
+
+	expression
+}
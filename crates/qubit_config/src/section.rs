@@ -0,0 +1,537 @@
+//! The binary format of the `.configuration` and `.keyboard` flash sections.
+//!
+//! Host tools read the sections out of a firmware image without knowing how it was built, so both
+//! of them describe themselves. They start with the same header, followed by their payload:
+//!
+//! | Offset | Size | Field                 |
+//! |--------|------|-----------------------|
+//! | 0      | 4    | Magic                 |
+//! | 4      | 2    | Format version        |
+//! | 6      | 2    | Payload length        |
+//! | 8      | 4    | CRC-32 of the payload |
+//!
+//! Every number is little endian and the bytes after the payload, up to the end of the section, are
+//! zero.
+//!
+//! The payload of the `.configuration` section:
+//!
+//! | Offset | Size | Field                   |
+//! |--------|------|-------------------------|
+//! | 0      | 4    | Firmware version bitmap |
+//! | 4      | 1    | Device                  |
+//! | 5      | 1    | MCU                     |
+//! | 6      | 2    | USB vendor ID           |
+//! | 8      | 2    | USB product ID          |
+//! | 10     | 1    | Rows of the matrix      |
+//! | 11     | 1    | Columns of the matrix   |
+//! | 12     | 1    | Layer count             |
+//! | 13     | ..   | Name                    |
+//! | ..     | ..   | Author                  |
+//!
+//! The strings are UTF-8, prefixed with their length in a byte.
+//!
+//! The payload of the `.keyboard` section:
+//!
+//! | Offset | Size | Field                 |
+//! |--------|------|-----------------------|
+//! | 0      | 1    | Rows of the matrix    |
+//! | 1      | 1    | Columns of the matrix |
+//! | 2      | 1    | Layer count           |
+//! | 3      | 2    | Key count             |
+//! | 5      | ..   | Layout                |
+//! | ..     | ..   | Keymaps               |
+//!
+//! The layout has a bit for every position of the matrix, row by row, set where there is a key. The
+//! keymaps only have the actions of those keys, as written by [`Keymaps::write_bytes`].
+//!
+//! [`Keymaps::write_bytes`]: crate::keyboard::Keymaps::write_bytes
+
+use crate::crc::crc32;
+use crate::general::{Configuration, Device};
+use crate::keyboard::layer::LAYER_COUNT;
+use crate::keyboard::{Action, Keymap};
+use crate::mcu::Mcu;
+use crate::usb::Usb;
+
+/// The version of the format, bumped whenever a field changes.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// The size of the header both sections start with.
+pub const HEADER_LEN: usize = 12;
+
+pub const CONFIGURATION_MAGIC: [u8; 4] = *b"QBCF";
+pub const KEYBOARD_MAGIC: [u8; 4] = *b"QBKB";
+
+/// The longest name or author, in bytes.
+pub const MAX_STRING_LEN: usize = 32;
+
+/// The size of the `.configuration` section.
+pub const CONFIGURATION_LEN: usize = 96;
+
+/// The fields of the `.configuration` payload before the strings.
+const CONFIGURATION_FIELDS_LEN: usize = 13;
+
+/// The fields of the `.keyboard` payload before the layout.
+const KEYBOARD_FIELDS_LEN: usize = 5;
+
+const _: () = assert!(
+	HEADER_LEN + CONFIGURATION_FIELDS_LEN + 2 * (1 + MAX_STRING_LEN) <= CONFIGURATION_LEN,
+	"The longest configuration must fit in the section."
+);
+
+/// A section that can't be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionError {
+	/// The bytes end before the header or the payload it announces.
+	TooShort,
+	/// The bytes don't start with the magic of the section.
+	BadMagic,
+	UnsupportedVersion(u16),
+	BadCrc,
+	/// The payload has a value the format doesn't allow.
+	InvalidPayload,
+}
+
+impl Configuration<'_> {
+	/// Encodes the configuration as its `.configuration` section.
+	///
+	/// # Panics
+	///
+	/// Panics if the name or the author is longer than [`MAX_STRING_LEN`], or if the matrix or the
+	/// layer count don't fit in a byte.
+	#[must_use]
+	pub const fn encode(&self) -> [u8; CONFIGURATION_LEN] {
+		assert!(
+			self.name.len() <= MAX_STRING_LEN && self.author.len() <= MAX_STRING_LEN,
+			"The name and the author can't be longer than 32 bytes."
+		);
+
+		let mut bytes = [0; CONFIGURATION_LEN];
+		let mut writer = Writer::new(&mut bytes);
+
+		writer.u32(self.version);
+		writer.u8(device_code(self.device));
+		writer.u8(mcu_code(self.mcu));
+		writer.u16(self.usb.vid);
+		writer.u16(self.usb.pid);
+		writer.u8(narrow_u8(self.rows));
+		writer.u8(narrow_u8(self.cols));
+		writer.u8(narrow_u8(self.layer_count));
+		writer.u8(narrow_u8(self.name.len()));
+		writer.bytes(self.name.as_bytes());
+		writer.u8(narrow_u8(self.author.len()));
+		writer.bytes(self.author.as_bytes());
+
+		writer.finish(CONFIGURATION_MAGIC);
+
+		bytes
+	}
+}
+
+impl<'a> Configuration<'a> {
+	/// Decodes a `.configuration` section, borrowing the strings from it.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the bytes aren't a valid `.configuration` section.
+	pub fn decode(bytes: &'a [u8]) -> Result<Self, SectionError> {
+		let mut reader = Reader::new(bytes, CONFIGURATION_MAGIC)?;
+
+		let version = reader.u32()?;
+		let device = device_from_code(reader.u8()?).ok_or(SectionError::InvalidPayload)?;
+		let mcu = mcu_from_code(reader.u8()?).ok_or(SectionError::InvalidPayload)?;
+		let usb = Usb::new(reader.u16()?, reader.u16()?);
+		let rows = usize::from(reader.u8()?);
+		let cols = usize::from(reader.u8()?);
+		let layer_count = usize::from(reader.u8()?);
+		let name = reader.string()?;
+		let author = reader.string()?;
+
+		if !reader.is_empty() {
+			return Err(SectionError::InvalidPayload);
+		}
+
+		Ok(Self {
+			name,
+			author,
+			version,
+			device,
+			mcu,
+			usb,
+			rows,
+			cols,
+			layer_count,
+		})
+	}
+}
+
+/// The size of the `.keyboard` section of a matrix with `key_count` keys.
+#[must_use]
+pub const fn keyboard_len(rows: usize, cols: usize, key_count: usize) -> usize {
+	HEADER_LEN + KEYBOARD_FIELDS_LEN + (rows * cols).div_ceil(8) + key_count * 2 * LAYER_COUNT
+}
+
+/// Encodes the keymaps as the `.keyboard` section.
+///
+/// # Panics
+///
+/// Panics if `N` isn't the [`keyboard_len`] of the keymaps, or for the reasons of [`write_keyboard`].
+#[must_use]
+pub const fn encode_keyboard<const N: usize, const R: usize, const C: usize>(
+	layers: [&Keymap<R, C>; LAYER_COUNT],
+) -> [u8; N] {
+	let mut flat: [&[Action]; LAYER_COUNT] = [&[]; LAYER_COUNT];
+
+	let mut i = 0;
+	while i < LAYER_COUNT {
+		flat[i] = layers[i].0.as_flattened();
+
+		i += 1;
+	}
+
+	let mut bytes = [0; N];
+
+	let len = write_keyboard(&mut bytes, R, C, &flat);
+	assert!(len == N, "The section length doesn't match the keymaps.");
+
+	bytes
+}
+
+/// Writes the keymaps as the `.keyboard` section and returns its length.
+///
+/// Every layer has all the positions of the matrix, row by row. The positions without a key in the
+/// first layer are left out of the section, so they must be empty in the other layers too.
+///
+/// # Panics
+///
+/// Panics if a layer doesn't have `rows * cols` actions, if a layer has an action where the first
+/// one has no key, if the matrix doesn't fit in a byte or if `bytes` is too short.
+pub const fn write_keyboard(bytes: &mut [u8], rows: usize, cols: usize, layers: &[&[Action]; LAYER_COUNT]) -> usize {
+	let positions = rows * cols;
+
+	let mut key_count = 0;
+
+	let mut i = 0;
+	while i < LAYER_COUNT {
+		assert!(layers[i].len() == positions, "Every layer must cover the whole matrix.");
+
+		let mut j = 0;
+		while j < positions {
+			assert!(
+				!layers[0][j].is_no() || layers[i][j].is_no(),
+				"Every layer must leave the positions without a key in the first one empty."
+			);
+
+			if i == 0 && !layers[0][j].is_no() {
+				key_count += 1;
+			}
+
+			j += 1;
+		}
+
+		i += 1;
+	}
+
+	assert!(
+		bytes.len() >= keyboard_len(rows, cols, key_count),
+		"The buffer is too short for the section."
+	);
+
+	let mut writer = Writer::new(bytes);
+
+	writer.u8(narrow_u8(rows));
+	writer.u8(narrow_u8(cols));
+	writer.u8(narrow_u8(LAYER_COUNT));
+	writer.u16(narrow_u16(key_count));
+
+	let mut byte = 0;
+	let mut j = 0;
+	while j < positions {
+		if !layers[0][j].is_no() {
+			byte |= 1 << (j % 8);
+		}
+
+		if j % 8 == 7 || j + 1 == positions {
+			writer.u8(byte);
+			byte = 0;
+		}
+
+		j += 1;
+	}
+
+	let mut i = 0;
+	while i < LAYER_COUNT {
+		let mut j = 0;
+		while j < positions {
+			if !layers[0][j].is_no() {
+				writer.u16(layers[i][j].to_bits());
+			}
+
+			j += 1;
+		}
+
+		i += 1;
+	}
+
+	writer.finish(KEYBOARD_MAGIC)
+}
+
+/// A decoded `.keyboard` section, borrowing the layout and the keymaps from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyboardSection<'a> {
+	pub rows: usize,
+	pub cols: usize,
+	pub layer_count: usize,
+	/// The number of keys, which is the length of every packed keymap.
+	pub key_count: usize,
+	layout: &'a [u8],
+	keymaps: &'a [u8],
+}
+
+impl<'a> KeyboardSection<'a> {
+	/// Decodes a `.keyboard` section.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the bytes aren't a valid `.keyboard` section.
+	pub fn decode(bytes: &'a [u8]) -> Result<Self, SectionError> {
+		let mut reader = Reader::new(bytes, KEYBOARD_MAGIC)?;
+
+		let rows = usize::from(reader.u8()?);
+		let cols = usize::from(reader.u8()?);
+		let layer_count = usize::from(reader.u8()?);
+		let key_count = usize::from(reader.u16()?);
+
+		let layout = reader.take((rows * cols).div_ceil(8))?;
+		let keymaps = reader.take(key_count * 2 * layer_count)?;
+
+		let section = Self {
+			rows,
+			cols,
+			layer_count,
+			key_count,
+			layout,
+			keymaps,
+		};
+
+		let keys = (0..rows * cols)
+			.filter(|&position| section.has_key_at(position))
+			.count();
+
+		if keys != key_count || !reader.is_empty() {
+			return Err(SectionError::InvalidPayload);
+		}
+
+		Ok(section)
+	}
+
+	/// Checks if there is a key at the position of the matrix.
+	#[must_use]
+	pub fn has_key(&self, row: usize, col: usize) -> bool {
+		row < self.rows && col < self.cols && self.has_key_at(row * self.cols + col)
+	}
+
+	fn has_key_at(&self, position: usize) -> bool {
+		self.layout[position / 8] & (1 << (position % 8)) != 0
+	}
+
+	/// Checks that the keymap has the same size and keys in the same positions as the section.
+	#[must_use]
+	pub fn has_layout_of<const R: usize, const C: usize>(&self, keymap: &Keymap<R, C>) -> bool {
+		self.rows == R
+			&& self.cols == C
+			&& (0..R).all(|row| (0..C).all(|col| self.has_key(row, col) != keymap.0[row][col].is_no()))
+	}
+
+	/// Returns the action of a key in a layer, by its index in the packed keymap.
+	#[must_use]
+	pub fn action(&self, layer: usize, index: usize) -> Option<Action> {
+		if layer >= self.layer_count || index >= self.key_count {
+			return None;
+		}
+
+		let offset = (layer * self.key_count + index) * 2;
+
+		Some(Action::from_bits(u16::from_le_bytes([
+			self.keymaps[offset],
+			self.keymaps[offset + 1],
+		])))
+	}
+
+	/// The keymaps of every layer, to be read with [`Keymaps::from_bytes`].
+	///
+	/// [`Keymaps::from_bytes`]: crate::keyboard::Keymaps::from_bytes
+	#[must_use]
+	pub const fn keymap_bytes(&self) -> &'a [u8] {
+		self.keymaps
+	}
+}
+
+const fn device_code(device: Device) -> u8 {
+	match device {
+		Device::Keyboard => 0x01,
+	}
+}
+
+const fn device_from_code(code: u8) -> Option<Device> {
+	match code {
+		0x01 => Some(Device::Keyboard),
+		_ => None,
+	}
+}
+
+const fn mcu_code(mcu: Mcu) -> u8 {
+	match mcu {
+		Mcu::RP2040 => 0x01,
+		Mcu::STM32F411 => 0x02,
+	}
+}
+
+const fn mcu_from_code(code: u8) -> Option<Mcu> {
+	match code {
+		0x01 => Some(Mcu::RP2040),
+		0x02 => Some(Mcu::STM32F411),
+		_ => None,
+	}
+}
+
+#[allow(clippy::cast_possible_truncation, reason = "The value is checked to fit first.")]
+const fn narrow_u8(value: usize) -> u8 {
+	assert!(value <= u8::MAX as usize, "The value doesn't fit in a byte.");
+
+	value as u8
+}
+
+#[allow(clippy::cast_possible_truncation, reason = "The value is checked to fit first.")]
+const fn narrow_u16(value: usize) -> u16 {
+	assert!(value <= u16::MAX as usize, "The value doesn't fit in 2 bytes.");
+
+	value as u16
+}
+
+/// Writes the payload of a section after the space left for the header.
+struct Writer<'a> {
+	bytes: &'a mut [u8],
+	position: usize,
+}
+
+impl<'a> Writer<'a> {
+	const fn new(bytes: &'a mut [u8]) -> Self {
+		Self {
+			bytes,
+			position: HEADER_LEN,
+		}
+	}
+
+	const fn u8(&mut self, value: u8) {
+		self.bytes[self.position] = value;
+		self.position += 1;
+	}
+
+	const fn u16(&mut self, value: u16) {
+		self.bytes(&value.to_le_bytes());
+	}
+
+	const fn u32(&mut self, value: u32) {
+		self.bytes(&value.to_le_bytes());
+	}
+
+	const fn bytes(&mut self, bytes: &[u8]) {
+		let mut i = 0;
+		while i < bytes.len() {
+			self.u8(bytes[i]);
+
+			i += 1;
+		}
+	}
+
+	/// Writes the header in front of the payload and returns the length of the section.
+	const fn finish(self, magic: [u8; 4]) -> usize {
+		let len = self.position;
+		let payload_len = narrow_u16(len - HEADER_LEN);
+
+		let (header, rest) = self.bytes.split_at_mut(HEADER_LEN);
+		let (payload, _) = rest.split_at(payload_len as usize);
+
+		let crc = crc32(payload);
+
+		let mut header = Writer {
+			bytes: header,
+			position: 0,
+		};
+
+		header.bytes(&magic);
+		header.u16(FORMAT_VERSION);
+		header.u16(payload_len);
+		header.u32(crc);
+
+		len
+	}
+}
+
+/// Reads the payload of a section, once its header was checked.
+struct Reader<'a> {
+	payload: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+	fn new(bytes: &'a [u8], magic: [u8; 4]) -> Result<Self, SectionError> {
+		let (header, rest) = bytes.split_at_checked(HEADER_LEN).ok_or(SectionError::TooShort)?;
+
+		if header[0..4] != magic {
+			return Err(SectionError::BadMagic);
+		}
+
+		let version = u16::from_le_bytes([header[4], header[5]]);
+
+		if version != FORMAT_VERSION {
+			return Err(SectionError::UnsupportedVersion(version));
+		}
+
+		let payload_len = usize::from(u16::from_le_bytes([header[6], header[7]]));
+		let crc = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+
+		let payload = rest.get(..payload_len).ok_or(SectionError::TooShort)?;
+
+		if crc32(payload) != crc {
+			return Err(SectionError::BadCrc);
+		}
+
+		Ok(Self { payload })
+	}
+
+	fn take(&mut self, len: usize) -> Result<&'a [u8], SectionError> {
+		let (bytes, rest) = self.payload.split_at_checked(len).ok_or(SectionError::InvalidPayload)?;
+
+		self.payload = rest;
+
+		Ok(bytes)
+	}
+
+	fn u8(&mut self) -> Result<u8, SectionError> {
+		Ok(self.take(1)?[0])
+	}
+
+	fn u16(&mut self) -> Result<u16, SectionError> {
+		let bytes = self.take(2)?;
+
+		Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+	}
+
+	fn u32(&mut self) -> Result<u32, SectionError> {
+		let bytes = self.take(4)?;
+
+		Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+	}
+
+	/// Reads a string prefixed with its length.
+	fn string(&mut self) -> Result<&'a str, SectionError> {
+		let len = usize::from(self.u8()?);
+
+		core::str::from_utf8(self.take(len)?).map_err(|_| SectionError::InvalidPayload)
+	}
+
+	const fn is_empty(&self) -> bool {
+		self.payload.is_empty()
+	}
+}
@@ -0,0 +1,407 @@
+//! The silverplate protocol, used by the host to talk to the device over the vendor HID reports.
+//!
+//! Every vendor report carries a single frame, in both directions:
+//!
+//! | Offset      | Size | Field                                                  |
+//! |-------------|------|--------------------------------------------------------|
+//! | 0           | 1    | Protocol version, [`PROTOCOL_VERSION`]                 |
+//! | 1           | 1    | [`Command`]                                            |
+//! | 2           | 1    | Sequence number                                        |
+//! | 3           | 1    | [`Status`], always [`Status::Ok`] in requests          |
+//! | 4           | 1    | Payload length, at most [`MAX_PAYLOAD_LEN`]            |
+//! | 5           | len  | Payload                                                |
+//! | 5 + len     | 4    | CRC-32 of everything before it, little endian          |
+//!
+//! The rest of the report is padding. Multi-byte values are little endian.
+//!
+//! The device answers every request with a frame that has the same command and sequence number.
+//! Frames the device sends on its own, like the report format changing, use the sequence number
+//! [`NOTIFICATION_SEQUENCE`], so the host should number its requests starting from 1.
+//!
+//! When a request can't be handled, the response has an error status and no payload. A response
+//! to a request with an unsupported protocol version uses the version of the device.
+//!
+//! The commands are handled by [`handle`], on top of a [`Device`].
+
+mod handler;
+
+pub use handler::{Device, FirmwareInfo, handle, notification};
+
+use crate::crc::Crc32;
+use crate::keyboard::layer::LAYER_COUNT;
+
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// The ID of the vendor reports sent by the host.
+pub const REPORT_ID_OUT: u8 = 0x03;
+/// The ID of the vendor reports sent by the device.
+pub const REPORT_ID_IN: u8 = 0x04;
+
+/// The size of a vendor report, without the report ID.
+pub const REPORT_LEN: usize = 63;
+
+const HEADER_LEN: usize = 5;
+const CRC_LEN: usize = 4;
+
+pub const MAX_PAYLOAD_LEN: usize = REPORT_LEN - HEADER_LEN - CRC_LEN;
+
+/// The sequence number of the frames the device sends on its own.
+pub const NOTIFICATION_SEQUENCE: u8 = 0;
+
+/// The size of the header of a keymap chunk: the layer, the part of the layer and the number of
+/// actions.
+const KEYMAP_CHUNK_HEADER_LEN: usize = 3;
+
+/// The number of actions in a keymap chunk, 2 bytes each.
+pub const ACTIONS_PER_CHUNK: usize = (MAX_PAYLOAD_LEN - KEYMAP_CHUNK_HEADER_LEN) / 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Command {
+	/// Request: nothing.
+	///
+	/// Response: the highest protocol version, [`MAX_PAYLOAD_LEN`] and the [`Capabilities`] as a u32.
+	GetCapabilities = 0x00,
+	/// Request: nothing.
+	///
+	/// Response: the build date bitmap as a u16, the version bitmap as a u32, then the name and the
+	/// author, each as a length byte followed by UTF-8 bytes.
+	GetFirmwareInfo = 0x01,
+	/// Request: nothing.
+	///
+	/// Response: the number of rows, columns and layers, the number of chunks a layer is split into
+	/// and [`ACTIONS_PER_CHUNK`].
+	GetKeymapInfo = 0x02,
+	/// Request: the layer and the part of the layer.
+	///
+	/// Response: the layer, the part, the number of actions and the actions as u16 values, for the
+	/// matrix positions in row-major order. Positions without a key have the `NO` action.
+	GetKeymapChunk = 0x03,
+	/// Request: the layer, the row, the column and the action as a u16.
+	///
+	/// Response: nothing. The change is pending until it's committed.
+	SetKey = 0x04,
+	/// Request: the same layout as the [`GetKeymapChunk`](Self::GetKeymapChunk) response. The
+	/// actions of positions without a key are ignored.
+	///
+	/// Response: nothing. The change is pending until it's committed.
+	SetKeymapChunk = 0x05,
+	/// Request: nothing.
+	///
+	/// Response: nothing. The pending changes are stored and used.
+	CommitKeymap = 0x06,
+	/// Request: nothing.
+	///
+	/// Response: nothing. The pending changes are dropped.
+	RevertKeymap = 0x07,
+	/// Request: nothing.
+	///
+	/// Response: the [`ReportFormat`]. Also sent as a notification when the format changes.
+	GetReportFormat = 0x08,
+	/// Request: 1 to use the NKRO report, 0 to use the 6KRO one.
+	///
+	/// Response: the [`ReportFormat`].
+	SetNkro = 0x09,
+	/// Request: nothing.
+	///
+	/// Response: the [`LayerState`], as the bitmap of the active layers and the default layer.
+	GetLayerState = 0x0A,
+	/// Request: the [`LayerState`] to use, in the same layout as the response of
+	/// [`GetLayerState`](Self::GetLayerState).
+	///
+	/// Response: nothing. The layers change before the next keys are handled.
+	SetLayerState = 0x0B,
+	/// Request: the [`RebootMode`].
+	///
+	/// Response: nothing. The device reboots shortly after answering.
+	Reboot = 0x0C,
+}
+
+impl Command {
+	#[must_use]
+	pub const fn from_u8(value: u8) -> Option<Self> {
+		match value {
+			0x00 => Some(Self::GetCapabilities),
+			0x01 => Some(Self::GetFirmwareInfo),
+			0x02 => Some(Self::GetKeymapInfo),
+			0x03 => Some(Self::GetKeymapChunk),
+			0x04 => Some(Self::SetKey),
+			0x05 => Some(Self::SetKeymapChunk),
+			0x06 => Some(Self::CommitKeymap),
+			0x07 => Some(Self::RevertKeymap),
+			0x08 => Some(Self::GetReportFormat),
+			0x09 => Some(Self::SetNkro),
+			0x0A => Some(Self::GetLayerState),
+			0x0B => Some(Self::SetLayerState),
+			0x0C => Some(Self::Reboot),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Status {
+	Ok = 0x00,
+	/// The device doesn't speak the protocol version of the request.
+	UnsupportedVersion = 0x01,
+	UnknownCommand = 0x02,
+	/// The payload length doesn't fit the frame or doesn't match the command.
+	InvalidLength = 0x03,
+	/// The CRC doesn't match the frame.
+	BadCrc = 0x04,
+	/// The payload has a value out of range, like a layer or a position without a key.
+	InvalidArgument = 0x05,
+	/// The device has no storage to persist the changes in.
+	NoStorage = 0x06,
+	/// The storage failed to persist the changes.
+	StorageError = 0x07,
+	/// The device knows the request but can't do what it asks for.
+	Unsupported = 0x08,
+}
+
+impl Status {
+	#[must_use]
+	pub const fn from_u8(value: u8) -> Option<Self> {
+		match value {
+			0x00 => Some(Self::Ok),
+			0x01 => Some(Self::UnsupportedVersion),
+			0x02 => Some(Self::UnknownCommand),
+			0x03 => Some(Self::InvalidLength),
+			0x04 => Some(Self::BadCrc),
+			0x05 => Some(Self::InvalidArgument),
+			0x06 => Some(Self::NoStorage),
+			0x07 => Some(Self::StorageError),
+			0x08 => Some(Self::Unsupported),
+			_ => None,
+		}
+	}
+}
+
+/// The features of the protocol a device supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+	pub const KEYMAP_READ: Self = Self(1 << 0);
+	pub const KEYMAP_WRITE: Self = Self(1 << 1);
+	/// Committed keymaps survive a reboot.
+	pub const KEYMAP_PERSIST: Self = Self(1 << 2);
+	pub const REPORT_FORMAT: Self = Self(1 << 3);
+	pub const LAYER_STATE: Self = Self(1 << 4);
+	pub const REBOOT: Self = Self(1 << 5);
+	/// The device can reboot into its bootloader.
+	pub const BOOTLOADER: Self = Self(1 << 6);
+
+	#[must_use]
+	pub const fn empty() -> Self {
+		Self(0)
+	}
+
+	#[must_use]
+	pub const fn from_bits(bits: u32) -> Self {
+		Self(bits)
+	}
+
+	#[must_use]
+	pub const fn bits(self) -> u32 {
+		self.0
+	}
+
+	#[must_use]
+	pub const fn union(self, other: Self) -> Self {
+		Self(self.0 | other.0)
+	}
+
+	#[must_use]
+	pub const fn contains(self, other: Self) -> bool {
+		self.0 & other.0 == other.0
+	}
+}
+
+/// The format of the keyboard reports the device sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ReportFormat {
+	SixKro = 0x00,
+	Nkro = 0x01,
+	/// The host asked for the boot protocol.
+	Boot = 0x02,
+}
+
+impl ReportFormat {
+	#[must_use]
+	pub const fn from_u8(value: u8) -> Option<Self> {
+		match value {
+			0x00 => Some(Self::SixKro),
+			0x01 => Some(Self::Nkro),
+			0x02 => Some(Self::Boot),
+			_ => None,
+		}
+	}
+}
+
+/// The layers the keys are looked up in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerState {
+	/// The bitmap of the layers active on top of the default one.
+	pub active: u8,
+	/// The layer that is always active and is checked last.
+	pub default: u8,
+}
+
+impl LayerState {
+	/// Checks that every layer exists.
+	#[must_use]
+	pub const fn is_valid(self) -> bool {
+		(self.default as usize) < LAYER_COUNT && (self.active as usize) >> LAYER_COUNT == 0
+	}
+}
+
+/// What the device runs after a reboot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RebootMode {
+	Firmware = 0x00,
+	/// The bootloader of the MCU, to flash a new firmware.
+	Bootloader = 0x01,
+}
+
+impl RebootMode {
+	#[must_use]
+	pub const fn from_u8(value: u8) -> Option<Self> {
+		match value {
+			0x00 => Some(Self::Firmware),
+			0x01 => Some(Self::Bootloader),
+			_ => None,
+		}
+	}
+}
+
+/// A frame that can't be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+	/// The report is shorter than the frame it contains.
+	TooShort,
+	UnsupportedVersion(u8),
+	InvalidLength,
+	BadCrc,
+}
+
+impl DecodeError {
+	/// The status a device answers the frame with.
+	#[must_use]
+	pub const fn status(self) -> Status {
+		match self {
+			Self::TooShort | Self::InvalidLength => Status::InvalidLength,
+			Self::UnsupportedVersion(_) => Status::UnsupportedVersion,
+			Self::BadCrc => Status::BadCrc,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+	pub version: u8,
+	pub command: u8,
+	pub sequence: u8,
+	pub status: u8,
+	len: u8,
+	payload: [u8; MAX_PAYLOAD_LEN],
+}
+
+impl Frame {
+	/// Creates a frame of the current protocol version.
+	///
+	/// Returns `None` if the payload is longer than [`MAX_PAYLOAD_LEN`].
+	#[must_use]
+	pub fn new(command: u8, sequence: u8, status: Status, payload: &[u8]) -> Option<Self> {
+		let mut frame = Self {
+			version: PROTOCOL_VERSION,
+			command,
+			sequence,
+			status: status as u8,
+			len: 0,
+			payload: [0; MAX_PAYLOAD_LEN],
+		};
+
+		frame.payload.get_mut(..payload.len())?.copy_from_slice(payload);
+		frame.len = u8::try_from(payload.len()).ok()?;
+
+		Some(frame)
+	}
+
+	/// Creates a request, which always has the [`Status::Ok`] status.
+	#[must_use]
+	pub fn request(command: Command, sequence: u8, payload: &[u8]) -> Option<Self> {
+		Self::new(command as u8, sequence, Status::Ok, payload)
+	}
+
+	#[must_use]
+	pub fn payload(&self) -> &[u8] {
+		&self.payload[..usize::from(self.len)]
+	}
+
+	/// The status of a response, `None` if the device sent one this side doesn't know.
+	#[must_use]
+	pub const fn status(&self) -> Option<Status> {
+		Status::from_u8(self.status)
+	}
+
+	#[must_use]
+	pub fn encode(&self) -> [u8; REPORT_LEN] {
+		let mut bytes = [0; REPORT_LEN];
+
+		let len = usize::from(self.len);
+		let crc_start = HEADER_LEN + len;
+
+		bytes[..HEADER_LEN].copy_from_slice(&[self.version, self.command, self.sequence, self.status, self.len]);
+		bytes[HEADER_LEN..crc_start].copy_from_slice(self.payload());
+
+		let crc = Crc32::new().update(&bytes[..crc_start]).finish();
+		bytes[crc_start..crc_start + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+
+		bytes
+	}
+
+	/// # Errors
+	///
+	/// Returns an error if the bytes don't contain a valid frame of the current protocol version.
+	pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+		let &[version, command, sequence, status, len, ..] = bytes else {
+			return Err(DecodeError::TooShort);
+		};
+
+		if version != PROTOCOL_VERSION {
+			return Err(DecodeError::UnsupportedVersion(version));
+		}
+
+		let payload_len = usize::from(len);
+
+		if payload_len > MAX_PAYLOAD_LEN {
+			return Err(DecodeError::InvalidLength);
+		}
+
+		let crc_start = HEADER_LEN + payload_len;
+
+		let crc_bytes = bytes.get(crc_start..crc_start + CRC_LEN).ok_or(DecodeError::TooShort)?;
+
+		let crc = u32::from_le_bytes([crc_bytes[0], crc_bytes[1], crc_bytes[2], crc_bytes[3]]);
+
+		if crc != Crc32::new().update(&bytes[..crc_start]).finish() {
+			return Err(DecodeError::BadCrc);
+		}
+
+		let mut payload = [0; MAX_PAYLOAD_LEN];
+		payload[..payload_len].copy_from_slice(&bytes[HEADER_LEN..crc_start]);
+
+		Ok(Self {
+			version,
+			command,
+			sequence,
+			status,
+			len,
+			payload,
+		})
+	}
+}
@@ -0,0 +1,324 @@
+use super::{
+	ACTIONS_PER_CHUNK, Capabilities, Command, Frame, LayerState, MAX_PAYLOAD_LEN, NOTIFICATION_SEQUENCE,
+	PROTOCOL_VERSION, REPORT_LEN, RebootMode, ReportFormat, Status,
+};
+use crate::keyboard::Action;
+use crate::keyboard::layer::LAYER_COUNT;
+
+/// The information about the firmware sent to the host.
+#[derive(Debug, Clone, Copy)]
+pub struct FirmwareInfo<'a> {
+	/// The build date, packed into a bitmap.
+	pub build_date: u16,
+	/// The firmware version, packed into a bitmap.
+	pub version: u32,
+	pub name: &'a str,
+	pub author: &'a str,
+}
+
+/// The side of the device the commands act on.
+///
+/// Keymap changes go to a pending copy of the keymaps, which only replaces the active one once it's
+/// committed.
+pub trait Device {
+	fn capabilities(&self) -> Capabilities;
+
+	fn firmware_info(&self) -> FirmwareInfo<'_>;
+
+	/// The number of rows and columns of the matrix.
+	fn matrix_size(&self) -> (usize, usize);
+
+	/// The action of the key at the position in the active keymaps, `None` if there is no key.
+	fn action(&self, layer: usize, row: usize, col: usize) -> Option<Action>;
+
+	/// Sets the action of the key at the position in the pending keymaps. Returns `false` if there is
+	/// no key.
+	fn set_action(&mut self, layer: usize, row: usize, col: usize, action: Action) -> bool;
+
+	/// Stores the pending keymaps and starts using them.
+	///
+	/// # Errors
+	///
+	/// Returns the status to answer with if the keymaps can't be stored.
+	fn commit(&mut self) -> Result<(), Status>;
+
+	/// Drops the pending changes.
+	fn revert(&mut self);
+
+	fn report_format(&self) -> ReportFormat;
+
+	/// Chooses between the NKRO and the 6KRO report.
+	fn set_nkro(&mut self, enabled: bool);
+
+	fn layer_state(&self) -> LayerState;
+
+	/// Replaces the layer state. The state was checked to be valid.
+	fn set_layer_state(&mut self, state: LayerState);
+
+	/// Reboots the device once the response was sent.
+	///
+	/// # Errors
+	///
+	/// Returns the status to answer with if the device can't reboot that way.
+	fn reboot(&mut self, mode: RebootMode) -> Result<(), Status>;
+}
+
+/// Handles a request and returns the response to send back.
+///
+/// `request` is the vendor report without the report ID.
+pub fn handle(device: &mut impl Device, request: &[u8]) -> [u8; REPORT_LEN] {
+	let command = request.get(1).copied().unwrap_or(0);
+	let sequence = request.get(2).copied().unwrap_or(0);
+
+	let response = match Frame::decode(request) {
+		Ok(frame) => match Command::from_u8(frame.command) {
+			Some(command) => handle_command(device, command, frame.payload()),
+			None => Err(Status::UnknownCommand),
+		},
+		Err(error) => Err(error.status()),
+	};
+
+	let frame = match response {
+		Ok(payload) => Frame::new(command, sequence, Status::Ok, payload.as_slice()),
+		Err(status) => Frame::new(command, sequence, status, &[]),
+	};
+
+	// The payloads are built to fit.
+	frame.map_or([0; REPORT_LEN], |frame| frame.encode())
+}
+
+/// Builds a frame the device sends without being asked.
+///
+/// # Panics
+///
+/// Panics if the payload is longer than [`MAX_PAYLOAD_LEN`].
+#[must_use]
+pub fn notification(command: Command, payload: &[u8]) -> [u8; REPORT_LEN] {
+	Frame::new(command as u8, NOTIFICATION_SEQUENCE, Status::Ok, payload)
+		.expect("The notification payload doesn't fit in a frame.")
+		.encode()
+}
+
+/// A response payload under construction.
+struct Payload {
+	bytes: [u8; MAX_PAYLOAD_LEN],
+	len: usize,
+}
+
+impl Payload {
+	const fn new() -> Self {
+		Self {
+			bytes: [0; MAX_PAYLOAD_LEN],
+			len: 0,
+		}
+	}
+
+	/// Appends as much of `bytes` as fits.
+	fn push(&mut self, bytes: &[u8]) {
+		let len = bytes.len().min(MAX_PAYLOAD_LEN - self.len);
+
+		self.bytes[self.len..self.len + len].copy_from_slice(&bytes[..len]);
+		self.len += len;
+	}
+
+	fn as_slice(&self) -> &[u8] {
+		&self.bytes[..self.len]
+	}
+}
+
+fn handle_command(device: &mut impl Device, command: Command, request: &[u8]) -> Result<Payload, Status> {
+	let mut payload = Payload::new();
+
+	match command {
+		Command::GetCapabilities => {
+			expect_len(request, 0)?;
+
+			#[allow(clippy::cast_possible_truncation, reason = "The payload is shorter than a report.")]
+			payload.push(&[PROTOCOL_VERSION, MAX_PAYLOAD_LEN as u8]);
+			payload.push(&device.capabilities().bits().to_le_bytes());
+		}
+		Command::GetFirmwareInfo => {
+			expect_len(request, 0)?;
+
+			push_firmware_info(&mut payload, device.firmware_info());
+		}
+		Command::GetKeymapInfo => {
+			expect_len(request, 0)?;
+
+			let (rows, cols) = device.matrix_size();
+			let chunks_per_layer = (rows * cols).div_ceil(ACTIONS_PER_CHUNK);
+
+			let sizes = [rows, cols, LAYER_COUNT, chunks_per_layer, ACTIONS_PER_CHUNK];
+
+			for size in sizes {
+				payload.push(&[u8::try_from(size).map_err(|_| Status::InvalidArgument)?]);
+			}
+		}
+		Command::GetKeymapChunk => {
+			let &[layer, part] = request else {
+				return Err(Status::InvalidLength);
+			};
+
+			push_keymap_chunk(&mut payload, device, layer, part)?;
+		}
+		Command::SetKey => {
+			let &[layer, row, col, action_low, action_high] = request else {
+				return Err(Status::InvalidLength);
+			};
+
+			let layer = usize::from(layer);
+			let action = Action::from_bits(u16::from_le_bytes([action_low, action_high]));
+
+			if layer >= LAYER_COUNT
+				|| !action.has_valid_layer()
+				|| !device.set_action(layer, usize::from(row), usize::from(col), action)
+			{
+				return Err(Status::InvalidArgument);
+			}
+		}
+		Command::SetKeymapChunk => set_keymap_chunk(device, request)?,
+		Command::CommitKeymap => {
+			expect_len(request, 0)?;
+
+			device.commit()?;
+		}
+		Command::RevertKeymap => {
+			expect_len(request, 0)?;
+
+			device.revert();
+		}
+		Command::GetReportFormat => {
+			expect_len(request, 0)?;
+
+			payload.push(&[device.report_format() as u8]);
+		}
+		Command::SetNkro => {
+			let &[enabled] = request else {
+				return Err(Status::InvalidLength);
+			};
+
+			match enabled {
+				0 => device.set_nkro(false),
+				1 => device.set_nkro(true),
+				_ => return Err(Status::InvalidArgument),
+			}
+
+			payload.push(&[device.report_format() as u8]);
+		}
+		Command::GetLayerState => {
+			expect_len(request, 0)?;
+
+			let state = device.layer_state();
+
+			payload.push(&[state.active, state.default]);
+		}
+		Command::SetLayerState => {
+			let &[active, default] = request else {
+				return Err(Status::InvalidLength);
+			};
+
+			let state = LayerState { active, default };
+
+			if !state.is_valid() {
+				return Err(Status::InvalidArgument);
+			}
+
+			device.set_layer_state(state);
+		}
+		Command::Reboot => {
+			let &[mode] = request else {
+				return Err(Status::InvalidLength);
+			};
+
+			device.reboot(RebootMode::from_u8(mode).ok_or(Status::InvalidArgument)?)?;
+		}
+	}
+
+	Ok(payload)
+}
+
+fn push_firmware_info(payload: &mut Payload, info: FirmwareInfo) {
+	payload.push(&info.build_date.to_le_bytes());
+	payload.push(&info.version.to_le_bytes());
+
+	// Long texts are cut to what's left of the payload, keeping room for the length of the author.
+	for (text, reserved) in [(info.name, 2), (info.author, 1)] {
+		let len = text.len().min(MAX_PAYLOAD_LEN - payload.len - reserved);
+
+		#[allow(clippy::cast_possible_truncation, reason = "The payload is shorter than a report.")]
+		payload.push(&[len as u8]);
+		payload.push(&text.as_bytes()[..len]);
+	}
+}
+
+fn push_keymap_chunk(payload: &mut Payload, device: &impl Device, layer: u8, part: u8) -> Result<(), Status> {
+	let (rows, cols) = device.matrix_size();
+
+	let positions = chunk_positions(usize::from(layer), usize::from(part), rows, cols)?;
+
+	#[allow(
+		clippy::cast_possible_truncation,
+		reason = "A chunk has at most `ACTIONS_PER_CHUNK` actions."
+	)]
+	payload.push(&[layer, part, positions.len() as u8]);
+
+	for position in positions {
+		let action = device
+			.action(usize::from(layer), position / cols, position % cols)
+			.unwrap_or(Action::NO);
+
+		payload.push(&action.to_bits().to_le_bytes());
+	}
+
+	Ok(())
+}
+
+fn set_keymap_chunk(device: &mut impl Device, request: &[u8]) -> Result<(), Status> {
+	let &[layer, part, count, ref actions @ ..] = request else {
+		return Err(Status::InvalidLength);
+	};
+
+	let (rows, cols) = device.matrix_size();
+
+	let positions = chunk_positions(usize::from(layer), usize::from(part), rows, cols)?;
+
+	if usize::from(count) != positions.len() || actions.len() != positions.len() * 2 {
+		return Err(Status::InvalidLength);
+	}
+
+	let actions = actions
+		.chunks_exact(2)
+		.map(|bytes| Action::from_bits(u16::from_le_bytes([bytes[0], bytes[1]])));
+
+	// The whole chunk is refused, so none of it is applied.
+	if !actions.clone().all(Action::has_valid_layer) {
+		return Err(Status::InvalidArgument);
+	}
+
+	for (position, action) in positions.zip(actions) {
+		// Positions without a key don't take any action.
+		device.set_action(usize::from(layer), position / cols, position % cols, action);
+	}
+
+	Ok(())
+}
+
+const fn expect_len(request: &[u8], len: usize) -> Result<(), Status> {
+	if request.len() == len {
+		Ok(())
+	} else {
+		Err(Status::InvalidLength)
+	}
+}
+
+/// The row-major matrix positions in a chunk of a layer.
+fn chunk_positions(layer: usize, part: usize, rows: usize, cols: usize) -> Result<core::ops::Range<usize>, Status> {
+	let positions = rows * cols;
+	let start = part * ACTIONS_PER_CHUNK;
+
+	if layer >= LAYER_COUNT || start >= positions {
+		return Err(Status::InvalidArgument);
+	}
+
+	Ok(start..(start + ACTIONS_PER_CHUNK).min(positions))
+}
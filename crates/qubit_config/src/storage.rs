@@ -0,0 +1,624 @@
+//! A wear-levelled key-value store kept in the flash region the linker script reserves.
+//!
+//! The region is split into sectors that are used as a ring. Every value is appended to the
+//! active sector as a record and the newest record of a key replaces the older ones. Once the
+//! active sector is full, the next one becomes active and the records of the oldest sector that
+//! are still in use are moved into it before that sector is erased. This keeps an erased sector
+//! ready at all times and erases every sector as often as the others.
+//!
+//! The store doesn't know how the flash is accessed, that is done by a [`Flash`] backend.
+
+#[cfg(feature = "std")]
+mod ram;
+
+#[cfg(feature = "std")]
+pub use ram::{RamFlash, RamFlashError};
+
+use crate::crc::Crc32;
+use crate::mcu::Mcu;
+
+/// Marks a sector that was prepared by the store.
+const SECTOR_MAGIC: u32 = u32::from_le_bytes(*b"QBST");
+/// The magic followed by the sequence number of the sector.
+const SECTOR_HEADER_LEN: u32 = 8;
+/// The key, the length of the value and the CRC of the record.
+const RECORD_HEADER_LEN: u32 = 8;
+/// Records start on a multiple of this.
+const RECORD_ALIGN: u32 = 4;
+/// The key of an erased record header.
+const ERASED_KEY: u16 = 0xFFFF;
+/// The size of the buffer used to move data around the flash.
+const CHUNK_LEN: usize = 32;
+
+/// The part of the flash reserved for the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageLayout {
+	/// The address of the first sector.
+	pub origin: u32,
+	/// The size of an erasable sector.
+	pub sector_size: u32,
+	pub sector_count: u32,
+}
+
+impl StorageLayout {
+	/// The store takes the last sectors of the flash.
+	///
+	/// # Panics
+	///
+	/// Panics if the flash is too small to fit the store.
+	#[must_use]
+	pub const fn new(mcu: Mcu, flash_size: u32) -> Self {
+		let (sector_size, sector_count) = match mcu {
+			// The smallest erasable unit of the external flash is a 4 KiB sector.
+			Mcu::RP2040 => (0x1000, 4),
+			// The last sectors are 128 KiB and the smaller ones sit at the start of the flash,
+			// where the vector table lives.
+			Mcu::STM32F411 => {
+				assert!(
+					flash_size >= 0x8_0000,
+					"The storage needs the two last 128 KiB sectors of a 512 KiB flash."
+				);
+
+				(0x2_0000, 2)
+			}
+		};
+
+		let length = sector_size * sector_count;
+
+		assert!(flash_size > length, "The flash is too small to fit the storage.");
+
+		Self {
+			origin: mcu.flash_origin() + flash_size - length,
+			sector_size,
+			sector_count,
+		}
+	}
+
+	#[must_use]
+	pub const fn length(&self) -> u32 {
+		self.sector_size * self.sector_count
+	}
+}
+
+/// Access to the flash sectors of the store.
+///
+/// Offsets are relative to the start of the store. Erasing sets every byte of a sector to `0xFF`
+/// and writing can only clear bits, so bytes need to be erased before they are written again.
+pub trait Flash {
+	type Error;
+
+	/// The size of an erasable sector.
+	fn sector_size(&self) -> u32;
+
+	fn sector_count(&self) -> u32;
+
+	/// # Errors
+	///
+	/// Returns an error if the flash can't be read.
+	fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error>;
+
+	/// # Errors
+	///
+	/// Returns an error if the flash can't be written.
+	fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error>;
+
+	/// # Errors
+	///
+	/// Returns an error if the sector can't be erased.
+	fn erase(&mut self, sector: u32) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error<E> {
+	/// The flash backend failed.
+	Flash(E),
+	/// The store needs at least two sectors to move records around.
+	TooFewSectors,
+	/// The key is reserved to mark erased records.
+	InvalidKey,
+	/// Values can't be empty, an empty record marks a removed key.
+	Empty,
+	/// The value doesn't fit in a sector.
+	TooLarge,
+	/// The values in use don't leave enough space for the new one.
+	Full,
+	/// The stored value doesn't fit in the given buffer.
+	BufferTooSmall,
+}
+
+impl<E> From<E> for Error<E> {
+	fn from(error: E) -> Self {
+		Self::Flash(error)
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RecordHeader {
+	key: u16,
+	len: u16,
+	crc: u32,
+}
+
+impl RecordHeader {
+	const fn from_bytes(bytes: [u8; RECORD_HEADER_LEN as usize]) -> Self {
+		Self {
+			key: u16::from_le_bytes([bytes[0], bytes[1]]),
+			len: u16::from_le_bytes([bytes[2], bytes[3]]),
+			crc: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+		}
+	}
+
+	const fn to_bytes(self) -> [u8; RECORD_HEADER_LEN as usize] {
+		let key = self.key.to_le_bytes();
+		let len = self.len.to_le_bytes();
+		let crc = self.crc.to_le_bytes();
+
+		[key[0], key[1], len[0], len[1], crc[0], crc[1], crc[2], crc[3]]
+	}
+
+	/// The space the record takes in the sector.
+	const fn size(self) -> u32 {
+		record_size(self.len as u32)
+	}
+}
+
+const fn record_size(len: u32) -> u32 {
+	RECORD_HEADER_LEN + len.next_multiple_of(RECORD_ALIGN)
+}
+
+/// The position of a record in the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Location {
+	sector: u32,
+	/// The offset of the record inside the sector.
+	offset: u32,
+}
+
+/// A key-value store on top of a [`Flash`] backend.
+///
+/// Values are at most a sector long and together they need to fit in a single sector, since all
+/// of them may end up in the same one.
+#[derive(Debug)]
+pub struct Store<F> {
+	flash: F,
+	active: u32,
+	/// The sequence number of the active sector. Older sectors have smaller numbers.
+	sequence: u32,
+	/// Where the next record goes in the active sector.
+	write_offset: u32,
+}
+
+impl<F: Flash> Store<F> {
+	/// Finds the active sector and prepares a new store if there is none.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the flash has less than two sectors or if the flash backend fails.
+	pub fn mount(flash: F) -> Result<Self, Error<F::Error>> {
+		if flash.sector_count() < 2 {
+			return Err(Error::TooFewSectors);
+		}
+
+		let mut store = Self {
+			flash,
+			active: 0,
+			sequence: 0,
+			write_offset: SECTOR_HEADER_LEN,
+		};
+
+		let mut newest = None;
+
+		for sector in 0..store.flash.sector_count() {
+			if let Some(sequence) = store.sector_sequence(sector)?
+				&& newest.is_none_or(|(_, newest_sequence)| sequence > newest_sequence)
+			{
+				newest = Some((sector, sequence));
+			}
+		}
+
+		if let Some((sector, sequence)) = newest {
+			store.active = sector;
+			store.sequence = sequence;
+			store.write_offset = store.records_end(sector)?;
+
+			// A sector is only left behind when moving its records was interrupted.
+			store.reclaim(store.next_sector(sector))?;
+		} else {
+			store.start_sector(0, 0)?;
+		}
+
+		Ok(store)
+	}
+
+	/// Reads the value of `key` into `bytes` and returns its length.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the value doesn't fit in `bytes` or if the flash backend fails.
+	pub fn read(&mut self, key: u16, bytes: &mut [u8]) -> Result<Option<usize>, Error<F::Error>> {
+		let Some((location, header)) = self.find(key)? else {
+			return Ok(None);
+		};
+
+		let len = usize::from(header.len);
+
+		if len == 0 {
+			return Ok(None);
+		}
+
+		let bytes = bytes.get_mut(..len).ok_or(Error::BufferTooSmall)?;
+
+		self.flash
+			.read(self.record_offset(location) + RECORD_HEADER_LEN, bytes)?;
+
+		Ok(Some(len))
+	}
+
+	/// Stores `value` as the new value of `key`.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the key is reserved, if the value is empty or doesn't fit, or if the
+	/// flash backend fails.
+	pub fn write(&mut self, key: u16, value: &[u8]) -> Result<(), Error<F::Error>> {
+		if value.is_empty() {
+			return Err(Error::Empty);
+		}
+
+		self.append(key, value)
+	}
+
+	/// Removes the value of `key`.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the key is reserved or if the flash backend fails.
+	pub fn remove(&mut self, key: u16) -> Result<(), Error<F::Error>> {
+		if self.find(key)?.is_none_or(|(_, header)| header.len == 0) {
+			return Ok(());
+		}
+
+		self.append(key, &[])
+	}
+
+	/// Erases every value in the store.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the flash backend fails.
+	pub fn clear(&mut self) -> Result<(), Error<F::Error>> {
+		for sector in 0..self.flash.sector_count() {
+			self.prepare_sector(sector)?;
+		}
+
+		// The new sector continues the sequence, so the erase counts keep growing evenly.
+		let next = self.next_sector(self.active);
+		self.start_sector(next, self.sequence.wrapping_add(1))
+	}
+
+	/// Gives the flash backend back.
+	pub fn into_flash(self) -> F {
+		self.flash
+	}
+
+	fn append(&mut self, key: u16, value: &[u8]) -> Result<(), Error<F::Error>> {
+		if key == ERASED_KEY {
+			return Err(Error::InvalidKey);
+		}
+
+		let len = u16::try_from(value.len()).map_err(|_| Error::TooLarge)?;
+		let size = record_size(u32::from(len));
+
+		if SECTOR_HEADER_LEN + size > self.flash.sector_size() {
+			return Err(Error::TooLarge);
+		}
+
+		if self.write_offset + size > self.flash.sector_size() {
+			// Everything else in use is moved into the next sector, together with the new value.
+			if self.used_size(Some(key))? + size > self.flash.sector_size() - SECTOR_HEADER_LEN {
+				return Err(Error::Full);
+			}
+
+			let next = self.next_sector(self.active);
+
+			self.start_sector(next, self.sequence.wrapping_add(1))?;
+			self.append_record(key, value)?;
+
+			// The old value of the key is now outdated, so it doesn't need to be moved.
+			return self.reclaim(self.next_sector(next));
+		}
+
+		self.append_record(key, value)
+	}
+
+	fn append_record(&mut self, key: u16, value: &[u8]) -> Result<(), Error<F::Error>> {
+		let len = u16::try_from(value.len()).map_err(|_| Error::TooLarge)?;
+
+		let header = RecordHeader {
+			key,
+			len,
+			crc: Crc32::new()
+				.update(&key.to_le_bytes())
+				.update(&len.to_le_bytes())
+				.update(value)
+				.finish(),
+		};
+
+		let offset = self.active * self.flash.sector_size() + self.write_offset;
+
+		// The header goes first, so an interrupted write leaves a record with a wrong CRC behind
+		// instead of data where the next header would go.
+		self.flash.write(offset, &header.to_bytes())?;
+		self.flash.write(offset + RECORD_HEADER_LEN, value)?;
+
+		self.write_offset += header.size();
+
+		Ok(())
+	}
+
+	/// Moves the records of `sector` that are still in use to the active sector and erases it.
+	fn reclaim(&mut self, sector: u32) -> Result<(), Error<F::Error>> {
+		if sector == self.active {
+			return Ok(());
+		}
+
+		if self.sector_sequence(sector)?.is_some() {
+			let mut offset = SECTOR_HEADER_LEN;
+
+			while let Some(header) = self.record_header(sector, offset)? {
+				let location = Location { sector, offset };
+
+				// The oldest sector has nothing older to hide, so removed keys don't need moving.
+				if header.len != 0 && self.find(header.key)?.is_some_and(|(latest, _)| latest == location) {
+					self.copy_record(location, header)?;
+				}
+
+				offset += header.size();
+			}
+		}
+
+		self.prepare_sector(sector)
+	}
+
+	fn copy_record(&mut self, location: Location, header: RecordHeader) -> Result<(), Error<F::Error>> {
+		let size = header.size();
+
+		if self.write_offset + size > self.flash.sector_size() {
+			return Err(Error::Full);
+		}
+
+		let from = self.record_offset(location);
+		let to = self.active * self.flash.sector_size() + self.write_offset;
+
+		let mut chunk = [0; CHUNK_LEN];
+		let mut copied = 0;
+
+		while copied < size {
+			let len = CHUNK_LEN.min((size - copied) as usize);
+
+			self.flash.read(from + copied, &mut chunk[..len])?;
+			self.flash.write(to + copied, &chunk[..len])?;
+
+			#[allow(clippy::cast_possible_truncation, reason = "`len` is at most `CHUNK_LEN`.")]
+			{
+				copied += len as u32;
+			}
+		}
+
+		self.write_offset += size;
+
+		Ok(())
+	}
+
+	/// The space taken by the values in use, other than the value of `skip`.
+	fn used_size(&mut self, skip: Option<u16>) -> Result<u32, Error<F::Error>> {
+		let mut size = 0;
+
+		let mut sector = self.active;
+		let mut sequence = self.sequence;
+
+		for _ in 0..self.flash.sector_count() {
+			if self.sector_sequence(sector)? != Some(sequence) {
+				break;
+			}
+
+			let mut offset = SECTOR_HEADER_LEN;
+
+			while let Some(header) = self.record_header(sector, offset)? {
+				let location = Location { sector, offset };
+
+				if Some(header.key) != skip
+					&& header.len != 0
+					&& self.find(header.key)?.is_some_and(|(latest, _)| latest == location)
+				{
+					size += header.size();
+				}
+
+				offset += header.size();
+			}
+
+			sector = self.previous_sector(sector);
+			sequence = sequence.wrapping_sub(1);
+		}
+
+		Ok(size)
+	}
+
+	/// Finds the newest valid record of `key`.
+	fn find(&mut self, key: u16) -> Result<Option<(Location, RecordHeader)>, Error<F::Error>> {
+		let mut sector = self.active;
+		let mut sequence = self.sequence;
+
+		for _ in 0..self.flash.sector_count() {
+			if self.sector_sequence(sector)? != Some(sequence) {
+				break;
+			}
+
+			let mut found = None;
+			let mut offset = SECTOR_HEADER_LEN;
+
+			while let Some(header) = self.record_header(sector, offset)? {
+				let location = Location { sector, offset };
+
+				if header.key == key && self.is_valid(location, header)? {
+					found = Some((location, header));
+				}
+
+				offset += header.size();
+			}
+
+			if found.is_some() {
+				return Ok(found);
+			}
+
+			sector = self.previous_sector(sector);
+			sequence = sequence.wrapping_sub(1);
+		}
+
+		Ok(None)
+	}
+
+	fn is_valid(&mut self, location: Location, header: RecordHeader) -> Result<bool, Error<F::Error>> {
+		let mut crc = Crc32::new()
+			.update(&header.key.to_le_bytes())
+			.update(&header.len.to_le_bytes());
+
+		let offset = self.record_offset(location) + RECORD_HEADER_LEN;
+
+		let mut chunk = [0; CHUNK_LEN];
+		let mut read = 0;
+
+		while read < usize::from(header.len) {
+			let len = CHUNK_LEN.min(usize::from(header.len) - read);
+
+			#[allow(clippy::cast_possible_truncation, reason = "`read` is below `u16::MAX`.")]
+			self.flash.read(offset + read as u32, &mut chunk[..len])?;
+
+			crc = crc.update(&chunk[..len]);
+			read += len;
+		}
+
+		Ok(crc.finish() == header.crc)
+	}
+
+	/// Reads the header of the record at `offset`, if there is one.
+	fn record_header(&mut self, sector: u32, offset: u32) -> Result<Option<RecordHeader>, Error<F::Error>> {
+		let sector_size = self.flash.sector_size();
+
+		if offset + RECORD_HEADER_LEN > sector_size {
+			return Ok(None);
+		}
+
+		let mut bytes = [0; RECORD_HEADER_LEN as usize];
+		self.flash.read(sector * sector_size + offset, &mut bytes)?;
+
+		let header = RecordHeader::from_bytes(bytes);
+
+		// A header that was only partly written can claim more than what's left of the sector.
+		if header.key == ERASED_KEY || offset + header.size() > sector_size {
+			return Ok(None);
+		}
+
+		Ok(Some(header))
+	}
+
+	/// Finds where the records of `sector` end.
+	fn records_end(&mut self, sector: u32) -> Result<u32, Error<F::Error>> {
+		let mut offset = SECTOR_HEADER_LEN;
+
+		while let Some(header) = self.record_header(sector, offset)? {
+			offset += header.size();
+		}
+
+		// A broken header still takes space, since its bytes can't be written again.
+		if offset + RECORD_HEADER_LEN <= self.flash.sector_size() {
+			let mut bytes = [0; RECORD_HEADER_LEN as usize];
+			self.flash
+				.read(sector * self.flash.sector_size() + offset, &mut bytes)?;
+
+			if bytes != [0xFF; RECORD_HEADER_LEN as usize] {
+				offset = self.flash.sector_size();
+			}
+		}
+
+		Ok(offset)
+	}
+
+	fn sector_sequence(&mut self, sector: u32) -> Result<Option<u32>, Error<F::Error>> {
+		let mut bytes = [0; SECTOR_HEADER_LEN as usize];
+		self.flash.read(sector * self.flash.sector_size(), &mut bytes)?;
+
+		let magic = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+		let sequence = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+
+		Ok((magic == SECTOR_MAGIC).then_some(sequence))
+	}
+
+	/// Makes `sector` the active one.
+	fn start_sector(&mut self, sector: u32, sequence: u32) -> Result<(), Error<F::Error>> {
+		self.prepare_sector(sector)?;
+
+		let magic = SECTOR_MAGIC.to_le_bytes();
+		let sequence_bytes = sequence.to_le_bytes();
+
+		let header = [
+			magic[0],
+			magic[1],
+			magic[2],
+			magic[3],
+			sequence_bytes[0],
+			sequence_bytes[1],
+			sequence_bytes[2],
+			sequence_bytes[3],
+		];
+
+		self.flash.write(sector * self.flash.sector_size(), &header)?;
+
+		self.active = sector;
+		self.sequence = sequence;
+		self.write_offset = SECTOR_HEADER_LEN;
+
+		Ok(())
+	}
+
+	/// Erases `sector`, unless it's erased already.
+	fn prepare_sector(&mut self, sector: u32) -> Result<(), Error<F::Error>> {
+		let sector_size = self.flash.sector_size();
+		let start = sector * sector_size;
+
+		let mut chunk = [0; CHUNK_LEN];
+		let mut offset = 0;
+
+		while offset < sector_size {
+			#[allow(clippy::cast_possible_truncation, reason = "`CHUNK_LEN` fits in a u32.")]
+			let len = CHUNK_LEN.min((sector_size - offset) as usize);
+
+			self.flash.read(start + offset, &mut chunk[..len])?;
+
+			if chunk[..len].iter().any(|&byte| byte != 0xFF) {
+				self.flash.erase(sector)?;
+
+				return Ok(());
+			}
+
+			#[allow(clippy::cast_possible_truncation, reason = "`len` is at most `CHUNK_LEN`.")]
+			{
+				offset += len as u32;
+			}
+		}
+
+		Ok(())
+	}
+
+	fn record_offset(&self, location: Location) -> u32 {
+		location.sector * self.flash.sector_size() + location.offset
+	}
+
+	fn next_sector(&self, sector: u32) -> u32 {
+		(sector + 1) % self.flash.sector_count()
+	}
+
+	fn previous_sector(&self, sector: u32) -> u32 {
+		sector.checked_sub(1).unwrap_or(self.flash.sector_count() - 1)
+	}
+}
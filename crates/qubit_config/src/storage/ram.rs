@@ -0,0 +1,94 @@
+use super::Flash;
+
+/// A flash kept in RAM, used to run the store on the host.
+///
+/// It behaves like a NOR flash: erasing sets the bytes to `0xFF` and writing can only clear bits.
+#[derive(Debug, Clone)]
+pub struct RamFlash {
+	memory: Vec<u8>,
+	sector_size: u32,
+	erase_counts: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RamFlashError {
+	OutOfBounds,
+}
+
+impl RamFlash {
+	/// Creates an erased flash.
+	#[must_use]
+	pub fn new(sector_size: u32, sector_count: u32) -> Self {
+		Self {
+			memory: vec![0xFF; (sector_size * sector_count) as usize],
+			sector_size,
+			erase_counts: vec![0; sector_count as usize],
+		}
+	}
+
+	/// How many times each sector was erased.
+	#[must_use]
+	pub fn erase_counts(&self) -> &[u32] {
+		&self.erase_counts
+	}
+
+	#[must_use]
+	pub fn memory(&self) -> &[u8] {
+		&self.memory
+	}
+
+	/// Gives direct access to the flash contents, to simulate corruption.
+	pub fn memory_mut(&mut self) -> &mut [u8] {
+		&mut self.memory
+	}
+
+	fn range(&self, offset: u32, len: usize) -> Result<core::ops::Range<usize>, RamFlashError> {
+		let start = offset as usize;
+		let end = start + len;
+
+		if end > self.memory.len() {
+			return Err(RamFlashError::OutOfBounds);
+		}
+
+		Ok(start..end)
+	}
+}
+
+impl Flash for RamFlash {
+	type Error = RamFlashError;
+
+	fn sector_size(&self) -> u32 {
+		self.sector_size
+	}
+
+	fn sector_count(&self) -> u32 {
+		u32::try_from(self.erase_counts.len()).unwrap()
+	}
+
+	fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+		let range = self.range(offset, bytes.len())?;
+
+		bytes.copy_from_slice(&self.memory[range]);
+
+		Ok(())
+	}
+
+	fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+		let range = self.range(offset, bytes.len())?;
+
+		for (byte, new) in self.memory[range].iter_mut().zip(bytes) {
+			*byte &= new;
+		}
+
+		Ok(())
+	}
+
+	fn erase(&mut self, sector: u32) -> Result<(), Self::Error> {
+		let range = self.range(sector * self.sector_size, self.sector_size as usize)?;
+
+		self.memory[range].fill(0xFF);
+		self.erase_counts[sector as usize] += 1;
+
+		Ok(())
+	}
+}
@@ -0,0 +1,195 @@
+//! Writes firmware as [UF2](https://github.com/microsoft/uf2) files, which bootloaders like the one
+//! in the RP2040 ROM take from a USB drive.
+//!
+//! A UF2 file is a list of 512 byte blocks, each with 256 bytes of the flash and the address they go
+//! to. Only the pages of the flash that have data get a block, so the gaps between the memory
+//! regions of the firmware don't make the file bigger.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::mcu::Mcu;
+
+pub const MAGIC_START_0: u32 = 0x0A32_4655;
+pub const MAGIC_START_1: u32 = 0x9E5D_5157;
+pub const MAGIC_END: u32 = 0x0AB1_6F30;
+
+pub const BLOCK_LEN: usize = 512;
+/// The offset of the data in a block, after the header.
+pub const DATA_OFFSET: usize = 32;
+/// The most data a block can hold.
+pub const MAX_PAYLOAD_LEN: usize = 476;
+/// The data every written block holds, which is the page size the RP2040 ROM expects.
+pub const PAYLOAD_LEN: usize = 256;
+
+/// The block isn't part of the main flash and is skipped.
+pub const FLAG_NOT_MAIN_FLASH: u32 = 0x0000_0001;
+/// The last word of the header is a family ID instead of the file size.
+pub const FLAG_FAMILY_ID_PRESENT: u32 = 0x0000_2000;
+
+pub const RP2040_FAMILY_ID: u32 = 0xE48B_FF56;
+pub const STM32F4_FAMILY_ID: u32 = 0x5775_5A57;
+
+/// The family ID bootloaders check to take a file for their MCU.
+#[must_use]
+pub const fn family_id(mcu: Mcu) -> u32 {
+	match mcu {
+		Mcu::RP2040 => RP2040_FAMILY_ID,
+		Mcu::STM32F411 => STM32F4_FAMILY_ID,
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Uf2Error {
+	/// The ELF file isn't a 32 bit little endian one, or its headers point outside the file.
+	InvalidElf,
+	/// The ELF file has nothing to write to the flash.
+	Empty,
+}
+
+impl fmt::Display for Uf2Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::InvalidElf => write!(f, "the firmware isn't a valid 32 bit little endian ELF file"),
+			Self::Empty => write!(f, "the firmware has nothing to write to the flash"),
+		}
+	}
+}
+
+impl std::error::Error for Uf2Error {}
+
+/// Data that goes to consecutive addresses of the flash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment<'a> {
+	pub address: u32,
+	pub data: &'a [u8],
+}
+
+/// Writes the segments as UF2 blocks, in the order of their addresses.
+///
+/// The segments are split in pages of [`PAYLOAD_LEN`] bytes, aligned to their size. The parts of a
+/// page without data are zero, and pages without any data are skipped.
+#[must_use]
+pub fn write(segments: &[Segment], family_id: u32) -> Vec<u8> {
+	#[allow(clippy::cast_possible_truncation, reason = "The size of a page fits in 32 bits.")]
+	const PAGE_LEN: u32 = PAYLOAD_LEN as u32;
+
+	let mut pages: BTreeMap<u32, [u8; PAYLOAD_LEN]> = BTreeMap::new();
+
+	for segment in segments {
+		for (address, &byte) in (segment.address..).zip(segment.data) {
+			let page = pages.entry(address - address % PAGE_LEN).or_insert([0; PAYLOAD_LEN]);
+
+			page[(address % PAGE_LEN) as usize] = byte;
+		}
+	}
+
+	#[allow(
+		clippy::cast_possible_truncation,
+		reason = "A 32 bit address space has less than 2^24 pages."
+	)]
+	let count = pages.len() as u32;
+
+	let mut file = Vec::with_capacity(pages.len() * BLOCK_LEN);
+
+	for (index, (address, data)) in (0..).zip(pages) {
+		let header = [
+			MAGIC_START_0,
+			MAGIC_START_1,
+			FLAG_FAMILY_ID_PRESENT,
+			address,
+			PAGE_LEN,
+			index,
+			count,
+			family_id,
+		];
+
+		for word in header {
+			file.extend_from_slice(&word.to_le_bytes());
+		}
+
+		file.extend_from_slice(&data);
+		file.resize(file.len() + MAX_PAYLOAD_LEN - PAYLOAD_LEN, 0);
+		file.extend_from_slice(&MAGIC_END.to_le_bytes());
+	}
+
+	file
+}
+
+/// Writes the parts of an ELF file that are loaded in the flash as UF2 blocks.
+///
+/// # Errors
+///
+/// Returns an error if the file isn't a valid ELF file or has nothing to load.
+pub fn from_elf(elf: &[u8], family_id: u32) -> Result<Vec<u8>, Uf2Error> {
+	let segments = elf_segments(elf)?;
+
+	if segments.is_empty() {
+		return Err(Uf2Error::Empty);
+	}
+
+	Ok(write(&segments, family_id))
+}
+
+/// The loadable segments of an ELF file that have data in the file, at the address they are loaded
+/// from. Data that is copied to the RAM at startup is stored in the flash, so its physical address
+/// is used.
+///
+/// # Errors
+///
+/// Returns an error if the file isn't a 32 bit little endian ELF file or its headers point outside
+/// of it.
+pub fn elf_segments(elf: &[u8]) -> Result<Vec<Segment<'_>>, Uf2Error> {
+	const PROGRAM_HEADER_LEN: usize = 32;
+	const PT_LOAD: u32 = 1;
+
+	if !elf.starts_with(b"\x7FELF") || elf.get(4..6) != Some(&[1, 1]) {
+		return Err(Uf2Error::InvalidElf);
+	}
+
+	let field = |offset: usize| {
+		elf.get(offset..offset + 4)
+			.map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+			.ok_or(Uf2Error::InvalidElf)
+	};
+
+	let half = |offset: usize| {
+		elf.get(offset..offset + 2)
+			.map(|bytes| usize::from(u16::from_le_bytes([bytes[0], bytes[1]])))
+			.ok_or(Uf2Error::InvalidElf)
+	};
+
+	let table = field(0x1C)? as usize;
+	let entry_len = half(0x2A)?;
+	let count = half(0x2C)?;
+
+	if count > 0 && entry_len < PROGRAM_HEADER_LEN {
+		return Err(Uf2Error::InvalidElf);
+	}
+
+	let mut segments = Vec::new();
+
+	for index in 0..count {
+		let entry = table + index * entry_len;
+
+		let kind = field(entry)?;
+		let offset = field(entry + 4)? as usize;
+		let address = field(entry + 12)?;
+		let len = field(entry + 16)?;
+
+		if kind != PT_LOAD || len == 0 {
+			continue;
+		}
+
+		// The segment has to fit in the 32 bit address space.
+		if address.checked_add(len - 1).is_none() {
+			return Err(Uf2Error::InvalidElf);
+		}
+
+		let data = elf.get(offset..offset + len as usize).ok_or(Uf2Error::InvalidElf)?;
+
+		segments.push(Segment { address, data });
+	}
+
+	Ok(segments)
+}
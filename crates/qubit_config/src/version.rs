@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Api {
 	V0,
 	V1,
@@ -16,9 +16,20 @@ impl Api {
 			Self::V3 => 3,
 		}
 	}
+
+	/// The API of the lowest 2 bits of the value.
+	#[must_use]
+	pub const fn from_bits(value: u8) -> Self {
+		match value & 0x03 {
+			0 => Self::V0,
+			1 => Self::V1,
+			2 => Self::V2,
+			_ => Self::V3,
+		}
+	}
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Version {
 	pub api: Api,
 	pub major: u16,
@@ -65,9 +76,51 @@ impl Version {
 		let mut bitmap: u32 = 0;
 		bitmap |= api_v & 0x03;
 		bitmap |= (major & 0x3FF) << 2;
-		bitmap |= (minor & 0x3FF) << 11;
+		bitmap |= (minor & 0x3FF) << 12;
 		bitmap |= (patch & 0x3FF) << 22;
 
 		bitmap
 	}
+
+	/// Reads a version from the bitmap made by [`as_bitmap`](Self::as_bitmap).
+	#[must_use]
+	#[allow(clippy::cast_possible_truncation, reason = "The fields are masked to their size.")]
+	pub const fn from_bitmap(bitmap: u32) -> Self {
+		Self {
+			api: Api::from_bits(bitmap as u8),
+			major: ((bitmap >> 2) & 0x3FF) as u16,
+			minor: ((bitmap >> 12) & 0x3FF) as u16,
+			patch: ((bitmap >> 22) & 0x3FF) as u16,
+		}
+	}
+}
+
+/// The date the firmware was built on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildDate {
+	pub year: u16,
+	pub month: u8,
+	pub day: u8,
+}
+
+impl BuildDate {
+	/// The first year a build date can have.
+	pub const EPOCH_YEAR: u16 = 2024;
+
+	/// Reads a date from the bitmap made by `qubit_macros::build_date_bitmap`.
+	///
+	/// # Layout
+	///
+	/// * Day - 5 bits (0-4)
+	/// * Month - 4 bits (5-8)
+	/// * Years since [`EPOCH_YEAR`](Self::EPOCH_YEAR) - 7 bits (9-15)
+	#[must_use]
+	#[allow(clippy::cast_possible_truncation, reason = "The fields are masked to their size.")]
+	pub const fn from_bitmap(bitmap: u16) -> Self {
+		Self {
+			year: Self::EPOCH_YEAR + (bitmap >> 9),
+			month: ((bitmap >> 5) & 0x0F) as u8,
+			day: (bitmap & 0x1F) as u8,
+		}
+	}
 }
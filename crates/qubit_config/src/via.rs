@@ -0,0 +1,247 @@
+//! The VIA protocol, used by the VIA and Vial configurators over a raw HID interface.
+//!
+//! The interface has its own usage page, [`USAGE_PAGE`], and exchanges reports of [`REPORT_LEN`]
+//! bytes without a report ID. The first byte of a report is the [`Command`] and the device answers
+//! every request by sending the same report back, with the values it asked for filled in. A request
+//! the device can't handle is answered with [`Command::Unhandled`] in its first byte.
+//!
+//! Unlike the rest of the firmware, multi-byte values are big endian.
+//!
+//! Keymaps are exchanged as QMK keycodes, see [`to_keycode`] and [`from_keycode`] for how they map
+//! to [`Action`]s. The commands are handled by [`handle`], on top of a [`Device`].
+
+#[cfg(feature = "std")]
+mod definition;
+mod handler;
+
+#[cfg(feature = "std")]
+pub use definition::definition;
+pub use handler::{Device, handle};
+
+use crate::keyboard::layer::{LAYER_COUNT, LayerAction};
+use crate::keyboard::{Action, ActionKind, FirmwareAction};
+
+/// The version of the protocol the VIA configurator expects.
+pub const PROTOCOL_VERSION: u16 = 0x000C;
+
+pub const USAGE_PAGE: u16 = 0xFF60;
+pub const USAGE: u8 = 0x61;
+
+pub const REPORT_LEN: usize = 32;
+
+/// The number of macros of the macro buffer.
+pub const MACRO_COUNT: u8 = 16;
+
+/// The channel of the backlight values of the custom commands.
+pub const BACKLIGHT_CHANNEL: u8 = 1;
+/// The brightness of the backlight. LEDs that can only be turned on or off treat any value above 0
+/// as on.
+pub const BACKLIGHT_BRIGHTNESS: u8 = 1;
+
+/// The most bytes of a buffer sent in a single report.
+const MAX_BUFFER_CHUNK: usize = REPORT_LEN - 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Command {
+	GetProtocolVersion = 0x01,
+	GetKeyboardValue = 0x02,
+	SetKeyboardValue = 0x03,
+	GetKeycode = 0x04,
+	SetKeycode = 0x05,
+	ResetKeymap = 0x06,
+	CustomSetValue = 0x07,
+	CustomGetValue = 0x08,
+	CustomSave = 0x09,
+	/// Resets every setting of the device: keymaps, macros and lighting.
+	EepromReset = 0x0A,
+	BootloaderJump = 0x0B,
+	GetMacroCount = 0x0C,
+	GetMacroBufferSize = 0x0D,
+	GetMacroBuffer = 0x0E,
+	SetMacroBuffer = 0x0F,
+	ResetMacros = 0x10,
+	GetLayerCount = 0x11,
+	GetKeymapBuffer = 0x12,
+	SetKeymapBuffer = 0x13,
+	/// Sent back in place of a request the device can't handle.
+	Unhandled = 0xFF,
+}
+
+impl Command {
+	#[must_use]
+	pub const fn from_u8(value: u8) -> Option<Self> {
+		match value {
+			0x01 => Some(Self::GetProtocolVersion),
+			0x02 => Some(Self::GetKeyboardValue),
+			0x03 => Some(Self::SetKeyboardValue),
+			0x04 => Some(Self::GetKeycode),
+			0x05 => Some(Self::SetKeycode),
+			0x06 => Some(Self::ResetKeymap),
+			0x07 => Some(Self::CustomSetValue),
+			0x08 => Some(Self::CustomGetValue),
+			0x09 => Some(Self::CustomSave),
+			0x0A => Some(Self::EepromReset),
+			0x0B => Some(Self::BootloaderJump),
+			0x0C => Some(Self::GetMacroCount),
+			0x0D => Some(Self::GetMacroBufferSize),
+			0x0E => Some(Self::GetMacroBuffer),
+			0x0F => Some(Self::SetMacroBuffer),
+			0x10 => Some(Self::ResetMacros),
+			0x11 => Some(Self::GetLayerCount),
+			0x12 => Some(Self::GetKeymapBuffer),
+			0x13 => Some(Self::SetKeymapBuffer),
+			0xFF => Some(Self::Unhandled),
+			_ => None,
+		}
+	}
+}
+
+/// The values of [`Command::GetKeyboardValue`] and [`Command::SetKeyboardValue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum KeyboardValue {
+	Uptime = 0x01,
+	LayoutOptions = 0x02,
+	SwitchMatrixState = 0x03,
+	FirmwareVersion = 0x04,
+}
+
+impl KeyboardValue {
+	#[must_use]
+	pub const fn from_u8(value: u8) -> Option<Self> {
+		match value {
+			0x01 => Some(Self::Uptime),
+			0x02 => Some(Self::LayoutOptions),
+			0x03 => Some(Self::SwitchMatrixState),
+			0x04 => Some(Self::FirmwareVersion),
+			_ => None,
+		}
+	}
+}
+
+// QMK keycode ranges that don't share the encoding of the actions.
+const QK_TO: u16 = 0x5200;
+const QK_MOMENTARY: u16 = 0x5220;
+const QK_TOGGLE_LAYER: u16 = 0x5260;
+const QK_MACRO: u16 = 0x7700;
+const QK_KB: u16 = 0x7E00;
+const QK_NKRO_ON: u16 = 0x7011;
+const QK_NKRO_OFF: u16 = 0x7012;
+const QK_NKRO_TOGGLE: u16 = 0x7013;
+const QK_BOOT: u16 = 0x7C00;
+const QK_REBOOT: u16 = 0x7C01;
+
+/// The basic QMK keycodes that stand for System Control usages, starting at `0xA5`.
+const SYSTEM_KEYCODES: [u8; 3] = [0x81, 0x82, 0x83];
+
+/// The basic QMK keycodes that stand for Consumer usages, starting at `0xA8`.
+const CONSUMER_KEYCODES: [u16; 23] = [
+	0x0E2, 0x0E9, 0x0EA, 0x0B5, 0x0B6, 0x0B7, 0x0CD, 0x183, 0x0B8, 0x18A, 0x192, 0x194, 0x221, 0x223, 0x224, 0x225,
+	0x226, 0x227, 0x22A, 0x0B3, 0x0B4, 0x06F, 0x070,
+];
+
+const SYSTEM_KEYCODES_START: u8 = 0xA5;
+const CONSUMER_KEYCODES_START: u8 = 0xA8;
+const LAST_MEDIA_KEYCODE: u8 = 0xBE;
+
+/// The basic keycode QMK has for a Consumer usage.
+const fn consumer_keycode(usage: u16) -> Option<u16> {
+	let mut i = 0;
+
+	while i < CONSUMER_KEYCODES.len() {
+		if CONSUMER_KEYCODES[i] == usage {
+			#[allow(clippy::cast_possible_truncation, reason = "The table is shorter than 256.")]
+			return Some(CONSUMER_KEYCODES_START as u16 + i as u16);
+		}
+
+		i += 1;
+	}
+
+	None
+}
+
+/// The basic keycode QMK has for a System Control usage.
+const fn system_keycode(usage: u8) -> Option<u16> {
+	let mut i = 0;
+
+	while i < SYSTEM_KEYCODES.len() {
+		if SYSTEM_KEYCODES[i] == usage {
+			#[allow(clippy::cast_possible_truncation, reason = "The table is shorter than 256.")]
+			return Some(SYSTEM_KEYCODES_START as u16 + i as u16);
+		}
+
+		i += 1;
+	}
+
+	None
+}
+
+/// The QMK keycode of an action.
+///
+/// Keys, modified keys, mod-taps and layer-taps share the encoding of QMK. Layer keys, macros,
+/// custom actions and the firmware actions are moved to their QMK ranges, and the System Control
+/// and Consumer usages QMK has a basic keycode for use that keycode.
+///
+/// Actions without a QMK keycode, and the HID keycodes QMK uses for its media keys, map to
+/// `KC_NO`.
+#[must_use]
+pub const fn to_keycode(action: Action) -> u16 {
+	let bits = action.to_bits();
+
+	match action.kind() {
+		ActionKind::Key(keycode) if keycode.get() < SYSTEM_KEYCODES_START || keycode.get() > LAST_MEDIA_KEYCODE => bits,
+		ActionKind::Transparent
+		| ActionKind::ModifiedKey { .. }
+		| ActionKind::Modifiers(_)
+		| ActionKind::ModTap { .. }
+		| ActionKind::Layer(LayerAction::Tap { .. }) => bits,
+		ActionKind::Layer(LayerAction::Momentary(layer)) => QK_MOMENTARY | layer as u16,
+		ActionKind::Layer(LayerAction::Toggle(layer)) => QK_TOGGLE_LAYER | layer as u16,
+		ActionKind::Layer(LayerAction::To(layer)) => QK_TO | layer as u16,
+		ActionKind::Consumer(usage) => match consumer_keycode(usage) {
+			Some(keycode) => keycode,
+			None => 0x0000,
+		},
+		ActionKind::System(usage) => match system_keycode(usage) {
+			Some(keycode) => keycode,
+			None => 0x0000,
+		},
+		ActionKind::Macro(index) if index < MACRO_COUNT => QK_MACRO | index as u16,
+		ActionKind::Custom(id) => QK_KB | id as u16,
+		ActionKind::Firmware(FirmwareAction::NkroOn) => QK_NKRO_ON,
+		ActionKind::Firmware(FirmwareAction::NkroOff) => QK_NKRO_OFF,
+		ActionKind::Firmware(FirmwareAction::NkroToggle) => QK_NKRO_TOGGLE,
+		ActionKind::Firmware(FirmwareAction::Bootloader) => QK_BOOT,
+		ActionKind::Firmware(FirmwareAction::Reboot) => QK_REBOOT,
+		ActionKind::No | ActionKind::Key(_) | ActionKind::Macro(_) => 0x0000,
+	}
+}
+
+/// The action of a QMK keycode, the reverse of [`to_keycode`].
+///
+/// Keycodes without an action map to [`Action::NO`].
+#[must_use]
+#[allow(clippy::cast_possible_truncation, reason = "All values are masked before the casts.")]
+pub const fn from_keycode(keycode: u16) -> Action {
+	let layer = (keycode & 0x1F) as u8;
+
+	match keycode {
+		0x0000..=0x00A4 | 0x00BF..=0x3FFF => Action::from_bits(keycode),
+		0x00A5..=0x00A7 => Action::system(SYSTEM_KEYCODES[(keycode - SYSTEM_KEYCODES_START as u16) as usize]),
+		0x00A8..=0x00BE => Action::consumer(CONSUMER_KEYCODES[(keycode - CONSUMER_KEYCODES_START as u16) as usize]),
+		// The layer-taps of QMK only have 4 bits for the layer.
+		0x4000..=0x4FFF if ((keycode >> 8) & 0x0F) < LAYER_COUNT as u16 => Action::from_bits(keycode),
+		0x5200..=0x521F if (layer as usize) < LAYER_COUNT => Action::to(layer),
+		0x5220..=0x523F if (layer as usize) < LAYER_COUNT => Action::momentary(layer),
+		0x5260..=0x527F if (layer as usize) < LAYER_COUNT => Action::toggle(layer),
+		0x7700..=0x777F if ((keycode & 0xFF) as u8) < MACRO_COUNT => Action::macro_action((keycode & 0xFF) as u8),
+		0x7E00..=0x7EFF => Action::custom((keycode & 0xFF) as u8),
+		QK_NKRO_ON => Action::firmware(FirmwareAction::NkroOn),
+		QK_NKRO_OFF => Action::firmware(FirmwareAction::NkroOff),
+		QK_NKRO_TOGGLE => Action::firmware(FirmwareAction::NkroToggle),
+		QK_BOOT => Action::firmware(FirmwareAction::Bootloader),
+		QK_REBOOT => Action::firmware(FirmwareAction::Reboot),
+		_ => Action::NO,
+	}
+}
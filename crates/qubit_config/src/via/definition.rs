@@ -0,0 +1,71 @@
+use serde_json::{Value, json};
+
+use super::{BACKLIGHT_BRIGHTNESS, BACKLIGHT_CHANNEL};
+use crate::keyboard::Keymap;
+use crate::usb::Usb;
+
+/// Builds the VIA definition of a keyboard, in the JSON format of VIA 3.
+///
+/// Every key of the layout is drawn 1 unit wide, in the position it has in the matrix, since the
+/// models don't describe the physical layout. With an LED, a menu turns it on and off through the
+/// backlight brightness.
+#[must_use]
+pub fn definition<const R: usize, const C: usize>(
+	name: &str,
+	usb: &Usb,
+	layout: &Keymap<R, C>,
+	has_led: bool,
+) -> String {
+	let keymap: Vec<Value> = layout
+		.0
+		.iter()
+		.enumerate()
+		.map(|(row, actions)| {
+			let mut keys = Vec::new();
+			let mut gap = 0;
+
+			for (col, action) in actions.iter().enumerate() {
+				if action.is_no() {
+					gap += 1;
+
+					continue;
+				}
+
+				// Positions without a key leave a gap of the same width.
+				if gap > 0 {
+					keys.push(json!({ "x": gap }));
+					gap = 0;
+				}
+
+				keys.push(json!(format!("{row},{col}")));
+			}
+
+			Value::Array(keys)
+		})
+		.collect();
+
+	let mut definition = json!({
+		"name": name,
+		"vendorId": format!("0x{:04X}", usb.vid),
+		"productId": format!("0x{:04X}", usb.pid),
+		"matrix": { "rows": R, "cols": C },
+		"layouts": { "keymap": keymap },
+	});
+
+	if has_led {
+		definition["menus"] = json!([{
+			"label": "Lighting",
+			"content": [{
+				"label": "LED",
+				"content": [{
+					"label": "Enabled",
+					"type": "toggle",
+					"content": ["id_qmk_backlight_brightness", BACKLIGHT_CHANNEL, BACKLIGHT_BRIGHTNESS],
+				}],
+			}],
+		}]);
+	}
+
+	// Serializing a `Value` can't fail.
+	serde_json::to_string_pretty(&definition).unwrap_or_default()
+}
@@ -0,0 +1,257 @@
+use super::{
+	BACKLIGHT_BRIGHTNESS, BACKLIGHT_CHANNEL, Command, KeyboardValue, MACRO_COUNT, MAX_BUFFER_CHUNK, PROTOCOL_VERSION,
+	REPORT_LEN, from_keycode, to_keycode,
+};
+use crate::keyboard::Action;
+use crate::keyboard::layer::LAYER_COUNT;
+
+/// The side of the device the commands act on.
+///
+/// Unlike the silverplate commands, every change is used right away. The handler calls the store
+/// methods once a request is done, so a device can persist all the changes of a request at once.
+pub trait Device {
+	/// The number of rows and columns of the matrix.
+	fn matrix_size(&self) -> (usize, usize);
+
+	/// The firmware version, packed into a bitmap.
+	fn firmware_version(&self) -> u32;
+
+	/// The action of the key at the position, `None` if there is no key.
+	fn action(&self, layer: usize, row: usize, col: usize) -> Option<Action>;
+
+	/// Sets the action of the key at the position. Returns `false` if there is no key.
+	fn set_action(&mut self, layer: usize, row: usize, col: usize, action: Action) -> bool;
+
+	/// Persists the keymaps after they changed.
+	fn store_keymaps(&mut self);
+
+	/// Goes back to the keymaps the firmware was built with.
+	fn reset_keymaps(&mut self);
+
+	/// The macros, one after the other and each ended by a 0 byte.
+	fn macro_buffer(&self) -> &[u8];
+
+	fn macro_buffer_mut(&mut self) -> &mut [u8];
+
+	/// Persists the macro buffer after it changed.
+	fn store_macros(&mut self);
+
+	/// The brightness of the backlight, `None` without a backlight.
+	fn backlight(&self) -> Option<u8> {
+		None
+	}
+
+	/// Changes the brightness of the backlight. Returns `false` without a backlight.
+	fn set_backlight(&mut self, _brightness: u8) -> bool {
+		false
+	}
+
+	/// Persists the brightness of the backlight. Returns `false` without a backlight.
+	fn store_backlight(&mut self) -> bool {
+		false
+	}
+
+	/// Goes back to the default brightness of the backlight.
+	fn reset_backlight(&mut self) {}
+
+	/// Restarts into the bootloader, once the response was sent. Returns `false` if the device
+	/// can't.
+	fn jump_to_bootloader(&mut self) -> bool {
+		false
+	}
+}
+
+/// Handles a request in place, turning the report into the response to send back.
+pub fn handle(device: &mut impl Device, report: &mut [u8; REPORT_LEN]) {
+	let handled = match Command::from_u8(report[0]) {
+		Some(command) => handle_command(device, command, report),
+		None => false,
+	};
+
+	if !handled {
+		report[0] = Command::Unhandled as u8;
+	}
+}
+
+fn handle_command(device: &mut impl Device, command: Command, report: &mut [u8; REPORT_LEN]) -> bool {
+	match command {
+		Command::GetProtocolVersion => report[1..3].copy_from_slice(&PROTOCOL_VERSION.to_be_bytes()),
+		Command::GetKeyboardValue => match KeyboardValue::from_u8(report[1]) {
+			// There is a single layout, without any options.
+			Some(KeyboardValue::LayoutOptions) => report[2..6].fill(0),
+			Some(KeyboardValue::FirmwareVersion) => {
+				report[2..6].copy_from_slice(&device.firmware_version().to_be_bytes());
+			}
+			_ => return false,
+		},
+		Command::GetKeycode => {
+			let layer = usize::from(report[1]);
+
+			let action = if layer < LAYER_COUNT {
+				device.action(layer, usize::from(report[2]), usize::from(report[3]))
+			} else {
+				None
+			};
+
+			report[4..6].copy_from_slice(&action.map_or(0, to_keycode).to_be_bytes());
+		}
+		Command::SetKeycode => {
+			let layer = usize::from(report[1]);
+			let action = from_keycode(u16::from_be_bytes([report[4], report[5]]));
+
+			if layer < LAYER_COUNT && device.set_action(layer, usize::from(report[2]), usize::from(report[3]), action) {
+				device.store_keymaps();
+			}
+		}
+		Command::ResetKeymap => device.reset_keymaps(),
+		Command::CustomSetValue | Command::CustomGetValue | Command::CustomSave => {
+			return handle_backlight(device, command, report);
+		}
+		Command::EepromReset => {
+			device.reset_keymaps();
+			reset_macros(device);
+			device.reset_backlight();
+		}
+		#[allow(clippy::cast_possible_truncation, reason = "`LAYER_COUNT` is below 256.")]
+		Command::GetLayerCount => report[1] = LAYER_COUNT as u8,
+		Command::GetMacroCount => report[1] = MACRO_COUNT,
+		Command::GetMacroBufferSize => {
+			let size = u16::try_from(device.macro_buffer().len()).unwrap_or(u16::MAX);
+
+			report[1..3].copy_from_slice(&size.to_be_bytes());
+		}
+		Command::GetMacroBuffer => {
+			let Some((offset, size)) = buffer_range(report) else {
+				return false;
+			};
+
+			let buffer = device.macro_buffer();
+			let end = (offset + size).min(buffer.len());
+			let len = end.saturating_sub(offset);
+
+			if len > 0 {
+				report[4..4 + len].copy_from_slice(&buffer[offset..end]);
+			}
+		}
+		Command::SetMacroBuffer => {
+			let Some((offset, size)) = buffer_range(report) else {
+				return false;
+			};
+
+			let buffer = device.macro_buffer_mut();
+			let end = (offset + size).min(buffer.len());
+			let len = end.saturating_sub(offset);
+
+			if len > 0 {
+				buffer[offset..end].copy_from_slice(&report[4..4 + len]);
+
+				device.store_macros();
+			}
+		}
+		Command::ResetMacros => reset_macros(device),
+		Command::GetKeymapBuffer => {
+			let Some((offset, size)) = buffer_range(report) else {
+				return false;
+			};
+
+			get_keymap_buffer(device, offset, &mut report[4..4 + size]);
+		}
+		Command::SetKeymapBuffer => {
+			let Some((offset, size)) = buffer_range(report) else {
+				return false;
+			};
+
+			let mut bytes = [0; MAX_BUFFER_CHUNK];
+			bytes[..size].copy_from_slice(&report[4..4 + size]);
+
+			set_keymap_buffer(device, offset, &bytes[..size]);
+		}
+		Command::BootloaderJump => return device.jump_to_bootloader(),
+		// The layout options are fixed.
+		Command::SetKeyboardValue | Command::Unhandled => return false,
+	}
+
+	true
+}
+
+fn handle_backlight(device: &mut impl Device, command: Command, report: &mut [u8; REPORT_LEN]) -> bool {
+	let &mut [_, channel, value_id, ref mut value @ ..] = report;
+
+	if channel != BACKLIGHT_CHANNEL {
+		return false;
+	}
+
+	match command {
+		Command::CustomGetValue if value_id == BACKLIGHT_BRIGHTNESS => match device.backlight() {
+			Some(brightness) => {
+				value[0] = brightness;
+
+				true
+			}
+			None => false,
+		},
+		Command::CustomSetValue if value_id == BACKLIGHT_BRIGHTNESS => device.set_backlight(value[0]),
+		Command::CustomSave => device.store_backlight(),
+		_ => false,
+	}
+}
+
+fn reset_macros(device: &mut impl Device) {
+	device.macro_buffer_mut().fill(0);
+	device.store_macros();
+}
+
+/// The offset and the size of a buffer request, `None` if the size doesn't fit in a report.
+fn buffer_range(report: &[u8; REPORT_LEN]) -> Option<(usize, usize)> {
+	let offset = usize::from(u16::from_be_bytes([report[1], report[2]]));
+	let size = usize::from(report[3]);
+
+	(size <= MAX_BUFFER_CHUNK).then_some((offset, size))
+}
+
+/// The layer, row and column of every keycode of the keymap buffer, which holds all the layers in
+/// row-major order with 2 bytes per keycode.
+fn keymap_position(index: usize, rows: usize, cols: usize) -> Option<(usize, usize, usize)> {
+	let keys = rows * cols;
+
+	if keys == 0 || index / keys >= LAYER_COUNT {
+		return None;
+	}
+
+	Some((index / keys, (index % keys) / cols, index % cols))
+}
+
+fn get_keymap_buffer(device: &impl Device, offset: usize, bytes: &mut [u8]) {
+	let (rows, cols) = device.matrix_size();
+
+	for (byte_offset, byte) in (offset..).zip(bytes.iter_mut()) {
+		let keycode = keymap_position(byte_offset / 2, rows, cols)
+			.and_then(|(layer, row, col)| device.action(layer, row, col))
+			.map_or(0, to_keycode);
+
+		*byte = keycode.to_be_bytes()[byte_offset % 2];
+	}
+}
+
+fn set_keymap_buffer(device: &mut impl Device, offset: usize, bytes: &[u8]) {
+	let (rows, cols) = device.matrix_size();
+
+	// Keycodes split between two requests are only partly known, and VIA always sends whole ones.
+	let skip = offset % 2;
+	let mut changed = false;
+
+	for (index, pair) in (offset.div_ceil(2)..).zip(bytes[skip..].chunks_exact(2)) {
+		let Some((layer, row, col)) = keymap_position(index, rows, cols) else {
+			break;
+		};
+
+		let action = from_keycode(u16::from_be_bytes([pair[0], pair[1]]));
+
+		// Positions without a key don't take any action.
+		changed |= device.set_action(layer, row, col, action);
+	}
+
+	if changed {
+		device.store_keymaps();
+	}
+}
@@ -0,0 +1,41 @@
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the tests."
+)]
+
+use qubit_config::keyboard::{Action, ActionKind, Modifiers, keycodes};
+
+#[test]
+fn adds_modifiers_of_the_same_side() {
+	let action = Action::key(keycodes::KC_A)
+		.with_modifiers(Modifiers::RSHIFT)
+		.with_modifiers(Modifiers::RCTRL);
+
+	assert_eq!(
+		action.kind(),
+		ActionKind::ModifiedKey {
+			modifiers: Modifiers::RCTRL.union(Modifiers::RSHIFT),
+			keycode: keycodes::KC_A,
+		}
+	);
+	assert_eq!(Modifiers::RCTRL.union(Modifiers::RSHIFT).hid_mask(), 0x30);
+}
+
+#[test]
+#[should_panic(expected = "Left and right modifiers can't be mixed.")]
+fn rejects_modifiers_of_both_sides() {
+	let _ = Action::key(keycodes::KC_A)
+		.with_modifiers(Modifiers::RSHIFT)
+		.with_modifiers(Modifiers::LCTRL);
+}
+
+#[test]
+fn decodes_the_reserved_keycodes_as_no_action() {
+	assert_eq!(Action::from_bits(0x0002).kind(), ActionKind::No);
+	assert_eq!(Action::from_bits(0x0003).kind(), ActionKind::No);
+	assert_eq!(Action::from_bits(0x0004).kind(), ActionKind::Key(keycodes::KC_A));
+	assert_eq!(
+		Action::from_bits(0x00FF).kind(),
+		ActionKind::Key(std::num::NonZeroU8::MAX)
+	);
+}
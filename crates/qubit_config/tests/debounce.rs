@@ -0,0 +1,175 @@
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the tests."
+)]
+
+use qubit_config::keyboard::{Debounce, Debouncer, key_bit};
+
+/// Enough keys to need more than one word.
+const KEYS: usize = 70;
+const WORDS: usize = KEYS.div_ceil(usize::BITS as usize);
+
+const KEY: usize = 0;
+/// A key in the last word of the bitmaps.
+const OTHER_KEY: usize = KEYS - 1;
+
+fn scan(keys: &[usize]) -> [usize; WORDS] {
+	let mut bitmaps = [0; WORDS];
+
+	for &index in keys {
+		let (word, mask) = key_bit(index);
+
+		bitmaps[word] |= mask;
+	}
+
+	bitmaps
+}
+
+/// Feeds the raw scans, made at the given times, and returns the debounced ones.
+fn replay(algorithm: Debounce, scans: &[(u32, &[usize])]) -> Vec<[usize; WORDS]> {
+	let mut debouncer = Debouncer::<KEYS, WORDS>::new(algorithm);
+
+	scans
+		.iter()
+		.map(|&(now, keys)| debouncer.debounce(scan(keys), now))
+		.collect()
+}
+
+fn scans(keys: &[&[usize]]) -> Vec<[usize; WORDS]> {
+	keys.iter().map(|keys| scan(keys)).collect()
+}
+
+#[test]
+fn symmetric_defer_waits_for_the_matrix_to_settle() {
+	let debounced = replay(
+		Debounce::SymmetricDefer { ms: 5 },
+		&[
+			(0, &[KEY]),
+			(1, &[]),
+			(2, &[KEY]),
+			(6, &[KEY]),
+			(7, &[KEY]),
+			(20, &[]),
+			(21, &[KEY]),
+			(22, &[]),
+			(27, &[]),
+		],
+	);
+
+	assert_eq!(
+		debounced,
+		scans(&[&[], &[], &[], &[], &[KEY], &[KEY], &[KEY], &[KEY], &[]])
+	);
+}
+
+#[test]
+fn symmetric_defer_keeps_held_keys() {
+	let debounced = replay(
+		Debounce::SymmetricDefer { ms: 5 },
+		&[
+			(0, &[KEY, OTHER_KEY]),
+			(5, &[KEY, OTHER_KEY]),
+			(100, &[KEY, OTHER_KEY]),
+			(1000, &[KEY, OTHER_KEY]),
+		],
+	);
+
+	assert_eq!(
+		debounced,
+		scans(&[&[], &[KEY, OTHER_KEY], &[KEY, OTHER_KEY], &[KEY, OTHER_KEY]])
+	);
+}
+
+#[test]
+fn eager_per_key_reports_changes_right_away() {
+	let debounced = replay(
+		Debounce::EagerPerKey { ms: 5 },
+		&[
+			(0, &[KEY]),
+			(1, &[]),
+			(2, &[KEY]),
+			(3, &[]),
+			(5, &[KEY]),
+			(10, &[]),
+			(11, &[KEY]),
+			(12, &[]),
+			(15, &[]),
+		],
+	);
+
+	assert_eq!(
+		debounced,
+		scans(&[&[KEY], &[KEY], &[KEY], &[KEY], &[KEY], &[], &[], &[], &[]])
+	);
+}
+
+#[test]
+fn eager_per_key_debounces_every_key_on_its_own() {
+	let debounced = replay(
+		Debounce::EagerPerKey { ms: 5 },
+		&[
+			(0, &[KEY]),
+			(2, &[KEY, OTHER_KEY]),
+			(3, &[KEY]),
+			(100, &[KEY, OTHER_KEY]),
+			(1000, &[KEY, OTHER_KEY]),
+		],
+	);
+
+	assert_eq!(
+		debounced,
+		scans(&[
+			&[KEY],
+			&[KEY, OTHER_KEY],
+			&[KEY, OTHER_KEY],
+			&[KEY, OTHER_KEY],
+			&[KEY, OTHER_KEY],
+		])
+	);
+}
+
+#[test]
+fn asymmetric_defers_releases_until_they_settle() {
+	let debounced = replay(
+		Debounce::Asymmetric {
+			press_ms: 5,
+			release_ms: 10,
+		},
+		&[
+			(0, &[KEY]),
+			(1, &[]),
+			(2, &[KEY]),
+			(20, &[]),
+			(25, &[KEY]),
+			(27, &[]),
+			(36, &[]),
+			(37, &[]),
+		],
+	);
+
+	assert_eq!(
+		debounced,
+		scans(&[&[KEY], &[KEY], &[KEY], &[KEY], &[KEY], &[KEY], &[KEY], &[]])
+	);
+}
+
+#[test]
+fn asymmetric_keeps_held_keys() {
+	let debounced = replay(
+		Debounce::Asymmetric {
+			press_ms: 5,
+			release_ms: 10,
+		},
+		&[
+			(0, &[OTHER_KEY]),
+			(10, &[OTHER_KEY]),
+			(100, &[OTHER_KEY]),
+			(1000, &[OTHER_KEY]),
+		],
+	);
+
+	assert_eq!(
+		debounced,
+		scans(&[&[OTHER_KEY], &[OTHER_KEY], &[OTHER_KEY], &[OTHER_KEY]])
+	);
+}
@@ -0,0 +1,66 @@
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the tests."
+)]
+// The pins of the build configuration are collected by the `cargo` module.
+#![cfg(feature = "build")]
+
+use qubit_config::cargo::{ErrReason, collect_pins};
+use qubit_config::mcu::{Mcu, PinError, Reserved};
+
+#[test]
+fn rp2040_has_30_gpio_pins() {
+	assert_eq!(Mcu::RP2040.pins().len(), 30);
+	assert!(Mcu::RP2040.has_pin("29"));
+	assert!(!Mcu::RP2040.has_pin("30"));
+	assert!(!Mcu::RP2040.has_pin("05"));
+
+	// Only the last 4 pins have an ADC channel, but every pin has PWM.
+	let adc: Vec<_> = Mcu::RP2040.pins().iter().filter_map(|pin| pin.adc).collect();
+
+	assert_eq!(adc, [0, 1, 2, 3]);
+	assert_eq!(Mcu::RP2040.pin("26").unwrap().adc, Some(0));
+	assert!(Mcu::RP2040.pins().iter().all(|pin| pin.pwm && pin.reserved.is_none()));
+}
+
+#[test]
+fn stm32f411_reserves_usb_swd_and_crystal_pins() {
+	let mcu = Mcu::STM32F411;
+
+	assert_eq!(mcu.usable_pin("A11"), Err(PinError::Reserved(Reserved::Usb)));
+	assert_eq!(mcu.usable_pin("A12"), Err(PinError::Reserved(Reserved::Usb)));
+	assert_eq!(mcu.usable_pin("A13"), Err(PinError::Reserved(Reserved::Swd)));
+	assert_eq!(mcu.usable_pin("A14"), Err(PinError::Reserved(Reserved::Swd)));
+	assert_eq!(mcu.usable_pin("H0"), Err(PinError::Reserved(Reserved::Crystal)));
+	assert_eq!(mcu.usable_pin("B11"), Err(PinError::Unknown));
+	assert_eq!(mcu.usable_pin("H2"), Err(PinError::Unknown));
+	assert_eq!(mcu.usable_pin("A16"), Err(PinError::Unknown));
+
+	let pin = mcu.usable_pin("A0").unwrap();
+	assert_eq!((pin.adc, pin.pwm), (Some(0), true));
+
+	let pin = mcu.usable_pin("C5").unwrap();
+	assert_eq!((pin.adc, pin.pwm), (Some(15), false));
+}
+
+#[test]
+fn collects_usable_pins() {
+	let pins = collect_pins(Mcu::STM32F411, &["B12", "B13"], &["A0", "A1", "A2"], Some("C13")).unwrap();
+
+	assert_eq!(pins.len(), 6);
+
+	let err = collect_pins(Mcu::STM32F411, &["B12", "A11"], &["A0"], None).unwrap_err();
+
+	assert!(matches!(err.reason, ErrReason::Reserved(Reserved::Usb)));
+	assert_eq!(err.to_string(), "Pin A11 is reserved for USB on the STM32F411.");
+
+	let err = collect_pins(Mcu::RP2040, &["2", "30"], &["4"], None).unwrap_err();
+
+	assert!(matches!(err.reason, ErrReason::Unknown));
+	assert_eq!(err.to_string(), "30 isn't a pin of the RP2040.");
+
+	let err = collect_pins(Mcu::RP2040, &["2", "3"], &["4"], Some("3")).unwrap_err();
+
+	assert!(matches!(err.reason, ErrReason::Duplicate));
+	assert_eq!(err.pin, "3");
+}
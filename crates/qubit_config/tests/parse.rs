@@ -0,0 +1,407 @@
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the tests."
+)]
+
+use std::num::NonZeroU8;
+
+use qubit_config::keyboard::{
+	Action, Bootmagic, BootmagicAction, Debounce, FirmwareAction, KeyPosition, Modifiers, TapHoldFlavor,
+};
+use qubit_config::keymap;
+use qubit_config::parse::{
+	Diagnostic, Location, ParseErrorKind, TomlConfiguration, action_name, parse_action, parse_file,
+};
+
+const BOARD: &str = r#"
+name = "Pebble"
+
+[firmware]
+author = "someone"
+id = "pebble"
+version = "0.1.2"
+device = "keyboard"
+
+[led]
+pin = "25"
+indicator = "num_lock"
+
+[usb]
+vid = 0x1209
+pid = 0x0002
+
+[keyboard]
+mcu = "RP2040"
+flash = 0x200000
+debounce = { algorithm = "asymmetric", press_ms = 5, release_ms = 10 }
+tap_hold = { tapping_term = 180, flavor = "permissive_hold", per_key = [{ row = 1, col = 2, tapping_term = 250 }] }
+
+[keyboard.keymap]
+rows = 2
+cols = 3
+row_pins = ["2", "3"]
+col_pins = ["4", "5", "6"]
+layer0 = [
+	["KC_ESC", "KC_A", "LT(1, KC_SPACE)"],
+	["MT(LCTRL, KC_B)", "-", "MO(2)"],
+]
+layer2 = [
+	["LCTL(LSFT(KC_C))", "_", "CC(0xE2)"],
+	["NK_TOGG", "-", "_"],
+]
+"#;
+
+fn key(keycode: u8) -> NonZeroU8 {
+	NonZeroU8::new(keycode).unwrap()
+}
+
+fn parse(board: &str) -> Result<TomlConfiguration, Vec<Diagnostic>> {
+	TomlConfiguration::from_toml(board)
+}
+
+fn keys(diagnostics: &[Diagnostic]) -> Vec<&str> {
+	diagnostics
+		.iter()
+		.map(|diagnostic| diagnostic.key.as_deref().unwrap_or_default())
+		.collect()
+}
+
+#[test]
+fn parses_every_layer() {
+	let config = parse(BOARD).unwrap();
+	let layers = &config.keyboard.keymap.layers;
+
+	assert_eq!(layers.len(), 5);
+	assert_eq!(
+		layers[0],
+		[
+			vec![
+				Action::key(key(0x29)),
+				Action::key(key(0x04)),
+				Action::layer_tap(1, key(0x2C))
+			],
+			vec![
+				Action::mod_tap(Modifiers::LCTRL, key(0x05)),
+				Action::NO,
+				Action::momentary(2)
+			],
+		]
+	);
+	assert_eq!(
+		layers[2],
+		[
+			vec![
+				Action::key(key(0x06))
+					.with_modifiers(Modifiers::LSHIFT)
+					.with_modifiers(Modifiers::LCTRL),
+				Action::TRANSPARENT,
+				Action::consumer(0xE2)
+			],
+			vec![
+				Action::firmware(FirmwareAction::NkroToggle),
+				Action::NO,
+				Action::TRANSPARENT
+			],
+		]
+	);
+
+	// The layers missing from the file are transparent, without keys in the same positions.
+	for layer in [&layers[1], &layers[3], &layers[4]] {
+		assert_eq!(
+			*layer,
+			[
+				vec![Action::TRANSPARENT; 3],
+				vec![Action::TRANSPARENT, Action::NO, Action::TRANSPARENT]
+			]
+		);
+	}
+
+	assert_eq!(
+		config.keyboard.debounce,
+		Debounce::Asymmetric {
+			press_ms: 5,
+			release_ms: 10
+		}
+	);
+	assert_eq!(config.keyboard.tap_hold.flavor, TapHoldFlavor::PermissiveHold);
+	assert_eq!(config.keyboard.tap_hold.per_key[0].tapping_term, 250);
+}
+
+#[test]
+fn generates_a_device_module() {
+	let module = parse(BOARD).unwrap().device_module();
+
+	for item in [
+		"pub const NAME: &str = \"Pebble\";",
+		"pub const AUTHOR: &str = \"someone\";",
+		"pub const VERSION: Version = Version::new_zero(0, 1, 2);",
+		"pub const DEVICE: Device = Device::Keyboard;",
+		"pub const LED_PIN: Option<&str> = Some(\"25\");",
+		"pub const LED_INDICATOR: LockIndicator = LockIndicator::NumLock;",
+		"pub const MCU: Mcu = Mcu::RP2040;",
+		"pub const FLASH: u32 = 0x0020_0000;",
+		"pub const USB: Usb = Usb::new(0x1209, 0x0002);",
+		"pub const ROW_NUM: usize = 2;",
+		"pub const COL_NUM: usize = 3;",
+		"pub const ROW_PINS: [&str; ROW_NUM] = [\"2\", \"3\"];",
+		"pub const COL_PINS: [&str; COL_NUM] = [\"4\", \"5\", \"6\"];",
+		"\t[Action::from_bits(0x2105), Action::from_bits(0x0000), Action::from_bits(0x5002)],",
+		"pub const DEBOUNCE: Debounce = Debounce::Asymmetric { press_ms: 5, release_ms: 10 };",
+		"pub const TAP_HOLD: TapHold = TapHold::new(180, TapHoldFlavor::PermissiveHold)\
+		 .with_per_key(&[::qubit_config::keyboard::KeyTappingTerm::new(1, 2, 250)]);",
+		"pub const BOOTMAGIC: Bootmagic = \
+		 Bootmagic::new(KeyPosition::new(0, 0)).with_bootloader(KeyPosition::new(0, 1));",
+	] {
+		assert!(module.contains(item), "missing `{item}` in:\n{module}");
+	}
+}
+
+#[test]
+fn accepts_stm32_pins() {
+	let board = BOARD
+		.replace("\"RP2040\"", "\"STM32F411\"")
+		.replace("[\"2\", \"3\"]", "[\"B12\", \"B13\"]")
+		.replace("[\"4\", \"5\", \"6\"]", "[\"A0\", \"A1\", \"C15\"]")
+		.replace("pin = \"25\"", "pin = \"C13\"");
+
+	let config = parse(&board).unwrap();
+
+	assert_eq!(config.keyboard.keymap.row_pins, ["B12", "B13"]);
+	assert_eq!(config.led.unwrap().pin, "C13");
+
+	// The USB pins can't be used by the matrix.
+	let diagnostics = parse(&board.replace("\"A1\"", "\"A12\"")).unwrap_err();
+
+	assert_eq!(diagnostics[0].message, "A12 is reserved for USB on the STM32F411");
+	assert_eq!(keys(&diagnostics), ["keyboard.keymap.col_pins[1]"]);
+}
+
+#[test]
+fn rejects_invalid_boards() {
+	let invalid = [
+		// An unknown field.
+		BOARD.replace("[usb]", "[usb]\nserial = 1"),
+		// Pins that don't match the matrix, the MCU or are used twice.
+		BOARD.replace("row_pins = [\"2\", \"3\"]", "row_pins = [\"2\"]"),
+		BOARD.replace("row_pins = [\"2\", \"3\"]", "row_pins = [\"2\", \"B12\"]"),
+		BOARD.replace("row_pins = [\"2\", \"3\"]", "row_pins = [\"2\", \"4\"]"),
+		BOARD.replace("pin = \"25\"", "pin = \"2\""),
+		// Actions that don't exist or are out of bounds.
+		BOARD.replace("KC_ESC", "KC_ESCAPE"),
+		BOARD.replace("MO(2)", "MO(5)"),
+		BOARD.replace("LCTL(LSFT(KC_C))", "LCTL(MO(1))"),
+		// A tapping term outside the matrix.
+		BOARD.replace("row = 1, col = 2", "row = 2, col = 2"),
+		// Bootmagic keys without a key, or the same key twice.
+		BOARD.replace(
+			"[keyboard.keymap]",
+			"bootmagic = { clear = { row = 1, col = 1 } }\n[keyboard.keymap]",
+		),
+		BOARD.replace(
+			"[keyboard.keymap]",
+			"bootmagic = { clear = { row = 0, col = 2 }, bootloader = { row = 0, col = 2 } }\n[keyboard.keymap]",
+		),
+	];
+
+	for board in invalid {
+		assert!(parse(&board).is_err(), "accepted:\n{board}");
+	}
+}
+
+#[test]
+fn picks_the_bootmagic_keys() {
+	let layer0 = keymap! {
+		[-, KC_A, KC_B],
+		[KC_C, -, KC_D],
+	};
+
+	let board = BOARD.replace("\"KC_ESC\", \"KC_A\"", "\"-\", \"KC_A\"");
+
+	// The first keys of the first layer are used by default, like the models do.
+	assert_eq!(parse(&board).unwrap().keyboard.bootmagic, Bootmagic::top_left(&layer0));
+	assert_eq!(
+		Bootmagic::top_left(&layer0),
+		Bootmagic::new(KeyPosition::new(0, 1)).with_bootloader(KeyPosition::new(0, 2))
+	);
+
+	// The bootloader key defaults to the first key that isn't the clear key.
+	let board = board.replace(
+		"[keyboard.keymap]",
+		"bootmagic = { clear = { row = 1, col = 2 } }\n[keyboard.keymap]",
+	);
+
+	assert_eq!(
+		parse(&board).unwrap().keyboard.bootmagic,
+		Bootmagic::new(KeyPosition::new(1, 2)).with_bootloader(KeyPosition::new(0, 1))
+	);
+
+	let diagnostics = parse(&board.replace("col = 2 }", "col = 1 }")).unwrap_err();
+
+	assert_eq!(diagnostics[0].message, "There is no key at row 1 col 1 of layer0");
+	assert_eq!(keys(&diagnostics), ["keyboard.bootmagic.clear"]);
+}
+
+#[test]
+fn bootmagic_needs_the_clear_key() {
+	const CLEAR: KeyPosition = KeyPosition::new(0, 0);
+	const BOOTLOADER: KeyPosition = KeyPosition::new(1, 0);
+
+	let bootmagic = Bootmagic::new(CLEAR).with_bootloader(BOOTLOADER);
+
+	let held = |keys: &'static [KeyPosition]| move |key| keys.contains(&key);
+
+	assert_eq!(bootmagic.action(held(&[])), None);
+	assert_eq!(bootmagic.action(held(&[BOOTLOADER])), None);
+	assert_eq!(bootmagic.action(held(&[CLEAR])), Some(BootmagicAction::ClearStorage));
+	assert_eq!(
+		bootmagic.action(held(&[BOOTLOADER, CLEAR])),
+		Some(BootmagicAction::Bootloader)
+	);
+
+	// Without a bootloader key, the clear key always clears.
+	assert_eq!(
+		Bootmagic::new(CLEAR).action(held(&[CLEAR, BOOTLOADER])),
+		Some(BootmagicAction::ClearStorage)
+	);
+}
+
+#[test]
+fn parses_actions_by_name() {
+	assert_eq!(parse_action("-"), Ok(Action::NO));
+	assert_eq!(parse_action("_"), Ok(Action::TRANSPARENT));
+	assert_eq!(parse_action("KC_ENTER"), Ok(Action::key(key(0x28))));
+	assert_eq!(parse_action("TG(3)"), Ok(Action::toggle(3)));
+	assert_eq!(parse_action("TO(0)"), Ok(Action::to(0)));
+	assert_eq!(parse_action("SYS(0x82)"), Ok(Action::system(0x82)));
+	assert_eq!(parse_action("MACRO(4)"), Ok(Action::macro_action(4)));
+	assert_eq!(parse_action("CUSTOM(7)"), Ok(Action::custom(7)));
+	assert_eq!(
+		parse_action("RGUI(KC_TAB)"),
+		Ok(Action::key(key(0x2B)).with_modifiers(Modifiers::RGUI))
+	);
+
+	assert!(parse_action("KC_NOPE").is_err());
+	assert!(parse_action("CC(0x1000)").is_err());
+	assert!(parse_action("MT(HYPER, KC_A)").is_err());
+}
+
+#[test]
+fn rejects_modifiers_of_both_sides() {
+	assert_eq!(
+		parse_action("RCTL(RSFT(KC_A))"),
+		Ok(Action::key(key(0x04)).with_modifiers(Modifiers::RCTRL.union(Modifiers::RSHIFT)))
+	);
+	assert_eq!(
+		parse_action("LCTL(RSFT(KC_A))"),
+		Err(String::from(
+			"Left and right modifiers can't be mixed, `RSFT(KC_A)` has modifiers of the other side"
+		))
+	);
+	assert!(parse_action("RGUI(LALT(KC_A))").is_err());
+}
+
+#[test]
+fn names_actions_the_way_they_are_parsed() {
+	let names = [
+		"-",
+		"_",
+		"KC_ESC",
+		"LCTL(LSFT(KC_C))",
+		"RALT(KC_E)",
+		"MT(RSHIFT, KC_ENTER)",
+		"LT(2, KC_SPACE)",
+		"MO(1)",
+		"TG(3)",
+		"TO(0)",
+		"CC(0x00E2)",
+		"SYS(0x82)",
+		"MACRO(4)",
+		"CUSTOM(7)",
+		"NK_TOGG",
+		"QK_BOOT",
+		"QK_RBT",
+	];
+
+	for name in names {
+		assert_eq!(action_name(parse_action(name).unwrap()), name);
+	}
+
+	// Only modifiers can't be written by name.
+	assert_eq!(action_name(Action::modifiers(Modifiers::LCTRL)), "0x0100");
+}
+
+#[test]
+fn reports_every_problem_at_once() {
+	let board = BOARD
+		.replace("row_pins = [\"2\", \"3\"]", "row_pins = [\"2\", \"2\", \"40\"]")
+		.replace("\"KC_A\"", "\"KC_NOPE\"")
+		.replace("[\"NK_TOGG\", \"-\", \"_\"]", "[\"NK_TOGG\", \"-\"]");
+
+	let diagnostics = parse(&board).unwrap_err();
+
+	assert_eq!(
+		keys(&diagnostics),
+		[
+			"keyboard.keymap.row_pins",
+			"keyboard.keymap.row_pins[1]",
+			"keyboard.keymap.row_pins[2]",
+			"keyboard.keymap.layer0[0][1]",
+			"keyboard.keymap.layer2[1]",
+		]
+	);
+
+	assert_eq!(
+		diagnostics[1].message,
+		"Pin 2 is already used by keyboard.keymap.row_pins[0]"
+	);
+	assert_eq!(diagnostics[2].message, "40 isn't a pin of the RP2040");
+
+	// The span points at the unknown keycode, in the line of the first row of the layer.
+	let span = diagnostics[3].span.as_ref().unwrap();
+	let line = board.lines().nth(span.start.line - 1).unwrap();
+
+	assert_eq!(&board[span.bytes.clone()], "\"KC_NOPE\"");
+	assert_eq!(span.start, Location { line: 30, column: 13 });
+	assert_eq!(&line[span.start.column - 1..span.end.column - 1], "\"KC_NOPE\"");
+}
+
+#[test]
+fn reports_the_key_of_schema_errors() {
+	let diagnostics = parse(&BOARD.replace("[usb]", "[usb]\nserial = 1")).unwrap_err();
+
+	assert_eq!(keys(&diagnostics), ["usb.serial"]);
+	assert_eq!(
+		diagnostics[0].span.as_ref().unwrap().start,
+		Location { line: 15, column: 1 }
+	);
+
+	let diagnostics = parse(&BOARD.replace("cols = 3", "cols = \"3\"")).unwrap_err();
+
+	assert_eq!(keys(&diagnostics), ["keyboard.keymap.cols"]);
+}
+
+#[test]
+fn renders_reports_for_files() {
+	let dir = std::env::temp_dir().join(format!("qubit-parse-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+
+	let path = dir.join("board.toml");
+	std::fs::write(&path, BOARD.replace("\"MO(2)\"", "\"MO(9)\"")).unwrap();
+
+	let error = parse_file(&path).unwrap_err();
+	let report = error.to_string();
+
+	assert!(matches!(error.kind, ParseErrorKind::Invalid(_)));
+	assert!(report.starts_with("error: Layer 9 doesn't exist, there are only 5 layers\n"));
+	assert!(report.contains(&format!("--> {}:31:", path.display())));
+	assert!(report.contains("\t[\"MT(LCTRL, KC_B)\", \"-\", \"MO(9)\"],"));
+	assert!(report.contains("^^^^^^^ keyboard.keymap.layer0[1][2]"));
+	assert!(report.ends_with("has 1 problem"));
+
+	let error = parse_file(dir.join("missing.toml")).unwrap_err();
+
+	assert!(matches!(error.kind, ParseErrorKind::Io(_)));
+	assert!(error.diagnostics().is_empty());
+
+	std::fs::remove_dir_all(dir).unwrap();
+}
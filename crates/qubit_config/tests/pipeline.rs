@@ -0,0 +1,323 @@
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the tests."
+)]
+
+use heapless::Deque;
+use qubit_config::keyboard::event::{self, EventSink, KeyEvent, ReportQueue, ResolvedEvent};
+use qubit_config::keyboard::{Action, ActionKind, Modifiers, TapHoldEngine, TapHoldFlavor, key_bit, keycodes};
+
+const KEYS: usize = 70;
+const WORDS: usize = KEYS.div_ceil(usize::BITS as usize);
+const REPORT_QUEUE_LEN: usize = 4;
+
+const KEY_A: usize = 0;
+const SHIFT_TAP: usize = 1;
+const KEY_C: usize = 2;
+/// A key in the last word of the bitmaps.
+const MUTE: usize = KEYS - 1;
+
+const SHIFT: u8 = Modifiers::LSHIFT.hid_mask();
+
+static TAPPING_TERMS: [u16; KEYS] = [200; KEYS];
+
+/// A simplified version of the reports the firmware sends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Report {
+	Keyboard { modifiers: u8, keys: Vec<u8> },
+	Consumer(u16),
+}
+
+/// Applies the resolved events to the held actions and queues a report when they change.
+struct Reporter {
+	actions: Vec<Action>,
+	held: Vec<(usize, Action)>,
+	last_keyboard: Report,
+	last_consumer: Report,
+	reports: ReportQueue<Report, REPORT_QUEUE_LEN>,
+}
+
+impl Reporter {
+	fn new() -> Self {
+		let mut actions = vec![Action::key(keycodes::KC_D); KEYS];
+		actions[KEY_A] = Action::key(keycodes::KC_A);
+		actions[SHIFT_TAP] = Action::mod_tap(Modifiers::LSHIFT, keycodes::KC_B);
+		actions[KEY_C] = Action::key(keycodes::KC_C);
+		actions[MUTE] = Action::consumer(0xE2);
+
+		Self {
+			actions,
+			held: Vec::new(),
+			last_keyboard: Report::Keyboard {
+				modifiers: 0,
+				keys: Vec::new(),
+			},
+			last_consumer: Report::Consumer(0),
+			reports: ReportQueue::new(),
+		}
+	}
+
+	fn keyboard_report(&self) -> Report {
+		let mut modifiers = 0;
+		let mut keys = Vec::new();
+
+		for (_, action) in &self.held {
+			match action.kind() {
+				ActionKind::Key(keycode) => keys.push(keycode.get()),
+				ActionKind::Modifiers(held) => modifiers |= held.hid_mask(),
+				_ => {}
+			}
+		}
+
+		Report::Keyboard { modifiers, keys }
+	}
+
+	fn consumer_report(&self) -> Report {
+		let usage = self.held.iter().find_map(|(_, action)| match action.kind() {
+			ActionKind::Consumer(usage) => Some(usage),
+			_ => None,
+		});
+
+		Report::Consumer(usage.unwrap_or(0))
+	}
+}
+
+impl EventSink for Reporter {
+	fn action(&self, index: usize) -> Action {
+		self.actions[index]
+	}
+
+	fn emit(&mut self, event: ResolvedEvent) {
+		match event {
+			ResolvedEvent::Press { index, action } => self.held.push((index, action)),
+			ResolvedEvent::Release { index } => self.held.retain(|&(held, _)| held != index),
+		}
+
+		for report in [self.keyboard_report(), self.consumer_report()] {
+			let last = match report {
+				Report::Keyboard { .. } => &mut self.last_keyboard,
+				Report::Consumer(_) => &mut self.last_consumer,
+			};
+
+			if report != *last {
+				last.clone_from(&report);
+
+				self.reports.push(report);
+			}
+		}
+	}
+}
+
+/// The stages of the firmware after the debouncer, fed with scans made at the given times.
+struct Keyboard {
+	prev: [usize; WORDS],
+	events: Deque<KeyEvent, 8>,
+	tap_hold: TapHoldEngine,
+	reporter: Reporter,
+}
+
+impl Keyboard {
+	fn new() -> Self {
+		Self {
+			prev: [0; WORDS],
+			events: Deque::new(),
+			tap_hold: TapHoldEngine::new(TapHoldFlavor::Default, &TAPPING_TERMS),
+			reporter: Reporter::new(),
+		}
+	}
+
+	fn scan(&mut self, now: u32, keys: &[usize]) {
+		event::diff_scans(&mut self.prev, scan(keys), now, &mut self.events);
+
+		while let Some(event) = self.events.pop_front() {
+			self.tap_hold.process(event, &mut self.reporter);
+		}
+
+		self.tap_hold.tick(now, &mut self.reporter);
+	}
+
+	fn replay(&mut self, scans: &[(u32, &[usize])]) {
+		for &(now, keys) in scans {
+			self.scan(now, keys);
+		}
+	}
+
+	/// Reads every queued report, like the host does.
+	fn read_reports(&mut self) -> Vec<Report> {
+		std::iter::from_fn(|| self.reporter.reports.pop_front()).collect()
+	}
+}
+
+fn scan(keys: &[usize]) -> [usize; WORDS] {
+	let mut bitmaps = [0; WORDS];
+
+	for &index in keys {
+		let (word, mask) = key_bit(index);
+
+		bitmaps[word] |= mask;
+	}
+
+	bitmaps
+}
+
+fn keyboard_report(modifiers: u8, keys: &[std::num::NonZeroU8]) -> Report {
+	Report::Keyboard {
+		modifiers,
+		keys: keys.iter().map(|keycode| keycode.get()).collect(),
+	}
+}
+
+#[test]
+fn diffs_scans_into_events() {
+	let mut prev = [0; WORDS];
+	let mut events = Deque::<KeyEvent, 8>::new();
+
+	event::diff_scans(&mut prev, scan(&[KEY_C, MUTE]), 10, &mut events);
+	event::diff_scans(&mut prev, scan(&[MUTE]), 20, &mut events);
+
+	let events: Vec<_> = events.into_iter().collect();
+
+	assert_eq!(
+		events,
+		[
+			KeyEvent {
+				index: KEY_C,
+				pressed: true,
+				time: 10,
+			},
+			KeyEvent {
+				index: MUTE,
+				pressed: true,
+				time: 10,
+			},
+			KeyEvent {
+				index: KEY_C,
+				pressed: false,
+				time: 20,
+			},
+		]
+	);
+	assert_eq!(prev, scan(&[MUTE]));
+}
+
+#[test]
+fn picks_up_the_keys_that_didnt_fit_on_the_next_diff() {
+	let mut prev = [0; WORDS];
+	let mut events = Deque::<KeyEvent, 2>::new();
+
+	event::diff_scans(&mut prev, scan(&[KEY_A, KEY_C, MUTE]), 10, &mut events);
+	assert_eq!(prev, scan(&[KEY_A, KEY_C]));
+
+	let indices: Vec<_> = std::iter::from_fn(|| events.pop_front())
+		.map(|event| event.index)
+		.collect();
+	assert_eq!(indices, [KEY_A, KEY_C]);
+
+	event::diff_scans(&mut prev, scan(&[KEY_A, KEY_C, MUTE]), 20, &mut events);
+	assert_eq!(prev, scan(&[KEY_A, KEY_C, MUTE]));
+
+	let events: Vec<_> = events.into_iter().collect();
+	assert_eq!(
+		events,
+		[KeyEvent {
+			index: MUTE,
+			pressed: true,
+			time: 20,
+		}]
+	);
+}
+
+#[test]
+fn sends_a_report_for_every_change() {
+	let mut keyboard = Keyboard::new();
+
+	// A and C are pressed and released between two reads of the host.
+	keyboard.replay(&[(0, &[KEY_A]), (5, &[KEY_A, KEY_C]), (10, &[KEY_C]), (15, &[])]);
+
+	assert_eq!(
+		keyboard.read_reports(),
+		[
+			keyboard_report(0, &[keycodes::KC_A]),
+			keyboard_report(0, &[keycodes::KC_A, keycodes::KC_C]),
+			keyboard_report(0, &[keycodes::KC_C]),
+			keyboard_report(0, &[]),
+		]
+	);
+}
+
+#[test]
+fn sends_the_resolved_tap_hold_keys() {
+	let mut keyboard = Keyboard::new();
+
+	keyboard.replay(&[(0, &[SHIFT_TAP]), (100, &[SHIFT_TAP]), (150, &[])]);
+
+	assert_eq!(
+		keyboard.read_reports(),
+		[keyboard_report(0, &[keycodes::KC_B]), keyboard_report(0, &[]),]
+	);
+
+	keyboard.replay(&[
+		(1000, &[SHIFT_TAP]),
+		(1200, &[SHIFT_TAP]),
+		(1210, &[SHIFT_TAP, KEY_A]),
+		(1220, &[SHIFT_TAP]),
+		(1230, &[]),
+	]);
+
+	assert_eq!(
+		keyboard.read_reports(),
+		[
+			keyboard_report(SHIFT, &[]),
+			keyboard_report(SHIFT, &[keycodes::KC_A]),
+			keyboard_report(SHIFT, &[]),
+			keyboard_report(0, &[]),
+		]
+	);
+}
+
+#[test]
+fn keeps_the_last_report_of_every_kind_when_the_host_falls_behind() {
+	let mut keyboard = Keyboard::new();
+
+	keyboard.replay(&[
+		(0, &[MUTE]),
+		(5, &[MUTE, KEY_A]),
+		(10, &[MUTE, KEY_A, KEY_C]),
+		(15, &[KEY_A, KEY_C]),
+		(20, &[KEY_C]),
+	]);
+
+	// The last keyboard report replaced the ones that didn't fit.
+	assert_eq!(
+		keyboard.read_reports(),
+		[
+			Report::Consumer(0xE2),
+			keyboard_report(0, &[keycodes::KC_A]),
+			keyboard_report(0, &[keycodes::KC_C]),
+			Report::Consumer(0),
+		]
+	);
+}
+
+#[test]
+fn drops_the_oldest_report_without_one_of_the_same_kind() {
+	let mut queue = ReportQueue::<Report, REPORT_QUEUE_LEN>::new();
+
+	for keycode in [keycodes::KC_A, keycodes::KC_B, keycodes::KC_C, keycodes::KC_D] {
+		queue.push(keyboard_report(0, &[keycode]));
+	}
+
+	queue.push(Report::Consumer(0xE2));
+
+	let reports: Vec<_> = std::iter::from_fn(|| queue.pop_front()).collect();
+
+	assert_eq!(
+		reports,
+		[
+			keyboard_report(0, &[keycodes::KC_B]),
+			keyboard_report(0, &[keycodes::KC_C]),
+			keyboard_report(0, &[keycodes::KC_D]),
+			Report::Consumer(0xE2),
+		]
+	);
+}
@@ -0,0 +1,213 @@
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the tests."
+)]
+
+use qubit_config::general::{Configuration, Device};
+use qubit_config::keyboard::layer::LAYER_COUNT;
+use qubit_config::keyboard::{Action, FirmwareAction, Keymap, Keymaps};
+use qubit_config::keymap;
+use qubit_config::mcu::Mcu;
+use qubit_config::section::{
+	self, CONFIGURATION_LEN, HEADER_LEN, KeyboardSection, SectionError, encode_keyboard, keyboard_len, write_keyboard,
+};
+use qubit_config::usb::Usb;
+use qubit_config::version::Version;
+
+const CONFIGURATION: Configuration<'static> = Configuration {
+	name: "Obsidian",
+	author: "cloudgazing",
+	version: Version::new_zero(1, 2, 3).as_bitmap(),
+	device: Device::Keyboard,
+	mcu: Mcu::STM32F411,
+	usb: Usb::new(0x1209, 0x0001),
+	rows: 2,
+	cols: 3,
+	layer_count: LAYER_COUNT,
+};
+
+const LAYER0: Keymap<2, 3> = keymap! {
+	[KC_A, KC_B, -],
+	[MO(1), LCTL(KC_C), KC_D],
+};
+
+const LAYER1: Keymap<2, 3> = keymap! {
+	[KC_1, _, -],
+	[_, MT(LCTRL, KC_B), NK_TOGG],
+};
+
+const EMPTY: Keymap<2, 3> = keymap! {
+	[_, _, -],
+	[_, _, _],
+};
+
+const KEYBOARD_LEN: usize = keyboard_len(2, 3, 5);
+
+static KEYBOARD: [u8; KEYBOARD_LEN] = encode_keyboard([&LAYER0, &LAYER1, &EMPTY, &EMPTY, &EMPTY]);
+
+#[test]
+fn configuration_round_trips() {
+	let bytes = CONFIGURATION.encode();
+
+	assert_eq!(&bytes[..4], b"QBCF");
+	assert_eq!(Configuration::decode(&bytes), Ok(CONFIGURATION));
+
+	// The strings are inline, right after the fixed fields.
+	let name_start = HEADER_LEN + 14;
+	assert_eq!(&bytes[name_start..name_start + 8], b"Obsidian");
+	assert!(bytes[HEADER_LEN + 13 + 1 + 8 + 1 + 11..].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn configuration_fits_the_longest_strings() {
+	let name = "n".repeat(section::MAX_STRING_LEN);
+	let author = "a".repeat(section::MAX_STRING_LEN);
+
+	let configuration = Configuration {
+		name: &name,
+		author: &author,
+		..CONFIGURATION
+	};
+
+	let bytes = configuration.encode();
+
+	assert_eq!(bytes.len(), CONFIGURATION_LEN);
+	assert_eq!(Configuration::decode(&bytes), Ok(configuration));
+}
+
+#[test]
+#[should_panic(expected = "can't be longer than 32 bytes")]
+fn configuration_rejects_long_names() {
+	let name = "n".repeat(section::MAX_STRING_LEN + 1);
+
+	let _ = Configuration {
+		name: &name,
+		..CONFIGURATION
+	}
+	.encode();
+}
+
+#[test]
+fn keyboard_round_trips() {
+	let keyboard = KeyboardSection::decode(&KEYBOARD).unwrap();
+
+	assert_eq!(&KEYBOARD[..4], b"QBKB");
+	assert_eq!(
+		(keyboard.rows, keyboard.cols, keyboard.layer_count, keyboard.key_count),
+		(2, 3, LAYER_COUNT, 5)
+	);
+
+	assert!(keyboard.has_key(0, 1));
+	assert!(!keyboard.has_key(0, 2));
+	assert!(keyboard.has_key(1, 2));
+	assert!(!keyboard.has_key(2, 0));
+	assert!(keyboard.has_layout_of(&LAYER0));
+
+	assert_eq!(keyboard.action(0, 2), Some(Action::momentary(1)));
+	assert_eq!(keyboard.action(1, 3).map(Action::to_bits), Some(0x2105));
+	assert_eq!(keyboard.action(1, 1), Some(Action::TRANSPARENT));
+	assert_eq!(keyboard.action(LAYER_COUNT, 0), None);
+	assert_eq!(keyboard.action(0, 5), None);
+
+	let keymaps = Keymaps::<5>::from_bytes(keyboard.keymap_bytes()).unwrap();
+
+	assert_eq!(keymaps.keymap_0, LAYER0.get_packed());
+	assert_eq!(keymaps.keymap_1, LAYER1.get_packed());
+	assert_eq!(keymaps.keymap_4, EMPTY.get_packed());
+}
+
+#[test]
+fn keyboard_checks_the_layout() {
+	let keyboard = KeyboardSection::decode(&KEYBOARD).unwrap();
+
+	let other: Keymap<2, 3> = keymap! {
+		[KC_A, -, KC_B],
+		[KC_C, KC_D, KC_E],
+	};
+
+	let smaller: Keymap<1, 3> = keymap! {
+		[KC_A, KC_B, -],
+	};
+
+	assert!(!keyboard.has_layout_of(&other));
+	assert!(!keyboard.has_layout_of(&smaller));
+}
+
+#[test]
+fn keymaps_write_the_layout_of_the_section() {
+	let mut layout = [0xFF; 3];
+	LAYER0.write_layout(&mut layout);
+
+	// The rows, the columns, and the layout after the layer and key counts.
+	let payload = &KEYBOARD[HEADER_LEN..];
+	assert_eq!(layout, [payload[0], payload[1], payload[5]]);
+	assert_eq!(layout, [2, 3, 0b11_1011]);
+
+	let other: Keymap<2, 3> = keymap! {
+		[KC_A, -, KC_B],
+		[KC_C, KC_D, KC_E],
+	};
+
+	let mut other_layout = [0; 3];
+	other.write_layout(&mut other_layout);
+
+	assert_ne!(layout, other_layout);
+	assert_eq!(LAYER0.layout_len(), layout.len());
+}
+
+#[test]
+fn keys_can_be_empty_after_the_first_layer() {
+	let mut second = LAYER1.0.as_flattened().to_vec();
+	second[4] = Action::NO;
+
+	let layers = [
+		LAYER0.0.as_flattened(),
+		&second,
+		EMPTY.0.as_flattened(),
+		EMPTY.0.as_flattened(),
+		EMPTY.0.as_flattened(),
+	];
+
+	let mut bytes = [0; KEYBOARD_LEN];
+	assert_eq!(write_keyboard(&mut bytes, 2, 3, &layers), KEYBOARD_LEN);
+
+	let keyboard = KeyboardSection::decode(&bytes).unwrap();
+
+	assert!(keyboard.has_layout_of(&LAYER0));
+	assert_eq!(keyboard.action(1, 3), Some(Action::NO));
+	assert_eq!(
+		keyboard.action(1, 4),
+		Some(Action::firmware(FirmwareAction::NkroToggle))
+	);
+}
+
+#[test]
+fn sections_with_padding_decode() {
+	let mut padded = [0; KEYBOARD_LEN + 16];
+	padded[..KEYBOARD_LEN].copy_from_slice(&KEYBOARD);
+
+	assert_eq!(KeyboardSection::decode(&padded), KeyboardSection::decode(&KEYBOARD));
+}
+
+#[test]
+fn damaged_sections_are_rejected() {
+	let configuration = CONFIGURATION.encode();
+
+	assert_eq!(KeyboardSection::decode(&configuration), Err(SectionError::BadMagic));
+	assert_eq!(
+		Configuration::decode(&configuration[..HEADER_LEN - 1]),
+		Err(SectionError::TooShort)
+	);
+	assert_eq!(
+		KeyboardSection::decode(&KEYBOARD[..KEYBOARD_LEN - 1]),
+		Err(SectionError::TooShort)
+	);
+
+	let mut corrupted = KEYBOARD;
+	corrupted[KEYBOARD_LEN - 1] ^= 0xFF;
+	assert_eq!(KeyboardSection::decode(&corrupted), Err(SectionError::BadCrc));
+
+	let mut newer = configuration;
+	newer[4] = 2;
+	assert_eq!(Configuration::decode(&newer), Err(SectionError::UnsupportedVersion(2)));
+}
@@ -0,0 +1,56 @@
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the tests."
+)]
+
+use qubit_config::silverplate::{Command, DecodeError, Frame, MAX_PAYLOAD_LEN, PROTOCOL_VERSION, REPORT_LEN, Status};
+
+#[test]
+fn frames_round_trip() {
+	let frame = Frame::request(Command::SetKey, 7, &[1, 2, 3, 0x04, 0x00]).unwrap();
+	let decoded = Frame::decode(&frame.encode()).unwrap();
+
+	assert_eq!(decoded, frame);
+	assert_eq!(decoded.command, Command::SetKey as u8);
+	assert_eq!(decoded.sequence, 7);
+	assert_eq!(decoded.status(), Some(Status::Ok));
+	assert_eq!(decoded.payload(), [1, 2, 3, 0x04, 0x00]);
+}
+
+#[test]
+fn payloads_fill_the_report() {
+	let payload = [0xAB; MAX_PAYLOAD_LEN];
+	let frame = Frame::request(Command::SetKeymapChunk, 1, &payload).unwrap();
+
+	assert_eq!(Frame::decode(&frame.encode()).unwrap().payload(), payload);
+	assert_eq!(
+		Frame::request(Command::SetKeymapChunk, 1, &[0; MAX_PAYLOAD_LEN + 1]),
+		None
+	);
+}
+
+#[test]
+fn rejects_damaged_frames() {
+	let bytes = Frame::request(Command::GetFirmwareInfo, 1, &[]).unwrap().encode();
+
+	let mut corrupted = bytes;
+	corrupted[2] ^= 0x01;
+	assert_eq!(Frame::decode(&corrupted), Err(DecodeError::BadCrc));
+
+	let mut version = bytes;
+	version[0] = PROTOCOL_VERSION + 1;
+	assert_eq!(
+		Frame::decode(&version),
+		Err(DecodeError::UnsupportedVersion(PROTOCOL_VERSION + 1))
+	);
+
+	let mut len = bytes;
+	len[4] = u8::MAX;
+	assert_eq!(Frame::decode(&len), Err(DecodeError::InvalidLength));
+
+	assert_eq!(Frame::decode(&bytes[..3]), Err(DecodeError::TooShort));
+	assert_eq!(
+		Frame::decode(&[0; REPORT_LEN]).map_err(DecodeError::status),
+		Err(Status::UnsupportedVersion)
+	);
+}
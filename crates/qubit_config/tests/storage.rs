@@ -0,0 +1,234 @@
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the tests."
+)]
+
+use qubit_config::storage::{Error, Flash, RamFlash, Store};
+
+const SECTOR_SIZE: u32 = 256;
+const SECTOR_COUNT: u32 = 4;
+
+fn mount(flash: RamFlash) -> Store<RamFlash> {
+	Store::mount(flash).unwrap()
+}
+
+fn read(store: &mut Store<RamFlash>, key: u16) -> Option<Vec<u8>> {
+	let mut bytes = [0; SECTOR_SIZE as usize];
+	let len = store.read(key, &mut bytes).unwrap()?;
+
+	Some(bytes[..len].to_vec())
+}
+
+#[test]
+fn reads_back_written_values() {
+	let mut store = mount(RamFlash::new(SECTOR_SIZE, SECTOR_COUNT));
+
+	assert_eq!(read(&mut store, 1), None);
+
+	store.write(1, b"first").unwrap();
+	store.write(2, b"second").unwrap();
+	store.write(1, b"replaced").unwrap();
+
+	assert_eq!(read(&mut store, 1).as_deref(), Some(&b"replaced"[..]));
+	assert_eq!(read(&mut store, 2).as_deref(), Some(&b"second"[..]));
+}
+
+#[test]
+fn values_survive_a_remount() {
+	let mut store = mount(RamFlash::new(SECTOR_SIZE, SECTOR_COUNT));
+
+	for i in 0..100u8 {
+		store.write(1, &[i; 20]).unwrap();
+		store.write(2, &[i; 3]).unwrap();
+	}
+
+	let mut store = mount(store.into_flash());
+
+	assert_eq!(read(&mut store, 1), Some(vec![99; 20]));
+	assert_eq!(read(&mut store, 2), Some(vec![99; 3]));
+}
+
+#[test]
+fn removed_values_stay_removed() {
+	let mut store = mount(RamFlash::new(SECTOR_SIZE, SECTOR_COUNT));
+
+	store.write(1, b"value").unwrap();
+	store.write(2, b"other").unwrap();
+	store.remove(1).unwrap();
+
+	assert_eq!(read(&mut store, 1), None);
+
+	// Rotate through every sector so the removal outlives the sector holding the old value.
+	for i in 0..50u8 {
+		store.write(2, &[i; 30]).unwrap();
+	}
+
+	let mut store = mount(store.into_flash());
+
+	assert_eq!(read(&mut store, 1), None);
+	assert_eq!(read(&mut store, 2), Some(vec![49; 30]));
+}
+
+#[test]
+fn clear_removes_everything() {
+	let mut store = mount(RamFlash::new(SECTOR_SIZE, SECTOR_COUNT));
+
+	store.write(1, b"value").unwrap();
+	store.clear().unwrap();
+
+	assert_eq!(read(&mut store, 1), None);
+
+	store.write(1, b"new").unwrap();
+
+	let mut store = mount(store.into_flash());
+
+	assert_eq!(read(&mut store, 1).as_deref(), Some(&b"new"[..]));
+}
+
+#[test]
+fn sectors_wear_evenly() {
+	let mut store = mount(RamFlash::new(SECTOR_SIZE, SECTOR_COUNT));
+
+	store.write(1, &[0xAA; 40]).unwrap();
+
+	for i in 0..1000u16 {
+		store.write(2, &i.to_le_bytes()).unwrap();
+	}
+
+	let flash = store.into_flash();
+	let counts = flash.erase_counts();
+
+	let min = counts.iter().min().unwrap();
+	let max = counts.iter().max().unwrap();
+
+	assert!(*min > 0);
+	assert!(max - min <= 1, "uneven erase counts: {counts:?}");
+
+	let mut store = mount(flash);
+
+	assert_eq!(read(&mut store, 1), Some(vec![0xAA; 40]));
+	assert_eq!(read(&mut store, 2), Some(999u16.to_le_bytes().to_vec()));
+}
+
+#[test]
+fn works_with_two_sectors() {
+	let mut store = mount(RamFlash::new(SECTOR_SIZE, 2));
+
+	for i in 0..200u8 {
+		store.write(u16::from(i % 3), &[i; 17]).unwrap();
+	}
+
+	let mut store = mount(store.into_flash());
+
+	assert_eq!(read(&mut store, 0), Some(vec![198; 17]));
+	assert_eq!(read(&mut store, 1), Some(vec![199; 17]));
+	assert_eq!(read(&mut store, 2), Some(vec![197; 17]));
+}
+
+#[test]
+fn rejects_values_that_dont_fit() {
+	let mut store = mount(RamFlash::new(SECTOR_SIZE, SECTOR_COUNT));
+
+	assert_eq!(store.write(1, &[0; SECTOR_SIZE as usize]), Err(Error::TooLarge));
+	assert_eq!(store.write(1, &[]), Err(Error::Empty));
+	assert_eq!(store.write(0xFFFF, &[0]), Err(Error::InvalidKey));
+
+	store.write(1, &[1; 120]).unwrap();
+
+	// The values in use need to fit in a single sector together.
+	assert_eq!(store.write(2, &[2; 120]), Err(Error::Full));
+
+	assert_eq!(read(&mut store, 1), Some(vec![1; 120]));
+	assert_eq!(read(&mut store, 2), None);
+
+	let mut bytes = [0; 10];
+	assert_eq!(store.read(1, &mut bytes), Err(Error::BufferTooSmall));
+}
+
+#[test]
+fn corrupted_records_fall_back_to_the_previous_value() {
+	let mut store = mount(RamFlash::new(SECTOR_SIZE, SECTOR_COUNT));
+
+	store.write(1, b"old").unwrap();
+	store.write(1, b"new").unwrap();
+
+	let mut flash = store.into_flash();
+
+	// Flip a bit in the data of the second record: sector header, first record, second header.
+	flash.memory_mut()[8 + 12 + 8] ^= 0x01;
+
+	let mut store = mount(flash);
+
+	assert_eq!(read(&mut store, 1).as_deref(), Some(&b"old"[..]));
+
+	store.write(1, b"newer").unwrap();
+
+	assert_eq!(read(&mut store, 1).as_deref(), Some(&b"newer"[..]));
+}
+
+#[test]
+fn finishes_an_interrupted_move() {
+	let mut store = mount(RamFlash::new(SECTOR_SIZE, 2));
+
+	// Every record takes 60 bytes, so four of them fill the first sector.
+	store.write(1, &[1; 50]).unwrap();
+
+	for i in 0..3 {
+		store.write(2, &[i; 50]).unwrap();
+	}
+
+	// The power goes out after the new sector and the new record are written, while the record of
+	// the first key is moved.
+	let mut store = Store::mount(PowerCut {
+		flash: store.into_flash(),
+		writes_left: 3,
+	})
+	.unwrap();
+
+	assert_eq!(store.write(2, &[3; 50]), Err(Error::Flash(PowerCutError)));
+
+	let mut store = mount(store.into_flash().flash);
+
+	assert_eq!(read(&mut store, 1), Some(vec![1; 50]));
+	assert_eq!(read(&mut store, 2), Some(vec![3; 50]));
+	assert_eq!(store.into_flash().erase_counts(), [1, 0]);
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct PowerCutError;
+
+/// Stops writing to the flash after a number of writes.
+struct PowerCut {
+	flash: RamFlash,
+	writes_left: u32,
+}
+
+impl Flash for PowerCut {
+	type Error = PowerCutError;
+
+	fn sector_size(&self) -> u32 {
+		self.flash.sector_size()
+	}
+
+	fn sector_count(&self) -> u32 {
+		self.flash.sector_count()
+	}
+
+	fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+		self.flash.read(offset, bytes).map_err(|_| PowerCutError)
+	}
+
+	fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+		self.writes_left = self.writes_left.checked_sub(1).ok_or(PowerCutError)?;
+
+		self.flash.write(offset, bytes).map_err(|_| PowerCutError)
+	}
+
+	fn erase(&mut self, sector: u32) -> Result<(), Self::Error> {
+		if self.writes_left == 0 {
+			return Err(PowerCutError);
+		}
+
+		self.flash.erase(sector).map_err(|_| PowerCutError)
+	}
+}
@@ -0,0 +1,301 @@
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the tests."
+)]
+
+use qubit_config::keyboard::event::{EventSink, KeyEvent, ResolvedEvent};
+use qubit_config::keyboard::{Action, Modifiers, TapHoldEngine, TapHoldFlavor, keycodes};
+
+const MOD_TAP: usize = 0;
+const LAYER_TAP: usize = 1;
+const KEY_C: usize = 2;
+const KEY_D: usize = 3;
+/// A layer-tap of layer 15 on `KC_E`, which the host can store.
+const MISSING_LAYER_TAP: usize = 4;
+/// Keys that only fill the buffer of held back events.
+const FILLERS: usize = 5;
+
+/// The layer-tap has a longer tapping term than the other keys.
+static TAPPING_TERMS: [u16; 32] = {
+	let mut terms = [200; 32];
+	terms[LAYER_TAP] = 300;
+	terms
+};
+
+/// Records the resolved events, with the actions of a single layer.
+struct Sink {
+	actions: Vec<Action>,
+	events: Vec<ResolvedEvent>,
+}
+
+impl Sink {
+	fn new() -> Self {
+		let mut actions = vec![
+			Action::mod_tap(Modifiers::LCTRL, keycodes::KC_A),
+			Action::layer_tap(2, keycodes::KC_B),
+			Action::key(keycodes::KC_C),
+			Action::key(keycodes::KC_D),
+			Action::from_bits(0x4F08),
+		];
+
+		actions.resize(TAPPING_TERMS.len(), Action::key(keycodes::KC_F));
+
+		Self {
+			actions,
+			events: Vec::new(),
+		}
+	}
+
+	fn take(&mut self) -> Vec<ResolvedEvent> {
+		std::mem::take(&mut self.events)
+	}
+}
+
+impl EventSink for Sink {
+	fn action(&self, index: usize) -> Action {
+		self.actions[index]
+	}
+
+	fn emit(&mut self, event: ResolvedEvent) {
+		self.events.push(event);
+	}
+}
+
+/// The engine and the sink, driven by a fake clock.
+struct Keyboard {
+	engine: TapHoldEngine,
+	sink: Sink,
+}
+
+impl Keyboard {
+	fn new(flavor: TapHoldFlavor) -> Self {
+		Self {
+			engine: TapHoldEngine::new(flavor, &TAPPING_TERMS),
+			sink: Sink::new(),
+		}
+	}
+
+	fn press(&mut self, index: usize, time: u32) {
+		self.engine.process(
+			KeyEvent {
+				index,
+				pressed: true,
+				time,
+			},
+			&mut self.sink,
+		);
+	}
+
+	fn release(&mut self, index: usize, time: u32) {
+		self.engine.process(
+			KeyEvent {
+				index,
+				pressed: false,
+				time,
+			},
+			&mut self.sink,
+		);
+	}
+
+	fn tick(&mut self, now: u32) {
+		self.engine.tick(now, &mut self.sink);
+	}
+}
+
+const fn press(index: usize, action: Action) -> ResolvedEvent {
+	ResolvedEvent::Press { index, action }
+}
+
+const fn release(index: usize) -> ResolvedEvent {
+	ResolvedEvent::Release { index }
+}
+
+fn key(keycode: std::num::NonZeroU8) -> Action {
+	Action::key(keycode)
+}
+
+#[test]
+fn taps_when_released_inside_the_term() {
+	let mut keyboard = Keyboard::new(TapHoldFlavor::Default);
+
+	keyboard.press(MOD_TAP, 1000);
+	keyboard.tick(1100);
+	assert_eq!(keyboard.sink.take(), []);
+
+	keyboard.release(MOD_TAP, 1199);
+	assert_eq!(
+		keyboard.sink.take(),
+		[press(MOD_TAP, key(keycodes::KC_A)), release(MOD_TAP)]
+	);
+}
+
+#[test]
+fn holds_once_the_term_runs_out() {
+	let mut keyboard = Keyboard::new(TapHoldFlavor::Default);
+
+	keyboard.press(MOD_TAP, 1000);
+	keyboard.tick(1199);
+	assert_eq!(keyboard.sink.take(), []);
+
+	keyboard.tick(1200);
+	assert_eq!(
+		keyboard.sink.take(),
+		[press(MOD_TAP, Action::modifiers(Modifiers::LCTRL))]
+	);
+
+	keyboard.release(MOD_TAP, 1500);
+	assert_eq!(keyboard.sink.take(), [release(MOD_TAP)]);
+
+	// A release after the term also holds, even without a tick in between.
+	keyboard.press(LAYER_TAP, 2000);
+	keyboard.release(LAYER_TAP, 2300);
+	assert_eq!(
+		keyboard.sink.take(),
+		[press(LAYER_TAP, Action::momentary(2)), release(LAYER_TAP)]
+	);
+}
+
+#[test]
+fn uses_the_tapping_term_of_the_key() {
+	let mut keyboard = Keyboard::new(TapHoldFlavor::Default);
+
+	keyboard.press(LAYER_TAP, 0);
+	keyboard.tick(299);
+	keyboard.release(LAYER_TAP, 299);
+
+	assert_eq!(
+		keyboard.sink.take(),
+		[press(LAYER_TAP, key(keycodes::KC_B)), release(LAYER_TAP)]
+	);
+}
+
+#[test]
+fn keeps_the_order_of_the_keys_pressed_while_undecided() {
+	let mut keyboard = Keyboard::new(TapHoldFlavor::Default);
+
+	// A key held before the tap-hold key is released right away.
+	keyboard.press(KEY_D, 0);
+	keyboard.press(MOD_TAP, 10);
+	keyboard.release(KEY_D, 20);
+	assert_eq!(
+		keyboard.sink.take(),
+		[press(KEY_D, key(keycodes::KC_D)), release(KEY_D)]
+	);
+
+	// A key tapped inside the term waits for the tap-hold key.
+	keyboard.press(KEY_C, 50);
+	keyboard.release(KEY_C, 80);
+	assert_eq!(keyboard.sink.take(), []);
+
+	keyboard.release(MOD_TAP, 100);
+	assert_eq!(
+		keyboard.sink.take(),
+		[
+			press(MOD_TAP, key(keycodes::KC_A)),
+			press(KEY_C, key(keycodes::KC_C)),
+			release(KEY_C),
+			release(MOD_TAP),
+		]
+	);
+}
+
+#[test]
+fn permissive_hold_holds_when_another_key_is_tapped() {
+	let mut keyboard = Keyboard::new(TapHoldFlavor::PermissiveHold);
+
+	keyboard.press(MOD_TAP, 0);
+	keyboard.press(KEY_C, 50);
+	assert_eq!(keyboard.sink.take(), []);
+
+	keyboard.release(KEY_C, 80);
+	assert_eq!(
+		keyboard.sink.take(),
+		[
+			press(MOD_TAP, Action::modifiers(Modifiers::LCTRL)),
+			press(KEY_C, key(keycodes::KC_C)),
+			release(KEY_C),
+		]
+	);
+
+	keyboard.release(MOD_TAP, 100);
+	assert_eq!(keyboard.sink.take(), [release(MOD_TAP)]);
+
+	// Releasing the tap-hold key first is still a tap.
+	keyboard.press(MOD_TAP, 1000);
+	keyboard.press(KEY_C, 1050);
+	keyboard.release(MOD_TAP, 1080);
+	keyboard.release(KEY_C, 1100);
+	assert_eq!(
+		keyboard.sink.take(),
+		[
+			press(MOD_TAP, key(keycodes::KC_A)),
+			press(KEY_C, key(keycodes::KC_C)),
+			release(MOD_TAP),
+			release(KEY_C),
+		]
+	);
+}
+
+#[test]
+fn hold_on_other_key_press_holds_right_away() {
+	let mut keyboard = Keyboard::new(TapHoldFlavor::HoldOnOtherKeyPress);
+
+	keyboard.press(LAYER_TAP, 0);
+	keyboard.press(KEY_C, 10);
+	assert_eq!(
+		keyboard.sink.take(),
+		[
+			press(LAYER_TAP, Action::momentary(2)),
+			press(KEY_C, key(keycodes::KC_C)),
+		]
+	);
+
+	keyboard.release(KEY_C, 20);
+	keyboard.release(LAYER_TAP, 30);
+	assert_eq!(keyboard.sink.take(), [release(KEY_C), release(LAYER_TAP)]);
+}
+
+#[test]
+fn holds_when_the_buffer_is_full() {
+	let mut keyboard = Keyboard::new(TapHoldFlavor::Default);
+
+	keyboard.press(MOD_TAP, 0);
+
+	// The engine holds back up to 16 events.
+	for index in FILLERS..FILLERS + 16 {
+		keyboard.press(index, 10);
+	}
+
+	assert_eq!(keyboard.sink.take(), []);
+
+	let last = FILLERS + 16;
+	keyboard.press(last, 20);
+
+	let mut expected = vec![press(MOD_TAP, Action::modifiers(Modifiers::LCTRL))];
+	expected.extend((FILLERS..=last).map(|index| press(index, key(keycodes::KC_F))));
+
+	assert_eq!(keyboard.sink.take(), expected);
+}
+
+#[test]
+fn layer_taps_of_missing_layers_do_nothing_when_held() {
+	let mut keyboard = Keyboard::new(TapHoldFlavor::Default);
+
+	keyboard.press(MISSING_LAYER_TAP, 0);
+	keyboard.tick(200);
+	keyboard.release(MISSING_LAYER_TAP, 300);
+	assert_eq!(
+		keyboard.sink.take(),
+		[press(MISSING_LAYER_TAP, Action::NO), release(MISSING_LAYER_TAP)]
+	);
+
+	keyboard.press(MISSING_LAYER_TAP, 1000);
+	keyboard.release(MISSING_LAYER_TAP, 1100);
+	assert_eq!(
+		keyboard.sink.take(),
+		[
+			press(MISSING_LAYER_TAP, key(keycodes::KC_E)),
+			release(MISSING_LAYER_TAP)
+		]
+	);
+}
@@ -0,0 +1,260 @@
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the tests."
+)]
+
+use std::path::PathBuf;
+
+use qubit_config::crc::crc32;
+use qubit_config::mcu::Mcu;
+use qubit_config::uf2::{self, Segment, Uf2Error};
+
+const BOOT2: u32 = 0x1000_0000;
+const FLASH: u32 = 0x1000_0100;
+const CONFIGURATION: u32 = 0x1010_0000;
+const KEYBOARD: u32 = 0x1010_0400;
+
+/// The CRC-32 of the golden file, so changing it has to be done on purpose.
+const GOLDEN_CRC: u32 = 0xE702_0607;
+
+fn pattern(len: usize, seed: u8) -> Vec<u8> {
+	(0..len)
+		.map(|index| u8::try_from(index % 251).unwrap().wrapping_mul(7).wrapping_add(seed))
+		.collect()
+}
+
+/// The data of a firmware laid out like the RP2040 linker script, with gaps between the regions.
+struct Firmware {
+	boot2: Vec<u8>,
+	code: Vec<u8>,
+	/// The initial values of the RAM, stored right after the code.
+	data: Vec<u8>,
+	configuration: Vec<u8>,
+	keyboard: Vec<u8>,
+}
+
+impl Firmware {
+	fn new() -> Self {
+		Self {
+			boot2: pattern(256, 1),
+			code: pattern(600, 2),
+			data: pattern(40, 3),
+			configuration: pattern(108, 4),
+			keyboard: pattern(300, 5),
+		}
+	}
+
+	fn data_address(&self) -> u32 {
+		FLASH + u32::try_from(self.code.len()).unwrap()
+	}
+
+	fn segments(&self) -> Vec<Segment<'_>> {
+		vec![
+			Segment {
+				address: BOOT2,
+				data: &self.boot2,
+			},
+			Segment {
+				address: FLASH,
+				data: &self.code,
+			},
+			Segment {
+				address: self.data_address(),
+				data: &self.data,
+			},
+			Segment {
+				address: CONFIGURATION,
+				data: &self.configuration,
+			},
+			Segment {
+				address: KEYBOARD,
+				data: &self.keyboard,
+			},
+		]
+	}
+
+	/// A 32 bit little endian ELF file with a program header for every segment, plus ones that
+	/// aren't loaded from the file.
+	fn elf(&self) -> Vec<u8> {
+		const PT_LOAD: u32 = 1;
+		const PT_NOTE: u32 = 4;
+
+		let mut file = vec![0; 52];
+		file[..6].copy_from_slice(b"\x7FELF\x01\x01");
+
+		let mut headers = Vec::new();
+
+		for segment in self.segments() {
+			let offset = file.len();
+			file.extend_from_slice(segment.data);
+
+			// The initial values of the RAM are loaded from the flash, but run from the RAM.
+			let virtual_address = if segment.address == self.data_address() {
+				0x2000_0000
+			} else {
+				segment.address
+			};
+
+			headers.push((PT_LOAD, offset, virtual_address, segment.address, segment.data.len()));
+		}
+
+		// The zeroed RAM has nothing in the file.
+		headers.push((PT_LOAD, 0, 0x2000_0100, 0x2000_0100, 0));
+		headers.push((PT_NOTE, 0, 0, 0, 16));
+
+		let table = file.len();
+
+		for (kind, offset, virtual_address, physical_address, len) in &headers {
+			let mut header = [0; 32];
+			header[0..4].copy_from_slice(&kind.to_le_bytes());
+			header[4..8].copy_from_slice(&u32::try_from(*offset).unwrap().to_le_bytes());
+			header[8..12].copy_from_slice(&virtual_address.to_le_bytes());
+			header[12..16].copy_from_slice(&physical_address.to_le_bytes());
+			header[16..20].copy_from_slice(&u32::try_from(*len).unwrap().to_le_bytes());
+			header[20..24].copy_from_slice(&u32::try_from(*len + 64).unwrap().to_le_bytes());
+
+			file.extend_from_slice(&header);
+		}
+
+		file[0x1C..0x20].copy_from_slice(&u32::try_from(table).unwrap().to_le_bytes());
+		file[0x2A..0x2C].copy_from_slice(&32_u16.to_le_bytes());
+		file[0x2C..0x2E].copy_from_slice(&u16::try_from(headers.len()).unwrap().to_le_bytes());
+
+		file
+	}
+}
+
+fn golden_path() -> PathBuf {
+	PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden/rp2040.uf2")
+}
+
+fn word(block: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes(block[offset..offset + 4].try_into().unwrap())
+}
+
+#[test]
+fn matches_the_golden_file() {
+	let firmware = Firmware::new();
+	let file = uf2::write(&firmware.segments(), uf2::family_id(Mcu::RP2040));
+
+	// Set to write the golden file again after changing the writer on purpose.
+	if std::env::var_os("QUBIT_UPDATE_GOLDEN").is_some() {
+		std::fs::write(golden_path(), &file).unwrap();
+	}
+
+	let golden = std::fs::read(golden_path()).unwrap();
+
+	assert_eq!(file, golden);
+	assert_eq!(crc32(&golden), GOLDEN_CRC);
+}
+
+#[test]
+fn skips_the_gaps_between_regions() {
+	let firmware = Firmware::new();
+	let file = uf2::write(&firmware.segments(), uf2::family_id(Mcu::RP2040));
+
+	let blocks: Vec<&[u8]> = file.chunks(uf2::BLOCK_LEN).collect();
+
+	let addresses: Vec<u32> = blocks.iter().map(|block| word(block, 12)).collect();
+
+	assert_eq!(
+		addresses,
+		[
+			BOOT2,
+			FLASH,
+			FLASH + 0x100,
+			FLASH + 0x200,
+			CONFIGURATION,
+			KEYBOARD,
+			KEYBOARD + 0x100
+		]
+	);
+
+	for (index, block) in blocks.iter().enumerate() {
+		assert_eq!(block.len(), uf2::BLOCK_LEN);
+		assert_eq!(word(block, 0), uf2::MAGIC_START_0);
+		assert_eq!(word(block, 4), uf2::MAGIC_START_1);
+		assert_eq!(word(block, 8), uf2::FLAG_FAMILY_ID_PRESENT);
+		assert_eq!(word(block, 16), 256);
+		assert_eq!(word(block, 20), u32::try_from(index).unwrap());
+		assert_eq!(word(block, 24), u32::try_from(blocks.len()).unwrap());
+		assert_eq!(word(block, 28), uf2::RP2040_FAMILY_ID);
+		assert_eq!(word(block, uf2::BLOCK_LEN - 4), uf2::MAGIC_END);
+
+		// Only the first 256 bytes of the data are used.
+		assert!(
+			block[uf2::DATA_OFFSET + 256..uf2::BLOCK_LEN - 4]
+				.iter()
+				.all(|&byte| byte == 0)
+		);
+	}
+
+	// The code and the initial values of the RAM share the last page of the code.
+	let last_code_page = &blocks[3][uf2::DATA_OFFSET..uf2::DATA_OFFSET + 256];
+	assert_eq!(&last_code_page[..88], &firmware.code[512..]);
+	assert_eq!(&last_code_page[88..128], firmware.data.as_slice());
+	assert!(last_code_page[128..].iter().all(|&byte| byte == 0));
+
+	// The pages of the sections keep their checksums.
+	let configuration = &blocks[4][uf2::DATA_OFFSET..uf2::DATA_OFFSET + firmware.configuration.len()];
+	assert_eq!(crc32(configuration), crc32(&firmware.configuration));
+
+	let keyboard = [
+		&blocks[5][uf2::DATA_OFFSET..uf2::DATA_OFFSET + 256],
+		&blocks[6][uf2::DATA_OFFSET..uf2::DATA_OFFSET + 44],
+	]
+	.concat();
+	assert_eq!(crc32(&keyboard), crc32(&firmware.keyboard));
+}
+
+#[test]
+fn converts_the_loaded_segments_of_an_elf_file() {
+	let firmware = Firmware::new();
+	let elf = firmware.elf();
+
+	assert_eq!(uf2::elf_segments(&elf).unwrap(), firmware.segments());
+	assert_eq!(
+		uf2::from_elf(&elf, uf2::family_id(Mcu::RP2040)).unwrap(),
+		std::fs::read(golden_path()).unwrap()
+	);
+}
+
+#[test]
+fn uses_the_family_of_the_mcu() {
+	let firmware = Firmware::new();
+
+	let rp2040 = uf2::write(&firmware.segments(), uf2::family_id(Mcu::RP2040));
+	let stm32 = uf2::write(&firmware.segments(), uf2::family_id(Mcu::STM32F411));
+
+	assert_eq!(uf2::family_id(Mcu::STM32F411), uf2::STM32F4_FAMILY_ID);
+
+	for (rp2040, stm32) in rp2040.chunks(uf2::BLOCK_LEN).zip(stm32.chunks(uf2::BLOCK_LEN)) {
+		assert_eq!(word(stm32, 28), 0x5775_5A57);
+		assert_eq!(rp2040[..28], stm32[..28]);
+		assert_eq!(rp2040[32..], stm32[32..]);
+	}
+}
+
+#[test]
+fn rejects_invalid_elf_files() {
+	let elf = Firmware::new().elf();
+
+	let mut big_endian = elf.clone();
+	big_endian[5] = 2;
+	assert_eq!(
+		uf2::from_elf(&big_endian, uf2::RP2040_FAMILY_ID),
+		Err(Uf2Error::InvalidElf)
+	);
+
+	// The program headers are at the end.
+	let mut truncated = elf.clone();
+	truncated.truncate(elf.len() - 40);
+	assert_eq!(
+		uf2::from_elf(&truncated, uf2::RP2040_FAMILY_ID),
+		Err(Uf2Error::InvalidElf)
+	);
+
+	let mut empty = elf;
+	empty[0x2C..0x2E].copy_from_slice(&0_u16.to_le_bytes());
+	assert_eq!(uf2::from_elf(&empty, uf2::RP2040_FAMILY_ID), Err(Uf2Error::Empty));
+}
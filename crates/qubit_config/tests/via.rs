@@ -0,0 +1,280 @@
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the tests."
+)]
+
+use std::num::NonZeroU8;
+
+use qubit_config::keyboard::layer::LAYER_COUNT;
+use qubit_config::keyboard::{Action, FirmwareAction, Keymap, Modifiers};
+use qubit_config::usb::Usb;
+use qubit_config::via::{self, Command, Device, REPORT_LEN};
+
+const ROWS: usize = 2;
+const COLS: usize = 3;
+
+/// A 2x3 keyboard without a key at the top right.
+struct TestDevice {
+	layers: Vec<Vec<Action>>,
+	keymap_stores: usize,
+	macros: Vec<u8>,
+	macro_stores: usize,
+	bootloader_jumps: usize,
+}
+
+impl TestDevice {
+	fn new() -> Self {
+		Self {
+			layers: vec![vec![Action::NO; ROWS * COLS]; LAYER_COUNT],
+			keymap_stores: 0,
+			macros: vec![0; 64],
+			macro_stores: 0,
+			bootloader_jumps: 0,
+		}
+	}
+
+	fn has_key(row: usize, col: usize) -> bool {
+		row < ROWS && col < COLS && (row, col) != (0, 2)
+	}
+}
+
+impl Device for TestDevice {
+	fn matrix_size(&self) -> (usize, usize) {
+		(ROWS, COLS)
+	}
+
+	fn firmware_version(&self) -> u32 {
+		0x0102_0304
+	}
+
+	fn action(&self, layer: usize, row: usize, col: usize) -> Option<Action> {
+		Self::has_key(row, col).then(|| self.layers[layer][row * COLS + col])
+	}
+
+	fn set_action(&mut self, layer: usize, row: usize, col: usize, action: Action) -> bool {
+		if !Self::has_key(row, col) {
+			return false;
+		}
+
+		self.layers[layer][row * COLS + col] = action;
+
+		true
+	}
+
+	fn store_keymaps(&mut self) {
+		self.keymap_stores += 1;
+	}
+
+	fn reset_keymaps(&mut self) {
+		*self = Self {
+			macros: std::mem::take(&mut self.macros),
+			..Self::new()
+		};
+	}
+
+	fn macro_buffer(&self) -> &[u8] {
+		&self.macros
+	}
+
+	fn macro_buffer_mut(&mut self) -> &mut [u8] {
+		&mut self.macros
+	}
+
+	fn store_macros(&mut self) {
+		self.macro_stores += 1;
+	}
+
+	fn jump_to_bootloader(&mut self) -> bool {
+		self.bootloader_jumps += 1;
+
+		true
+	}
+}
+
+fn key(keycode: u8) -> Action {
+	Action::key(NonZeroU8::new(keycode).unwrap())
+}
+
+fn request(device: &mut TestDevice, bytes: &[u8]) -> [u8; REPORT_LEN] {
+	let mut report = [0; REPORT_LEN];
+	report[..bytes.len()].copy_from_slice(bytes);
+
+	via::handle(device, &mut report);
+
+	report
+}
+
+#[test]
+fn keycodes_match_qmk() {
+	let pairs = [
+		(Action::NO, 0x0000),
+		(Action::TRANSPARENT, 0x0001),
+		(key(0x04), 0x0004),
+		(key(0x04).with_modifiers(Modifiers::LCTRL), 0x0104),
+		(
+			Action::mod_tap(Modifiers::RSHIFT, NonZeroU8::new(0x28).unwrap()),
+			0x3228,
+		),
+		(Action::layer_tap(2, NonZeroU8::new(0x2C).unwrap()), 0x422C),
+		(Action::momentary(1), 0x5221),
+		(Action::toggle(3), 0x5263),
+		(Action::to(4), 0x5204),
+		(Action::consumer(0xE2), 0x00A8),
+		(Action::system(0x82), 0x00A6),
+		(Action::macro_action(3), 0x7703),
+		(Action::custom(5), 0x7E05),
+		(Action::firmware(FirmwareAction::NkroToggle), 0x7013),
+		(Action::firmware(FirmwareAction::Bootloader), 0x7C00),
+		(Action::firmware(FirmwareAction::Reboot), 0x7C01),
+	];
+
+	for (action, keycode) in pairs {
+		assert_eq!(via::to_keycode(action), keycode, "{action:?}");
+		assert_eq!(via::from_keycode(keycode), action, "{keycode:#06x}");
+	}
+}
+
+#[test]
+fn keycodes_without_an_action_do_nothing() {
+	// A layer QMK has but the keymaps don't, a QMK key the firmware doesn't have and a Consumer
+	// usage without a basic keycode.
+	assert_eq!(via::from_keycode(0x5227), Action::NO);
+	assert_eq!(via::from_keycode(0x7C03), Action::NO);
+	assert_eq!(via::to_keycode(Action::consumer(0x0030)), 0x0000);
+}
+
+#[test]
+fn answers_the_protocol_version_and_layer_count() {
+	let mut device = TestDevice::new();
+
+	assert_eq!(
+		request(&mut device, &[Command::GetProtocolVersion as u8])[..3],
+		[0x01, 0x00, 0x0C]
+	);
+	assert_eq!(request(&mut device, &[Command::GetLayerCount as u8])[..2], [0x11, 5]);
+	assert_eq!(
+		request(&mut device, &[Command::GetKeyboardValue as u8, 0x04])[..6],
+		[0x02, 0x04, 0x01, 0x02, 0x03, 0x04]
+	);
+}
+
+#[test]
+fn sets_and_gets_keycodes() {
+	let mut device = TestDevice::new();
+
+	request(&mut device, &[Command::SetKeycode as u8, 1, 1, 2, 0x52, 0x22]);
+
+	assert_eq!(device.layers[1][5], Action::momentary(2));
+	assert_eq!(device.keymap_stores, 1);
+
+	let response = request(&mut device, &[Command::GetKeycode as u8, 1, 1, 2]);
+	assert_eq!(response[4..6], [0x52, 0x22]);
+
+	// Positions without a key are ignored.
+	request(&mut device, &[Command::SetKeycode as u8, 0, 0, 2, 0x00, 0x04]);
+	request(&mut device, &[Command::SetKeycode as u8, 9, 0, 0, 0x00, 0x04]);
+	assert_eq!(device.keymap_stores, 1);
+}
+
+#[test]
+fn keymap_buffers_cover_every_layer() {
+	let mut device = TestDevice::new();
+
+	// The second layer starts at byte 12, its first row holds A, B and a position without a key.
+	request(
+		&mut device,
+		&[
+			Command::SetKeymapBuffer as u8,
+			0x00,
+			12,
+			6,
+			0x00,
+			0x04,
+			0x00,
+			0x05,
+			0x00,
+			0x06,
+		],
+	);
+
+	assert_eq!(device.layers[1][..3], [key(0x04), key(0x05), Action::NO]);
+	assert_eq!(device.keymap_stores, 1);
+
+	let response = request(&mut device, &[Command::GetKeymapBuffer as u8, 0x00, 12, 6]);
+	assert_eq!(response[4..10], [0x00, 0x04, 0x00, 0x05, 0x00, 0x00]);
+
+	// Reads past the last layer are empty and sizes that don't fit are refused.
+	let response = request(&mut device, &[Command::GetKeymapBuffer as u8, 0x00, 60, 4]);
+	assert_eq!(response[4..8], [0; 4]);
+	assert_eq!(
+		request(&mut device, &[Command::GetKeymapBuffer as u8, 0x00, 0, 29])[0],
+		Command::Unhandled as u8
+	);
+}
+
+#[test]
+fn macro_buffers_are_stored() {
+	let mut device = TestDevice::new();
+
+	assert_eq!(request(&mut device, &[Command::GetMacroCount as u8])[1], 16);
+	assert_eq!(
+		request(&mut device, &[Command::GetMacroBufferSize as u8])[1..3],
+		[0x00, 64]
+	);
+
+	request(
+		&mut device,
+		&[Command::SetMacroBuffer as u8, 0x00, 62, 4, b'a', b'b', b'c', b'd'],
+	);
+
+	// Only the part inside the buffer is written.
+	assert_eq!(device.macros[62..], [b'a', b'b']);
+	assert_eq!(device.macro_stores, 1);
+
+	let response = request(&mut device, &[Command::GetMacroBuffer as u8, 0x00, 61, 3]);
+	assert_eq!(response[4..7], [0, b'a', b'b']);
+
+	request(&mut device, &[Command::ResetMacros as u8]);
+	assert!(device.macros.iter().all(|&byte| byte == 0));
+	assert_eq!(device.macro_stores, 2);
+}
+
+#[test]
+fn jumps_to_the_bootloader() {
+	let mut device = TestDevice::new();
+
+	let response = request(&mut device, &[Command::BootloaderJump as u8]);
+
+	assert_eq!(response[0], Command::BootloaderJump as u8);
+	assert_eq!(device.bootloader_jumps, 1);
+}
+
+#[test]
+fn lighting_is_unhandled_without_a_backlight() {
+	let mut device = TestDevice::new();
+
+	let response = request(&mut device, &[Command::CustomGetValue as u8, 1, 1]);
+
+	assert_eq!(response[0], Command::Unhandled as u8);
+	assert_eq!(response[1..3], [1, 1]);
+}
+
+#[test]
+fn definitions_follow_the_layout() {
+	let layout = Keymap::new([[key(0x04), Action::NO, key(0x05)], [Action::NO, key(0x06), key(0x07)]]);
+
+	let definition = via::definition("Test", &Usb::new(0x1209, 0x0001), &layout, true);
+	let definition: serde_json::Value = serde_json::from_str(&definition).unwrap();
+
+	assert_eq!(definition["vendorId"], "0x1209");
+	assert_eq!(definition["productId"], "0x0001");
+	assert_eq!(definition["matrix"], serde_json::json!({ "rows": 2, "cols": 3 }));
+	assert_eq!(
+		definition["layouts"]["keymap"],
+		serde_json::json!([["0,0", { "x": 1 }, "0,2"], [{ "x": 1 }, "1,1", "1,2"]])
+	);
+	assert_eq!(
+		definition["menus"][0]["content"][0]["content"][0]["content"],
+		serde_json::json!(["id_qmk_backlight_brightness", 1, 1])
+	);
+}
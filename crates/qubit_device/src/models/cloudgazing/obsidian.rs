@@ -2,7 +2,7 @@
 // multi-target compilation.
 
 use qubit_config::general::Device;
-use qubit_config::keyboard::Keymap;
+use qubit_config::keyboard::{Bootmagic, Debounce, Keymap, LockIndicator, TapHold, TapHoldFlavor};
 use qubit_config::keymap;
 use qubit_config::mcu::Mcu;
 use qubit_config::usb::Usb;
@@ -15,6 +15,7 @@ pub const AUTHOR: &str = "cloudgazing";
 pub const VERSION: Version = Version::new_zero(0, 0, 1);
 pub const DEVICE: Device = Device::Keyboard;
 pub const LED_PIN: Option<&str> = None;
+pub const LED_INDICATOR: LockIndicator = LockIndicator::CapsLock;
 
 // Keyboard
 pub const MCU: Mcu = Mcu::STM32F411;
@@ -43,20 +44,29 @@ pub const LAYER1: Keymap<ROW_NUM, COL_NUM> = keymap! [
 //
 #[rustfmt::skip]
 pub const LAYER2: Keymap<ROW_NUM, COL_NUM> = keymap! [
-	[KC_0, KC_1],
-	[KC_3, KC_4],
+	[_, _],
+	[_, _],
 ];
 #[rustfmt::skip]
 pub const LAYER3: Keymap<ROW_NUM, COL_NUM> = keymap! [
-	[KC_0, KC_1],
-	[KC_3, KC_4],
+	[_, _],
+	[_, _],
 ];
 #[rustfmt::skip]
 pub const LAYER4: Keymap<ROW_NUM, COL_NUM> = keymap! [
-	[KC_0, KC_1],
-	[KC_3, KC_4],
+	[_, _],
+	[_, _],
 ];
 
+// Switch debouncing
+pub const DEBOUNCE: Debounce = Debounce::SymmetricDefer { ms: 5 };
+
+// Tap-hold keys
+pub const TAP_HOLD: TapHold = TapHold::new(200, TapHoldFlavor::Default);
+
+// Keys held at power on to recover the board
+pub const BOOTMAGIC: Bootmagic = Bootmagic::top_left(&LAYER0);
+
 // Keyboard layout
 
 // This VID/PID is provided by pid.codes and is reserved for testing.
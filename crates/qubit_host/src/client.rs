@@ -0,0 +1,389 @@
+use std::time::Duration;
+
+use qubit_config::keyboard::Action;
+use qubit_config::silverplate::{
+	Capabilities, Command, Frame, LayerState, MAX_PAYLOAD_LEN, NOTIFICATION_SEQUENCE, RebootMode, ReportFormat, Status,
+};
+use qubit_config::version::{BuildDate, Version};
+
+use crate::{Error, Transport};
+
+/// The time a device has to answer a request.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What the device supports, from its capability query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceCapabilities {
+	/// The highest protocol version of the device.
+	pub protocol_version: u8,
+	pub max_payload_len: u8,
+	pub capabilities: Capabilities,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareInfo {
+	pub name: String,
+	pub author: String,
+	pub version: Version,
+	pub build_date: BuildDate,
+}
+
+/// The size of the keymaps of the device and how they are split into chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeymapInfo {
+	pub rows: u8,
+	pub cols: u8,
+	pub layers: u8,
+	pub chunks_per_layer: u8,
+	pub actions_per_chunk: u8,
+}
+
+/// The keymaps of every layer, for every matrix position in row-major order.
+///
+/// Positions without a key have the `NO` action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+	pub rows: usize,
+	pub cols: usize,
+	pub layers: Vec<Vec<Action>>,
+}
+
+impl Keymap {
+	/// Creates a keymap with every action set to `NO`.
+	#[must_use]
+	pub fn new(rows: usize, cols: usize, layers: usize) -> Self {
+		Self {
+			rows,
+			cols,
+			layers: vec![vec![Action::NO; rows * cols]; layers],
+		}
+	}
+
+	#[must_use]
+	pub fn get(&self, layer: usize, row: usize, col: usize) -> Option<Action> {
+		if row >= self.rows || col >= self.cols {
+			return None;
+		}
+
+		self.layers.get(layer)?.get(row * self.cols + col).copied()
+	}
+
+	/// Sets the action at the position. Returns `false` if the position is out of the keymap.
+	pub fn set(&mut self, layer: usize, row: usize, col: usize, action: Action) -> bool {
+		if row >= self.rows || col >= self.cols {
+			return false;
+		}
+
+		let Some(slot) = self
+			.layers
+			.get_mut(layer)
+			.and_then(|layer| layer.get_mut(row * self.cols + col))
+		else {
+			return false;
+		};
+
+		*slot = action;
+
+		true
+	}
+}
+
+/// Sends typed silverplate requests through a [`Transport`].
+#[derive(Debug)]
+pub struct Client<T> {
+	transport: T,
+	sequence: u8,
+	timeout: Duration,
+}
+
+impl<T: Transport> Client<T> {
+	#[must_use]
+	pub const fn new(transport: T) -> Self {
+		Self {
+			transport,
+			sequence: NOTIFICATION_SEQUENCE,
+			timeout: DEFAULT_TIMEOUT,
+		}
+	}
+
+	/// Sets the time the device has to answer a request.
+	#[must_use]
+	pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+		self.timeout = timeout;
+		self
+	}
+
+	#[must_use]
+	pub const fn transport(&self) -> &T {
+		&self.transport
+	}
+
+	pub const fn transport_mut(&mut self) -> &mut T {
+		&mut self.transport
+	}
+
+	#[must_use]
+	pub fn into_transport(self) -> T {
+		self.transport
+	}
+
+	/// Sends a request and waits for its response.
+	///
+	/// Notifications and responses to earlier requests are skipped.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the request can't be sent, the device doesn't answer or it answers with
+	/// an error status.
+	pub fn request(&mut self, command: Command, payload: &[u8]) -> Result<Frame, Error> {
+		// The sequence numbers of requests skip the one of notifications.
+		self.sequence = self.sequence.checked_add(1).unwrap_or(NOTIFICATION_SEQUENCE + 1);
+
+		let request = Frame::request(command, self.sequence, payload).ok_or(Error::PayloadTooLarge)?;
+
+		self.transport.send(&request.encode())?;
+
+		loop {
+			let bytes = self.transport.receive(self.timeout)?.ok_or(Error::Timeout)?;
+
+			let response = Frame::decode(&bytes)?;
+
+			if response.sequence != self.sequence || response.command != command as u8 {
+				continue;
+			}
+
+			return match response.status() {
+				Some(Status::Ok) => Ok(response),
+				Some(status) => Err(Error::Status(status)),
+				None => Err(Error::UnknownStatus(response.status)),
+			};
+		}
+	}
+
+	/// # Errors
+	///
+	/// Returns an error if the request fails.
+	pub fn capabilities(&mut self) -> Result<DeviceCapabilities, Error> {
+		let response = self.request(Command::GetCapabilities, &[])?;
+
+		let &[protocol_version, max_payload_len, a, b, c, d] = response.payload() else {
+			return Err(Error::InvalidResponse);
+		};
+
+		Ok(DeviceCapabilities {
+			protocol_version,
+			max_payload_len,
+			capabilities: Capabilities::from_bits(u32::from_le_bytes([a, b, c, d])),
+		})
+	}
+
+	/// # Errors
+	///
+	/// Returns an error if the request fails.
+	pub fn firmware_info(&mut self) -> Result<FirmwareInfo, Error> {
+		let response = self.request(Command::GetFirmwareInfo, &[])?;
+
+		let &[date_low, date_high, a, b, c, d, ref texts @ ..] = response.payload() else {
+			return Err(Error::InvalidResponse);
+		};
+
+		let (name, texts) = split_text(texts)?;
+		let (author, _) = split_text(texts)?;
+
+		Ok(FirmwareInfo {
+			name,
+			author,
+			version: Version::from_bitmap(u32::from_le_bytes([a, b, c, d])),
+			build_date: BuildDate::from_bitmap(u16::from_le_bytes([date_low, date_high])),
+		})
+	}
+
+	/// # Errors
+	///
+	/// Returns an error if the request fails.
+	pub fn keymap_info(&mut self) -> Result<KeymapInfo, Error> {
+		let response = self.request(Command::GetKeymapInfo, &[])?;
+
+		let &[rows, cols, layers, chunks_per_layer, actions_per_chunk] = response.payload() else {
+			return Err(Error::InvalidResponse);
+		};
+
+		Ok(KeymapInfo {
+			rows,
+			cols,
+			layers,
+			chunks_per_layer,
+			actions_per_chunk,
+		})
+	}
+
+	/// Reads the keymaps the device uses, chunk by chunk.
+	///
+	/// # Errors
+	///
+	/// Returns an error if any of the requests fails.
+	pub fn read_keymap(&mut self) -> Result<Keymap, Error> {
+		let info = self.keymap_info()?;
+
+		let mut keymap = Keymap::new(usize::from(info.rows), usize::from(info.cols), usize::from(info.layers));
+
+		for layer in 0..info.layers {
+			let mut actions = keymap.layers[usize::from(layer)].iter_mut();
+
+			for part in 0..info.chunks_per_layer {
+				let response = self.request(Command::GetKeymapChunk, &[layer, part])?;
+
+				let &[response_layer, response_part, count, ref data @ ..] = response.payload() else {
+					return Err(Error::InvalidResponse);
+				};
+
+				if (response_layer, response_part) != (layer, part) || data.len() < usize::from(count) * 2 {
+					return Err(Error::InvalidResponse);
+				}
+
+				for bytes in data.chunks_exact(2).take(usize::from(count)) {
+					let slot = actions.next().ok_or(Error::InvalidResponse)?;
+
+					*slot = Action::from_bits(u16::from_le_bytes([bytes[0], bytes[1]]));
+				}
+			}
+		}
+
+		Ok(keymap)
+	}
+
+	/// Writes all the keymaps to the device, without committing them.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the keymap doesn't have the size of the keymaps of the device or any
+	/// of the requests fails.
+	pub fn write_keymap(&mut self, keymap: &Keymap) -> Result<(), Error> {
+		let info = self.keymap_info()?;
+
+		if (keymap.rows, keymap.cols, keymap.layers.len())
+			!= (usize::from(info.rows), usize::from(info.cols), usize::from(info.layers))
+			|| keymap
+				.layers
+				.iter()
+				.any(|layer| layer.len() != keymap.rows * keymap.cols)
+		{
+			return Err(Error::KeymapMismatch);
+		}
+
+		for (layer, actions) in (0..info.layers).zip(&keymap.layers) {
+			for (part, chunk) in (0..info.chunks_per_layer).zip(actions.chunks(usize::from(info.actions_per_chunk))) {
+				let count = u8::try_from(chunk.len()).map_err(|_| Error::PayloadTooLarge)?;
+
+				let mut payload = Vec::with_capacity(MAX_PAYLOAD_LEN);
+
+				payload.extend_from_slice(&[layer, part, count]);
+				payload.extend(chunk.iter().flat_map(|action| action.to_bits().to_le_bytes()));
+
+				self.request(Command::SetKeymapChunk, &payload)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Changes a single key, without committing it.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the request fails.
+	pub fn set_key(&mut self, layer: u8, row: u8, col: u8, action: Action) -> Result<(), Error> {
+		let [low, high] = action.to_bits().to_le_bytes();
+
+		self.request(Command::SetKey, &[layer, row, col, low, high]).map(drop)
+	}
+
+	/// Stores the changed keymaps and starts using them.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the request fails.
+	pub fn commit_keymap(&mut self) -> Result<(), Error> {
+		self.request(Command::CommitKeymap, &[]).map(drop)
+	}
+
+	/// Drops the changes made since the last commit.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the request fails.
+	pub fn revert_keymap(&mut self) -> Result<(), Error> {
+		self.request(Command::RevertKeymap, &[]).map(drop)
+	}
+
+	/// # Errors
+	///
+	/// Returns an error if the request fails.
+	pub fn report_format(&mut self) -> Result<ReportFormat, Error> {
+		let response = self.request(Command::GetReportFormat, &[])?;
+
+		parse_report_format(response.payload())
+	}
+
+	/// Chooses between the NKRO and the 6KRO report, returning the format used afterwards.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the request fails.
+	pub fn set_nkro(&mut self, enabled: bool) -> Result<ReportFormat, Error> {
+		let response = self.request(Command::SetNkro, &[u8::from(enabled)])?;
+
+		parse_report_format(response.payload())
+	}
+
+	/// # Errors
+	///
+	/// Returns an error if the request fails.
+	pub fn layer_state(&mut self) -> Result<LayerState, Error> {
+		let response = self.request(Command::GetLayerState, &[])?;
+
+		let &[active, default] = response.payload() else {
+			return Err(Error::InvalidResponse);
+		};
+
+		Ok(LayerState { active, default })
+	}
+
+	/// # Errors
+	///
+	/// Returns an error if the request fails.
+	pub fn set_layer_state(&mut self, state: LayerState) -> Result<(), Error> {
+		self.request(Command::SetLayerState, &[state.active, state.default])
+			.map(drop)
+	}
+
+	/// Asks the device to reboot. The device disconnects shortly after answering.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the request fails.
+	pub fn reboot(&mut self, mode: RebootMode) -> Result<(), Error> {
+		self.request(Command::Reboot, &[mode as u8]).map(drop)
+	}
+}
+
+/// Splits a text prefixed by its length from the rest of the bytes.
+fn split_text(bytes: &[u8]) -> Result<(String, &[u8]), Error> {
+	let (&len, rest) = bytes.split_first().ok_or(Error::InvalidResponse)?;
+
+	if rest.len() < usize::from(len) {
+		return Err(Error::InvalidResponse);
+	}
+
+	let (text, rest) = rest.split_at(usize::from(len));
+
+	Ok((String::from_utf8_lossy(text).into_owned(), rest))
+}
+
+fn parse_report_format(payload: &[u8]) -> Result<ReportFormat, Error> {
+	let &[format] = payload else {
+		return Err(Error::InvalidResponse);
+	};
+
+	ReportFormat::from_u8(format).ok_or(Error::InvalidResponse)
+}
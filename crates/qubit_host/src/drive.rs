@@ -0,0 +1,67 @@
+//! Finds the drives UF2 bootloaders mount, to flash a firmware by copying it to them.
+//!
+//! Drives are found through `/proc/mounts`, and told apart from other drives by the info file every
+//! UF2 bootloader has at its root.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+const PROC_MOUNTS: &str = "/proc/mounts";
+
+/// The file every UF2 bootloader drive has at its root.
+pub const INFO_FILE: &str = "INFO_UF2.TXT";
+
+/// The mount points of a table in the format of `/proc/mounts`, with their escaped characters
+/// decoded.
+#[must_use]
+pub fn mount_points(mounts: &str) -> Vec<PathBuf> {
+	mounts
+		.lines()
+		.filter_map(|line| line.split_whitespace().nth(1))
+		.map(|path| PathBuf::from(unescape(path)))
+		.collect()
+}
+
+/// Lists the mounted drives of UF2 bootloaders.
+///
+/// # Errors
+///
+/// Returns an error if the mounted drives can't be listed.
+pub fn uf2_drives() -> io::Result<Vec<PathBuf>> {
+	let mounts = std::fs::read_to_string(PROC_MOUNTS)?;
+
+	Ok(mount_points(&mounts)
+		.into_iter()
+		.filter(|path| is_uf2_drive(path))
+		.collect())
+}
+
+fn is_uf2_drive(path: &Path) -> bool {
+	path.join(INFO_FILE).is_file()
+}
+
+/// Decodes the octal escapes the kernel writes for spaces, tabs, new lines and backslashes.
+fn unescape(path: &str) -> String {
+	let mut unescaped = String::with_capacity(path.len());
+	let mut rest = path;
+
+	while let Some(start) = rest.find('\\') {
+		unescaped.push_str(&rest[..start]);
+
+		let code = rest
+			.get(start + 1..start + 4)
+			.and_then(|digits| u8::from_str_radix(digits, 8).ok());
+
+		if let Some(code) = code {
+			unescaped.push(char::from(code));
+			rest = &rest[start + 4..];
+		} else {
+			unescaped.push('\\');
+			rest = &rest[start + 1..];
+		}
+	}
+
+	unescaped.push_str(rest);
+
+	unescaped
+}
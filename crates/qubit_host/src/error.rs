@@ -0,0 +1,59 @@
+use std::fmt;
+use std::io;
+
+use qubit_config::silverplate::{DecodeError, Status};
+
+#[derive(Debug)]
+pub enum Error {
+	Io(io::Error),
+	/// The device didn't answer in time.
+	Timeout,
+	/// The device sent a frame that can't be decoded.
+	Decode(DecodeError),
+	/// The device refused the request.
+	Status(Status),
+	/// The device answered with a status this crate doesn't know.
+	UnknownStatus(u8),
+	/// The response doesn't have the layout of its command.
+	InvalidResponse,
+	/// The keymap doesn't have the size of the keymap of the device.
+	KeymapMismatch,
+	/// The payload of the request doesn't fit in a frame.
+	PayloadTooLarge,
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Io(error) => write!(f, "I/O error: {error}"),
+			Self::Timeout => write!(f, "the device didn't answer in time"),
+			Self::Decode(error) => write!(f, "invalid frame from the device: {error:?}"),
+			Self::Status(status) => write!(f, "the device refused the request: {status:?}"),
+			Self::UnknownStatus(status) => write!(f, "the device answered with an unknown status {status:#04x}"),
+			Self::InvalidResponse => write!(f, "the response of the device has an invalid layout"),
+			Self::KeymapMismatch => write!(f, "the keymap doesn't match the matrix and layers of the device"),
+			Self::PayloadTooLarge => write!(f, "the request doesn't fit in a frame"),
+		}
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Io(error) => Some(error),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for Error {
+	fn from(error: io::Error) -> Self {
+		Self::Io(error)
+	}
+}
+
+impl From<DecodeError> for Error {
+	fn from(error: DecodeError) -> Self {
+		Self::Decode(error)
+	}
+}
@@ -0,0 +1,199 @@
+//! The transport of real devices, through the hidraw interface of Linux.
+//!
+//! Devices are found through `/sys/class/hidraw`, which lists the USB IDs and the report
+//! descriptor of every HID interface.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read as _, Write as _};
+use std::os::fd::AsRawFd as _;
+use std::os::unix::fs::OpenOptionsExt as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use qubit_config::silverplate::{REPORT_ID_IN, REPORT_ID_OUT, REPORT_LEN};
+
+use crate::Transport;
+
+const SYSFS_HIDRAW: &str = "/sys/class/hidraw";
+
+/// The start of the output report in the descriptor of the silverplate interface: its report ID
+/// and the vendor defined usage page.
+const SILVERPLATE_DESCRIPTOR: [u8; 5] = [0x85, REPORT_ID_OUT, 0x06, 0x00, 0xFF];
+
+/// A HID interface that speaks the silverplate protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+	/// The path of the hidraw node, like `/dev/hidraw3`.
+	pub path: PathBuf,
+	pub vendor_id: u16,
+	pub product_id: u16,
+	/// The name of the device, made of its manufacturer and product strings.
+	pub name: String,
+}
+
+/// Lists the connected silverplate devices.
+///
+/// # Errors
+///
+/// Returns an error if the hidraw devices can't be listed.
+pub fn devices() -> io::Result<Vec<DeviceInfo>> {
+	let mut devices = Vec::new();
+
+	for entry in fs::read_dir(SYSFS_HIDRAW)? {
+		let entry = entry?;
+		let device_dir = entry.path().join("device");
+
+		// Devices can disappear while they are listed.
+		let Ok(descriptor) = fs::read(device_dir.join("report_descriptor")) else {
+			continue;
+		};
+
+		if !descriptor
+			.windows(SILVERPLATE_DESCRIPTOR.len())
+			.any(|window| window == SILVERPLATE_DESCRIPTOR)
+		{
+			continue;
+		}
+
+		let Ok(uevent) = fs::read_to_string(device_dir.join("uevent")) else {
+			continue;
+		};
+
+		if let Some((vendor_id, product_id, name)) = parse_uevent(&uevent) {
+			devices.push(DeviceInfo {
+				path: Path::new("/dev").join(entry.file_name()),
+				vendor_id,
+				product_id,
+				name,
+			});
+		}
+	}
+
+	devices.sort_by(|a, b| a.path.cmp(&b.path));
+
+	Ok(devices)
+}
+
+/// Reads the USB IDs and the name from the `uevent` file of a HID device.
+///
+/// The IDs are in a line like `HID_ID=0003:0000CAFE:00000001`: the bus, the vendor ID and the
+/// product ID.
+fn parse_uevent(uevent: &str) -> Option<(u16, u16, String)> {
+	let mut ids = None;
+	let mut name = String::new();
+
+	for line in uevent.lines() {
+		if let Some(id) = line.strip_prefix("HID_ID=") {
+			let mut parts = id.split(':').skip(1);
+
+			let vendor_id = u32::from_str_radix(parts.next()?, 16).ok()?;
+			let product_id = u32::from_str_radix(parts.next()?, 16).ok()?;
+
+			ids = Some((u16::try_from(vendor_id).ok()?, u16::try_from(product_id).ok()?));
+		} else if let Some(value) = line.strip_prefix("HID_NAME=") {
+			value.clone_into(&mut name);
+		}
+	}
+
+	ids.map(|(vendor_id, product_id)| (vendor_id, product_id, name))
+}
+
+/// The transport of a device opened through its hidraw node.
+#[derive(Debug)]
+pub struct HidrawTransport {
+	file: File,
+}
+
+impl HidrawTransport {
+	/// Opens the hidraw node of a device.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the node can't be opened for reading and writing.
+	pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+		let file = OpenOptions::new()
+			.read(true)
+			.write(true)
+			.custom_flags(libc::O_NONBLOCK)
+			.open(path)?;
+
+		Ok(Self { file })
+	}
+
+	/// Opens the first device with the USB IDs, or the first device at all without them.
+	///
+	/// # Errors
+	///
+	/// Returns an error if no such device is connected or it can't be opened.
+	pub fn open_first(ids: Option<(u16, u16)>) -> io::Result<Self> {
+		let device = devices()?
+			.into_iter()
+			.find(|device| ids.is_none_or(|ids| ids == (device.vendor_id, device.product_id)))
+			.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no silverplate device found"))?;
+
+		Self::open(device.path)
+	}
+
+	/// Waits until the node can be read, for at most `timeout`. Returns `false` on timeout.
+	fn wait_readable(&self, timeout: Duration) -> io::Result<bool> {
+		let mut poll_fd = libc::pollfd {
+			fd: self.file.as_raw_fd(),
+			events: libc::POLLIN,
+			revents: 0,
+		};
+
+		let timeout = libc::c_int::try_from(timeout.as_millis()).unwrap_or(libc::c_int::MAX);
+
+		// SAFETY: `poll_fd` is a valid pollfd and the count matches the single one passed.
+		let ready = unsafe { libc::poll(&raw mut poll_fd, 1, timeout) };
+
+		match ready {
+			0 => Ok(false),
+			1.. => Ok(true),
+			_ => Err(io::Error::last_os_error()),
+		}
+	}
+}
+
+impl Transport for HidrawTransport {
+	fn send(&mut self, frame: &[u8; REPORT_LEN]) -> io::Result<()> {
+		let mut report = [0; REPORT_LEN + 1];
+
+		report[0] = REPORT_ID_OUT;
+		report[1..].copy_from_slice(frame);
+
+		self.file.write_all(&report)
+	}
+
+	fn receive(&mut self, timeout: Duration) -> io::Result<Option<[u8; REPORT_LEN]>> {
+		let deadline = Instant::now() + timeout;
+
+		// Keyboard reports are sent through the same interface, so everything that isn't a vendor
+		// report is skipped.
+		loop {
+			let remaining = deadline.saturating_duration_since(Instant::now());
+
+			if !self.wait_readable(remaining)? {
+				return Ok(None);
+			}
+
+			let mut report = [0; REPORT_LEN + 1];
+
+			match self.file.read(&mut report) {
+				Ok(len) if len == report.len() && report[0] == REPORT_ID_IN => {
+					let mut frame = [0; REPORT_LEN];
+					frame.copy_from_slice(&report[1..]);
+
+					return Ok(Some(frame));
+				}
+				Ok(_) => {}
+				Err(error) if error.kind() == io::ErrorKind::WouldBlock => {}
+				Err(error) => return Err(error),
+			}
+
+			if remaining.is_zero() {
+				return Ok(None);
+			}
+		}
+	}
+}
@@ -0,0 +1,479 @@
+//! Firmware images of a board, as ELF files, raw binaries or UF2 files.
+//!
+//! The `.configuration` and `.keyboard` sections of the firmware describe the board and hold its
+//! default keymaps, in the format of [`qubit_config::section`]. They are found in an image without
+//! knowing how it was built, and the keymaps can be replaced to give the board a new default layout
+//! without building the firmware again.
+
+use std::fmt;
+use std::ops::Range;
+
+use qubit_config::general::Configuration;
+use qubit_config::keyboard::Action;
+use qubit_config::keyboard::layer::LAYER_COUNT;
+use qubit_config::section::{self, CONFIGURATION_MAGIC, HEADER_LEN, KEYBOARD_MAGIC, KeyboardSection, SectionError};
+use qubit_config::uf2;
+
+use crate::Keymap;
+
+const ELF_MAGIC: &[u8; 4] = b"\x7FELF";
+
+const CONFIGURATION_SECTION: &str = ".configuration";
+const KEYBOARD_SECTION: &str = ".keyboard";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+	Elf,
+	/// A raw copy of the flash, e.g. from `objcopy -O binary`.
+	Bin,
+	Uf2,
+}
+
+impl ImageFormat {
+	/// Recognizes the format from the first bytes of the image. Anything that isn't an ELF or a UF2
+	/// file is a raw binary.
+	#[must_use]
+	pub fn detect(bytes: &[u8]) -> Self {
+		if bytes.starts_with(ELF_MAGIC) {
+			Self::Elf
+		} else if read_u32(bytes, 0) == Some(uf2::MAGIC_START_0) && read_u32(bytes, 4) == Some(uf2::MAGIC_START_1) {
+			Self::Uf2
+		} else {
+			Self::Bin
+		}
+	}
+
+	#[must_use]
+	pub const fn as_str(self) -> &'static str {
+		match self {
+			Self::Elf => "ELF",
+			Self::Bin => "BIN",
+			Self::Uf2 => "UF2",
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageError {
+	/// The ELF file isn't a 32 bit little endian one, or its headers point outside the file.
+	InvalidElf,
+	/// A block of the UF2 file is damaged.
+	InvalidUf2 { block: usize },
+	/// The image has no valid section with the name.
+	MissingSection(&'static str),
+	/// The ELF section was found, but can't be decoded.
+	Section { name: &'static str, error: SectionError },
+	/// The keymap doesn't have the matrix and the layers of the image.
+	KeymapMismatch { rows: usize, cols: usize, layers: usize },
+	/// The keymap has an action where the image has no key, or no action where the image has a key
+	/// in the first layer.
+	LayoutMismatch { layer: usize, row: usize, col: usize },
+}
+
+impl fmt::Display for ImageError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::InvalidElf => write!(f, "the image isn't a valid 32 bit little endian ELF file"),
+			Self::InvalidUf2 { block } => write!(f, "block {block} of the UF2 file is damaged"),
+			Self::MissingSection(name) => write!(f, "the image has no valid {name} section"),
+			Self::Section { name, error } => write!(f, "the {name} section of the image is invalid: {error:?}"),
+			Self::KeymapMismatch { rows, cols, layers } => write!(
+				f,
+				"the keymap doesn't match the {rows}x{cols} matrix and the {layers} layers of the image"
+			),
+			Self::LayoutMismatch { layer, row, col } => write!(
+				f,
+				"layer {layer} doesn't match the keys of the image at row {row} col {col}, the image only \
+				 has keys where its first layer has an action"
+			),
+		}
+	}
+}
+
+impl std::error::Error for ImageError {}
+
+/// Where a section is in the file, in several pieces when a UF2 file splits it between blocks.
+#[derive(Debug, Clone)]
+struct Location(Vec<Range<usize>>);
+
+impl Location {
+	fn read(&self, bytes: &[u8]) -> Vec<u8> {
+		self.0.iter().flat_map(|range| &bytes[range.clone()]).copied().collect()
+	}
+
+	fn write(&self, bytes: &mut [u8], mut data: &[u8]) {
+		for range in &self.0 {
+			let (piece, rest) = data.split_at(range.len());
+
+			bytes[range.clone()].copy_from_slice(piece);
+			data = rest;
+		}
+	}
+}
+
+/// A section found in an image, with a copy of its bytes.
+#[derive(Debug, Clone)]
+struct FoundSection {
+	location: Location,
+	bytes: Vec<u8>,
+}
+
+/// A firmware image, with its `.configuration` and `.keyboard` sections checked.
+#[derive(Debug, Clone)]
+pub struct FirmwareImage {
+	format: ImageFormat,
+	bytes: Vec<u8>,
+	configuration: FoundSection,
+	keyboard: FoundSection,
+}
+
+impl FirmwareImage {
+	/// Opens an image from the contents of its file.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the file is damaged or doesn't have valid sections.
+	pub fn parse(bytes: Vec<u8>) -> Result<Self, ImageError> {
+		let format = ImageFormat::detect(&bytes);
+
+		let (configuration, keyboard) = match format {
+			ImageFormat::Elf => {
+				let sections = elf_sections(&bytes)?;
+
+				(
+					elf_section(&bytes, &sections, CONFIGURATION_SECTION, |bytes| {
+						Configuration::decode(bytes).map(drop)
+					})?,
+					elf_section(&bytes, &sections, KEYBOARD_SECTION, |bytes| {
+						KeyboardSection::decode(bytes).map(drop)
+					})?,
+				)
+			}
+			ImageFormat::Bin | ImageFormat::Uf2 => {
+				let runs = if format == ImageFormat::Uf2 {
+					uf2_runs(&bytes)?
+				} else {
+					vec![Run::whole(&bytes)]
+				};
+
+				(
+					find_section(&runs, CONFIGURATION_MAGIC, |bytes| Configuration::decode(bytes).is_ok())
+						.ok_or(ImageError::MissingSection(CONFIGURATION_SECTION))?,
+					find_section(&runs, KEYBOARD_MAGIC, |bytes| KeyboardSection::decode(bytes).is_ok())
+						.ok_or(ImageError::MissingSection(KEYBOARD_SECTION))?,
+				)
+			}
+		};
+
+		Ok(Self {
+			format,
+			configuration: FoundSection {
+				bytes: configuration.read(&bytes),
+				location: configuration,
+			},
+			keyboard: FoundSection {
+				bytes: keyboard.read(&bytes),
+				location: keyboard,
+			},
+			bytes,
+		})
+	}
+
+	#[must_use]
+	pub const fn format(&self) -> ImageFormat {
+		self.format
+	}
+
+	/// The description of the board the firmware was built for.
+	///
+	/// # Panics
+	///
+	/// Only panics on a logic error, since the section is checked when the image is opened.
+	#[must_use]
+	pub fn configuration(&self) -> Configuration<'_> {
+		Configuration::decode(&self.configuration.bytes).expect("The section is checked when the image is opened.")
+	}
+
+	/// The layout and the default keymaps of the keyboard.
+	///
+	/// # Panics
+	///
+	/// Only panics on a logic error, since the section is checked when the image is opened.
+	#[must_use]
+	pub fn keyboard(&self) -> KeyboardSection<'_> {
+		KeyboardSection::decode(&self.keyboard.bytes).expect("The section is checked when the image is opened.")
+	}
+
+	/// The default keymaps of the firmware.
+	#[must_use]
+	pub fn keymap(&self) -> Keymap {
+		let keyboard = self.keyboard();
+
+		let mut keymap = Keymap::new(keyboard.rows, keyboard.cols, keyboard.layer_count);
+
+		for (layer, actions) in keymap.layers.iter_mut().enumerate() {
+			let keys = (0..keyboard.rows * keyboard.cols)
+				.filter(|position| keyboard.has_key(position / keyboard.cols, position % keyboard.cols));
+
+			for (index, position) in keys.enumerate() {
+				actions[position] = keyboard.action(layer, index).unwrap_or(Action::NO);
+			}
+		}
+
+		keymap
+	}
+
+	/// Replaces the default keymaps of the firmware.
+	///
+	/// The keys of the image can't change, since the firmware was built for them, so the keymap
+	/// needs the same matrix and layers, and actions only where the image has a key.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the keymap doesn't match the keys of the image.
+	pub fn set_keymap(&mut self, keymap: &Keymap) -> Result<(), ImageError> {
+		let keyboard = self.keyboard();
+
+		if keymap.rows != keyboard.rows
+			|| keymap.cols != keyboard.cols
+			|| keymap.layers.len() != keyboard.layer_count
+			|| keyboard.layer_count != LAYER_COUNT
+			|| keymap
+				.layers
+				.iter()
+				.any(|layer| layer.len() != keymap.rows * keymap.cols)
+		{
+			return Err(ImageError::KeymapMismatch {
+				rows: keyboard.rows,
+				cols: keyboard.cols,
+				layers: keyboard.layer_count,
+			});
+		}
+
+		for (layer, actions) in keymap.layers.iter().enumerate() {
+			for (position, action) in actions.iter().enumerate() {
+				let (row, col) = (position / keymap.cols, position % keymap.cols);
+				let has_key = keyboard.has_key(row, col);
+
+				// Other layers can leave a key without an action, but the first one decides which
+				// positions have keys.
+				if (!has_key && !action.is_no()) || (layer == 0 && has_key && action.is_no()) {
+					return Err(ImageError::LayoutMismatch { layer, row, col });
+				}
+			}
+		}
+
+		let layers: [&[Action]; LAYER_COUNT] = std::array::from_fn(|layer| keymap.layers[layer].as_slice());
+
+		// The same keys take the same space, so the section keeps its size.
+		let mut bytes = vec![0; self.keyboard.bytes.len()];
+		section::write_keyboard(&mut bytes, keymap.rows, keymap.cols, &layers);
+
+		self.keyboard.location.write(&mut self.bytes, &bytes);
+		self.keyboard.bytes = bytes;
+
+		Ok(())
+	}
+
+	/// The contents of the image file, with the changes made to it.
+	#[must_use]
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.bytes
+	}
+
+	#[must_use]
+	pub fn into_bytes(self) -> Vec<u8> {
+		self.bytes
+	}
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+	Some(u16::from_le_bytes(bytes.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+	Some(u32::from_le_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+/// The name and the file range of every section of an ELF file that has contents in the file.
+fn elf_sections(bytes: &[u8]) -> Result<Vec<(String, Range<usize>)>, ImageError> {
+	const SECTION_HEADER_LEN: usize = 40;
+	/// A section that takes no space in the file, like `.bss`.
+	const SHT_NOBITS: u32 = 8;
+
+	// Only 32 bit little endian files, like the ones of the supported MCUs.
+	if bytes.get(4..6) != Some(&[1, 1]) {
+		return Err(ImageError::InvalidElf);
+	}
+
+	let header = |offset: usize, field: fn(&[u8], usize) -> Option<u32>| {
+		field(bytes, offset)
+			.and_then(|value| usize::try_from(value).ok())
+			.ok_or(ImageError::InvalidElf)
+	};
+
+	let u16_field = |bytes: &[u8], offset: usize| read_u16(bytes, offset).map(u32::from);
+
+	let table = header(0x20, read_u32)?;
+	let entry_len = header(0x2E, u16_field)?;
+	let count = header(0x30, u16_field)?;
+	let names_index = header(0x32, u16_field)?;
+
+	if entry_len < SECTION_HEADER_LEN {
+		return Err(ImageError::InvalidElf);
+	}
+
+	let entries = (0..count)
+		.map(|index| {
+			let entry = table + index * entry_len;
+
+			let name = header(entry, read_u32)?;
+			let kind = read_u32(bytes, entry + 4).ok_or(ImageError::InvalidElf)?;
+			let offset = header(entry + 16, read_u32)?;
+			let size = header(entry + 20, read_u32)?;
+
+			let range = offset..offset + if kind == SHT_NOBITS { 0 } else { size };
+
+			if range.end > bytes.len() {
+				return Err(ImageError::InvalidElf);
+			}
+
+			Ok((name, range))
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	let (_, names) = entries.get(names_index).ok_or(ImageError::InvalidElf)?;
+	let names = &bytes[names.clone()];
+
+	entries
+		.into_iter()
+		.map(|(name, range)| {
+			let name = names.get(name..).ok_or(ImageError::InvalidElf)?;
+			let end = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+
+			Ok((String::from_utf8_lossy(&name[..end]).into_owned(), range))
+		})
+		.collect()
+}
+
+fn elf_section(
+	bytes: &[u8],
+	sections: &[(String, Range<usize>)],
+	name: &'static str,
+	decode: impl Fn(&[u8]) -> Result<(), SectionError>,
+) -> Result<Location, ImageError> {
+	let (_, range) = sections
+		.iter()
+		.find(|(section, _)| section == name)
+		.ok_or(ImageError::MissingSection(name))?;
+
+	decode(&bytes[range.clone()]).map_err(|error| ImageError::Section { name, error })?;
+
+	Ok(Location(vec![range.clone()]))
+}
+
+/// Bytes at consecutive addresses of the flash, made of pieces of the file.
+struct Run {
+	data: Vec<u8>,
+	/// The offset of every piece in `data`, with where it is in the file.
+	pieces: Vec<(usize, Range<usize>)>,
+}
+
+impl Run {
+	fn whole(bytes: &[u8]) -> Self {
+		Self {
+			data: bytes.to_vec(),
+			pieces: vec![(0, 0..bytes.len())],
+		}
+	}
+
+	/// Maps a range of `data` to the ranges of the file it comes from.
+	fn locate(&self, range: Range<usize>) -> Location {
+		let ranges = self
+			.pieces
+			.iter()
+			.filter_map(|(start, file)| {
+				let end = start + file.len();
+
+				let from = range.start.max(*start);
+				let to = range.end.min(end);
+
+				(from < to).then(|| file.start + from - start..file.start + to - start)
+			})
+			.collect();
+
+		Location(ranges)
+	}
+}
+
+/// Joins the payloads of the UF2 blocks at consecutive addresses.
+fn uf2_runs(bytes: &[u8]) -> Result<Vec<Run>, ImageError> {
+	let mut blocks = Vec::new();
+
+	for (block, chunk) in bytes.chunks(uf2::BLOCK_LEN).enumerate() {
+		let field = |offset| read_u32(chunk, offset).ok_or(ImageError::InvalidUf2 { block });
+
+		if chunk.len() != uf2::BLOCK_LEN
+			|| field(0)? != uf2::MAGIC_START_0
+			|| field(4)? != uf2::MAGIC_START_1
+			|| field(uf2::BLOCK_LEN - 4)? != uf2::MAGIC_END
+		{
+			return Err(ImageError::InvalidUf2 { block });
+		}
+
+		if field(8)? & uf2::FLAG_NOT_MAIN_FLASH != 0 {
+			continue;
+		}
+
+		let address = field(12)?;
+		let len = usize::try_from(field(16)?).map_err(|_| ImageError::InvalidUf2 { block })?;
+
+		if len > uf2::MAX_PAYLOAD_LEN {
+			return Err(ImageError::InvalidUf2 { block });
+		}
+
+		let start = block * uf2::BLOCK_LEN + uf2::DATA_OFFSET;
+
+		blocks.push((u64::from(address), start..start + len));
+	}
+
+	blocks.sort_by_key(|(address, _)| *address);
+
+	let mut runs: Vec<(u64, Run)> = Vec::new();
+
+	for (address, file) in blocks {
+		match runs.last_mut() {
+			Some((end, run)) if *end == address => {
+				run.pieces.push((run.data.len(), file.clone()));
+				run.data.extend_from_slice(&bytes[file.clone()]);
+				*end += file.len() as u64;
+			}
+			_ => runs.push((
+				address + file.len() as u64,
+				Run {
+					data: bytes[file.clone()].to_vec(),
+					pieces: vec![(0, file)],
+				},
+			)),
+		}
+	}
+
+	Ok(runs.into_iter().map(|(_, run)| run).collect())
+}
+
+/// Looks for the first valid section with the magic, since the magic can also appear in the code
+/// of the firmware.
+fn find_section(runs: &[Run], magic: [u8; 4], is_valid: impl Fn(&[u8]) -> bool) -> Option<Location> {
+	runs.iter().find_map(|run| {
+		let data = &run.data;
+
+		(0..data.len().saturating_sub(HEADER_LEN))
+			.filter(|&start| data[start..].starts_with(&magic))
+			.find_map(|start| {
+				let payload_len = usize::from(read_u16(data, start + 6)?);
+				let end = start + HEADER_LEN + payload_len;
+
+				(end <= data.len() && is_valid(&data[start..end])).then(|| run.locate(start..end))
+			})
+	})
+}
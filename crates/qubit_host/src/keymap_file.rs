@@ -0,0 +1,172 @@
+//! Keymaps stored in TOML or JSON files.
+//!
+//! Every layer is a list of rows and every row a list of actions, in their u16 encoding:
+//!
+//! ```toml
+//! rows = 2
+//! cols = 2
+//! layers = [
+//!     [[4, 5], [6, 0]],
+//!     [[1, 1], [1, 0]],
+//! ]
+//! ```
+//!
+//! The TOML configuration a board is built from is read as a keymap too, from the named actions of
+//! its `[keyboard.keymap]` table.
+
+use std::fmt;
+use std::path::Path;
+
+use qubit_config::keyboard::Action;
+use qubit_config::parse::{Diagnostic, TomlConfiguration};
+use serde::{Deserialize, Serialize};
+
+use crate::Keymap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+	Toml,
+	Json,
+}
+
+impl Format {
+	/// Picks the format from the extension of the file.
+	#[must_use]
+	pub fn from_path(path: &Path) -> Option<Self> {
+		match path.extension()?.to_str()? {
+			"toml" => Some(Self::Toml),
+			"json" => Some(Self::Json),
+			_ => None,
+		}
+	}
+
+	#[must_use]
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"toml" => Some(Self::Toml),
+			"json" => Some(Self::Json),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum FormatError {
+	Toml(toml::de::Error),
+	TomlSerialize(toml::ser::Error),
+	Json(serde_json::Error),
+	/// The board configuration has problems.
+	Configuration(Vec<Diagnostic>),
+	/// A layer doesn't have the size given by the rows and columns.
+	Shape {
+		layer: usize,
+	},
+}
+
+impl fmt::Display for FormatError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Toml(error) => write!(f, "invalid TOML keymap: {error}"),
+			Self::TomlSerialize(error) => write!(f, "can't write the keymap as TOML: {error}"),
+			Self::Json(error) => write!(f, "invalid JSON keymap: {error}"),
+			Self::Configuration(diagnostics) => {
+				write!(f, "invalid board configuration:")?;
+
+				for diagnostic in diagnostics {
+					match &diagnostic.key {
+						Some(key) => write!(f, "\n  {key}: {}", diagnostic.message)?,
+						None => write!(f, "\n  {}", diagnostic.message)?,
+					}
+				}
+
+				Ok(())
+			}
+			Self::Shape { layer } => write!(f, "layer {layer} doesn't match the rows and cols of the keymap"),
+		}
+	}
+}
+
+impl std::error::Error for FormatError {}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct KeymapFile {
+	rows: usize,
+	cols: usize,
+	layers: Vec<Vec<Vec<u16>>>,
+}
+
+/// # Errors
+///
+/// Returns an error if the keymap can't be written in the format.
+pub fn to_string(keymap: &Keymap, format: Format) -> Result<String, FormatError> {
+	let file = KeymapFile {
+		rows: keymap.rows,
+		cols: keymap.cols,
+		layers: keymap
+			.layers
+			.iter()
+			.map(|layer| {
+				layer
+					.chunks(keymap.cols.max(1))
+					.map(|row| row.iter().map(|action| action.to_bits()).collect())
+					.collect()
+			})
+			.collect(),
+	};
+
+	match format {
+		Format::Toml => toml::to_string(&file).map_err(FormatError::TomlSerialize),
+		Format::Json => serde_json::to_string_pretty(&file).map_err(FormatError::Json),
+	}
+}
+
+/// Reads a keymap file, or the keymap of a board configuration.
+///
+/// # Errors
+///
+/// Returns an error if the text isn't a keymap in the format or the layers don't have the size
+/// given by the rows and columns.
+pub fn from_str(text: &str, format: Format) -> Result<Keymap, FormatError> {
+	let file: KeymapFile = match format {
+		Format::Toml => {
+			let table: toml::Table = toml::from_str(text).map_err(FormatError::Toml)?;
+
+			if table.contains_key("keyboard") {
+				return from_configuration(text);
+			}
+
+			table.try_into().map_err(FormatError::Toml)?
+		}
+		Format::Json => serde_json::from_str(text).map_err(FormatError::Json)?,
+	};
+
+	let mut keymap = Keymap::new(file.rows, file.cols, file.layers.len());
+
+	for (index, (layer, rows)) in keymap.layers.iter_mut().zip(file.layers).enumerate() {
+		if rows.len() != file.rows || rows.iter().any(|row| row.len() != file.cols) {
+			return Err(FormatError::Shape { layer: index });
+		}
+
+		for (slot, bits) in layer.iter_mut().zip(rows.into_iter().flatten()) {
+			*slot = Action::from_bits(bits);
+		}
+	}
+
+	Ok(keymap)
+}
+
+fn from_configuration(text: &str) -> Result<Keymap, FormatError> {
+	let configuration = TomlConfiguration::from_toml(text).map_err(FormatError::Configuration)?;
+	let keymap = configuration.keyboard.keymap;
+
+	Ok(Keymap {
+		rows: keymap.rows,
+		cols: keymap.cols,
+		layers: keymap
+			.layers
+			.into_iter()
+			.map(|layer| layer.into_iter().flatten().collect())
+			.collect(),
+	})
+}
@@ -0,0 +1,27 @@
+//! # Qubit Host
+//!
+//! Talks to Qubit keyboards from a computer, over the silverplate protocol of their vendor HID
+//! reports.
+//!
+//! A [`Client`] sends typed requests through a [`Transport`]: [`HidrawTransport`] for real
+//! devices on Linux, or [`MockTransport`] to run the firmware side of the protocol in memory.
+//!
+//! A [`FirmwareImage`] reads the board and the default keymaps out of a built firmware, and replaces
+//! the keymaps without building it again, and [`drive`] finds the UF2 bootloaders to copy it to.
+
+mod client;
+pub mod drive;
+mod error;
+pub mod hidraw;
+pub mod image;
+pub mod keymap_file;
+pub mod mock;
+mod transport;
+
+pub use client::{Client, DeviceCapabilities, FirmwareInfo, Keymap, KeymapInfo};
+pub use error::Error;
+pub use hidraw::HidrawTransport;
+pub use image::{FirmwareImage, ImageError, ImageFormat};
+pub use mock::{MockDevice, MockTransport};
+pub use qubit_config::silverplate::{Capabilities, LayerState, RebootMode, ReportFormat, Status};
+pub use transport::Transport;
@@ -0,0 +1,557 @@
+//! # Qubit CLI
+//!
+//! Inspects and configures a connected Qubit board over its silverplate interface, or the keymaps
+//! of a built firmware image.
+
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the binary."
+)]
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use qubit_config::keyboard::Action;
+use qubit_config::mcu::Mcu;
+use qubit_config::parse::action_name;
+use qubit_config::uf2;
+use qubit_config::version::Version;
+use qubit_host::keymap_file::{self, Format};
+use qubit_host::{Client, FirmwareImage, HidrawTransport, LayerState, RebootMode, ReportFormat, drive, hidraw};
+
+const USAGE: &str = "\
+Usage: qubit [--device <path>] [--id <vid>:<pid>] <command>
+
+Commands:
+  list                                   List the connected devices
+  info                                   Print the name, author, version and build date
+  keymap dump [--format toml|json] [<file>]
+                                         Write the keymaps to the file, or print them
+  keymap load [--format toml|json] [--no-commit] <file>
+                                         Send the keymaps of the file and store them
+  layer get                              Print the active layers
+  layer set [--default <layer>] [<layer>...]
+                                         Activate the layers on top of the default one
+  settings get [<name>]                  Print the settings
+  settings set <name> <value>            Change a setting
+  reboot                                 Restart the firmware
+  bootloader                             Restart into the bootloader, to flash a new firmware
+  firmware show <image>                  Print the board and the keymaps of a firmware image
+  firmware patch [--format toml|json] [--output <file>] <image> <keymap>
+                                         Replace the keymaps of a firmware image with the ones of
+                                         a keymap file or a board configuration
+  firmware uf2 [--mcu <mcu>] [--output <file>] [--deploy] <elf>
+                                         Write the firmware as a UF2 file, next to the ELF file
+                                         unless another one is given, and copy it to the drive
+                                         of the mounted UF2 bootloader with --deploy
+
+Settings:
+  nkro            on|off    Send the NKRO report instead of the 6KRO one
+  report-format   (read only) The report the device sends";
+
+/// The device the command is sent to.
+#[derive(Debug, Default)]
+struct Target {
+	path: Option<PathBuf>,
+	ids: Option<(u16, u16)>,
+}
+
+impl Target {
+	fn connect(&self) -> Result<Client<HidrawTransport>, String> {
+		let transport = match &self.path {
+			Some(path) => HidrawTransport::open(path),
+			None => HidrawTransport::open_first(self.ids),
+		}
+		.map_err(|error| format!("can't open the device: {error}"))?;
+
+		Ok(Client::new(transport))
+	}
+}
+
+fn main() -> ExitCode {
+	let args: Vec<String> = std::env::args().skip(1).collect();
+
+	match run(&args) {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(error) => {
+			eprintln!("error: {error}");
+
+			ExitCode::FAILURE
+		}
+	}
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+	let mut target = Target::default();
+	let mut args = args.iter().map(String::as_str);
+
+	let command = loop {
+		match args.next() {
+			Some("--device") => target.path = Some(PathBuf::from(value(&mut args, "--device")?)),
+			Some("--id") => target.ids = Some(parse_ids(value(&mut args, "--id")?)?),
+			Some("-h" | "--help") => {
+				println!("{USAGE}");
+
+				return Ok(());
+			}
+			Some(command) => break command,
+			None => return Err(format!("missing command\n\n{USAGE}")),
+		}
+	};
+
+	let rest: Vec<&str> = args.collect();
+
+	match (command, rest.split_first()) {
+		("list", None) => list(),
+		("info", None) => info(&target),
+		("keymap", Some((&"dump", options))) => keymap_dump(&target, options),
+		("keymap", Some((&"load", options))) => keymap_load(&target, options),
+		("layer", Some((&"get", []))) => layer_get(&target),
+		("layer", Some((&"set", options))) => layer_set(&target, options),
+		("settings", Some((&"get", options))) => settings_get(&target, options),
+		("settings", Some((&"set", options))) => settings_set(&target, options),
+		("reboot", None) => reboot(&target, RebootMode::Firmware),
+		("bootloader", None) => reboot(&target, RebootMode::Bootloader),
+		("firmware", Some((&"show", options))) => firmware_show(options),
+		("firmware", Some((&"patch", options))) => firmware_patch(options),
+		("firmware", Some((&"uf2", options))) => firmware_uf2(options),
+		_ => Err(format!("unexpected arguments\n\n{USAGE}")),
+	}
+}
+
+fn value<'a>(args: &mut impl Iterator<Item = &'a str>, option: &str) -> Result<&'a str, String> {
+	args.next().ok_or_else(|| format!("missing value for {option}"))
+}
+
+/// Parses USB IDs written as `cafe:0001`.
+fn parse_ids(ids: &str) -> Result<(u16, u16), String> {
+	let parse = |id: &str| u16::from_str_radix(id.trim_start_matches("0x"), 16).ok();
+
+	ids.split_once(':')
+		.and_then(|(vendor_id, product_id)| Some((parse(vendor_id)?, parse(product_id)?)))
+		.ok_or_else(|| format!("invalid USB IDs `{ids}`, expected <vid>:<pid> in hexadecimal"))
+}
+
+fn parse_layer(layer: &str) -> Result<u8, String> {
+	layer.parse().map_err(|_| format!("invalid layer `{layer}`"))
+}
+
+fn list() -> Result<(), String> {
+	let devices = hidraw::devices().map_err(|error| format!("can't list the devices: {error}"))?;
+
+	if devices.is_empty() {
+		println!("No devices found.");
+	}
+
+	for device in devices {
+		println!(
+			"{}  {:04x}:{:04x}  {}",
+			device.path.display(),
+			device.vendor_id,
+			device.product_id,
+			device.name
+		);
+	}
+
+	Ok(())
+}
+
+fn info(target: &Target) -> Result<(), String> {
+	let mut client = target.connect()?;
+
+	let info = client.firmware_info().map_err(|error| error.to_string())?;
+	let version = info.version;
+	let date = info.build_date;
+
+	println!("Name:       {}", info.name);
+	println!("Author:     {}", info.author);
+	println!(
+		"Version:    {}.{}.{} (API v{})",
+		version.major,
+		version.minor,
+		version.patch,
+		version.api.as_u8()
+	);
+	println!("Build date: {:04}-{:02}-{:02}", date.year, date.month, date.day);
+
+	Ok(())
+}
+
+/// The format of a keymap file, from the `--format` option or the extension of the file.
+fn keymap_format(format: Option<&str>, path: Option<&Path>) -> Result<Format, String> {
+	match (format, path) {
+		(Some(name), _) => Format::from_name(name).ok_or_else(|| format!("unknown format `{name}`")),
+		(None, Some(path)) => Format::from_path(path)
+			.ok_or_else(|| format!("can't tell the format of `{}`, use --format", path.display())),
+		(None, None) => Ok(Format::Toml),
+	}
+}
+
+fn keymap_dump(target: &Target, options: &[&str]) -> Result<(), String> {
+	let mut format = None;
+	let mut path = None;
+
+	let mut options = options.iter().copied();
+
+	while let Some(option) = options.next() {
+		match option {
+			"--format" => format = Some(value(&mut options, "--format")?),
+			_ if path.is_none() => path = Some(Path::new(option)),
+			_ => return Err(format!("unexpected argument `{option}`")),
+		}
+	}
+
+	let format = keymap_format(format, path)?;
+
+	let keymap = target.connect()?.read_keymap().map_err(|error| error.to_string())?;
+
+	let text = keymap_file::to_string(&keymap, format).map_err(|error| error.to_string())?;
+
+	if let Some(path) = path {
+		return std::fs::write(path, text).map_err(|error| format!("can't write `{}`: {error}", path.display()));
+	}
+
+	print!("{text}");
+
+	Ok(())
+}
+
+fn keymap_load(target: &Target, options: &[&str]) -> Result<(), String> {
+	let mut format = None;
+	let mut commit = true;
+	let mut path = None;
+
+	let mut options = options.iter().copied();
+
+	while let Some(option) = options.next() {
+		match option {
+			"--format" => format = Some(value(&mut options, "--format")?),
+			"--no-commit" => commit = false,
+			_ if path.is_none() => path = Some(Path::new(option)),
+			_ => return Err(format!("unexpected argument `{option}`")),
+		}
+	}
+
+	let path = path.ok_or("missing keymap file")?;
+	let format = keymap_format(format, Some(path))?;
+
+	let text = std::fs::read_to_string(path).map_err(|error| format!("can't read `{}`: {error}", path.display()))?;
+	let keymap = keymap_file::from_str(&text, format).map_err(|error| error.to_string())?;
+
+	let mut client = target.connect()?;
+
+	client.write_keymap(&keymap).map_err(|error| error.to_string())?;
+
+	if commit {
+		client.commit_keymap().map_err(|error| error.to_string())?;
+	}
+
+	Ok(())
+}
+
+fn layer_get(target: &Target) -> Result<(), String> {
+	let state = target.connect()?.layer_state().map_err(|error| error.to_string())?;
+
+	let mut active = String::new();
+
+	for layer in (0..8).filter(|layer| state.active & (1 << layer) != 0) {
+		_ = write!(active, " {layer}");
+	}
+
+	println!("Default layer: {}", state.default);
+	println!("Active layers:{}", if active.is_empty() { " none" } else { &active });
+
+	Ok(())
+}
+
+fn layer_set(target: &Target, options: &[&str]) -> Result<(), String> {
+	let mut default = None;
+	let mut active = 0u8;
+
+	let mut options = options.iter().copied();
+
+	while let Some(option) = options.next() {
+		match option {
+			"--default" => default = Some(parse_layer(value(&mut options, "--default")?)?),
+			layer => {
+				let layer = parse_layer(layer)?;
+
+				active |= 1u8
+					.checked_shl(u32::from(layer))
+					.ok_or_else(|| format!("invalid layer `{layer}`"))?;
+			}
+		}
+	}
+
+	let mut client = target.connect()?;
+
+	let default = match default {
+		Some(default) => default,
+		None => client.layer_state().map_err(|error| error.to_string())?.default,
+	};
+
+	client
+		.set_layer_state(LayerState { active, default })
+		.map_err(|error| error.to_string())
+}
+
+const fn report_format_name(format: ReportFormat) -> &'static str {
+	match format {
+		ReportFormat::SixKro => "6kro",
+		ReportFormat::Nkro => "nkro",
+		ReportFormat::Boot => "boot",
+	}
+}
+
+fn settings_get(target: &Target, options: &[&str]) -> Result<(), String> {
+	let name = match options {
+		[] => None,
+		[name] => Some(*name),
+		_ => return Err(format!("unexpected arguments\n\n{USAGE}")),
+	};
+
+	let format = target.connect()?.report_format().map_err(|error| error.to_string())?;
+
+	// The boot protocol is chosen by the host, the NKRO setting is kept while it's used.
+	let nkro = match format {
+		ReportFormat::Nkro => "on",
+		ReportFormat::SixKro => "off",
+		ReportFormat::Boot => "unknown (boot protocol)",
+	};
+
+	let settings = [("nkro", nkro), ("report-format", report_format_name(format))];
+
+	match name {
+		None => {
+			for (name, value) in settings {
+				println!("{name} = {value}");
+			}
+		}
+		Some(name) => {
+			let (_, value) = settings
+				.iter()
+				.find(|(setting, _)| *setting == name)
+				.ok_or_else(|| format!("unknown setting `{name}`"))?;
+
+			println!("{value}");
+		}
+	}
+
+	Ok(())
+}
+
+fn settings_set(target: &Target, options: &[&str]) -> Result<(), String> {
+	let &[name, value] = options else {
+		return Err(format!("expected a setting and a value\n\n{USAGE}"));
+	};
+
+	match name {
+		"nkro" => {
+			let enabled = match value {
+				"on" | "true" | "1" => true,
+				"off" | "false" | "0" => false,
+				_ => return Err(format!("invalid value `{value}` for nkro, expected on or off")),
+			};
+
+			let format = target.connect()?.set_nkro(enabled).map_err(|error| error.to_string())?;
+
+			println!("report-format = {}", report_format_name(format));
+
+			Ok(())
+		}
+		"report-format" => Err(String::from("report-format is read only, change nkro instead")),
+		_ => Err(format!("unknown setting `{name}`")),
+	}
+}
+
+fn reboot(target: &Target, mode: RebootMode) -> Result<(), String> {
+	target.connect()?.reboot(mode).map_err(|error| error.to_string())
+}
+
+fn open_image(path: &Path) -> Result<FirmwareImage, String> {
+	let bytes = std::fs::read(path).map_err(|error| format!("can't read `{}`: {error}", path.display()))?;
+
+	FirmwareImage::parse(bytes).map_err(|error| format!("can't open `{}`: {error}", path.display()))
+}
+
+fn firmware_show(options: &[&str]) -> Result<(), String> {
+	let &[path] = options else {
+		return Err(format!("expected a firmware image\n\n{USAGE}"));
+	};
+
+	let image = open_image(Path::new(path))?;
+
+	let configuration = image.configuration();
+	let version = Version::from_bitmap(configuration.version);
+
+	println!("Name:       {}", configuration.name);
+	println!("Author:     {}", configuration.author);
+	println!(
+		"Version:    {}.{}.{} (API v{})",
+		version.major,
+		version.minor,
+		version.patch,
+		version.api.as_u8()
+	);
+	println!("MCU:        {}", configuration.mcu.as_str());
+	println!(
+		"USB IDs:    {:04x}:{:04x}",
+		configuration.usb.vid, configuration.usb.pid
+	);
+	println!(
+		"Matrix:     {}x{}, {} layers",
+		configuration.rows, configuration.cols, configuration.layer_count
+	);
+	println!("Image:      {}", image.format().as_str());
+
+	let keymap = image.keymap();
+
+	for (index, layer) in keymap.layers.iter().enumerate() {
+		println!();
+		println!("Layer {index}:");
+		print!("{}", layer_grid(layer, keymap.cols));
+	}
+
+	Ok(())
+}
+
+/// Writes the actions of a layer by name, in aligned columns.
+fn layer_grid(layer: &[Action], cols: usize) -> String {
+	let cols = cols.max(1);
+	let names: Vec<String> = layer.iter().map(|&action| action_name(action)).collect();
+
+	let widths: Vec<usize> = (0..cols)
+		.map(|col| names.iter().skip(col).step_by(cols).map(String::len).max().unwrap_or(0))
+		.collect();
+
+	let mut grid = String::new();
+
+	for row in names.chunks(cols) {
+		let mut line = String::from(" ");
+
+		for (name, width) in row.iter().zip(&widths) {
+			_ = write!(line, " {name:<width$}");
+		}
+
+		grid.push_str(line.trim_end());
+		grid.push('\n');
+	}
+
+	grid
+}
+
+fn firmware_patch(options: &[&str]) -> Result<(), String> {
+	let mut format = None;
+	let mut output = None;
+	let mut paths = Vec::new();
+
+	let mut options = options.iter().copied();
+
+	while let Some(option) = options.next() {
+		match option {
+			"--format" => format = Some(value(&mut options, "--format")?),
+			"--output" => output = Some(Path::new(value(&mut options, "--output")?)),
+			_ => paths.push(Path::new(option)),
+		}
+	}
+
+	let &[image_path, keymap_path] = paths.as_slice() else {
+		return Err(format!("expected a firmware image and a keymap file\n\n{USAGE}"));
+	};
+
+	let mut image = open_image(image_path)?;
+
+	let format = keymap_format(format, Some(keymap_path))?;
+	let text = std::fs::read_to_string(keymap_path)
+		.map_err(|error| format!("can't read `{}`: {error}", keymap_path.display()))?;
+	let keymap = keymap_file::from_str(&text, format).map_err(|error| error.to_string())?;
+
+	image.set_keymap(&keymap).map_err(|error| error.to_string())?;
+
+	// The image is changed in place unless another file is given.
+	let output = output.unwrap_or(image_path);
+
+	std::fs::write(output, image.as_bytes()).map_err(|error| format!("can't write `{}`: {error}", output.display()))
+}
+
+fn firmware_uf2(options: &[&str]) -> Result<(), String> {
+	let mut mcu = None;
+	let mut output = None;
+	let mut deploy = false;
+	let mut paths = Vec::new();
+
+	let mut options = options.iter().copied();
+
+	while let Some(option) = options.next() {
+		match option {
+			"--mcu" => {
+				let name = value(&mut options, "--mcu")?;
+
+				mcu = Some(
+					name.parse::<Mcu>()
+						.map_err(|_| format!("unknown MCU `{name}`, expected RP2040 or STM32F411"))?,
+				);
+			}
+			"--output" => output = Some(PathBuf::from(value(&mut options, "--output")?)),
+			"--deploy" => deploy = true,
+			_ => paths.push(Path::new(option)),
+		}
+	}
+
+	let &[elf_path] = paths.as_slice() else {
+		return Err(format!("expected a firmware ELF file\n\n{USAGE}"));
+	};
+
+	let elf = std::fs::read(elf_path).map_err(|error| format!("can't read `{}`: {error}", elf_path.display()))?;
+
+	// The firmware knows the MCU it was built for.
+	let mcu = match mcu {
+		Some(mcu) => mcu,
+		None => FirmwareImage::parse(elf.clone())
+			.map(|image| image.configuration().mcu)
+			.map_err(|error| format!("can't tell the MCU of `{}`, use --mcu: {error}", elf_path.display()))?,
+	};
+
+	let file = uf2::from_elf(&elf, uf2::family_id(mcu))
+		.map_err(|error| format!("can't convert `{}`: {error}", elf_path.display()))?;
+
+	let output = output.unwrap_or_else(|| elf_path.with_extension("uf2"));
+
+	std::fs::write(&output, &file).map_err(|error| format!("can't write `{}`: {error}", output.display()))?;
+
+	println!("Wrote {}", output.display());
+
+	if deploy {
+		deploy_uf2(&file, &output)?;
+	}
+
+	Ok(())
+}
+
+/// Copies a UF2 file to the drive of the mounted bootloader, which flashes it and restarts.
+fn deploy_uf2(file: &[u8], path: &Path) -> Result<(), String> {
+	let drives = drive::uf2_drives().map_err(|error| format!("can't list the mounted drives: {error}"))?;
+
+	let drive = match drives.as_slice() {
+		[drive] => drive,
+		[] => {
+			return Err(String::from(
+				"no UF2 bootloader is mounted, restart the board into its bootloader first",
+			));
+		}
+		_ => {
+			return Err(String::from(
+				"more than one UF2 bootloader is mounted, copy the file by hand",
+			));
+		}
+	};
+
+	let target = drive.join(path.file_name().unwrap_or("qubit.uf2".as_ref()));
+
+	std::fs::write(&target, file).map_err(|error| format!("can't write `{}`: {error}", target.display()))?;
+
+	println!("Flashed {}", drive.display());
+
+	Ok(())
+}
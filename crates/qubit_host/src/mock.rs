@@ -0,0 +1,252 @@
+//! An in-memory transport, answering requests with the same handler the firmware uses.
+
+use std::collections::VecDeque;
+use std::io;
+use std::time::Duration;
+
+use qubit_config::keyboard::Action;
+use qubit_config::keyboard::layer::LAYER_COUNT;
+use qubit_config::silverplate::{
+	self, Capabilities, Command, Device, FirmwareInfo, LayerState, REPORT_LEN, RebootMode, ReportFormat, Status,
+};
+
+use crate::Transport;
+
+/// Passes every frame to [`silverplate::handle`] and queues the response.
+#[derive(Debug)]
+pub struct MockTransport<D = MockDevice> {
+	device: D,
+	responses: VecDeque<[u8; REPORT_LEN]>,
+}
+
+impl<D: Device> MockTransport<D> {
+	#[must_use]
+	pub const fn new(device: D) -> Self {
+		Self {
+			device,
+			responses: VecDeque::new(),
+		}
+	}
+
+	#[must_use]
+	pub const fn device(&self) -> &D {
+		&self.device
+	}
+
+	pub const fn device_mut(&mut self) -> &mut D {
+		&mut self.device
+	}
+
+	#[must_use]
+	pub fn into_device(self) -> D {
+		self.device
+	}
+
+	/// Queues a frame the device sends on its own.
+	pub fn notify(&mut self, command: Command, payload: &[u8]) {
+		self.responses.push_back(silverplate::notification(command, payload));
+	}
+}
+
+impl<D: Device> Transport for MockTransport<D> {
+	fn send(&mut self, frame: &[u8; REPORT_LEN]) -> io::Result<()> {
+		let response = silverplate::handle(&mut self.device, frame);
+
+		self.responses.push_back(response);
+
+		Ok(())
+	}
+
+	fn receive(&mut self, _timeout: Duration) -> io::Result<Option<[u8; REPORT_LEN]>> {
+		Ok(self.responses.pop_front())
+	}
+}
+
+/// A keyboard that keeps its whole state in memory.
+///
+/// Keymaps are stored row-major, one per layer, with `NO` at the positions without a key.
+#[derive(Debug, Clone)]
+pub struct MockDevice {
+	rows: usize,
+	cols: usize,
+	has_key: Vec<bool>,
+	active: Vec<Vec<Action>>,
+	pending: Vec<Vec<Action>>,
+	has_storage: bool,
+	/// The keymaps of the last commit.
+	stored: Option<Vec<Vec<Action>>>,
+	nkro: bool,
+	layer_state: LayerState,
+	reboots: Vec<RebootMode>,
+	name: String,
+	author: String,
+	version: u32,
+	build_date: u16,
+}
+
+impl MockDevice {
+	/// Creates a device with a key at every position of the matrix, all of them without an action.
+	#[must_use]
+	pub fn new(rows: usize, cols: usize) -> Self {
+		let keymap = vec![Action::NO; rows * cols];
+
+		Self {
+			rows,
+			cols,
+			has_key: vec![true; rows * cols],
+			active: vec![keymap.clone(); LAYER_COUNT],
+			pending: vec![keymap; LAYER_COUNT],
+			has_storage: true,
+			stored: None,
+			nkro: true,
+			layer_state: LayerState { active: 0, default: 0 },
+			reboots: Vec::new(),
+			name: String::from("mock"),
+			author: String::from("qubit"),
+			version: 0,
+			build_date: 0,
+		}
+	}
+
+	/// Removes the key at the position.
+	#[must_use]
+	pub fn without_key(mut self, row: usize, col: usize) -> Self {
+		let index = row * self.cols + col;
+
+		self.has_key[index] = false;
+
+		for keymap in self.active.iter_mut().chain(&mut self.pending) {
+			keymap[index] = Action::NO;
+		}
+
+		self
+	}
+
+	/// Removes the storage, so keymaps can't be committed.
+	#[must_use]
+	pub fn without_storage(mut self) -> Self {
+		self.has_storage = false;
+		self
+	}
+
+	/// Sets what the device answers with to the firmware info request.
+	#[must_use]
+	pub fn with_firmware_info(mut self, name: &str, author: &str, version: u32, build_date: u16) -> Self {
+		name.clone_into(&mut self.name);
+		author.clone_into(&mut self.author);
+		self.version = version;
+		self.build_date = build_date;
+		self
+	}
+
+	/// The action used for the key, `None` if there is no key at the position.
+	#[must_use]
+	pub fn active_action(&self, layer: usize, row: usize, col: usize) -> Option<Action> {
+		let index = self.index(row, col)?;
+
+		Some(self.active.get(layer)?[index])
+	}
+
+	/// The keymaps of the last commit, `None` if nothing was committed.
+	#[must_use]
+	pub fn stored_keymaps(&self) -> Option<&[Vec<Action>]> {
+		self.stored.as_deref()
+	}
+
+	/// The reboots the host asked for, in order.
+	#[must_use]
+	pub fn reboots(&self) -> &[RebootMode] {
+		&self.reboots
+	}
+
+	fn index(&self, row: usize, col: usize) -> Option<usize> {
+		let index = row * self.cols + col;
+
+		(row < self.rows && col < self.cols && self.has_key[index]).then_some(index)
+	}
+}
+
+impl Device for MockDevice {
+	fn capabilities(&self) -> Capabilities {
+		let capabilities = Capabilities::KEYMAP_READ
+			.union(Capabilities::KEYMAP_WRITE)
+			.union(Capabilities::REPORT_FORMAT)
+			.union(Capabilities::LAYER_STATE)
+			.union(Capabilities::REBOOT)
+			.union(Capabilities::BOOTLOADER);
+
+		if self.has_storage {
+			capabilities.union(Capabilities::KEYMAP_PERSIST)
+		} else {
+			capabilities
+		}
+	}
+
+	fn firmware_info(&self) -> FirmwareInfo<'_> {
+		FirmwareInfo {
+			build_date: self.build_date,
+			version: self.version,
+			name: &self.name,
+			author: &self.author,
+		}
+	}
+
+	fn matrix_size(&self) -> (usize, usize) {
+		(self.rows, self.cols)
+	}
+
+	fn action(&self, layer: usize, row: usize, col: usize) -> Option<Action> {
+		self.active_action(layer, row, col)
+	}
+
+	fn set_action(&mut self, layer: usize, row: usize, col: usize, action: Action) -> bool {
+		let Some(index) = self.index(row, col) else {
+			return false;
+		};
+
+		self.pending[layer][index] = action;
+
+		true
+	}
+
+	fn commit(&mut self) -> Result<(), Status> {
+		if !self.has_storage {
+			return Err(Status::NoStorage);
+		}
+
+		self.stored = Some(self.pending.clone());
+		self.active.clone_from(&self.pending);
+
+		Ok(())
+	}
+
+	fn revert(&mut self) {
+		self.pending.clone_from(&self.active);
+	}
+
+	fn report_format(&self) -> ReportFormat {
+		if self.nkro {
+			ReportFormat::Nkro
+		} else {
+			ReportFormat::SixKro
+		}
+	}
+
+	fn set_nkro(&mut self, enabled: bool) {
+		self.nkro = enabled;
+	}
+
+	fn layer_state(&self) -> LayerState {
+		self.layer_state
+	}
+
+	fn set_layer_state(&mut self, state: LayerState) {
+		self.layer_state = state;
+	}
+
+	fn reboot(&mut self, mode: RebootMode) -> Result<(), Status> {
+		self.reboots.push(mode);
+
+		Ok(())
+	}
+}
@@ -0,0 +1,35 @@
+use std::io;
+use std::time::Duration;
+
+use qubit_config::silverplate::REPORT_LEN;
+
+/// Carries silverplate frames to a device and back.
+///
+/// The frames are the vendor reports without their report ID.
+pub trait Transport {
+	/// Sends a frame to the device.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the frame can't be sent.
+	fn send(&mut self, frame: &[u8; REPORT_LEN]) -> io::Result<()>;
+
+	/// Waits for the next frame from the device, for at most `timeout`.
+	///
+	/// Returns `None` if no frame arrived in time.
+	///
+	/// # Errors
+	///
+	/// Returns an error if reading from the device fails.
+	fn receive(&mut self, timeout: Duration) -> io::Result<Option<[u8; REPORT_LEN]>>;
+}
+
+impl<T: Transport + ?Sized> Transport for &mut T {
+	fn send(&mut self, frame: &[u8; REPORT_LEN]) -> io::Result<()> {
+		(**self).send(frame)
+	}
+
+	fn receive(&mut self, timeout: Duration) -> io::Result<Option<[u8; REPORT_LEN]>> {
+		(**self).receive(timeout)
+	}
+}
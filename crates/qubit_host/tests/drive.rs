@@ -0,0 +1,28 @@
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the tests."
+)]
+
+use std::path::PathBuf;
+
+use qubit_host::drive;
+
+#[test]
+fn reads_the_mount_points() {
+	let mounts = "\
+sysfs /sys sysfs rw,nosuid,nodev,noexec,relatime 0 0
+/dev/sda1 /media/someone/RPI-RP2 vfat rw,nosuid,nodev,relatime,uid=1000 0 0
+/dev/sdb1 /media/someone/My\\040Drive vfat rw,relatime 0 0
+/dev/sdc1 /mnt/back\\134slash vfat rw,relatime 0 0
+";
+
+	assert_eq!(
+		drive::mount_points(mounts),
+		[
+			PathBuf::from("/sys"),
+			PathBuf::from("/media/someone/RPI-RP2"),
+			PathBuf::from("/media/someone/My Drive"),
+			PathBuf::from("/mnt/back\\slash"),
+		]
+	);
+}
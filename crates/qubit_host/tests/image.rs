@@ -0,0 +1,321 @@
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the tests."
+)]
+
+use qubit_config::general::{Configuration, Device};
+use qubit_config::keyboard::layer::LAYER_COUNT;
+use qubit_config::keyboard::{Action, Keymap as LayerKeymap};
+use qubit_config::keymap;
+use qubit_config::mcu::Mcu;
+use qubit_config::section::{encode_keyboard, keyboard_len};
+use qubit_config::usb::Usb;
+use qubit_host::keymap_file::{self, Format};
+use qubit_host::{FirmwareImage, ImageError, ImageFormat, Keymap};
+
+const CONFIGURATION: Configuration<'static> = Configuration {
+	name: "Pebble",
+	author: "cloudgazing",
+	version: 0x0001_0000,
+	device: Device::Keyboard,
+	mcu: Mcu::RP2040,
+	usb: Usb::new(0x1209, 0x0002),
+	rows: 2,
+	cols: 3,
+	layer_count: LAYER_COUNT,
+};
+
+const LAYER0: LayerKeymap<2, 3> = keymap! {
+	[KC_A, KC_B, -],
+	[MO(1), KC_C, KC_D],
+};
+
+const LAYER1: LayerKeymap<2, 3> = keymap! {
+	[KC_1, KC_2, -],
+	[_, _, KC_3],
+};
+
+const EMPTY: LayerKeymap<2, 3> = keymap! {
+	[_, _, -],
+	[_, _, _],
+};
+
+const KEYBOARD_LEN: usize = keyboard_len(2, 3, 5);
+
+static KEYBOARD: [u8; KEYBOARD_LEN] = encode_keyboard([&LAYER0, &LAYER1, &EMPTY, &EMPTY, &EMPTY]);
+
+/// The flash of a firmware: some code, then the sections, like the linker script lays them out.
+fn flash() -> Vec<u8> {
+	// The magic of a section can also show up in the code.
+	let mut flash = b"code QBKB QBCF code".repeat(20);
+
+	flash.extend_from_slice(&CONFIGURATION.encode());
+	flash.extend_from_slice(&KEYBOARD);
+	flash.extend_from_slice(&[0xFF; 64]);
+
+	flash
+}
+
+/// A 32 bit little endian ELF file with the sections, and their names.
+fn elf() -> Vec<u8> {
+	let configuration = CONFIGURATION.encode();
+
+	let names = b"\0.configuration\0.keyboard\0.shstrtab\0";
+
+	let mut file = vec![0; 52];
+	file[..6].copy_from_slice(b"\x7FELF\x01\x01");
+
+	let configuration_offset = file.len();
+	file.extend_from_slice(&configuration);
+
+	let keyboard_offset = file.len();
+	file.extend_from_slice(&KEYBOARD);
+
+	let names_offset = file.len();
+	file.extend_from_slice(names);
+
+	let table = file.len();
+
+	let sections = [
+		(0, 0, 0),
+		(1, configuration_offset, configuration.len()),
+		(16, keyboard_offset, KEYBOARD.len()),
+		(26, names_offset, names.len()),
+	];
+
+	for (name, offset, size) in sections {
+		let mut header = [0; 40];
+		header[0..4].copy_from_slice(&u32::try_from(name).unwrap().to_le_bytes());
+		header[4..8].copy_from_slice(&1_u32.to_le_bytes());
+		header[16..20].copy_from_slice(&u32::try_from(offset).unwrap().to_le_bytes());
+		header[20..24].copy_from_slice(&u32::try_from(size).unwrap().to_le_bytes());
+
+		file.extend_from_slice(&header);
+	}
+
+	file[0x20..0x24].copy_from_slice(&u32::try_from(table).unwrap().to_le_bytes());
+	file[0x2E..0x30].copy_from_slice(&40_u16.to_le_bytes());
+	file[0x30..0x32].copy_from_slice(&4_u16.to_le_bytes());
+	file[0x32..0x34].copy_from_slice(&3_u16.to_le_bytes());
+
+	file
+}
+
+/// The flash in UF2 blocks of 256 bytes, written out of order to check they are sorted.
+fn uf2() -> Vec<u8> {
+	let flash = flash();
+	let count = flash.len().div_ceil(256);
+
+	let mut blocks: Vec<Vec<u8>> = flash
+		.chunks(256)
+		.enumerate()
+		.map(|(index, data)| {
+			let address = 0x1000_0000 + u32::try_from(index * 256).unwrap();
+
+			let mut block = vec![0; 512];
+			block[0..4].copy_from_slice(&0x0A32_4655_u32.to_le_bytes());
+			block[4..8].copy_from_slice(&0x9E5D_5157_u32.to_le_bytes());
+			block[8..12].copy_from_slice(&0x0000_2000_u32.to_le_bytes());
+			block[12..16].copy_from_slice(&address.to_le_bytes());
+			block[16..20].copy_from_slice(&u32::try_from(data.len()).unwrap().to_le_bytes());
+			block[20..24].copy_from_slice(&u32::try_from(index).unwrap().to_le_bytes());
+			block[24..28].copy_from_slice(&u32::try_from(count).unwrap().to_le_bytes());
+			block[28..32].copy_from_slice(&0xE48B_FF56_u32.to_le_bytes());
+			block[32..32 + data.len()].copy_from_slice(data);
+			block[508..512].copy_from_slice(&0x0AB1_6F30_u32.to_le_bytes());
+
+			block
+		})
+		.collect();
+
+	blocks.reverse();
+	blocks.concat()
+}
+
+/// A new keymap for the keys of the images.
+fn patched_keymap() -> Keymap {
+	let mut keymap = Keymap::new(2, 3, LAYER_COUNT);
+
+	for (layer, actions) in keymap.layers.iter_mut().enumerate() {
+		for position in [0, 1, 3, 4, 5] {
+			actions[position] = Action::momentary(u8::try_from(layer).unwrap());
+		}
+	}
+
+	// Keys other than in the first layer can be left without an action.
+	keymap.set(2, 1, 1, Action::NO);
+
+	keymap
+}
+
+#[test]
+fn detects_the_format() {
+	assert_eq!(ImageFormat::detect(&elf()), ImageFormat::Elf);
+	assert_eq!(ImageFormat::detect(&uf2()), ImageFormat::Uf2);
+	assert_eq!(ImageFormat::detect(&flash()), ImageFormat::Bin);
+}
+
+#[test]
+fn reads_the_sections_of_every_format() {
+	for bytes in [elf(), flash(), uf2()] {
+		let image = FirmwareImage::parse(bytes).unwrap();
+
+		assert_eq!(image.configuration(), CONFIGURATION);
+
+		let keymap = image.keymap();
+
+		assert_eq!((keymap.rows, keymap.cols, keymap.layers.len()), (2, 3, LAYER_COUNT));
+		assert_eq!(keymap.layers[0], LAYER0.0.as_flattened());
+		assert_eq!(keymap.layers[1], LAYER1.0.as_flattened());
+		assert_eq!(keymap.layers[4], EMPTY.0.as_flattened());
+	}
+}
+
+#[test]
+fn patches_the_keymaps_of_every_format() {
+	for bytes in [elf(), flash(), uf2()] {
+		let len = bytes.len();
+
+		let mut image = FirmwareImage::parse(bytes).unwrap();
+		image.set_keymap(&patched_keymap()).unwrap();
+
+		let patched = image.into_bytes();
+		assert_eq!(patched.len(), len);
+
+		let image = FirmwareImage::parse(patched).unwrap();
+
+		assert_eq!(image.keymap(), patched_keymap());
+		assert_eq!(image.configuration(), CONFIGURATION);
+	}
+}
+
+#[test]
+fn only_the_keyboard_section_changes() {
+	let original = uf2();
+
+	let mut image = FirmwareImage::parse(original.clone()).unwrap();
+	image.set_keymap(&patched_keymap()).unwrap();
+
+	let changed: Vec<usize> = (0..original.len())
+		.filter(|&index| original[index] != image.as_bytes()[index])
+		.collect();
+
+	// The blocks are reversed, so the keyboard section is in the first two, split between them.
+	assert!(!changed.is_empty());
+	assert!(changed.iter().all(|&index| index < 1024 && index % 512 >= 32));
+}
+
+#[test]
+fn rejects_keymaps_of_another_board() {
+	let mut image = FirmwareImage::parse(elf()).unwrap();
+
+	assert_eq!(
+		image.set_keymap(&Keymap::new(3, 3, LAYER_COUNT)),
+		Err(ImageError::KeymapMismatch {
+			rows: 2,
+			cols: 3,
+			layers: LAYER_COUNT
+		})
+	);
+
+	let mut keymap = patched_keymap();
+	keymap.set(3, 0, 2, Action::TRANSPARENT);
+
+	assert_eq!(
+		image.set_keymap(&keymap),
+		Err(ImageError::LayoutMismatch {
+			layer: 3,
+			row: 0,
+			col: 2
+		})
+	);
+
+	let mut keymap = patched_keymap();
+	keymap.set(0, 1, 0, Action::NO);
+
+	assert_eq!(
+		image.set_keymap(&keymap),
+		Err(ImageError::LayoutMismatch {
+			layer: 0,
+			row: 1,
+			col: 0
+		})
+	);
+
+	// Nothing was written.
+	assert_eq!(image.as_bytes(), elf());
+}
+
+#[test]
+fn rejects_images_without_sections() {
+	let mut damaged = flash();
+	let start = damaged.len() - 64 - KEYBOARD_LEN;
+	damaged[start + 20] ^= 0xFF;
+
+	assert_eq!(
+		FirmwareImage::parse(damaged).unwrap_err(),
+		ImageError::MissingSection(".keyboard")
+	);
+
+	let mut truncated = uf2();
+	truncated.truncate(700);
+
+	assert_eq!(
+		FirmwareImage::parse(truncated).unwrap_err(),
+		ImageError::InvalidUf2 { block: 1 }
+	);
+
+	let mut elf = elf();
+	elf[4] = 2;
+
+	assert_eq!(FirmwareImage::parse(elf).unwrap_err(), ImageError::InvalidElf);
+}
+
+#[test]
+fn patches_from_a_board_configuration() {
+	let source = r#"
+name = "Pebble"
+
+[firmware]
+author = "cloudgazing"
+id = "pebble"
+version = "0.1.0"
+device = "keyboard"
+
+[usb]
+vid = 0x1209
+pid = 0x0002
+
+[keyboard]
+mcu = "RP2040"
+flash = 0x200000
+
+[keyboard.keymap]
+rows = 2
+cols = 3
+row_pins = ["0", "1"]
+col_pins = ["2", "3", "4"]
+layer0 = [
+	["KC_Q", "KC_W", "-"],
+	["MO(1)", "LCTL(KC_C)", "KC_E"],
+]
+"#;
+
+	let keymap = keymap_file::from_str(source, Format::Toml).unwrap();
+
+	let mut image = FirmwareImage::parse(flash()).unwrap();
+	image.set_keymap(&keymap).unwrap();
+
+	let keymap = image.keymap();
+
+	assert_eq!(
+		keymap.layers[0],
+		keymap! {
+			[KC_Q, KC_W, -],
+			[MO(1), LCTL(KC_C), KC_E],
+		}
+		.0
+		.as_flattened()
+	);
+	assert_eq!(keymap.layers[1], EMPTY.0.as_flattened());
+}
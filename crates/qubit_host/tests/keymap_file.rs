@@ -0,0 +1,58 @@
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the tests."
+)]
+
+use qubit_config::keyboard::Action;
+use qubit_host::Keymap;
+use qubit_host::keymap_file::{self, Format, FormatError};
+
+fn keymap() -> Keymap {
+	let mut keymap = Keymap::new(2, 3, 2);
+
+	for (index, action) in keymap.layers.iter_mut().flatten().enumerate() {
+		*action = Action::from_bits(u16::try_from(index).unwrap() * 0x101);
+	}
+
+	keymap
+}
+
+#[test]
+fn round_trips_through_toml() {
+	let text = keymap_file::to_string(&keymap(), Format::Toml).unwrap();
+
+	assert_eq!(keymap_file::from_str(&text, Format::Toml).unwrap(), keymap());
+}
+
+#[test]
+fn round_trips_through_json() {
+	let text = keymap_file::to_string(&keymap(), Format::Json).unwrap();
+
+	assert_eq!(keymap_file::from_str(&text, Format::Json).unwrap(), keymap());
+}
+
+#[test]
+fn reads_handwritten_files() {
+	let keymap = keymap_file::from_str(
+		"rows = 1\ncols = 2\nlayers = [[[0x04, 0x05]], [[1, 0]]]\n",
+		Format::Toml,
+	)
+	.unwrap();
+
+	assert_eq!(keymap.get(0, 0, 1), Some(Action::from_bits(0x05)));
+	assert_eq!(keymap.get(1, 0, 0), Some(Action::TRANSPARENT));
+}
+
+#[test]
+fn rejects_layers_of_the_wrong_size() {
+	let result = keymap_file::from_str(r#"{"rows": 1, "cols": 2, "layers": [[[4, 5]], [[4]]]}"#, Format::Json);
+
+	assert!(matches!(result, Err(FormatError::Shape { layer: 1 })));
+}
+
+#[test]
+fn picks_the_format_from_the_extension() {
+	assert_eq!(Format::from_path("keymap.json".as_ref()), Some(Format::Json));
+	assert_eq!(Format::from_path("keymap.toml".as_ref()), Some(Format::Toml));
+	assert_eq!(Format::from_path("keymap".as_ref()), None);
+}
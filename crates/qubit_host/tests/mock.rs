@@ -0,0 +1,259 @@
+#![allow(
+	unused_crate_dependencies,
+	reason = "The dependencies are used by the library, not by the tests."
+)]
+
+use std::num::NonZeroU8;
+
+use qubit_config::keyboard::Action;
+use qubit_config::silverplate::Command;
+use qubit_config::version::{Api, BuildDate, Version};
+use qubit_host::{
+	Capabilities, Client, Error, Keymap, LayerState, MockDevice, MockTransport, RebootMode, ReportFormat, Status,
+};
+
+fn client(device: MockDevice) -> Client<MockTransport> {
+	Client::new(MockTransport::new(device))
+}
+
+fn key(keycode: u8) -> Action {
+	Action::key(NonZeroU8::new(keycode).unwrap())
+}
+
+#[test]
+fn reads_the_firmware_info() {
+	let version = Version::new(Api::V1, 3, 17, 402);
+	// 2026-10-18
+	let build_date = (2 << 9) | (10 << 5) | 18;
+
+	let mut client =
+		client(MockDevice::new(2, 2).with_firmware_info("quartz", "cloudgazing", version.as_bitmap(), build_date));
+
+	let info = client.firmware_info().unwrap();
+
+	assert_eq!(info.name, "quartz");
+	assert_eq!(info.author, "cloudgazing");
+	assert_eq!(info.version, version);
+	assert_eq!(
+		info.build_date,
+		BuildDate {
+			year: 2026,
+			month: 10,
+			day: 18
+		}
+	);
+}
+
+#[test]
+fn cuts_long_names_to_the_frame() {
+	let name = "n".repeat(100);
+
+	let mut client = client(MockDevice::new(2, 2).with_firmware_info(&name, "author", 0, 0));
+
+	let info = client.firmware_info().unwrap();
+
+	assert!(name.starts_with(&info.name));
+	assert!(info.name.len() > 30);
+	assert_eq!(info.author, "");
+}
+
+#[test]
+fn reports_the_capabilities() {
+	let mut client = client(MockDevice::new(2, 2).without_storage());
+
+	let capabilities = client.capabilities().unwrap();
+
+	assert_eq!(capabilities.protocol_version, 1);
+	assert!(capabilities.capabilities.contains(Capabilities::KEYMAP_WRITE));
+	assert!(!capabilities.capabilities.contains(Capabilities::KEYMAP_PERSIST));
+}
+
+#[test]
+fn keymaps_round_trip_over_several_chunks() {
+	let mut client = client(MockDevice::new(5, 14).without_key(4, 13));
+
+	let info = client.keymap_info().unwrap();
+	assert_eq!((info.rows, info.cols, info.layers), (5, 14, 5));
+	assert!(info.chunks_per_layer > 1);
+
+	let mut keymap = client.read_keymap().unwrap();
+	assert_eq!(keymap, Keymap::new(5, 14, 5));
+
+	for layer in 0..5 {
+		for row in 0..5 {
+			for col in 0..14 {
+				#[allow(clippy::cast_possible_truncation, reason = "The keycodes are small.")]
+				keymap.set(layer, row, col, key((4 + layer * 20 + row * 14 + col) as u8));
+			}
+		}
+	}
+
+	client.write_keymap(&keymap).unwrap();
+
+	// Nothing changes until the keymaps are committed.
+	assert_eq!(client.read_keymap().unwrap(), Keymap::new(5, 14, 5));
+
+	client.commit_keymap().unwrap();
+
+	// The position without a key ignores its action.
+	for layer in 0..5 {
+		keymap.set(layer, 4, 13, Action::NO);
+	}
+
+	assert_eq!(client.read_keymap().unwrap(), keymap);
+
+	let device = client.into_transport().into_device();
+
+	assert_eq!(device.stored_keymaps(), Some(&keymap.layers[..]));
+	assert_eq!(device.active_action(1, 0, 0), Some(key(24)));
+}
+
+#[test]
+fn single_keys_can_be_reverted() {
+	let mut client = client(MockDevice::new(2, 3));
+
+	client.set_key(0, 1, 2, key(0x04)).unwrap();
+	client.revert_keymap().unwrap();
+	client.commit_keymap().unwrap();
+
+	assert_eq!(client.read_keymap().unwrap().get(0, 1, 2), Some(Action::NO));
+
+	client.set_key(0, 1, 2, key(0x05)).unwrap();
+	client.commit_keymap().unwrap();
+
+	assert_eq!(client.read_keymap().unwrap().get(0, 1, 2), Some(key(0x05)));
+}
+
+#[test]
+fn rejects_invalid_keymap_changes() {
+	let mut client = client(MockDevice::new(2, 2).without_key(0, 0).without_storage());
+
+	assert!(matches!(
+		client.set_key(0, 0, 0, key(0x04)),
+		Err(Error::Status(Status::InvalidArgument))
+	));
+	assert!(matches!(
+		client.set_key(5, 1, 1, key(0x04)),
+		Err(Error::Status(Status::InvalidArgument))
+	));
+	assert!(matches!(
+		client.write_keymap(&Keymap::new(3, 2, 5)),
+		Err(Error::KeymapMismatch)
+	));
+	assert!(matches!(client.commit_keymap(), Err(Error::Status(Status::NoStorage))));
+}
+
+#[test]
+fn rejects_actions_of_layers_that_dont_exist() {
+	let mut client = client(MockDevice::new(2, 2));
+
+	// A layer-tap of layer 15 on KC_A.
+	assert!(matches!(
+		client.set_key(0, 1, 1, Action::from_bits(0x4F04)),
+		Err(Error::Status(Status::InvalidArgument))
+	));
+	assert!(matches!(
+		client.set_key(0, 1, 1, Action::from_bits(0x5005)),
+		Err(Error::Status(Status::InvalidArgument))
+	));
+
+	let mut keymap = Keymap::new(2, 2, 5);
+	keymap.set(0, 0, 0, key(0x04));
+	keymap.set(0, 1, 1, Action::from_bits(0x502A));
+
+	assert!(matches!(
+		client.write_keymap(&keymap),
+		Err(Error::Status(Status::InvalidArgument))
+	));
+
+	client
+		.set_key(0, 1, 1, Action::layer_tap(4, NonZeroU8::new(0x04).unwrap()))
+		.unwrap();
+	client.commit_keymap().unwrap();
+
+	// Nothing of the refused chunk was applied.
+	let keymap = client.read_keymap().unwrap();
+
+	assert_eq!(keymap.get(0, 0, 0), Some(Action::NO));
+	assert_eq!(keymap.get(0, 1, 1).map(Action::to_bits), Some(0x4404));
+}
+
+#[test]
+fn changes_the_report_format() {
+	let mut client = client(MockDevice::new(1, 1));
+
+	assert_eq!(client.report_format().unwrap(), ReportFormat::Nkro);
+	assert_eq!(client.set_nkro(false).unwrap(), ReportFormat::SixKro);
+	assert_eq!(client.report_format().unwrap(), ReportFormat::SixKro);
+}
+
+#[test]
+fn changes_the_layer_state() {
+	let mut client = client(MockDevice::new(1, 1));
+
+	assert_eq!(client.layer_state().unwrap(), LayerState { active: 0, default: 0 });
+
+	let state = LayerState {
+		active: 0b0_0110,
+		default: 1,
+	};
+
+	client.set_layer_state(state).unwrap();
+	assert_eq!(client.layer_state().unwrap(), state);
+
+	assert!(matches!(
+		client.set_layer_state(LayerState { active: 0, default: 5 }),
+		Err(Error::Status(Status::InvalidArgument))
+	));
+	assert!(matches!(
+		client.set_layer_state(LayerState {
+			active: 0b10_0000,
+			default: 0
+		}),
+		Err(Error::Status(Status::InvalidArgument))
+	));
+}
+
+#[test]
+fn asks_for_reboots() {
+	let mut client = client(MockDevice::new(1, 1));
+
+	client.reboot(RebootMode::Firmware).unwrap();
+	client.reboot(RebootMode::Bootloader).unwrap();
+
+	assert_eq!(
+		client.into_transport().into_device().reboots(),
+		[RebootMode::Firmware, RebootMode::Bootloader]
+	);
+}
+
+#[test]
+fn skips_notifications() {
+	let mut client = client(MockDevice::new(1, 1));
+
+	client
+		.transport_mut()
+		.notify(Command::GetReportFormat, &[ReportFormat::SixKro as u8]);
+
+	assert_eq!(client.report_format().unwrap(), ReportFormat::Nkro);
+}
+
+#[test]
+fn times_out_without_a_response() {
+	struct Silent;
+
+	impl qubit_host::Transport for Silent {
+		fn send(&mut self, _frame: &[u8; qubit_config::silverplate::REPORT_LEN]) -> std::io::Result<()> {
+			Ok(())
+		}
+
+		fn receive(
+			&mut self,
+			_timeout: std::time::Duration,
+		) -> std::io::Result<Option<[u8; qubit_config::silverplate::REPORT_LEN]>> {
+			Ok(None)
+		}
+	}
+
+	assert!(matches!(Client::new(Silent).capabilities(), Err(Error::Timeout)));
+}
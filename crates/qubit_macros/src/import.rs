@@ -4,9 +4,18 @@ use quote::quote;
 use syn::parse_macro_input;
 use syn::{Ident, Token};
 
-struct Input {
-	author: Ident,
-	model: Ident,
+enum Input {
+	/// A model of `qubit_device`.
+	Model { author: Ident, model: Ident },
+	/// A module generated from a TOML file, at the path.
+	Module(String),
+}
+
+fn read_env(ident: &Ident) -> Result<String, syn::Error> {
+	std::env::var(ident.to_string()).map_err(|_| {
+		let err_msg = format!("Failed to read env value {ident}");
+		syn::Error::new(ident.span(), err_msg)
+	})
 }
 
 impl syn::parse::Parse for Input {
@@ -17,29 +26,33 @@ impl syn::parse::Parse for Input {
 
 		let model_ident: Ident = input.parse()?;
 
-		let Ok(author_value) = std::env::var(author_ident.to_string()) else {
-			let err_msg = format!("Failed to read env value {author_ident}");
-			return Err(syn::Error::new(author_ident.span(), err_msg));
-		};
-
-		let author = Ident::new(&author_value, author_ident.span());
+		if input.parse::<Option<Token![,]>>()?.is_some() {
+			let module_ident: Ident = input.parse()?;
 
-		let Ok(model_value) = std::env::var(model_ident.to_string()) else {
-			let err_msg = format!("Failed to read env value {model_ident}");
-			return Err(syn::Error::new(model_ident.span(), err_msg));
-		};
+			if let Ok(path) = std::env::var(module_ident.to_string())
+				&& !path.is_empty()
+			{
+				return Ok(Self::Module(path));
+			}
+		}
 
-		let model = Ident::new(&model_value, model_ident.span());
+		let author = Ident::new(&read_env(&author_ident)?, author_ident.span());
+		let model = Ident::new(&read_env(&model_ident)?, model_ident.span());
 
-		Ok(Self { author, model })
+		Ok(Self::Model { author, model })
 	}
 }
 
 pub fn import_device_macro(input: TokenStream) -> TokenStream {
-	let input = parse_macro_input!(input as Input);
-
-	let author = input.author;
-	let model = input.model;
-
-	quote! { pub use ::qubit_device::models::#author::#model as device; }.into()
+	match parse_macro_input!(input as Input) {
+		Input::Model { author, model } => quote! { pub use ::qubit_device::models::#author::#model as device; },
+		Input::Module(path) => quote! {
+			pub mod device {
+				include!(#path);
+			}
+
+			use ::qubit_device as _;
+		},
+	}
+	.into()
 }
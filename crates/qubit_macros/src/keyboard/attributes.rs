@@ -1,6 +1,7 @@
 use std::str::FromStr;
 
-use qubit_config::mcu::Mcu;
+use qubit_config::mcu::{Mcu, PinError};
+use quote::ToTokens;
 use syn::spanned::Spanned;
 use syn::{Expr, ExprArray, Ident, Lit, LitInt, LitStr, Token};
 
@@ -24,14 +25,14 @@ impl FromStr for Direction {
 
 #[derive(Debug)]
 pub struct KeymapExpr {
-	pub keymap: Vec<Vec<u8>>,
+	pub keymap: Vec<Vec<u16>>,
 }
 
 impl KeymapExpr {
 	fn from_arr_expr(arr_expr: ExprArray) -> Result<Self, syn::Error> {
 		let mut expected_col_len: Option<usize> = None;
 
-		let keymap: Vec<Vec<u8>> = arr_expr
+		let keymap: Vec<Vec<u16>> = arr_expr
 			.elems
 			.into_iter()
 			.map(|row| {
@@ -41,7 +42,7 @@ impl KeymapExpr {
 
 				let row_expr_span = row_expr.span();
 
-				let row: Vec<u8> = row_expr
+				let row: Vec<u16> = row_expr
 					.elems
 					.into_iter()
 					.map(|key| {
@@ -49,7 +50,7 @@ impl KeymapExpr {
 							return Err(syn::Error::new(key.span(), "Expected literal expression."));
 						};
 
-						let value: u8 = match lit_expr.lit {
+						let value: u16 = match lit_expr.lit {
 							Lit::Int(value) => value.base10_parse()?,
 							_ => {
 								return Err(syn::Error::new(lit_expr.span(), "Expected literal int expression."));
@@ -176,6 +177,8 @@ impl syn::parse::Parse for Attributes {
 		let keymap = keymap.ok_or(syn::Error::new(stream.span(), "Missing `keymap` argument."))?;
 		let direction = direction.unwrap_or(Direction::ColRow);
 
+		check_pins(mcu, &rows, &cols)?;
+
 		Ok(Self {
 			delay,
 			mcu,
@@ -186,3 +189,37 @@ impl syn::parse::Parse for Attributes {
 		})
 	}
 }
+
+/// Checks the pins against the pins of the MCU, reporting every problem at once.
+fn check_pins(mcu: Mcu, rows: &ExprArray, cols: &ExprArray) -> Result<(), syn::Error> {
+	let mut errors: Option<syn::Error> = None;
+	let mut used = Vec::new();
+
+	for pin in rows.elems.iter().chain(&cols.elems) {
+		let name = pin.to_token_stream().to_string();
+
+		let message = match mcu.usable_pin(&name) {
+			Err(PinError::Unknown) => Some(format!("{name} isn't a pin of the {}.", mcu.as_str())),
+			Err(PinError::Reserved(reserved)) => Some(format!(
+				"Pin {name} is reserved for {} on the {}.",
+				reserved.as_str(),
+				mcu.as_str()
+			)),
+			Ok(_) if used.contains(&name) => Some(format!("Pin {name} is used more than once.")),
+			Ok(_) => None,
+		};
+
+		if let Some(message) = message {
+			let error = syn::Error::new(pin.span(), message);
+
+			match &mut errors {
+				Some(errors) => errors.combine(error),
+				None => errors = Some(error),
+			}
+		}
+
+		used.push(name);
+	}
+
+	errors.map_or(Ok(()), Err)
+}
@@ -19,7 +19,7 @@ mod keyboard;
 /// The macro takes the following arguments:
 ///
 /// - `mcu` *(required)*: The target microcontroller (e.g., `"RP2040"`).
-/// - `keymap` *(required)*: A 2D array of HID keycodes that defines the layout.
+/// - `keymap` *(required)*: A 2D array of keymap entries that defines the layout, `0` marks an empty space.
 /// - `rows` *(required)*: An array of GPIO pin numbers used as rows.
 /// - `cols` *(required)*: An array of GPIO pin numbers used as columns.
 /// - `direction` *(optional)*: Scanning direction, either `"RowCol"` or `"ColRow"`. Defaults to `"ColRow"`.
@@ -99,6 +99,23 @@ pub fn build_date_bitmap(_input: TokenStream) -> TokenStream {
 /// ```rust
 /// pub use ::qubit_device::models::cloudgazing::quartz as device;
 /// ```
+///
+/// A third environment variable can name a device module generated from a TOML file. When it's
+/// set, the module is included instead:
+///
+/// ```ignore
+/// import_device!(QUBIT_AUTHOR, QUBIT_MODEL, QUBIT_DEVICE);
+/// ```
+///
+/// expands to:
+///
+/// ```ignore
+/// pub mod device {
+///     include!("/path/to/device.rs");
+/// }
+///
+/// use ::qubit_device as _;
+/// ```
 #[cfg(feature = "import")]
 #[proc_macro]
 pub fn import_device(input: TokenStream) -> TokenStream {
@@ -0,0 +1,63 @@
+#!/usr/bin/env cargo
+---cargo
+package.edition = "2024"
+
+[dependencies]
+qubit_config = { path = "../crates/qubit_config", features = ["std"] }
+toml = "0.9.2"
+---
+
+use std::io::Write;
+
+fn main() {
+	let custom = env!("CUSTOM");
+
+	// The error lists every problem in the file, with the lines they are on.
+	let config = qubit_config::parse::parse_file(custom).unwrap_or_else(|err| {
+		eprintln!("{err}");
+
+		std::process::exit(1);
+	});
+
+	let cwd = std::env::current_dir().unwrap();
+
+	// The firmware's build script includes the module, so it's written next to the cargo config.
+	let device_path = cwd.join(".cargo/device.rs");
+	std::fs::write(&device_path, config.device_module()).unwrap();
+
+	let target_triple = config.keyboard.mcu.target_triple();
+	let mcu = config.keyboard.mcu.as_cfg_str();
+
+	// The values come from the file, so they are written as TOML strings, which escapes them.
+	let author = toml::Value::from(config.author.as_str());
+	let model = toml::Value::from(config.id.as_str());
+	let device = toml::Value::from(device_path.display().to_string());
+
+	let extension = format!(
+		r#"
+[build]
+target = "{target_triple}"
+rustflags = ["--cfg", 'mcu="{mcu}"']
+
+[host]
+rustflags = ["--cfg", 'device="import"']
+
+[env]
+QUBIT_AUTHOR = {author}
+QUBIT_MODEL = {model}
+QUBIT_DEVICE = {device}
+"#
+	);
+
+	let config_extend_path = cwd.join(".cargo/config-extend.toml");
+
+	let mut file = std::fs::File::create(&config_extend_path).unwrap();
+
+	writeln!(
+		&mut file,
+		"# This file is automatically generated and not intended for manual editing."
+	)
+	.unwrap();
+
+	file.write_all(&extension.into_bytes()).unwrap();
+}
@@ -48,8 +48,21 @@ fn main() {
 				std::process::exit(1);
 			}
 		}
-		(None, None, Some(_c)) => {
-			todo!()
+		(None, None, Some(c)) => {
+			let cwd = std::env::current_dir().unwrap();
+			let prepare_path = cwd.join("scripts/_prepare_custom.rs");
+
+			let status = Command::new("cargo")
+				.env("CUSTOM", c)
+				.args(["+nightly", "-Zscript", prepare_path.to_str().unwrap()])
+				.status()
+				.expect("failed to run cargo script");
+
+			if !status.success() {
+				eprintln!("cargo script failed with code: {status}");
+
+				std::process::exit(1);
+			}
 		}
 		_ => {
 			eprintln!("Usage: --author <name> --model <name>  OR  --custom <path>");